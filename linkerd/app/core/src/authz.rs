@@ -0,0 +1,491 @@
+//! Authorizes inbound connections and requests by the client's identity and
+//! network address.
+//!
+//! Each inbound port may have a policy that lists the client identities (or
+//! identity suffixes) and unauthenticated networks that may connect to it.
+//! Policies may further restrict HTTP requests whose paths begin with a prefix.
+//! Paths are percent-decoded and normalized before they are matched, and
+//! prefixes only match whole path segments, so that `/admin` matches
+//! `/admin/users` and `/./%61dmin` but not `/administrator`. Denied connections
+//! are dropped; denied requests fail with an `HttpError` so that clients
+//! receive a 403 (or a gRPC `PERMISSION_DENIED` status).
+
+use super::errors::HttpError;
+use crate::proxy::identity;
+use crate::transport::tls;
+use futures::{future, TryFutureExt};
+use indexmap::IndexMap;
+use ipnet::IpNet;
+use linkerd2_admit as admit;
+use linkerd2_error::Error;
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetrics};
+use linkerd2_stack::NewService;
+use percent_encoding::percent_decode_str;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tracing::{debug, trace};
+
+metrics! {
+    inbound_authz_denied_total: Counter {
+        "Total number of inbound connections and requests denied by an authorization policy"
+    }
+}
+
+/// Authorization policies, keyed by inbound port.
+pub type Config = IndexMap<u16, PortPolicy>;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PortPolicy {
+    /// Authorizes connections to the port. All clients are authorized when
+    /// this is unset.
+    pub connections: Option<Authorization>,
+
+    /// Further authorizes requests whose paths start with each prefix's
+    /// segments. The longest matching prefix applies.
+    pub paths: Vec<(String, Authorization)>,
+}
+
+/// The clients that are authorized by a policy.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Authorization {
+    pub identities: Vec<IdentityMatch>,
+
+    /// Networks from which clients are authorized without an identity.
+    pub networks: Vec<IpNet>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IdentityMatch {
+    Exact(identity::Name),
+
+    /// Matches names ending with the suffix, which starts with a `.`. An empty
+    /// suffix matches all names.
+    Suffix(String),
+}
+
+/// Enforces authorization policies on inbound connections, and builds
+/// services that enforce them on requests.
+#[derive(Clone, Debug)]
+pub struct Authorize {
+    ports: Arc<Config>,
+    registry: Registry,
+}
+
+/// Records denials for each policy.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<IndexMap<Labels, Arc<Counter>>>>);
+
+#[derive(Clone, Debug)]
+pub struct HttpLayer(Authorize);
+
+#[derive(Clone, Debug)]
+pub struct NewAuthorizeHttp<N> {
+    authorize: Authorize,
+    inner: N,
+}
+
+/// Enforces a port's path policies on a connection's requests.
+#[derive(Clone, Debug)]
+pub struct AuthorizeHttp<S> {
+    inner: S,
+    client: Option<Client>,
+}
+
+/// Indicates that a connection was denied by an authorization policy.
+#[derive(Debug)]
+pub struct Unauthorized {
+    port: u16,
+}
+
+#[derive(Clone, Debug)]
+struct Client {
+    port: u16,
+    identity: Option<identity::Name>,
+    ip: IpAddr,
+    authorize: Authorize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Labels {
+    port: u16,
+    path_prefix: Option<String>,
+}
+
+// === impl Authorization ===
+
+impl Authorization {
+    fn is_authorized(&self, identity: Option<&identity::Name>, ip: IpAddr) -> bool {
+        if let Some(name) = identity {
+            if self.identities.iter().any(|m| m.matches(name)) {
+                return true;
+            }
+        }
+
+        self.networks.iter().any(|net| match (net, ip) {
+            (IpNet::V4(net), IpAddr::V4(addr)) => net.contains(&addr),
+            (IpNet::V6(net), IpAddr::V6(addr)) => net.contains(&addr),
+            _ => false,
+        })
+    }
+}
+
+// === impl IdentityMatch ===
+
+impl IdentityMatch {
+    fn matches(&self, name: &identity::Name) -> bool {
+        match self {
+            IdentityMatch::Exact(n) => n == name,
+            IdentityMatch::Suffix(suffix) => {
+                let name = name.as_ref();
+                name.len() > suffix.len()
+                    && name[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+            }
+        }
+    }
+}
+
+// === impl Authorize ===
+
+impl Authorize {
+    pub fn new(ports: Config, registry: Registry) -> Self {
+        Self {
+            ports: Arc::new(ports),
+            registry,
+        }
+    }
+
+    /// Builds a layer that enforces path policies on each connection's
+    /// requests.
+    pub fn http_layer(&self) -> HttpLayer {
+        HttpLayer(self.clone())
+    }
+}
+
+impl admit::Admit<tls::accept::Meta> for Authorize {
+    type Error = Unauthorized;
+
+    fn admit(&mut self, meta: &tls::accept::Meta) -> Result<(), Self::Error> {
+        let port = meta.addrs.target_addr().port();
+        let authz = match self.ports.get(&port).and_then(|p| p.connections.as_ref()) {
+            Some(authz) => authz,
+            None => return Ok(()),
+        };
+
+        let identity = meta.peer_identity.value();
+        if authz.is_authorized(identity, meta.addrs.peer().ip()) {
+            trace!(%port, peer.id = ?identity, "Connection authorized");
+            return Ok(());
+        }
+
+        debug!(%port, peer.id = ?identity, peer.addr = %meta.addrs.peer(), "Connection denied");
+        self.registry.denied(port, None);
+        Err(Unauthorized { port })
+    }
+}
+
+// === impl HttpLayer ===
+
+impl<N> tower::layer::Layer<N> for HttpLayer {
+    type Service = NewAuthorizeHttp<N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewAuthorizeHttp {
+            authorize: self.0.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl NewAuthorizeHttp ===
+
+impl<N> NewService<tls::accept::Meta> for NewAuthorizeHttp<N>
+where
+    N: NewService<tls::accept::Meta>,
+{
+    type Service = AuthorizeHttp<N::Service>;
+
+    fn new_service(&self, meta: tls::accept::Meta) -> Self::Service {
+        let port = meta.addrs.target_addr().port();
+        let has_paths = self
+            .authorize
+            .ports
+            .get(&port)
+            .map(|p| !p.paths.is_empty())
+            .unwrap_or(false);
+        let client = if has_paths {
+            Some(Client {
+                port,
+                identity: meta.peer_identity.value().cloned(),
+                ip: meta.addrs.peer().ip(),
+                authorize: self.authorize.clone(),
+            })
+        } else {
+            None
+        };
+
+        let inner = self.inner.new_service(meta);
+        AuthorizeHttp { inner, client }
+    }
+}
+
+// === impl AuthorizeHttp ===
+
+impl<S, B> tower::Service<http::Request<B>> for AuthorizeHttp<S>
+where
+    S: tower::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::MapErr<S::Future, fn(S::Error) -> Error>,
+        future::Ready<Result<Self::Response, Self::Error>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if let Some(client) = self.client.as_ref() {
+            if let Err(error) = client.authorize_path(req.uri().path()) {
+                return future::Either::Right(future::err(error.into()));
+            }
+        }
+
+        future::Either::Left(self.inner.call(req).map_err(Into::into as fn(_) -> _))
+    }
+}
+
+// === impl Client ===
+
+impl Client {
+    fn authorize_path(&self, path: &str) -> Result<(), HttpError> {
+        let policy = match self.authorize.ports.get(&self.port) {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+        let path = segments(path);
+        let (prefix, authz) = match policy
+            .paths
+            .iter()
+            .filter_map(|(prefix, authz)| {
+                let prefix_segments = segments(prefix);
+                if path.starts_with(&prefix_segments) {
+                    Some((prefix, authz, prefix_segments.len()))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(_, _, len)| *len)
+        {
+            Some((prefix, authz, _)) => (prefix, authz),
+            None => return Ok(()),
+        };
+
+        if authz.is_authorized(self.identity.as_ref(), self.ip) {
+            return Ok(());
+        }
+
+        debug!(port = %self.port, %prefix, peer.id = ?self.identity, "Request denied");
+        self.authorize.registry.denied(self.port, Some(prefix));
+        Err(HttpError::unauthorized(
+            "request denied by authorization policy",
+        ))
+    }
+}
+
+/// Percent-decodes and normalizes a path into its segments, ignoring empty
+/// and `.` segments and resolving `..` segments.
+fn segments(path: &str) -> Vec<String> {
+    let path = percent_decode_str(path).decode_utf8_lossy();
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment.to_string()),
+        }
+    }
+    segments
+}
+
+// === impl Unauthorized ===
+
+impl fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "connection to port {} denied by authorization policy",
+            self.port
+        )
+    }
+}
+
+impl std::error::Error for Unauthorized {}
+
+// === impl Registry ===
+
+impl Registry {
+    fn denied(&self, port: u16, path_prefix: Option<&str>) {
+        let labels = Labels {
+            port,
+            path_prefix: path_prefix.map(String::from),
+        };
+        if let Ok(mut counters) = self.0.lock() {
+            counters
+                .entry(labels)
+                .or_insert_with(Default::default)
+                .incr();
+        }
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counters = match self.0.lock() {
+            Ok(counters) => counters,
+            Err(_) => return Ok(()),
+        };
+        if counters.is_empty() {
+            return Ok(());
+        }
+
+        inbound_authz_denied_total.fmt_help(f)?;
+        inbound_authz_denied_total.fmt_scopes(f, counters.iter(), |c| c)?;
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl FmtLabels for Labels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "port=\"{}\"", self.port)?;
+        if let Some(prefix) = self.path_prefix.as_ref() {
+            write!(f, ",path_prefix=\"{}\"", prefix)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(s: &str) -> identity::Name {
+        identity::Name::from_hostname(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn authorizes_identities_and_networks() {
+        let authz = Authorization {
+            identities: vec![
+                IdentityMatch::Exact(name("web.ns.serviceaccount.identity.linkerd.cluster.local")),
+                IdentityMatch::Suffix(
+                    ".admin.serviceaccount.identity.linkerd.cluster.local".into(),
+                ),
+            ],
+            networks: vec!["10.0.0.0/8".parse().unwrap()],
+        };
+        let ip = IpAddr::from([192, 168, 0, 1]);
+
+        assert!(authz.is_authorized(
+            Some(&name(
+                "web.ns.serviceaccount.identity.linkerd.cluster.local"
+            )),
+            ip
+        ));
+        assert!(authz.is_authorized(
+            Some(&name(
+                "ops.admin.serviceaccount.identity.linkerd.cluster.local"
+            )),
+            ip
+        ));
+        assert!(!authz.is_authorized(
+            Some(&name(
+                "api.ns.serviceaccount.identity.linkerd.cluster.local"
+            )),
+            ip
+        ));
+        assert!(!authz.is_authorized(None, ip));
+
+        // Unauthenticated clients are authorized by network.
+        assert!(authz.is_authorized(None, IpAddr::from([10, 1, 1, 1])));
+    }
+
+    #[test]
+    fn authorizes_longest_path_prefix() {
+        let allow_all = Authorization {
+            networks: vec!["0.0.0.0/0".parse().unwrap()],
+            ..Authorization::default()
+        };
+        let mut ports = Config::new();
+        ports.insert(
+            8080,
+            PortPolicy {
+                connections: None,
+                paths: vec![
+                    ("/admin".into(), Authorization::default()),
+                    ("/admin/health".into(), allow_all),
+                ],
+            },
+        );
+        let registry = Registry::default();
+        let client = Client {
+            port: 8080,
+            identity: None,
+            ip: IpAddr::from([10, 1, 1, 1]),
+            authorize: Authorize::new(ports, registry.clone()),
+        };
+
+        assert!(client.authorize_path("/").is_ok());
+        assert!(client.authorize_path("/admin/health").is_ok());
+        let error = client.authorize_path("/admin/users").unwrap_err();
+        assert_eq!(error.status(), http::StatusCode::FORBIDDEN);
+
+        let labels = Labels {
+            port: 8080,
+            path_prefix: Some("/admin".into()),
+        };
+        let counters = registry.0.lock().unwrap();
+        assert_eq!(counters.get(&labels).map(|c| c.value()), Some(1));
+    }
+
+    #[test]
+    fn matches_normalized_path_segments() {
+        let mut ports = Config::new();
+        ports.insert(
+            8080,
+            PortPolicy {
+                connections: None,
+                paths: vec![("/admin".into(), Authorization::default())],
+            },
+        );
+        let client = Client {
+            port: 8080,
+            identity: None,
+            ip: IpAddr::from([10, 1, 1, 1]),
+            authorize: Authorize::new(ports, Registry::default()),
+        };
+
+        assert!(client.authorize_path("/administrator").is_ok());
+        assert!(client.authorize_path("/public/../administrator").is_ok());
+        for path in &[
+            "/admin",
+            "/admin/",
+            "//admin",
+            "/./admin",
+            "/%61dmin",
+            "/public/../admin/users",
+            "/public/%2e%2e/admin",
+        ] {
+            assert!(client.authorize_path(path).is_err(), "{}", path);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::errors::{HttpError, Reason};
 use crate::profiles;
 use http;
 use linkerd2_error::Error;
@@ -148,6 +149,8 @@ impl classify::ClassifyResponse for Response {
     fn error(self, err: &Error) -> Self::Class {
         let msg = if err.is::<ResponseTimeout>() {
             "timeout".into()
+        } else if is_fault(err) {
+            "fault".into()
         } else {
             h2_error(err).into()
         };
@@ -201,6 +204,14 @@ fn grpc_class(headers: &http::HeaderMap) -> Option<Class> {
         })
 }
 
+/// Indicates whether a request was aborted by an injected fault, so that these
+/// failures may be distinguished from errors returned by the destination.
+fn is_fault(err: &Error) -> bool {
+    err.downcast_ref::<HttpError>()
+        .map(|e| e.reason() == Reason::FaultInjected)
+        .unwrap_or(false)
+}
+
 fn h2_error(err: &Error) -> String {
     if let Some(reason) = err.h2_reason() {
         // This should output the error code in the same format as the spec,
@@ -225,6 +236,18 @@ impl Class {
     }
 }
 
+impl linkerd2_http_outlier::IsFailure for Class {
+    fn is_failure(&self) -> bool {
+        Class::is_failure(self)
+    }
+}
+
+impl linkerd2_concurrency_limit::adaptive::IsFailure for Class {
+    fn is_failure(&self) -> bool {
+        Class::is_failure(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Class, SuccessOrFailure};
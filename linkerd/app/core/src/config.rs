@@ -1,8 +1,10 @@
 pub use super::control::ControlAddr;
+pub use crate::concurrency_limit::adaptive::Config as AdaptiveConcurrencyLimit;
 pub use crate::exp_backoff::ExponentialBackoff;
 pub use crate::proxy::http::h2;
+pub use crate::proxy::PortProtocol;
 pub use crate::transport::{Bind, DefaultOrigDstAddr, NoOrigDstAddr, OrigDstAddr};
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -27,8 +29,13 @@ pub struct ProxyConfig {
     pub buffer_capacity: usize,
     pub cache_max_idle_age: Duration,
     pub disable_protocol_detection_for_ports: Arc<IndexSet<u16>>,
+    /// Ports whose protocol is declared, so that it need not be detected.
+    pub port_protocols: Arc<IndexMap<u16, PortProtocol>>,
     pub dispatch_timeout: Duration,
     pub max_in_flight_requests: usize,
+    /// When set, the number of in-flight requests is limited adaptively,
+    /// bounded by `max_in_flight_requests`.
+    pub adaptive_concurrency_limit: Option<AdaptiveConcurrencyLimit>,
     pub detect_protocol_timeout: Duration,
 }
 
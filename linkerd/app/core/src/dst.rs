@@ -1,9 +1,16 @@
 use super::classify;
+use super::metric_labels::Direction;
 use crate::profiles;
+use crate::proxy::{identity, tap};
+use crate::transport::{listen, tls};
+use crate::Conditional;
+use indexmap::IndexMap;
 use linkerd2_addr::Addr;
 use linkerd2_http_classify::CanClassify;
 use linkerd2_proxy_http::timeout;
 use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -29,6 +36,53 @@ impl timeout::HasTimeout for Route {
     }
 }
 
+/// Describes requests that are handled by a route without being sent to an
+/// endpoint, i.e. because a fault was injected.
+impl tap::Inspect for Route {
+    fn src_addr<B>(&self, req: &http::Request<B>) -> Option<SocketAddr> {
+        req.extensions().get::<listen::Addrs>().map(|s| s.peer())
+    }
+
+    fn src_tls<'a, B>(
+        &self,
+        req: &'a http::Request<B>,
+    ) -> Conditional<&'a identity::Name, tls::ReasonForNoPeerName> {
+        match self.direction {
+            Direction::Out => Conditional::None(tls::ReasonForNoPeerName::Loopback),
+            Direction::In => req
+                .extensions()
+                .get::<tls::accept::Meta>()
+                .map(|m| m.peer_identity.as_ref())
+                .unwrap_or_else(|| {
+                    Conditional::None(tls::ReasonForNoPeerName::LocalIdentityDisabled)
+                }),
+        }
+    }
+
+    fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        None
+    }
+
+    fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+        None
+    }
+
+    fn dst_tls<B>(
+        &self,
+        _: &http::Request<B>,
+    ) -> Conditional<&identity::Name, tls::ReasonForNoPeerName> {
+        Conditional::None(tls::ReasonForNoPeerName::NotProvidedByServiceDiscovery)
+    }
+
+    fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+        Some(self.route.labels().clone())
+    }
+
+    fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+        self.direction == Direction::Out
+    }
+}
+
 impl fmt::Display for Route {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.target.fmt(f)
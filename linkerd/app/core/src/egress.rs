@@ -0,0 +1,637 @@
+//! Restricts the external destinations that outbound traffic may reach.
+//!
+//! The policy applies where traffic is forwarded to its original destination
+//! address because the destination service does not resolve its target;
+//! traffic that is routed to discovered endpoints never leaves the mesh.
+//! Destinations with addresses in the configured internal networks are also
+//! exempt. Each connection, or HTTP client, to an external destination is
+//! matched against an ordered list of rules by its name (the request's host or
+//! the TLS server name), its address and its port; the first matching rule
+//! decides whether it is allowed, falling back to the policy's default.
+//!
+//! Names are chosen by the client, so a name only matches an allow rule when
+//! DNS resolves it to the destination's address. Deny rules match any name.
+//! Denied connections are dropped; denied requests fail with a 403.
+//!
+//! HTTP clients are cached, so each client's decision is made once and is only
+//! made again when the DNS records that verified its name expire.
+//!
+//! In dry-run mode, denials are recorded and logged but not enforced.
+
+use crate::{dns, svc};
+use futures::prelude::*;
+use indexmap::IndexMap;
+use ipnet::IpNet;
+use linkerd2_error::Error;
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetrics};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::util::ServiceExt;
+use tracing::{debug, info};
+
+metrics! {
+    outbound_egress_decisions_total: Counter {
+        "Total number of outbound connections and clients to external destinations, by egress policy decision"
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Evaluated in order; the first matching rule applies.
+    pub rules: Vec<Rule>,
+
+    /// Applies to external destinations that match no rule.
+    pub default: Action,
+
+    /// Destinations with addresses in these networks are not external.
+    pub internal_networks: Vec<IpNet>,
+
+    /// Records and logs denials without enforcing them.
+    pub dry_run: bool,
+
+    /// Logs every decision, rather than only at the debug level.
+    pub log: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub action: Action,
+
+    /// Matches all destinations when unset.
+    pub dst: Option<DstMatch>,
+
+    /// Matches all ports when unset.
+    pub port: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DstMatch {
+    Name(dns::Suffix),
+    Network(IpNet),
+}
+
+/// An outbound destination.
+#[derive(Clone, Debug)]
+pub struct Dst {
+    /// The name the client asked for, if any.
+    pub name: Option<dns::Name>,
+    /// The address that the proxy connects to.
+    pub addr: SocketAddr,
+}
+
+/// A target that is checked against the egress policy.
+pub trait HasDst {
+    fn dst(&self) -> Dst;
+}
+
+/// Enforces an egress policy, if one is configured.
+#[derive(Clone, Debug)]
+pub struct Egress {
+    config: Option<Arc<Config>>,
+    resolver: dns::Resolver,
+    registry: Registry,
+}
+
+/// Checks targets against the egress policy before they are built.
+#[derive(Clone, Debug)]
+pub struct MakeEgress<M> {
+    egress: Egress,
+    inner: M,
+}
+
+/// Builds services that enforce the egress policy for their target.
+#[derive(Clone, Debug)]
+pub struct NewEnforce<N> {
+    egress: Egress,
+    inner: N,
+}
+
+/// Enforces the egress policy for a single target, failing requests while it
+/// is denied.
+pub struct Enforce<S> {
+    egress: Egress,
+    dst: Dst,
+    state: State,
+    inner: S,
+}
+
+enum State {
+    Init,
+    Pending(CheckFuture),
+    Decided(Decision),
+}
+
+type CheckFuture = Pin<Box<dyn Future<Output = Decision> + Send + 'static>>;
+
+/// The outcome of checking a destination, which must be checked again once
+/// the DNS records that verified its name expire.
+#[derive(Clone, Debug)]
+struct Decision {
+    result: Result<(), Denied>,
+    valid_until: Option<Instant>,
+}
+
+/// Records decisions by action.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<IndexMap<Labels, Arc<Counter>>>>);
+
+/// Indicates that a connection was denied by the egress policy.
+#[derive(Clone, Debug)]
+pub struct Denied(String);
+
+/// How long a decision stands when a name could not be resolved.
+const RESOLVE_FAILURE_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Labels {
+    action: Action,
+    dry_run: bool,
+}
+
+// === impl Config ===
+
+impl Config {
+    fn is_internal(&self, dst: &Dst) -> bool {
+        let ip = dst.addr.ip();
+        self.internal_networks.iter().any(|net| contains(net, ip))
+    }
+
+    /// Indicates whether a rule could allow `dst` by its name, in which case
+    /// the name must be verified.
+    fn allows_name(&self, dst: &Dst) -> bool {
+        match dst.name.as_ref() {
+            Some(name) => self.rules.iter().any(|rule| {
+                rule.action == Action::Allow
+                    && rule.port.map(|p| p == dst.addr.port()).unwrap_or(true)
+                    && match rule.dst.as_ref() {
+                        Some(DstMatch::Name(suffix)) => suffix.contains(name),
+                        _ => false,
+                    }
+            }),
+            None => false,
+        }
+    }
+
+    /// Decides whether an external destination is allowed.
+    fn decide(&self, dst: &Dst, name_verified: bool) -> Action {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(dst, name_verified))
+            .map(|rule| rule.action)
+            .unwrap_or(self.default)
+    }
+
+    /// Decides, records and logs whether an external destination is allowed,
+    /// failing if it is denied.
+    fn enforce(&self, dst: &Dst, name_verified: bool, registry: &Registry) -> Result<(), Denied> {
+        let action = self.decide(dst, name_verified);
+        let dry_run = self.dry_run;
+        registry.record(action, dry_run);
+        if self.log {
+            info!(%dst, %action, dry_run, name_verified, "Egress policy decision");
+        } else {
+            debug!(%dst, %action, dry_run, name_verified, "Egress policy decision");
+        }
+
+        if action == Action::Deny && !dry_run {
+            return Err(Denied(dst.to_string()));
+        }
+        Ok(())
+    }
+}
+
+// === impl Rule ===
+
+impl Rule {
+    fn matches(&self, dst: &Dst, name_verified: bool) -> bool {
+        if let Some(port) = self.port {
+            if port != dst.addr.port() {
+                return false;
+            }
+        }
+
+        match self.dst.as_ref() {
+            None => true,
+            // Unverified names may only be denied.
+            Some(DstMatch::Name(suffix)) => {
+                (name_verified || self.action == Action::Deny)
+                    && dst
+                        .name
+                        .as_ref()
+                        .map(|n| suffix.contains(n))
+                        .unwrap_or(false)
+            }
+            Some(DstMatch::Network(net)) => contains(net, dst.addr.ip()),
+        }
+    }
+}
+
+fn contains(net: &IpNet, ip: IpAddr) -> bool {
+    match (net, ip) {
+        (IpNet::V4(net), IpAddr::V4(addr)) => net.contains(&addr),
+        (IpNet::V6(net), IpAddr::V6(addr)) => net.contains(&addr),
+        _ => false,
+    }
+}
+
+// === impl Action ===
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Allow => write!(f, "allow"),
+            Action::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+// === impl Dst ===
+
+impl fmt::Display for Dst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name.as_ref() {
+            Some(name) => write!(
+                f,
+                "{}:{} ({})",
+                name.without_trailing_dot(),
+                self.addr.port(),
+                self.addr.ip()
+            ),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+// === impl Egress ===
+
+impl Egress {
+    pub fn new(config: Option<Config>, resolver: dns::Resolver, registry: Registry) -> Self {
+        Self {
+            config: config.map(Arc::new),
+            resolver,
+            registry,
+        }
+    }
+
+    pub fn layer<M>(&self) -> impl svc::Layer<M, Service = MakeEgress<M>> + Clone {
+        let egress = self.clone();
+        svc::layer::mk(move |inner| MakeEgress {
+            egress: egress.clone(),
+            inner,
+        })
+    }
+
+    /// Enforces the policy in each service built by a `NewService`, so that
+    /// cached services only make a decision when they are built or when it
+    /// expires.
+    pub fn new_service_layer<N>(&self) -> impl svc::Layer<N, Service = NewEnforce<N>> + Clone {
+        let egress = self.clone();
+        svc::layer::mk(move |inner| NewEnforce {
+            egress: egress.clone(),
+            inner,
+        })
+    }
+
+    /// Checks a connection to `dst`.
+    fn check(&self, dst: Dst) -> CheckFuture {
+        let config = match self.config.as_ref() {
+            Some(config) if !config.is_internal(&dst) => config.clone(),
+            _ => {
+                return Box::pin(future::ready(Decision {
+                    result: Ok(()),
+                    valid_until: None,
+                }))
+            }
+        };
+        let registry = self.registry.clone();
+
+        // Only resolve names that may be allowed.
+        let resolve = match dst.name.as_ref() {
+            Some(name) if config.allows_name(&dst) => Some(self.resolver.resolve_ips(name)),
+            _ => None,
+        };
+
+        Box::pin(async move {
+            let (name_verified, valid_until) = match resolve {
+                None => (false, None),
+                Some(resolve) => match resolve.await {
+                    Ok((ips, valid_until)) => (ips.contains(&dst.addr.ip()), Some(valid_until)),
+                    Err(error) => {
+                        debug!(%error, "Failed to resolve name");
+                        (false, Some(Instant::now() + RESOLVE_FAILURE_TTL))
+                    }
+                },
+            };
+            Decision {
+                result: config.enforce(&dst, name_verified, &registry),
+                valid_until,
+            }
+        })
+    }
+}
+
+// === impl MakeEgress ===
+
+impl<T, M> tower::Service<T> for MakeEgress<M>
+where
+    T: HasDst + Send + 'static,
+    M: tower::Service<T> + Clone + Send + 'static,
+    M::Error: Into<Error>,
+    M::Future: Send,
+{
+    type Response = M::Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<M::Response, Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The inner service is cloned into the response future, so its
+        // readiness isn't important.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let check = self.egress.check(target.dst());
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            check.await.result?;
+            inner.oneshot(target).await.map_err(Into::into)
+        })
+    }
+}
+
+// === impl NewEnforce ===
+
+impl<T, N> svc::NewService<T> for NewEnforce<N>
+where
+    T: HasDst,
+    N: svc::NewService<T>,
+{
+    type Service = Enforce<N::Service>;
+
+    fn new_service(&self, target: T) -> Self::Service {
+        Enforce {
+            egress: self.egress.clone(),
+            dst: target.dst(),
+            state: State::Init,
+            inner: self.inner.new_service(target),
+        }
+    }
+}
+
+// === impl Enforce ===
+
+impl<S, Req> tower::Service<Req> for Enforce<S>
+where
+    S: tower::Service<Req>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::Ready<Result<S::Response, Error>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let State::Decided(ref decision) = self.state {
+            if decision.is_expired() {
+                self.state = State::Init;
+            }
+        }
+        if let State::Init = self.state {
+            self.state = State::Pending(self.egress.check(self.dst.clone()));
+        }
+        if let State::Pending(ref mut check) = self.state {
+            let decision = futures::ready!(check.as_mut().poll(cx));
+            self.state = State::Decided(decision);
+        }
+
+        match self.state {
+            // Denied requests fail without being dispatched.
+            State::Decided(Decision { result: Err(_), .. }) => Poll::Ready(Ok(())),
+            _ => self.inner.poll_ready(cx).map_err(Into::into),
+        }
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        match self.state {
+            State::Decided(Decision {
+                result: Err(ref denied),
+                ..
+            }) => future::Either::Right(future::err(denied.clone().into())),
+            _ => future::Either::Left(self.inner.call(req).err_into()),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Enforce<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Enforce")
+            .field("dst", &self.dst)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// === impl Decision ===
+
+impl Decision {
+    fn is_expired(&self) -> bool {
+        self.valid_until
+            .map(|until| until <= Instant::now())
+            .unwrap_or(false)
+    }
+}
+
+// === impl Denied ===
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "connection to {} denied by egress policy", self.0)
+    }
+}
+
+impl std::error::Error for Denied {}
+
+// === impl Registry ===
+
+impl Registry {
+    fn record(&self, action: Action, dry_run: bool) {
+        if let Ok(mut counters) = self.0.lock() {
+            counters
+                .entry(Labels { action, dry_run })
+                .or_insert_with(Default::default)
+                .incr();
+        }
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counters = match self.0.lock() {
+            Ok(counters) => counters,
+            Err(_) => return Ok(()),
+        };
+        if counters.is_empty() {
+            return Ok(());
+        }
+
+        outbound_egress_decisions_total.fmt_help(f)?;
+        outbound_egress_decisions_total.fmt_scopes(f, counters.iter(), |c| c)?;
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl FmtLabels for Labels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "action=\"{}\",dry_run=\"{}\"", self.action, self.dry_run)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use tower::Service;
+
+    fn config(dry_run: bool) -> Config {
+        Config {
+            rules: vec![
+                Rule {
+                    action: Action::Deny,
+                    dst: Some(DstMatch::Name(
+                        dns::Suffix::try_from("evil.example.com").unwrap(),
+                    )),
+                    port: None,
+                },
+                Rule {
+                    action: Action::Allow,
+                    dst: Some(DstMatch::Name(
+                        dns::Suffix::try_from("example.com").unwrap(),
+                    )),
+                    port: Some(443),
+                },
+                Rule {
+                    action: Action::Allow,
+                    dst: Some(DstMatch::Network("203.0.113.0/24".parse().unwrap())),
+                    port: None,
+                },
+            ],
+            default: Action::Deny,
+            internal_networks: vec!["10.0.0.0/8".parse().unwrap()],
+            dry_run,
+            log: false,
+        }
+    }
+
+    fn dst(name: Option<&str>, ip: [u8; 4], port: u16) -> Dst {
+        Dst {
+            name: name.map(|n| dns::Name::try_from(n.as_bytes()).unwrap()),
+            addr: (ip, port).into(),
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let config = config(false);
+        let registry = Registry::default();
+        let ext = [192, 0, 2, 1];
+
+        let allowed = dst(Some("api.example.com"), ext, 443);
+        assert!(config.allows_name(&allowed));
+        assert!(config.enforce(&allowed, true, &registry).is_ok());
+        let denied = dst(Some("api.evil.example.com"), ext, 443);
+        assert!(config.enforce(&denied, true, &registry).is_err());
+        let wrong_port = dst(Some("api.example.com"), ext, 80);
+        assert!(!config.allows_name(&wrong_port));
+        assert!(config.enforce(&wrong_port, true, &registry).is_err());
+        let net = dst(None, [203, 0, 113, 7], 5432);
+        assert!(config.enforce(&net, false, &registry).is_ok());
+        assert!(config
+            .enforce(&dst(None, ext, 443), false, &registry)
+            .is_err());
+    }
+
+    #[test]
+    fn unverified_names_are_only_denied() {
+        let config = config(false);
+        let registry = Registry::default();
+        let ext = [192, 0, 2, 1];
+
+        // A client may claim any name for an address.
+        let claimed = dst(Some("api.example.com"), ext, 443);
+        assert!(config.enforce(&claimed, false, &registry).is_err());
+        let denied = dst(Some("api.evil.example.com"), [203, 0, 113, 7], 443);
+        assert!(!config.allows_name(&denied));
+        assert!(config.enforce(&denied, false, &registry).is_err());
+    }
+
+    #[test]
+    fn internal_destinations_are_addressed_in_internal_networks() {
+        let config = config(false);
+        assert!(config.is_internal(&dst(None, [10, 1, 1, 1], 80)));
+        assert!(!config.is_internal(&dst(Some("web.ns.svc.cluster.local"), [192, 0, 2, 1], 80)));
+    }
+
+    #[test]
+    fn dry_run_records_denials() {
+        let config = config(true);
+        let registry = Registry::default();
+
+        assert!(config
+            .enforce(&dst(None, [192, 0, 2, 1], 443), false, &registry)
+            .is_ok());
+        let counters = registry.0.lock().unwrap();
+        assert!(counters.contains_key(&Labels {
+            action: Action::Deny,
+            dry_run: true,
+        }));
+    }
+
+    #[tokio::test]
+    async fn services_decide_until_their_decision_expires() {
+        let dns = crate::dns::Config {
+            min_ttl: None,
+            max_ttl: None,
+            resolv_conf_path: "/etc/resolv.conf".into(),
+        }
+        .build();
+        let registry = Registry::default();
+        let decisions = || {
+            let counters = registry.0.lock().unwrap();
+            counters.values().map(|c| c.value()).sum::<u64>()
+        };
+        let mut svc = Enforce {
+            egress: Egress::new(Some(config(false)), dns.resolver, registry.clone()),
+            dst: dst(None, [192, 0, 2, 1], 443),
+            state: State::Init,
+            inner: tower::service_fn(|()| future::ok::<_, Error>(())),
+        };
+
+        for _ in 0..3 {
+            let rsp = svc.ready_and().await.unwrap().call(()).await;
+            assert!(rsp.unwrap_err().is::<Denied>());
+        }
+        assert_eq!(decisions(), 1);
+
+        if let State::Decided(ref mut decision) = svc.state {
+            decision.valid_until = Some(Instant::now());
+        }
+        let rsp = svc.ready_and().await.unwrap().call(()).await;
+        assert!(rsp.unwrap_err().is::<Denied>());
+        assert_eq!(decisions(), 2);
+    }
+}
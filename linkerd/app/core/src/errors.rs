@@ -1,5 +1,7 @@
+use crate::egress;
 use crate::proxy::identity;
 use http::{header::HeaderValue, StatusCode};
+use linkerd2_concurrency_limit::adaptive::LimitExceeded;
 use linkerd2_errno::Errno;
 use linkerd2_error::Error;
 use linkerd2_error_metrics as metrics;
@@ -43,8 +45,12 @@ pub enum Reason {
     IdentityRequired,
     Io(Option<Errno>),
     FailFast,
+    ConcurrencyLimit,
     GatewayLoop,
     NotFound,
+    FaultInjected,
+    RateLimit,
+    Unauthorized,
     Unexpected,
 }
 
@@ -214,10 +220,14 @@ fn http_status(error: &(dyn std::error::Error + 'static)) -> StatusCode {
         http::StatusCode::GATEWAY_TIMEOUT
     } else if error.is::<FailFastError>() {
         http::StatusCode::SERVICE_UNAVAILABLE
+    } else if error.is::<LimitExceeded>() {
+        http::StatusCode::SERVICE_UNAVAILABLE
     } else if error.is::<tower::timeout::error::Elapsed>() {
         http::StatusCode::SERVICE_UNAVAILABLE
     } else if error.is::<IdentityRequired>() {
         http::StatusCode::FORBIDDEN
+    } else if error.is::<egress::Denied>() {
+        http::StatusCode::FORBIDDEN
     } else if let Some(source) = error.source() {
         http_status(source)
     } else {
@@ -249,6 +259,14 @@ fn set_grpc_status(
             HeaderValue::from_static("proxy max-concurrency exhausted"),
         );
         code
+    } else if error.is::<LimitExceeded>() {
+        let code = Code::Unavailable;
+        headers.insert(GRPC_STATUS, code_header(code));
+        headers.insert(
+            GRPC_MESSAGE,
+            HeaderValue::from_static("proxy concurrency limit exceeded"),
+        );
+        code
     } else if error.is::<tower::timeout::error::Elapsed>() {
         let code = Code::Unavailable;
         headers.insert(GRPC_STATUS, code_header(code));
@@ -264,6 +282,14 @@ fn set_grpc_status(
             headers.insert(GRPC_MESSAGE, msg);
         }
         code
+    } else if error.is::<egress::Denied>() {
+        let code = Code::PermissionDenied;
+        headers.insert(GRPC_STATUS, code_header(code));
+        headers.insert(
+            GRPC_MESSAGE,
+            HeaderValue::from_static("request denied by egress policy"),
+        );
+        code
     } else if error.is::<std::io::Error>() {
         let code = Code::Unavailable;
         headers.insert(GRPC_STATUS, code_header(code));
@@ -338,10 +364,14 @@ impl LabelError {
             Reason::ResponseTimeout
         } else if err.is::<FailFastError>() {
             Reason::FailFast
+        } else if err.is::<LimitExceeded>() {
+            Reason::ConcurrencyLimit
         } else if err.is::<tower::timeout::error::Elapsed>() {
             Reason::DispatchTimeout
         } else if err.is::<IdentityRequired>() {
             Reason::IdentityRequired
+        } else if err.is::<egress::Denied>() {
+            Reason::Unauthorized
         } else if let Some(e) = err.downcast_ref::<std::io::Error>() {
             Reason::Io(e.raw_os_error().map(Errno::from))
         } else if let Some(e) = err.source() {
@@ -367,11 +397,15 @@ impl metrics::FmtLabels for Reason {
             "message=\"{}\"",
             match self {
                 Reason::FailFast => "failfast",
+                Reason::ConcurrencyLimit => "concurrency limit",
                 Reason::DispatchTimeout => "dispatch timeout",
                 Reason::ResponseTimeout => "response timeout",
                 Reason::IdentityRequired => "identity required",
                 Reason::GatewayLoop => "gateway loop",
                 Reason::NotFound => "not found",
+                Reason::FaultInjected => "fault injected",
+                Reason::RateLimit => "rate limit",
+                Reason::Unauthorized => "unauthorized",
                 Reason::Io(_) => "i/o",
                 Reason::Unexpected => "unexpected",
             }
@@ -411,6 +445,16 @@ impl HttpError {
         }
     }
 
+    /// Builds an error for a request denied by an authorization policy.
+    pub fn unauthorized(message: &'static str) -> Self {
+        Self {
+            message,
+            http: http::StatusCode::FORBIDDEN,
+            grpc: Code::PermissionDenied,
+            reason: Reason::Unauthorized,
+        }
+    }
+
     pub fn not_found(message: &'static str) -> Self {
         Self {
             message,
@@ -429,9 +473,77 @@ impl HttpError {
         }
     }
 
+    /// Builds an error for a request rejected because a rate limit was
+    /// exceeded.
+    pub fn rate_limited() -> Self {
+        Self {
+            message: "rate limit exceeded",
+            http: http::StatusCode::TOO_MANY_REQUESTS,
+            grpc: Code::ResourceExhausted,
+            reason: Reason::RateLimit,
+        }
+    }
+
+    /// Builds an error for a request aborted by an injected fault with the
+    /// given HTTP status.
+    ///
+    /// gRPC clients receive the status mapped as described by the gRPC HTTP to
+    /// gRPC status code mapping.
+    pub fn fault_http(http: http::StatusCode) -> Self {
+        let grpc = match http {
+            http::StatusCode::BAD_REQUEST => Code::Internal,
+            http::StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            http::StatusCode::FORBIDDEN => Code::PermissionDenied,
+            http::StatusCode::NOT_FOUND => Code::Unimplemented,
+            http::StatusCode::TOO_MANY_REQUESTS
+            | http::StatusCode::BAD_GATEWAY
+            | http::StatusCode::SERVICE_UNAVAILABLE
+            | http::StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+            _ => Code::Unknown,
+        };
+        Self {
+            message: "fault injected",
+            http,
+            grpc,
+            reason: Reason::FaultInjected,
+        }
+    }
+
+    /// Builds an error for a request aborted by an injected fault with the
+    /// given gRPC status code.
+    ///
+    /// Non-gRPC clients receive the HTTP status that most closely matches the
+    /// code.
+    pub fn fault_grpc(grpc: Code) -> Self {
+        let http = match grpc {
+            Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+                http::StatusCode::BAD_REQUEST
+            }
+            Code::Unauthenticated => http::StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => http::StatusCode::FORBIDDEN,
+            Code::NotFound => http::StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => http::StatusCode::CONFLICT,
+            Code::ResourceExhausted => http::StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => http::StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => http::StatusCode::SERVICE_UNAVAILABLE,
+            Code::DeadlineExceeded => http::StatusCode::GATEWAY_TIMEOUT,
+            _ => http::StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self {
+            message: "fault injected",
+            http,
+            grpc,
+            reason: Reason::FaultInjected,
+        }
+    }
+
     pub fn status(&self) -> http::StatusCode {
         self.http
     }
+
+    pub fn reason(&self) -> Reason {
+        self.reason
+    }
 }
 
 impl std::fmt::Display for HttpError {
@@ -0,0 +1,777 @@
+//! Fails over from a concrete destination to backup destinations when the
+//! destination has no available endpoints.
+//!
+//! Each destination with configured backups is served by a list of tiers: the
+//! destination's own balancer, followed by its backups in order of preference.
+//! The destination's balancer is built by the inner stack and owned by the
+//! failover service, so that its readiness is observed directly. Backups are
+//! obtained from a shared stack, with the `Concrete` target's address
+//! overridden, so that a backup's balancer is shared by every destination that
+//! it backs up and by the backup's own traffic.
+//!
+//! Requests are dispatched to the first tier that is ready. A tier that is not
+//! ready holds requests until it has been unavailable for the failover
+//! threshold, after which requests move on to the next tier. Higher-priority
+//! tiers continue to be polled so that traffic moves back as soon as they
+//! recover. When a tier recovers, the lower tiers' outages are forgotten, so
+//! that each tier holds requests for the full threshold in a later outage.
+//!
+//! Targets without backups are rejected with `NoBackups` by `HasBackups`, so
+//! that they may fall back to the shared stack with
+//! `push_fallback_with_predicate`.
+
+use crate::profiles::OverrideDestination;
+use crate::NameAddr;
+use futures::{future::JoinAll, ready, TryFuture};
+use indexmap::IndexMap;
+use linkerd2_admit::Admit;
+use linkerd2_error::Error;
+use linkerd2_metrics::{metrics, FmtLabels, FmtMetrics, Gauge};
+use pin_project::pin_project;
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{self, Delay, Instant};
+use tower::util::{Either, Oneshot};
+use tracing::{debug, info, warn};
+
+metrics! {
+    failover_serving: Gauge {
+        "Whether a destination's failover tier is currently serving its traffic"
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Backup destinations, in order of preference, keyed by the authority of
+    /// the destination they back up.
+    pub backups: IndexMap<String, Vec<NameAddr>>,
+
+    /// Requests fail over to the next tier once a tier has been unavailable
+    /// for this long.
+    pub threshold: Duration,
+}
+
+/// Admits only targets that have backups.
+#[derive(Clone, Debug)]
+pub struct HasBackups(Arc<Config>);
+
+/// Indicates that a target has no backups.
+#[derive(Debug)]
+pub struct NoBackups(String);
+
+/// Records which tier serves each destination that has backups.
+#[derive(Debug)]
+pub struct Registry<L: Hash + Eq>(Arc<Mutex<IndexMap<Labels<L>, Arc<Gauge>>>>);
+
+/// Builds a failover service for each target.
+#[derive(Debug)]
+pub struct Layer<B, L: Hash + Eq> {
+    config: Arc<Config>,
+    backups: B,
+    scope: L,
+    registry: Registry<L>,
+}
+
+#[derive(Debug)]
+pub struct MakeFailover<M, B, L: Hash + Eq> {
+    inner: M,
+    layer: Layer<B, L>,
+}
+
+#[pin_project]
+pub struct MakeFailoverFuture<F, B, T, L>
+where
+    F: TryFuture,
+    B: tower::Service<T>,
+    L: Hash + Eq,
+{
+    #[pin]
+    primary: F,
+    primary_svc: Option<F::Ok>,
+    #[pin]
+    backups: JoinAll<Oneshot<B, T>>,
+    addrs: Vec<NameAddr>,
+    dst: String,
+    threshold: Duration,
+    scope: L,
+    registry: Registry<L>,
+}
+
+/// Dispatches requests to the most-preferred available tier.
+pub struct Failover<P, B, L: Hash + Eq> {
+    primary: Tier<P>,
+    backups: Vec<Tier<B>>,
+    /// The index of the serving tier, where 0 is the primary.
+    active: usize,
+    threshold: Duration,
+    registry: Registry<L>,
+}
+
+struct Tier<S> {
+    dst: String,
+    service: S,
+    /// Set while the tier is unavailable, and fires once it has been
+    /// unavailable for the threshold.
+    deadline: Option<Delay>,
+    serving: Arc<Gauge>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Labels<L> {
+    scope: L,
+    dst: String,
+    tier: usize,
+    tier_dst: String,
+}
+
+/// Fails over requests to backup destinations, which are built by `backups`,
+/// recording the tier that serves each destination in `registry`, labeled
+/// with `scope`.
+pub fn layer<B, L: Hash + Eq>(
+    config: Config,
+    backups: B,
+    scope: L,
+    registry: Registry<L>,
+) -> Layer<B, L> {
+    Layer {
+        config: Arc::new(config),
+        backups,
+        scope,
+        registry,
+    }
+}
+
+/// Returns true if the error indicates that the target has no backups.
+pub fn is_no_backups(error: &Error) -> bool {
+    error.is::<NoBackups>()
+}
+
+// === impl HasBackups ===
+
+impl From<Config> for HasBackups {
+    fn from(config: Config) -> Self {
+        HasBackups(Arc::new(config))
+    }
+}
+
+impl<T: fmt::Display> Admit<T> for HasBackups {
+    type Error = NoBackups;
+
+    fn admit(&mut self, target: &T) -> Result<(), Self::Error> {
+        let dst = target.to_string();
+        match self.0.backups.get(&dst) {
+            Some(backups) if !backups.is_empty() => Ok(()),
+            _ => Err(NoBackups(dst)),
+        }
+    }
+}
+
+// === impl NoBackups ===
+
+impl fmt::Display for NoBackups {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} has no backups", self.0)
+    }
+}
+
+impl std::error::Error for NoBackups {}
+
+// === impl Layer ===
+
+impl<B: Clone, L: Clone + Hash + Eq> Clone for Layer<B, L> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            backups: self.backups.clone(),
+            scope: self.scope.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<M, B: Clone, L: Clone + Hash + Eq> tower::layer::Layer<M> for Layer<B, L> {
+    type Service = MakeFailover<M, B, L>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeFailover {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+// === impl MakeFailover ===
+
+impl<M: Clone, B: Clone, L: Clone + Hash + Eq> Clone for MakeFailover<M, B, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<T, M, B, L> tower::Service<T> for MakeFailover<M, B, L>
+where
+    T: OverrideDestination + fmt::Display + Clone,
+    M: tower::Service<T>,
+    M::Error: Into<Error>,
+    B: tower::Service<T> + Clone,
+    B::Error: Into<Error>,
+    L: Clone + Hash + Eq,
+{
+    type Response = Failover<M::Response, B::Response, L>;
+    type Error = M::Error;
+    type Future = MakeFailoverFuture<M::Future, B, T, L>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.to_string();
+        let addrs = self
+            .layer
+            .config
+            .backups
+            .get(&dst)
+            .cloned()
+            .unwrap_or_default();
+        if !addrs.is_empty() {
+            debug!(%dst, backups = ?addrs, "Building failover tiers");
+        }
+
+        let backups = addrs
+            .iter()
+            .map(|addr| {
+                let mut target = target.clone();
+                *target.dst_mut() = addr.clone().into();
+                Oneshot::new(self.layer.backups.clone(), target)
+            })
+            .collect::<Vec<_>>();
+
+        MakeFailoverFuture {
+            primary: self.inner.call(target),
+            primary_svc: None,
+            backups: futures::future::join_all(backups),
+            addrs,
+            dst,
+            threshold: self.layer.config.threshold,
+            scope: self.layer.scope.clone(),
+            registry: self.layer.registry.clone(),
+        }
+    }
+}
+
+// === impl MakeFailoverFuture ===
+
+impl<F, B, T, L> Future for MakeFailoverFuture<F, B, T, L>
+where
+    F: TryFuture,
+    B: tower::Service<T>,
+    B::Error: Into<Error>,
+    L: Clone + Hash + Eq,
+{
+    type Output = Result<Failover<F::Ok, B::Response, L>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if this.primary_svc.is_none() {
+            let primary = ready!(this.primary.try_poll(cx))?;
+            *this.primary_svc = Some(primary);
+        }
+        let results = ready!(this.backups.poll(cx));
+
+        let mut backups = Vec::with_capacity(results.len());
+        for (addr, backup) in this.addrs.drain(..).zip(results) {
+            // A backup that cannot be built, e.g. because it cannot be
+            // resolved, is skipped rather than failing the primary.
+            match backup {
+                Ok(service) => backups.push((addr.to_string(), service)),
+                Err(e) => {
+                    let error: Error = e.into();
+                    warn!(backup = %addr, %error, "Failed to build backup");
+                }
+            }
+        }
+
+        let primary = this.primary_svc.take().expect("polled after ready");
+        let (primary, backups) = if backups.is_empty() {
+            (Tier::new(this.dst.clone(), primary, Arc::default()), vec![])
+        } else {
+            let scope = &*this.scope;
+            let dst = &*this.dst;
+            let registry = &*this.registry;
+            let gauge = |tier: usize, tier_dst: &str| {
+                registry.gauge(Labels {
+                    scope: scope.clone(),
+                    dst: dst.to_string(),
+                    tier,
+                    tier_dst: tier_dst.to_string(),
+                })
+            };
+            let primary = Tier::new(dst.to_string(), primary, gauge(0, dst));
+            let backups = backups
+                .into_iter()
+                .enumerate()
+                .map(|(i, (tier_dst, service))| {
+                    let serving = gauge(i + 1, &tier_dst);
+                    Tier::new(tier_dst, service, serving)
+                })
+                .collect();
+            (primary, backups)
+        };
+
+        Poll::Ready(Ok(Failover {
+            primary,
+            backups,
+            active: 0,
+            threshold: *this.threshold,
+            registry: this.registry.clone(),
+        }))
+    }
+}
+
+// === impl Failover ===
+
+impl<P, B, L: Hash + Eq> Failover<P, B, L> {
+    /// Serves requests from the given tier, which is ready, and forgets the
+    /// outages of the tiers below it.
+    fn serve(&mut self, tier: usize) {
+        if tier == 0 {
+            self.primary.deadline = None;
+        }
+        for t in self.backups.iter_mut().skip(tier.saturating_sub(1)) {
+            t.deadline = None;
+        }
+
+        if tier != self.active {
+            if tier == 0 {
+                info!("Destination recovered; no longer failing over");
+            } else {
+                info!(backup = %self.backups[tier - 1].dst, "Failing over");
+            }
+        }
+        self.active = tier;
+
+        self.primary.set_serving(tier == 0);
+        for (i, t) in self.backups.iter_mut().enumerate() {
+            t.set_serving(i + 1 == tier);
+        }
+    }
+}
+
+impl<P, B, L, Req> tower::Service<Req> for Failover<P, B, L>
+where
+    P: tower::Service<Req>,
+    P::Error: Into<Error>,
+    B: tower::Service<Req, Response = P::Response>,
+    B::Error: Into<Error>,
+    L: Hash + Eq,
+{
+    type Response = P::Response;
+    type Error = Error;
+    type Future = Either<P::Future, B::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let now = Instant::now();
+
+        // The destination's own errors are surfaced.
+        match self.primary.service.poll_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                self.serve(0);
+                return Poll::Ready(Ok(()));
+            }
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+            Poll::Pending => {
+                if self
+                    .primary
+                    .poll_unavailable(self.threshold, now, cx)
+                    .is_pending()
+                {
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        let mut i = 0;
+        while i < self.backups.len() {
+            match self.backups[i].service.poll_ready(cx) {
+                Poll::Ready(Ok(())) => {
+                    self.serve(i + 1);
+                    return Poll::Ready(Ok(()));
+                }
+
+                // A failed backup is only dropped.
+                Poll::Ready(Err(e)) => {
+                    let tier = self.backups.remove(i);
+                    self.registry.remove(&tier.serving);
+                    let error: Error = e.into();
+                    warn!(backup = %tier.dst, %error, "Backup failed");
+                }
+
+                Poll::Pending => {
+                    if self.backups[i]
+                        .poll_unavailable(self.threshold, now, cx)
+                        .is_pending()
+                    {
+                        return Poll::Pending;
+                    }
+                    i += 1;
+                }
+            }
+        }
+
+        // All tiers have been unavailable for the threshold; wait for any of
+        // them to become ready.
+        Poll::Pending
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        match self.active {
+            0 => Either::A(self.primary.service.call(req)),
+            i => Either::B(self.backups[i - 1].service.call(req)),
+        }
+    }
+}
+
+impl<P, B, L: Hash + Eq> Drop for Failover<P, B, L> {
+    fn drop(&mut self) {
+        self.registry.remove(&self.primary.serving);
+        for tier in self.backups.iter() {
+            self.registry.remove(&tier.serving);
+        }
+    }
+}
+
+// === impl Tier ===
+
+impl<S> Tier<S> {
+    fn new(dst: String, service: S, serving: Arc<Gauge>) -> Self {
+        Self {
+            dst,
+            service,
+            deadline: None,
+            serving,
+        }
+    }
+
+    /// Records that the tier is unavailable, returning ready once it has been
+    /// unavailable for the threshold. Otherwise, the task is notified when
+    /// the threshold elapses.
+    fn poll_unavailable(
+        &mut self,
+        threshold: Duration,
+        now: Instant,
+        cx: &mut Context<'_>,
+    ) -> Poll<()> {
+        let deadline = self
+            .deadline
+            .get_or_insert_with(|| time::delay_until(now + threshold));
+        Pin::new(deadline).poll(cx)
+    }
+
+    fn set_serving(&mut self, serving: bool) {
+        let is_serving = self.serving.value() != 0;
+        if serving && !is_serving {
+            self.serving.incr();
+        } else if !serving && is_serving {
+            self.serving.decr();
+        }
+    }
+}
+
+// === impl Registry ===
+
+impl<L: Hash + Eq> Registry<L> {
+    fn gauge(&self, labels: Labels<L>) -> Arc<Gauge> {
+        match self.0.lock() {
+            Ok(mut gauges) => gauges
+                .entry(labels)
+                .or_insert_with(Default::default)
+                .clone(),
+            Err(_) => Arc::default(),
+        }
+    }
+
+    /// Removes a gauge from the registry, unless it is still held by another
+    /// failover service for the same destination, e.g. while the destination's
+    /// failover service is rebuilt.
+    fn remove(&self, gauge: &Arc<Gauge>) {
+        if let Ok(mut registry) = self.0.lock() {
+            registry.retain(|_, g| !Arc::ptr_eq(g, gauge) || Arc::strong_count(g) > 2);
+        }
+    }
+}
+
+impl<L: Hash + Eq> Default for Registry<L> {
+    fn default() -> Self {
+        Registry(Default::default())
+    }
+}
+
+impl<L: Hash + Eq> Clone for Registry<L> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+impl<L: FmtLabels + Hash + Eq> FmtMetrics for Registry<L> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gauges = match self.0.lock() {
+            Ok(gauges) => gauges,
+            Err(_) => return Ok(()),
+        };
+        if gauges.is_empty() {
+            return Ok(());
+        }
+
+        failover_serving.fmt_help(f)?;
+        failover_serving.fmt_scopes(f, gauges.iter(), |g| g)?;
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl<L: FmtLabels> FmtLabels for Labels<L> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.scope.fmt_labels(f)?;
+        write!(
+            f,
+            ",dst=\"{}\",tier=\"{}\",tier_dst=\"{}\"",
+            self.dst, self.tier, self.tier_dst
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_test::{assert_pending, assert_ready_err, assert_ready_ok};
+    use tower_test::mock::{self, Spawn};
+
+    type Mock = mock::Mock<(), ()>;
+    type Handle = mock::Handle<(), ()>;
+
+    fn labels(tier: usize, tier_dst: &str) -> Labels<()> {
+        Labels {
+            scope: (),
+            dst: "primary".into(),
+            tier,
+            tier_dst: tier_dst.into(),
+        }
+    }
+
+    fn failover(
+        threshold: Duration,
+        backups: usize,
+        registry: &Registry<()>,
+    ) -> (Spawn<Failover<Mock, Mock, ()>>, Handle, Vec<Handle>) {
+        let (primary, primary_handle) = mock::pair();
+        let mut handles = Vec::with_capacity(backups);
+        let backups = (0..backups)
+            .map(|i| {
+                let (backup, handle) = mock::pair();
+                handles.push(handle);
+                let dst = format!("backup{}", i + 1);
+                let serving = registry.gauge(labels(i + 1, &dst));
+                Tier::new(dst, backup, serving)
+            })
+            .collect();
+        let failover = Failover {
+            primary: Tier::new(
+                "primary".into(),
+                primary,
+                registry.gauge(labels(0, "primary")),
+            ),
+            backups,
+            active: 0,
+            threshold,
+            registry: registry.clone(),
+        };
+        (Spawn::new(failover), primary_handle, handles)
+    }
+
+    fn registered(registry: &Registry<()>) -> Vec<Labels<()>> {
+        registry.0.lock().unwrap().keys().cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_recovers() {
+        let threshold = Duration::from_millis(100);
+        let registry = Registry::default();
+        let (mut svc, mut primary, mut backups) = failover(threshold, 1, &registry);
+        backups[0].allow(1);
+
+        // The primary is preferred while it is ready.
+        primary.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 0);
+        drop(svc.call(()));
+
+        // Requests are held while the primary has been unavailable for less
+        // than the threshold.
+        primary.allow(0);
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold / 2).await;
+        assert_pending!(svc.poll_ready());
+
+        // Then they fail over to the backup.
+        time::delay_for(threshold).await;
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 1);
+        assert_eq!(svc.get_ref().backups[0].serving.value(), 1);
+
+        // Traffic moves back once the primary recovers.
+        primary.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 0);
+        assert_eq!(svc.get_ref().primary.serving.value(), 1);
+        assert_eq!(svc.get_ref().backups[0].serving.value(), 0);
+    }
+
+    #[tokio::test]
+    async fn fails_over_through_backups_in_order() {
+        let threshold = Duration::from_millis(100);
+        let registry = Registry::default();
+        let (mut svc, mut primary, mut backups) = failover(threshold, 2, &registry);
+        primary.allow(0);
+        backups[0].allow(0);
+        backups[1].allow(1);
+
+        // Each unavailable tier holds requests for the threshold before they
+        // move on to the next tier.
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold + threshold / 2).await;
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold).await;
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 2);
+
+        // A recovered backup is preferred over lower-priority backups.
+        backups[0].allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 1);
+        assert_eq!(svc.get_ref().backups[0].serving.value(), 1);
+        assert_eq!(svc.get_ref().backups[1].serving.value(), 0);
+    }
+
+    #[tokio::test]
+    async fn backups_hold_requests_in_each_outage() {
+        let threshold = Duration::from_millis(100);
+        let registry = Registry::default();
+        let (mut svc, mut primary, mut backups) = failover(threshold, 2, &registry);
+        backups[0].allow(0);
+        backups[1].allow(1);
+
+        // The first outage fails over through the first backup, which is
+        // unavailable, to the second.
+        primary.allow(0);
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold + threshold / 2).await;
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold).await;
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 2);
+
+        primary.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 0);
+        time::delay_for(threshold * 3).await;
+
+        // In a later outage, the first backup holds requests for the full
+        // threshold again, rather than being skipped because of the earlier
+        // outage.
+        primary.allow(0);
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold + threshold / 2).await;
+        assert_pending!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 0);
+        time::delay_for(threshold).await;
+        assert_ready_ok!(svc.poll_ready());
+        assert_eq!(svc.get_ref().active, 2);
+    }
+
+    #[tokio::test]
+    async fn primary_errors_are_returned() {
+        let registry = Registry::default();
+        let (mut svc, mut primary, _backups) = failover(Duration::from_millis(100), 1, &registry);
+        primary.send_error("unavailable");
+        assert_ready_err!(svc.poll_ready());
+    }
+
+    #[tokio::test]
+    async fn failed_backups_are_removed() {
+        let threshold = Duration::from_millis(100);
+        let registry = Registry::default();
+        let (mut svc, mut primary, mut backups) = failover(threshold, 2, &registry);
+        primary.allow(0);
+        backups[0].send_error("unavailable");
+        backups[1].allow(0);
+
+        assert_pending!(svc.poll_ready());
+        time::delay_for(threshold + threshold / 2).await;
+        assert_pending!(svc.poll_ready());
+        assert_eq!(svc.get_ref().backups.len(), 1);
+        assert_eq!(svc.get_ref().backups[0].dst, "backup2");
+
+        // The failed tier's gauge is no longer reported.
+        assert_eq!(
+            registered(&registry),
+            vec![labels(0, "primary"), labels(2, "backup2")]
+        );
+    }
+
+    #[tokio::test]
+    async fn gauges_are_removed_on_drop() {
+        let registry = Registry::default();
+        let (svc, _primary, _backups) = failover(Duration::from_millis(100), 2, &registry);
+        assert_eq!(registered(&registry).len(), 3);
+        drop(svc);
+        assert!(registered(&registry).is_empty());
+    }
+
+    #[tokio::test]
+    async fn gauges_are_retained_while_rebuilt() {
+        let registry = Registry::default();
+        let (old, _primary, _backups) = failover(Duration::from_millis(100), 1, &registry);
+        let (new, _primary, _backups) = failover(Duration::from_millis(100), 1, &registry);
+
+        // Dropping the replaced service does not remove the gauges shared with
+        // its replacement.
+        drop(old);
+        assert_eq!(
+            registered(&registry),
+            vec![labels(0, "primary"), labels(1, "backup1")]
+        );
+        drop(new);
+        assert!(registered(&registry).is_empty());
+    }
+
+    #[test]
+    fn admits_targets_with_backups() {
+        let mut backups = IndexMap::new();
+        backups.insert(
+            "primary".to_string(),
+            vec![NameAddr::from_str("backup1:80").unwrap()],
+        );
+        backups.insert("empty".to_string(), vec![]);
+        let mut admit = HasBackups::from(Config {
+            backups,
+            threshold: Duration::from_secs(1),
+        });
+
+        assert!(admit.admit(&"primary").is_ok());
+        for dst in &["empty", "other"] {
+            let error: Error = admit.admit(dst).unwrap_err().into();
+            assert!(is_no_backups(&error));
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Injects delays and aborts into a share of a route's requests.
+//!
+//! This layer records the route's metrics so that requests with injected
+//! faults are recorded separately from the route's other requests, with a
+//! `fault` route label. Aborted requests fail with an `HttpError` so that they
+//! are answered like any other proxy error and are classified as `fault`
+//! failures. Because aborted requests are never sent to an endpoint, they are
+//! reported to tap here; delayed requests are annotated with an `Injected`
+//! extension so that they may be identified by the endpoint's tap.
+
+use super::dst::Route;
+use super::errors::HttpError;
+use super::{classify, HttpRouteMetrics};
+use crate::profiles::{AbortFault, AbortStatus, DelayFault, Faults};
+use crate::proxy::{
+    http::{boxed::Payload, HasH2Reason},
+    tap,
+};
+use futures::{future, ready, FutureExt};
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use linkerd2_stack::{NewService, Proxy, ProxyService};
+use pin_project::pin_project;
+use rand::Rng;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::time::{self, Delay};
+use tower::util::{Oneshot, ServiceExt};
+use tracing::debug;
+
+/// The route label that identifies requests with injected faults.
+pub const FAULT_LABEL: &str = "fault";
+
+type Metered<P> = super::http_metrics::requests::Service<P, classify::Response>;
+
+/// Injects faults into requests on routes that configure them, recording
+/// per-route metrics in `metrics` and reporting aborted requests to `tap`.
+pub fn layer(metrics: HttpRouteMetrics, tap: tap::Layer) -> Layer {
+    Layer { metrics, tap }
+}
+
+/// Returns the route labels for a request, including a `fault` label if a
+/// fault was injected into the request.
+pub fn route_labels<B>(req: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+    let labels = req
+        .extensions()
+        .get::<Route>()
+        .map(|r| r.route.labels().clone());
+
+    match req.extensions().get::<Injected>() {
+        None => labels,
+        Some(injected) => {
+            let mut labels = labels.map(|l| (*l).clone()).unwrap_or_default();
+            labels.insert(FAULT_LABEL.to_string(), injected.as_str().to_string());
+            Some(Arc::new(labels))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    metrics: HttpRouteMetrics,
+    tap: tap::Layer,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewInject<N> {
+    metrics: HttpRouteMetrics,
+    tap: tap::Layer,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct Inject<P> {
+    faults: Option<Faults>,
+    route: Metered<P>,
+    delayed: Metered<P>,
+    aborted: Aborted,
+}
+
+/// Records aborted requests.
+#[derive(Clone, Debug)]
+struct Aborted {
+    route: Route,
+    metrics: Metered<()>,
+    tap: tap::Layer,
+}
+
+/// A service that fails all requests with an injected fault.
+#[derive(Copy, Clone, Debug)]
+struct Abort(HttpError);
+
+/// A request extension indicating that a fault was injected into the request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Injected {
+    Delay,
+    Abort,
+}
+
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    Inner(#[pin] P::Future),
+    Abort(Pin<Box<dyn Future<Output = Error> + Send + 'static>>),
+    Delay {
+        #[pin]
+        delay: Delay,
+        #[pin]
+        dispatch: Oneshot<ProxyService<P, S>, Req>,
+    },
+}
+
+// === impl Layer ===
+
+impl<N> tower::layer::Layer<N> for Layer {
+    type Service = NewInject<N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewInject {
+            metrics: self.metrics.clone(),
+            tap: self.tap.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl NewInject ===
+
+impl<N> NewService<Route> for NewInject<N>
+where
+    N: NewService<Route>,
+    N::Service: Clone,
+{
+    type Service = Inject<N::Service>;
+
+    fn new_service(&self, route: Route) -> Self::Service {
+        use tower::layer::Layer;
+
+        let faults = route.route.faults().cloned();
+        let metrics = self.metrics.clone().into_layer::<classify::Response>();
+        let with_fault = |injected: Injected| Route {
+            route: route.route.with_label(FAULT_LABEL, injected.as_str()),
+            ..route.clone()
+        };
+
+        // Requests with injected faults are recorded against the route with
+        // an additional `fault` label, but share the route's inner stack so
+        // that, e.g., its rate limit applies to delayed requests.
+        let inner = self.inner.new_service(route.clone());
+        let delayed = {
+            let inner = inner.clone();
+            metrics
+                .layer(move |_: Route| inner.clone())
+                .new_service(with_fault(Injected::Delay))
+        };
+        let aborted = {
+            let route = with_fault(Injected::Abort);
+            Aborted {
+                metrics: metrics.layer(|_: Route| ()).new_service(route.clone()),
+                tap: self.tap.clone(),
+                route,
+            }
+        };
+        let route = metrics
+            .layer(move |_: Route| inner.clone())
+            .new_service(route);
+
+        Inject {
+            faults,
+            route,
+            delayed,
+            aborted,
+        }
+    }
+}
+
+// === impl Inject ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for Inject<P>
+where
+    Metered<P>: Proxy<http::Request<B>, S> + Clone,
+    S: tower::Service<<Metered<P> as Proxy<http::Request<B>, S>>::Request> + Clone,
+    S::Error: Into<Error>,
+    B: http_body::Body + Send + 'static,
+    B::Error: HasH2Reason,
+{
+    type Request = <Metered<P> as Proxy<http::Request<B>, S>>::Request;
+    type Response = <Metered<P> as Proxy<http::Request<B>, S>>::Response;
+    type Error = Error;
+    type Future = ResponseFuture<Metered<P>, S, http::Request<B>>;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        let faults = match self.faults.as_ref() {
+            Some(faults) => faults,
+            None => return ResponseFuture::Inner(self.route.proxy(svc, req)),
+        };
+
+        let mut rng = rand::thread_rng();
+
+        if let Some(AbortFault { ratio, status }) = faults.abort.as_ref() {
+            if rng.gen::<f64>() < *ratio {
+                debug!(?status, "Aborting request");
+                let error = match *status {
+                    AbortStatus::Http(status) => HttpError::fault_http(status),
+                    AbortStatus::Grpc(code) => {
+                        HttpError::fault_grpc(tonic::Code::from_i32(code as i32))
+                    }
+                };
+                return ResponseFuture::Abort(self.aborted.abort(error, req));
+            }
+        }
+
+        if let Some(DelayFault { ratio, min, max }) = faults.delay.as_ref() {
+            if rng.gen::<f64>() < *ratio {
+                let delay = if min < max {
+                    rng.gen_range(*min, *max)
+                } else {
+                    *min
+                };
+                debug!(?delay, "Delaying request");
+                req.extensions_mut().insert(Injected::Delay);
+                let svc = self.delayed.clone().wrap_service(svc.clone());
+                return ResponseFuture::Delay {
+                    delay: time::delay_for(delay),
+                    dispatch: svc.oneshot(req),
+                };
+            }
+        }
+
+        ResponseFuture::Inner(self.route.proxy(svc, req))
+    }
+}
+
+// === impl Aborted ===
+
+impl Aborted {
+    /// Records an aborted request in the route's metrics and in tap, returning
+    /// a future that fails with `error` once the abort has been recorded.
+    fn abort<B>(
+        &self,
+        error: HttpError,
+        req: http::Request<B>,
+    ) -> Pin<Box<dyn Future<Output = Error> + Send + 'static>>
+    where
+        B: http_body::Body + Send + 'static,
+        B::Error: HasH2Reason,
+    {
+        use tower::layer::Layer;
+
+        let abort = Abort(error);
+        let mut tapped = self
+            .tap
+            .layer(move |_: Route| abort)
+            .new_service(self.route.clone());
+        let rsp = self.metrics.proxy(&mut tapped, req);
+        Box::pin(rsp.map(move |_| error.into()))
+    }
+}
+
+// === impl Abort ===
+
+impl<B> tower::Service<http::Request<B>> for Abort {
+    type Response = http::Response<Payload>;
+    type Error = Error;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: http::Request<B>) -> Self::Future {
+        future::err(self.0.into())
+    }
+}
+
+// === impl Injected ===
+
+impl Injected {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Injected::Delay => "delay",
+            Injected::Abort => "abort",
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<P, S, Req> Future for ResponseFuture<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner(f) => f.poll(cx).map_err(Into::into),
+            ResponseFutureProj::Abort(f) => f.as_mut().poll(cx).map(Err),
+            ResponseFutureProj::Delay { delay, dispatch } => {
+                ready!(delay.poll(cx));
+                dispatch.poll(cx)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Reason;
+    use crate::metric_labels::Direction;
+    use crate::NameAddr;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+    use tower::layer::Layer as _;
+
+    /// Counts the requests it receives and those that were delayed.
+    #[derive(Clone, Default)]
+    struct Endpoint {
+        requests: Arc<AtomicUsize>,
+        delayed: Arc<AtomicUsize>,
+    }
+
+    impl<B> tower::Service<http::Request<B>> for Endpoint {
+        type Response = http::Response<hyper::Body>;
+        type Error = Error;
+        type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if req.extensions().get::<Injected>() == Some(&Injected::Delay) {
+                self.delayed.fetch_add(1, Ordering::SeqCst);
+            }
+            future::ok(http::Response::new(hyper::Body::empty()))
+        }
+    }
+
+    fn inject(faults: Faults) -> Inject<()> {
+        let mut route = crate::profiles::Route::new(std::iter::empty(), Vec::new());
+        route.set_faults(faults);
+        let route = Route {
+            target: NameAddr::from_str("web.ns.svc.cluster.local:8080")
+                .unwrap()
+                .into(),
+            route,
+            direction: Direction::Out,
+        };
+        let (_, tap, _) = tap::new();
+        layer(HttpRouteMetrics::default(), tap)
+            .layer(|_: Route| ())
+            .new_service(route)
+    }
+
+    #[tokio::test]
+    async fn aborts_requests() {
+        let inject = inject(Faults {
+            delay: None,
+            abort: Some(AbortFault {
+                ratio: 1.0,
+                status: AbortStatus::Http(http::StatusCode::SERVICE_UNAVAILABLE),
+            }),
+        });
+        let mut endpoint = Endpoint::default();
+
+        let req = http::Request::new(hyper::Body::empty());
+        let error = match inject.proxy(&mut endpoint, req).await {
+            Ok(_) => panic!("request must be aborted"),
+            Err(error) => error,
+        };
+        let error = error
+            .downcast_ref::<HttpError>()
+            .expect("aborts must fail with an HttpError");
+        assert_eq!(error.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(error.reason(), Reason::FaultInjected);
+        assert_eq!(endpoint.requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn delays_requests() {
+        let delay = Duration::from_millis(20);
+        let inject = inject(Faults {
+            delay: Some(DelayFault {
+                ratio: 1.0,
+                min: delay,
+                max: delay,
+            }),
+            abort: None,
+        });
+        let mut endpoint = Endpoint::default();
+
+        let start = Instant::now();
+        let req = http::Request::new(hyper::Body::empty());
+        assert!(inject.proxy(&mut endpoint, req).await.is_ok());
+        assert!(start.elapsed() >= delay);
+        assert_eq!(endpoint.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(endpoint.delayed.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn only_injects_faults_into_a_ratio_of_requests() {
+        let inject = inject(Faults {
+            delay: Some(DelayFault {
+                ratio: 0.0,
+                min: Duration::from_secs(60),
+                max: Duration::from_secs(60),
+            }),
+            abort: Some(AbortFault {
+                ratio: 0.0,
+                status: AbortStatus::Grpc(14),
+            }),
+        });
+        let mut endpoint = Endpoint::default();
+
+        for _ in 0..10 {
+            let req = http::Request::new(hyper::Body::empty());
+            assert!(inject.proxy(&mut endpoint, req).await.is_ok());
+        }
+        assert_eq!(endpoint.requests.load(Ordering::SeqCst), 10);
+        assert_eq!(endpoint.delayed.load(Ordering::SeqCst), 0);
+    }
+}
@@ -12,11 +12,15 @@
 pub use linkerd2_addr::{self as addr, Addr, NameAddr};
 pub use linkerd2_admit as admit;
 pub use linkerd2_cache as cache;
+pub use linkerd2_concurrency_limit as concurrency_limit;
 pub use linkerd2_conditional::Conditional;
 pub use linkerd2_drain as drain;
 pub use linkerd2_error::{Error, Never, Recover};
 pub use linkerd2_exp_backoff as exp_backoff;
+pub use linkerd2_http_health as http_health;
+pub use linkerd2_http_locality as http_locality;
 pub use linkerd2_http_metrics as http_metrics;
+pub use linkerd2_http_outlier as http_outlier;
 pub use linkerd2_metrics as metrics;
 pub use linkerd2_opencensus as opencensus;
 pub use linkerd2_reconnect as reconnect;
@@ -28,15 +32,22 @@ pub use linkerd2_stack_tracing as stack_tracing;
 pub use linkerd2_trace_context::TraceContextLayer;
 
 pub mod admin;
+pub mod authz;
 pub mod classify;
 pub mod config;
 pub mod control;
 pub mod dns;
 pub mod dst;
+pub mod egress;
 pub mod errors;
+pub mod failover;
+pub mod fault;
 pub mod handle_time;
 pub mod metric_labels;
+pub mod mirror;
 pub mod proxy;
+pub mod proxy_protocol;
+pub mod rate_limit;
 pub mod retry;
 pub mod serve;
 pub mod spans;
@@ -86,14 +97,42 @@ pub type HttpRouteRetry = http_metrics::Retries<metric_labels::RouteLabels>;
 
 pub type StackMetrics = stack_metrics::Registry<metric_labels::StackLabels>;
 
+pub type HttpOutlierMetrics = http_outlier::Registry<metric_labels::Direction>;
+
+pub type HttpLocalityMetrics = http_locality::Registry<metric_labels::Direction>;
+
+pub type HttpHealthMetrics = http_health::Registry<metric_labels::Direction>;
+
+pub type HttpBalanceMetrics = proxy::http::balance::Registry<metric_labels::Direction>;
+
+pub type HttpFailoverMetrics = failover::Registry<metric_labels::Direction>;
+
+pub type AuthzMetrics = authz::Registry;
+
+pub type EgressMetrics = egress::Registry;
+
+pub type ProxyProtocolMetrics = proxy_protocol::Registry;
+
+pub type ConcurrencyLimitMetrics = concurrency_limit::adaptive::Registry<metric_labels::Direction>;
+
 #[derive(Clone)]
 pub struct ProxyMetrics {
     pub http_handle_time: handle_time::Scope,
     pub http_route: HttpRouteMetrics,
     pub http_route_actual: HttpRouteMetrics,
+    pub http_route_mirror: HttpRouteMetrics,
     pub http_route_retry: HttpRouteRetry,
     pub http_endpoint: HttpEndpointMetrics,
     pub http_errors: errors::MetricsLayer,
+    pub http_outlier: HttpOutlierMetrics,
+    pub http_locality: HttpLocalityMetrics,
+    pub http_health: HttpHealthMetrics,
+    pub http_balance: HttpBalanceMetrics,
+    pub http_failover: HttpFailoverMetrics,
+    pub authz: AuthzMetrics,
+    pub egress: EgressMetrics,
+    pub proxy_protocol: ProxyProtocolMetrics,
+    pub concurrency_limit: ConcurrencyLimitMetrics,
     pub stack: StackMetrics,
     pub transport: transport::Metrics,
 }
@@ -0,0 +1,758 @@
+//! Mirrors a share of a route's requests to another logical destination.
+//!
+//! Mirroring is split across two layers. The route-level `Mirror` proxy
+//! samples requests, tees their bodies, and attaches a `Dispatch` extension to
+//! the original request. The concrete-level `Dispatcher`, which knows the
+//! request's concrete target, takes this extension and sends the mirrored
+//! request through the concrete stack for the mirror's destination. Mirrored
+//! responses are discarded.
+
+use super::classify;
+use super::dst::Route;
+use super::HttpRouteMetrics;
+use crate::profiles;
+use crate::proxy::http::boxed::Payload;
+use crate::NameAddr;
+use bytes::{Buf, Bytes};
+use futures::{ready, TryFuture, TryFutureExt};
+use http::HeaderMap;
+use http_body::Body;
+use linkerd2_error::Error;
+use linkerd2_stack::{NewService, Proxy};
+use pin_project::pin_project;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+use tower::util::ServiceExt;
+use tracing::{debug, trace};
+use tracing_futures::Instrument;
+
+type MetricsProxy = super::http_metrics::requests::Service<(), classify::Response>;
+
+type Slot = Arc<Mutex<Option<(http::Request<Payload>, oneshot::Sender<MirrorResult>)>>>;
+
+type MirrorResult = Result<http::Response<Payload>, Error>;
+
+/// Mirrors a share of requests on routes that have a mirror policy,
+/// recording the mirrored requests' metrics in `metrics`.
+///
+/// Up to `max_body_bytes` of each mirrored request's body is buffered. The
+/// mirrored request fails if the body exceeds this limit.
+pub fn layer(metrics: HttpRouteMetrics, max_body_bytes: usize) -> Layer {
+    Layer {
+        metrics,
+        max_body_bytes,
+    }
+}
+
+/// Sends mirrored requests through the wrapped concrete stack.
+///
+/// Must be applied to the concrete stack beneath a route stack that includes
+/// `layer`.
+pub fn dispatch_layer() -> DispatchLayer {
+    DispatchLayer(())
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer {
+    metrics: HttpRouteMetrics,
+    max_body_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewMirror<N> {
+    metrics: HttpRouteMetrics,
+    max_body_bytes: usize,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct Mirror<P> {
+    policy: Option<Policy>,
+    inner: P,
+}
+
+#[derive(Clone, Debug)]
+struct Policy {
+    addr: NameAddr,
+    ratio: f64,
+    max_body_bytes: usize,
+    metrics: MetricsProxy,
+}
+
+/// A request extension that carries a mirrored request to the concrete stack.
+#[derive(Clone)]
+struct Dispatch {
+    addr: NameAddr,
+    slot: Slot,
+}
+
+/// Hands mirrored requests off to a `Dispatch` extension.
+struct Deliver(Slot);
+
+#[pin_project]
+struct DeliverFuture(#[pin] oneshot::Receiver<MirrorResult>);
+
+#[derive(Clone, Debug)]
+pub struct DispatchLayer(());
+
+#[derive(Clone, Debug)]
+pub struct MakeDispatch<M> {
+    make: M,
+}
+
+#[pin_project]
+pub struct MakeDispatchFuture<F, M, T> {
+    #[pin]
+    future: F,
+    make: Option<M>,
+    target: Option<T>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Dispatcher<M, T, S> {
+    make: M,
+    target: T,
+    inner: S,
+}
+
+/// The body of a request that may be mirrored. Data is buffered as it is
+/// read so that it may be sent to the mirror once the body is complete.
+#[pin_project]
+#[derive(Debug)]
+pub struct TeeBody<B> {
+    #[pin]
+    inner: B,
+    tee: Option<Tee>,
+}
+
+#[derive(Debug)]
+struct Tee {
+    tx: oneshot::Sender<Buffered>,
+    buffered: Buffered,
+    buffered_bytes: usize,
+    max_bytes: usize,
+}
+
+/// The body of a mirrored request, which is sent once the original request's
+/// body has been read completely.
+#[derive(Debug)]
+pub struct MirrorBody {
+    rx: Option<oneshot::Receiver<Buffered>>,
+    buffered: Buffered,
+}
+
+#[derive(Debug, Default)]
+struct Buffered {
+    data: VecDeque<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+/// Indicates that a request's body could not be mirrored, i.e. because it
+/// exceeded the buffer limit.
+#[derive(Debug)]
+pub struct BodyNotMirrored(());
+
+/// Indicates that a mirrored request was not dispatched.
+#[derive(Debug)]
+pub struct NotDispatched(());
+
+// === impl Layer ===
+
+impl<N> tower::layer::Layer<N> for Layer {
+    type Service = NewMirror<N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewMirror {
+            metrics: self.metrics.clone(),
+            max_body_bytes: self.max_body_bytes,
+            inner,
+        }
+    }
+}
+
+// === impl NewMirror ===
+
+impl<N> NewService<Route> for NewMirror<N>
+where
+    N: NewService<Route>,
+{
+    type Service = Mirror<N::Service>;
+
+    fn new_service(&self, route: Route) -> Self::Service {
+        use tower::layer::Layer;
+
+        let policy = route.route.mirror().map(|mirror| {
+            // Mirrored requests are recorded against the mirror's
+            // destination.
+            let mirrored = Route {
+                target: mirror.addr().clone().into(),
+                route: route.route.clone(),
+                direction: route.direction,
+            };
+            let metrics = self
+                .metrics
+                .clone()
+                .into_layer::<classify::Response>()
+                .layer(|_: Route| ())
+                .new_service(mirrored);
+            Policy {
+                addr: mirror.addr().clone(),
+                ratio: mirror.ratio(),
+                max_body_bytes: self.max_body_bytes,
+                metrics,
+            }
+        });
+
+        let inner = self.inner.new_service(route);
+        Mirror { policy, inner }
+    }
+}
+
+// === impl Mirror ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for Mirror<P>
+where
+    B: Body,
+    B::Error: Into<Error>,
+    P: Proxy<http::Request<TeeBody<B>>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        let policy = match self.policy.as_ref() {
+            Some(policy) if rand::thread_rng().gen::<f64>() < policy.ratio => policy,
+            _ => return self.inner.proxy(svc, req.map(TeeBody::disabled)),
+        };
+
+        trace!(addr = %policy.addr, "Mirroring request");
+        let (tx, rx) = oneshot::channel();
+        let mut mirror = http::Request::new(MirrorBody::new(rx));
+        *mirror.method_mut() = req.method().clone();
+        *mirror.uri_mut() = req.uri().clone();
+        *mirror.version_mut() = req.version();
+        *mirror.headers_mut() = req.headers().clone();
+        if let Some(classify) = req.extensions().get::<classify::Response>() {
+            mirror.extensions_mut().insert(classify.clone());
+        }
+        let dispatch = policy.mirror(mirror);
+
+        let max_bytes = policy.max_body_bytes;
+        let mut req = req.map(|body| TeeBody::new(body, max_bytes, tx));
+        req.extensions_mut().insert(dispatch);
+        self.inner.proxy(svc, req)
+    }
+}
+
+// === impl Policy ===
+
+impl Policy {
+    /// Records metrics for a mirrored request, discarding its response, and
+    /// returns an extension that dispatches it.
+    fn mirror(&self, req: http::Request<MirrorBody>) -> Dispatch {
+        let slot = Slot::default();
+        let rsp = self.metrics.proxy(&mut Deliver(slot.clone()), req);
+        tokio::spawn(
+            async move {
+                match rsp.await {
+                    Ok(rsp) => {
+                        // Read the response to completion so that it is
+                        // classified.
+                        let mut body = rsp.into_body();
+                        while let Some(Ok(_)) = body.data().await {}
+                        let _ = body.trailers().await;
+                    }
+                    Err(error) => debug!(%error, "Mirrored request failed"),
+                }
+            }
+            .in_current_span(),
+        );
+
+        Dispatch {
+            addr: self.addr.clone(),
+            slot,
+        }
+    }
+}
+
+// === impl Deliver ===
+
+impl<B> tower::Service<http::Request<B>> for Deliver
+where
+    B: Body + Send + 'static,
+    B::Data: Send + 'static,
+    B::Error: Into<Error>,
+{
+    type Response = http::Response<Payload>;
+    type Error = Error;
+    type Future = DeliverFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let (tx, rx) = oneshot::channel();
+        if let Ok(mut slot) = self.0.lock() {
+            *slot = Some((req.map(Payload::new), tx));
+        }
+        DeliverFuture(rx)
+    }
+}
+
+impl Future for DeliverFuture {
+    type Output = MirrorResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match ready!(self.project().0.poll(cx)) {
+            Ok(res) => Poll::Ready(res),
+            Err(_) => Poll::Ready(Err(NotDispatched(()).into())),
+        }
+    }
+}
+
+// === impl DispatchLayer ===
+
+impl<M> tower::layer::Layer<M> for DispatchLayer {
+    type Service = MakeDispatch<M>;
+
+    fn layer(&self, make: M) -> Self::Service {
+        MakeDispatch { make }
+    }
+}
+
+// === impl MakeDispatch ===
+
+impl<T, M> tower::Service<T> for MakeDispatch<M>
+where
+    T: Clone,
+    M: tower::Service<T> + Clone,
+{
+    type Response = Dispatcher<M, T, M::Response>;
+    type Error = M::Error;
+    type Future = MakeDispatchFuture<M::Future, M, T>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.make.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        MakeDispatchFuture {
+            future: self.make.call(target.clone()),
+            make: Some(self.make.clone()),
+            target: Some(target),
+        }
+    }
+}
+
+impl<F, M, T> Future for MakeDispatchFuture<F, M, T>
+where
+    F: TryFuture,
+{
+    type Output = Result<Dispatcher<M, T, F::Ok>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(Dispatcher {
+            make: this.make.take().expect("polled after ready"),
+            target: this.target.take().expect("polled after ready"),
+            inner,
+        }))
+    }
+}
+
+// === impl Dispatcher ===
+
+impl<M, T, S, B> tower::Service<http::Request<B>> for Dispatcher<M, T, S>
+where
+    T: profiles::OverrideDestination + Clone + Send + 'static,
+    M: tower::Service<T> + Clone + Send + 'static,
+    M::Error: Into<Error>,
+    M::Future: Send,
+    M::Response:
+        tower::Service<http::Request<Payload>, Response = http::Response<Payload>> + Send + 'static,
+    <M::Response as tower::Service<http::Request<Payload>>>::Error: Into<Error>,
+    <M::Response as tower::Service<http::Request<Payload>>>::Future: Send,
+    S: tower::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(Dispatch { addr, slot }) = req.extensions_mut().remove::<Dispatch>() {
+            let mirror = slot.lock().ok().and_then(|mut s| s.take());
+            if let Some((mirror, tx)) = mirror {
+                let mut target = self.target.clone();
+                *target.dst_mut() = addr.into();
+                let rsp = self
+                    .make
+                    .clone()
+                    .oneshot(target)
+                    .err_into::<Error>()
+                    .and_then(|svc| svc.oneshot(mirror).err_into::<Error>());
+                tokio::spawn(
+                    async move {
+                        let _ = tx.send(rsp.await);
+                    }
+                    .in_current_span(),
+                );
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+// === impl TeeBody ===
+
+impl<B: Body> TeeBody<B> {
+    fn new(inner: B, max_bytes: usize, tx: oneshot::Sender<Buffered>) -> Self {
+        let tee = Tee {
+            tx,
+            buffered: Buffered::default(),
+            buffered_bytes: 0,
+            max_bytes,
+        };
+        if inner.is_end_stream() {
+            tee.complete(None);
+            return Self::disabled(inner);
+        }
+        Self {
+            inner,
+            tee: Some(tee),
+        }
+    }
+
+    fn disabled(inner: B) -> Self {
+        Self { inner, tee: None }
+    }
+}
+
+impl<B> Body for TeeBody<B>
+where
+    B: Body,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        let mut data = match ready!(this.inner.as_mut().poll_data(cx)) {
+            Some(Ok(data)) => data,
+            Some(Err(e)) => {
+                *this.tee = None;
+                return Poll::Ready(Some(Err(e.into())));
+            }
+            None => {
+                if this.inner.is_end_stream() {
+                    if let Some(tee) = this.tee.take() {
+                        tee.complete(None);
+                    }
+                }
+                return Poll::Ready(None);
+            }
+        };
+
+        let chunk = data.to_bytes();
+        if let Some(tee) = this.tee.as_mut() {
+            if tee.buffered_bytes + chunk.len() > tee.max_bytes {
+                debug!("Request body too large to be mirrored");
+                *this.tee = None;
+            } else {
+                tee.buffered_bytes += chunk.len();
+                tee.buffered.data.push_back(chunk.clone());
+            }
+        }
+        if this.inner.is_end_stream() {
+            if let Some(tee) = this.tee.take() {
+                tee.complete(None);
+            }
+        }
+
+        Poll::Ready(Some(Ok(chunk)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.project();
+        let trailers = ready!(this.inner.poll_trailers(cx));
+        let tee = this.tee.take();
+        match trailers {
+            Ok(trailers) => {
+                if let Some(tee) = tee {
+                    tee.complete(trailers.clone());
+                }
+                Poll::Ready(Ok(trailers))
+            }
+            Err(e) => Poll::Ready(Err(e.into())),
+        }
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// === impl Tee ===
+
+impl Tee {
+    fn complete(mut self, trailers: Option<HeaderMap>) {
+        self.buffered.trailers = trailers;
+        let _ = self.tx.send(self.buffered);
+    }
+}
+
+// === impl MirrorBody ===
+
+impl MirrorBody {
+    fn new(rx: oneshot::Receiver<Buffered>) -> Self {
+        Self {
+            rx: Some(rx),
+            buffered: Buffered::default(),
+        }
+    }
+
+    /// Waits for the original request's body to complete.
+    fn poll_buffered(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        if let Some(rx) = self.rx.as_mut() {
+            let res = ready!(Pin::new(rx).poll(cx));
+            self.rx = None;
+            self.buffered = res.map_err(|_| BodyNotMirrored(()))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Body for MirrorBody {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.rx.is_none() && self.buffered.data.is_empty() && self.buffered.trailers.is_none()
+    }
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        if let Err(e) = ready!(this.poll_buffered(cx)) {
+            return Poll::Ready(Some(Err(e)));
+        }
+        Poll::Ready(this.buffered.data.pop_front().map(Ok))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_buffered(cx))?;
+        Poll::Ready(Ok(this.buffered.trailers.take()))
+    }
+}
+
+// === impl BodyNotMirrored ===
+
+impl std::fmt::Display for BodyNotMirrored {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request body could not be mirrored")
+    }
+}
+
+impl std::error::Error for BodyNotMirrored {}
+
+// === impl NotDispatched ===
+
+impl std::fmt::Display for NotDispatched {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "mirrored request was not dispatched")
+    }
+}
+
+impl std::error::Error for NotDispatched {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric_labels::Direction;
+    use crate::Addr;
+    use futures::future;
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+    use tower::layer::Layer as _;
+
+    type Request = http::Request<TeeBody<hyper::Body>>;
+
+    #[derive(Clone, Debug)]
+    struct Target(Addr);
+
+    /// A mirror destination that records the requests it receives and never
+    /// responds to them.
+    #[derive(Clone)]
+    struct Shadow {
+        tx: mpsc::UnboundedSender<http::Uri>,
+        fail: bool,
+    }
+
+    impl profiles::OverrideDestination for Target {
+        fn dst_mut(&mut self) -> &mut Addr {
+            &mut self.0
+        }
+    }
+
+    impl tower::Service<Target> for Shadow {
+        type Response = Self;
+        type Error = Error;
+        type Future = future::Ready<Result<Self, Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, target: Target) -> Self::Future {
+            assert_eq!(target.0.to_string(), "shadow.ns.svc.cluster.local:8080");
+            if self.fail {
+                return future::err("mirror unavailable".into());
+            }
+            future::ok(self.clone())
+        }
+    }
+
+    impl tower::Service<http::Request<Payload>> for Shadow {
+        type Response = http::Response<Payload>;
+        type Error = Error;
+        type Future = future::Pending<Result<Self::Response, Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<Payload>) -> Self::Future {
+            let _ = self.tx.send(req.uri().clone());
+            future::pending()
+        }
+    }
+
+    fn mirror(ratio: f64) -> Mirror<()> {
+        let mut route = profiles::Route::new(std::iter::empty(), Vec::new());
+        route.set_mirror(
+            NameAddr::from_str("shadow.ns.svc.cluster.local:8080").unwrap(),
+            ratio,
+        );
+        let route = Route {
+            target: NameAddr::from_str("web.ns.svc.cluster.local:8080")
+                .unwrap()
+                .into(),
+            route,
+            direction: Direction::Out,
+        };
+        layer(HttpRouteMetrics::default(), 1024)
+            .layer(|_: Route| ())
+            .new_service(route)
+    }
+
+    fn ok(_: Request) -> future::Ready<Result<http::Response<hyper::Body>, Error>> {
+        future::ok(http::Response::new(hyper::Body::empty()))
+    }
+
+    /// Counts the requests that are marked to be mirrored.
+    async fn mirrored(ratio: f64, requests: usize) -> usize {
+        let mirror = mirror(ratio);
+        let mut svc = tower::service_fn(|req: Request| {
+            future::ok::<_, Error>(req.extensions().get::<Dispatch>().is_some())
+        });
+        let mut n = 0;
+        for _ in 0..requests {
+            let req = http::Request::new(hyper::Body::empty());
+            if mirror.proxy(&mut svc, req).await.unwrap() {
+                n += 1;
+            }
+        }
+        n
+    }
+
+    #[tokio::test]
+    async fn mirrors_a_ratio_of_requests() {
+        assert_eq!(mirrored(0.0, 100).await, 0);
+        assert_eq!(mirrored(1.0, 100).await, 100);
+        let n = mirrored(0.5, 1_000).await;
+        assert!(300 < n && n < 700, "mirrored {} of 1000 requests", n);
+
+        // Out-of-range ratios are clamped.
+        assert_eq!(mirrored(-1.0, 100).await, 0);
+        assert_eq!(mirrored(2.0, 100).await, 100);
+    }
+
+    #[tokio::test]
+    async fn does_not_wait_for_mirrored_responses() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut dispatcher = Dispatcher {
+            make: Shadow { tx, fail: false },
+            target: Target(
+                NameAddr::from_str("web.ns.svc.cluster.local:8080")
+                    .unwrap()
+                    .into(),
+            ),
+            inner: tower::service_fn(ok),
+        };
+
+        // The original request completes even though the mirror never
+        // responds.
+        let req = http::Request::builder()
+            .uri("http://web.ns.svc.cluster.local:8080/users")
+            .body(hyper::Body::empty())
+            .unwrap();
+        let rsp = mirror(1.0).proxy(&mut dispatcher, req).await;
+        assert_eq!(rsp.unwrap().status(), http::StatusCode::OK);
+
+        let uri = rx.recv().await.expect("request must be mirrored");
+        assert_eq!(uri.path(), "/users");
+    }
+
+    #[tokio::test]
+    async fn ignores_mirror_failures() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut dispatcher = Dispatcher {
+            make: Shadow { tx, fail: true },
+            target: Target(
+                NameAddr::from_str("web.ns.svc.cluster.local:8080")
+                    .unwrap()
+                    .into(),
+            ),
+            inner: tower::service_fn(ok),
+        };
+
+        let req = http::Request::new(hyper::Body::empty());
+        let rsp = mirror(1.0).proxy(&mut dispatcher, req).await;
+        assert_eq!(rsp.unwrap().status(), http::StatusCode::OK);
+
+        // The mirror is dropped once the mirrored request fails.
+        drop(dispatcher);
+        assert!(rx.recv().await.is_none());
+    }
+}
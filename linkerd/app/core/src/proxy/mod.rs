@@ -11,4 +11,4 @@ pub use linkerd2_proxy_tcp as tcp;
 
 mod skip_detect;
 
-pub use self::skip_detect::SkipDetect;
+pub use self::skip_detect::{PortProtocol, SkipDetect};
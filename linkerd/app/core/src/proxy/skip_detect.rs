@@ -1,7 +1,11 @@
 use futures::prelude::*;
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use linkerd2_error::Error;
-use linkerd2_proxy_transport::listen::Addrs;
+use linkerd2_proxy_http::{
+    detect::{KnownProtocol, Protocol},
+    Version,
+};
+use linkerd2_proxy_transport::{listen::Addrs, tls};
 use std::{
     future::Future,
     pin::Pin,
@@ -20,6 +24,42 @@ impl SkipTarget<Addrs> for Arc<IndexSet<u16>> {
     }
 }
 
+/// The protocol declared for a port.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PortProtocol {
+    Opaque,
+    Http1,
+    /// HTTP/2 with prior knowledge, including gRPC.
+    Http2,
+    /// Connections are forwarded without terminating TLS.
+    TlsPassthrough,
+}
+
+impl PortProtocol {
+    fn protocol(self) -> Protocol {
+        match self {
+            Self::Http1 => Protocol::Http(Version::Http1),
+            Self::Http2 => Protocol::Http(Version::H2),
+            Self::Opaque | Self::TlsPassthrough => Protocol::Opaque,
+        }
+    }
+}
+
+impl KnownProtocol<Addrs> for Arc<IndexMap<u16, PortProtocol>> {
+    fn known_protocol(&self, addrs: &Addrs) -> Protocol {
+        self.get(&addrs.target_addr().port())
+            .map(|p| p.protocol())
+            .unwrap_or(Protocol::Detect)
+    }
+}
+
+impl KnownProtocol<tls::accept::Meta> for Arc<IndexMap<u16, PortProtocol>> {
+    fn known_protocol(&self, meta: &tls::accept::Meta) -> Protocol {
+        self.known_protocol(&meta.addrs)
+    }
+}
+
+#[derive(Clone)]
 pub struct SkipDetect<S, D, F> {
     skip: S,
     detect: D,
@@ -0,0 +1,512 @@
+//! Reads PROXY protocol (v1 and v2) headers from inbound connections.
+//!
+//! When the proxy is behind a layer 4 load balancer, the connection's peer is
+//! the load balancer rather than the client. Load balancers that speak the
+//! PROXY protocol send a header with the client's address, which replaces the
+//! connection's peer address before TLS detection.
+//!
+//! Headers are only read from peers in the configured load balancer networks,
+//! since any other client could use one to impersonate an arbitrary source
+//! address. The connection's local and original destination addresses are
+//! never taken from the header, so it cannot redirect the connection.
+//!
+//! See <https://www.haproxy.org/download/2.2/doc/proxy-protocol.txt>.
+
+use crate::transport::listen::Addrs;
+use futures::prelude::*;
+use indexmap::{IndexMap, IndexSet};
+use ipnet::IpNet;
+use linkerd2_error::Error;
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetrics};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tower::util::ServiceExt;
+use tracing::{debug, trace};
+
+metrics! {
+    proxy_protocol_rejected_total: Counter {
+        "Total number of connections rejected because of a missing or malformed PROXY protocol header"
+    }
+}
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The shortest v1 header, `PROXY UNKNOWN\r\n`.
+const V1_MIN_LEN: usize = 15;
+
+/// The longest v1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of a v2 header before its addresses.
+const V2_PREFIX_LEN: usize = 16;
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// Ports on which connections from load balancers begin with a header.
+    pub ports: IndexSet<u16>,
+
+    /// Networks of the load balancers from which headers are read.
+    pub trusted_networks: IndexSet<IpNet>,
+}
+
+/// Reads PROXY protocol headers on connections to the configured ports.
+#[derive(Clone, Debug)]
+pub struct ProxyProtocol<M> {
+    config: Arc<Config>,
+    timeout: Duration,
+    registry: Registry,
+    inner: M,
+}
+
+#[derive(Clone, Debug)]
+pub struct Accept<M> {
+    addrs: Addrs,
+    header: Option<(Duration, Registry)>,
+    inner: M,
+}
+
+/// Records rejected connections for each port.
+#[derive(Clone, Debug, Default)]
+pub struct Registry(Arc<Mutex<IndexMap<Port, Arc<Counter>>>>);
+
+/// Indicates that a connection did not begin with a valid PROXY protocol
+/// header.
+#[derive(Debug)]
+pub struct InvalidHeader(());
+
+#[derive(Debug)]
+pub struct HeaderTimeout(Duration);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Port(u16);
+
+/// The addresses described by a header, if any. `LOCAL` (v2) and `UNKNOWN`
+/// (v1) headers do not describe a proxied connection.
+type Header = Option<(SocketAddr, SocketAddr)>;
+
+// === impl ProxyProtocol ===
+
+impl<M> ProxyProtocol<M> {
+    pub fn new(config: Config, timeout: Duration, registry: Registry, inner: M) -> Self {
+        Self {
+            config: Arc::new(config),
+            timeout,
+            registry,
+            inner,
+        }
+    }
+}
+
+impl<M: Clone> tower::Service<Addrs> for ProxyProtocol<M> {
+    type Response = Accept<M>;
+    type Error = Error;
+    type Future = future::Ready<Result<Accept<M>, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, addrs: Addrs) -> Self::Future {
+        let header = if self.config.reads_header(&addrs) {
+            Some((self.timeout, self.registry.clone()))
+        } else {
+            None
+        };
+        future::ok(Accept {
+            addrs,
+            header,
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+// === impl Accept ===
+
+impl<M, A, I> tower::Service<I> for Accept<M>
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    M: tower::Service<Addrs, Response = A> + Clone + Send + 'static,
+    M::Error: Into<Error>,
+    M::Future: Send,
+    A: tower::Service<I, Response = ()> + Send + 'static,
+    A::Error: Into<Error>,
+    A::Future: Send,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut io: I) -> Self::Future {
+        let addrs = self.addrs.clone();
+        let header = self.header.clone();
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let addrs = match header {
+                None => addrs,
+                Some((timeout, registry)) => {
+                    let port = addrs.target_addr().port();
+                    let read = tokio::time::timeout(timeout, read(&mut io))
+                        .await
+                        .map_err(|_| Error::from(HeaderTimeout(timeout)))
+                        .and_then(|res| res);
+                    match read {
+                        Ok(Some(peer)) => {
+                            debug!(peer.addr = %peer, "Read PROXY header");
+                            Addrs::new(addrs.local(), peer, addrs.orig_dst())
+                        }
+                        Ok(None) => {
+                            trace!("PROXY header does not describe a proxied connection");
+                            addrs
+                        }
+                        Err(error) => {
+                            registry.rejected(port);
+                            return Err(error);
+                        }
+                    }
+                }
+            };
+
+            let accept = inner.oneshot(addrs).await.map_err(Into::into)?;
+            accept.oneshot(io).await.map_err(Into::into)
+        })
+    }
+}
+
+/// Reads a PROXY protocol header, returning the client's address, if any.
+///
+/// Only the header's bytes are read, so that the rest of the connection can be
+/// detected and forwarded as-is. v1 headers are read a byte at a time until
+/// their CRLF; v2 headers are prefixed by their length.
+async fn read<I>(io: &mut I) -> Result<Option<SocketAddr>, Error>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut buf = vec![0u8; V1_MIN_LEN];
+    io.read_exact(&mut buf).await?;
+    loop {
+        if let Some((header, _)) = parse(&buf)? {
+            return Ok(header.map(|(src, _)| src));
+        }
+
+        let pos = buf.len();
+        buf.resize(pos + needed(&buf), 0);
+        io.read_exact(&mut buf[pos..]).await?;
+    }
+}
+
+/// Returns the number of bytes that may be read without reading past the end
+/// of an incomplete header.
+fn needed(buf: &[u8]) -> usize {
+    if !buf.starts_with(&V2_SIGNATURE[..V2_SIGNATURE.len().min(buf.len())]) {
+        return 1;
+    }
+    if buf.len() < V2_PREFIX_LEN {
+        return V2_PREFIX_LEN - buf.len();
+    }
+    let len = V2_PREFIX_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    len.saturating_sub(buf.len()).max(1)
+}
+
+/// Parses a header from the start of `buf`, returning `None` when more bytes
+/// are needed.
+fn parse(buf: &[u8]) -> Result<Option<(Header, usize)>, InvalidHeader> {
+    let n = buf.len().min(V2_SIGNATURE.len());
+    if buf[..n] == V2_SIGNATURE[..n] {
+        return parse_v2(buf);
+    }
+
+    let n = buf.len().min(V1_PREFIX.len());
+    if buf[..n] == V1_PREFIX[..n] {
+        return parse_v1(buf);
+    }
+
+    Err(InvalidHeader(()))
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Option<(Header, usize)>, InvalidHeader> {
+    let len = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(idx) => idx + 2,
+        None if buf.len() < V1_MAX_LEN => return Ok(None),
+        None => return Err(InvalidHeader(())),
+    };
+
+    let line =
+        std::str::from_utf8(&buf[V1_PREFIX.len()..len - 2]).map_err(|_| InvalidHeader(()))?;
+    let mut parts = line.split(' ');
+    let header = match parts.next() {
+        Some("UNKNOWN") => None,
+        Some("TCP4") | Some("TCP6") => {
+            let mut next = || parts.next().ok_or(InvalidHeader(()));
+            let src = next()?.parse().map_err(|_| InvalidHeader(()))?;
+            let dst = next()?.parse().map_err(|_| InvalidHeader(()))?;
+            let sport = next()?.parse().map_err(|_| InvalidHeader(()))?;
+            let dport = next()?.parse().map_err(|_| InvalidHeader(()))?;
+            if parts.next().is_some() {
+                return Err(InvalidHeader(()));
+            }
+            Some((SocketAddr::new(src, sport), SocketAddr::new(dst, dport)))
+        }
+        _ => return Err(InvalidHeader(())),
+    };
+
+    Ok(Some((header, len)))
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Option<(Header, usize)>, InvalidHeader> {
+    if buf.len() < V2_PREFIX_LEN {
+        return Ok(None);
+    }
+
+    let version_command = buf[12];
+    let family = buf[13];
+    let len = V2_PREFIX_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if version_command >> 4 != 2 {
+        return Err(InvalidHeader(()));
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+
+    let addrs = &buf[V2_PREFIX_LEN..len];
+    let header = match (version_command & 0x0f, family) {
+        // LOCAL connections, e.g. health checks, are not proxied.
+        (0x0, _) => None,
+        // TCP over IPv4.
+        (0x1, 0x11) if addrs.len() >= 12 => {
+            let ip = |b: &[u8]| Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+            let src = SocketAddr::new(ip(&addrs[0..4]).into(), port(&addrs[8..10]));
+            let dst = SocketAddr::new(ip(&addrs[4..8]).into(), port(&addrs[10..12]));
+            Some((src, dst))
+        }
+        // TCP over IPv6.
+        (0x1, 0x21) if addrs.len() >= 36 => {
+            let ip = |b: &[u8]| {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&b[..16]);
+                Ipv6Addr::from(octets)
+            };
+            let src = SocketAddr::new(ip(&addrs[0..16]).into(), port(&addrs[32..34]));
+            let dst = SocketAddr::new(ip(&addrs[16..32]).into(), port(&addrs[34..36]));
+            Some((src, dst))
+        }
+        // Other address families can't describe the connection's addresses.
+        (0x1, _) => None,
+        _ => return Err(InvalidHeader(())),
+    };
+
+    Ok(Some((header, len)))
+}
+
+fn port(b: &[u8]) -> u16 {
+    u16::from_be_bytes([b[0], b[1]])
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Headers are only read from trusted peers on the configured ports.
+    fn reads_header(&self, addrs: &Addrs) -> bool {
+        if !self.ports.contains(&addrs.target_addr().port()) {
+            return false;
+        }
+
+        let peer = addrs.peer().ip();
+        let trusted = self.trusted_networks.iter().any(|net| match (net, peer) {
+            (IpNet::V4(net), IpAddr::V4(ip)) => net.contains(&ip),
+            (IpNet::V6(net), IpAddr::V6(ip)) => net.contains(&ip),
+            _ => false,
+        });
+        if !trusted {
+            debug!(peer.addr = %addrs.peer(), "Not reading PROXY header from untrusted peer");
+        }
+        trusted
+    }
+}
+
+// === impl Registry ===
+
+impl Registry {
+    fn rejected(&self, port: u16) {
+        if let Ok(mut counters) = self.0.lock() {
+            counters
+                .entry(Port(port))
+                .or_insert_with(Default::default)
+                .incr();
+        }
+    }
+}
+
+impl FmtMetrics for Registry {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counters = match self.0.lock() {
+            Ok(counters) => counters,
+            Err(_) => return Ok(()),
+        };
+        if counters.is_empty() {
+            return Ok(());
+        }
+
+        proxy_protocol_rejected_total.fmt_help(f)?;
+        proxy_protocol_rejected_total.fmt_scopes(f, counters.iter(), |c| c)?;
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for Port {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "port=\"{}\"", self.0)
+    }
+}
+
+// === impl InvalidHeader ===
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "missing or malformed PROXY protocol header")
+    }
+}
+
+impl std::error::Error for InvalidHeader {}
+
+// === impl HeaderTimeout ===
+
+impl fmt::Display for HeaderTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PROXY protocol header not read after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for HeaderTimeout {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_v1() {
+        let buf = b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 8080\r\nGET /";
+        let (header, len) = parse(buf).unwrap().unwrap();
+        assert_eq!(
+            header,
+            Some((addr("192.168.0.1:56324"), addr("10.0.0.1:8080")))
+        );
+        assert_eq!(&buf[len..], b"GET /");
+
+        let buf = b"PROXY TCP6 fd00::1 fd00::2 56324 8080\r\n";
+        let (header, _) = parse(buf).unwrap().unwrap();
+        assert_eq!(
+            header,
+            Some((addr("[fd00::1]:56324"), addr("[fd00::2]:8080")))
+        );
+
+        let (header, _) = parse(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!(header, None);
+
+        // Incomplete headers need more bytes.
+        assert!(parse(b"PROXY TCP4 192.168").unwrap().is_none());
+
+        assert!(parse(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324\r\n").is_err());
+        assert!(parse(b"PROXY UDP4 192.168.0.1 10.0.0.1 56324 8080\r\n").is_err());
+        assert!(parse(b"GET / HTTP/1.1\r\n").is_err());
+        let mut unterminated = V1_PREFIX.to_vec();
+        unterminated.resize(V1_MAX_LEN, b'x');
+        assert!(parse(&unterminated).is_err());
+    }
+
+    #[test]
+    fn parses_v2() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        buf.extend_from_slice(&[192, 168, 0, 1, 10, 0, 0, 1]);
+        buf.extend_from_slice(&56324u16.to_be_bytes());
+        buf.extend_from_slice(&8080u16.to_be_bytes());
+        buf.extend_from_slice(b"\x16\x03\x01");
+
+        // Incomplete headers need more bytes.
+        assert!(parse(&buf[..20]).unwrap().is_none());
+
+        let (header, len) = parse(&buf).unwrap().unwrap();
+        assert_eq!(
+            header,
+            Some((addr("192.168.0.1:56324"), addr("10.0.0.1:8080")))
+        );
+        assert_eq!(&buf[len..], b"\x16\x03\x01");
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(parse(&local).unwrap(), Some((None, V2_PREFIX_LEN)));
+
+        let mut version1 = V2_SIGNATURE.to_vec();
+        version1.extend_from_slice(&[0x11, 0x11, 0, 0]);
+        assert!(parse(&version1).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_connections_without_header() {
+        let mut io = tokio_test::io::Builder::new()
+            .read(b"\x16\x03\x01\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00")
+            .build();
+        assert!(read(&mut io).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_only_the_header() {
+        let mut io = tokio_test::io::Builder::new()
+            .read(b"PROXY TCP4 192.168.0.1 10.0.0.1 56324 8080\r\nGET / HTTP/1.1\r\n")
+            .build();
+        let peer = read(&mut io).await.unwrap();
+        assert_eq!(peer, Some(addr("192.168.0.1:56324")));
+        let mut rest = vec![0u8; 16];
+        io.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest[..], b"GET / HTTP/1.1\r\n");
+
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        buf.extend_from_slice(&[192, 168, 0, 1, 10, 0, 0, 1]);
+        buf.extend_from_slice(&56324u16.to_be_bytes());
+        buf.extend_from_slice(&8080u16.to_be_bytes());
+        buf.extend_from_slice(b"\x16\x03\x01");
+        let mut io = tokio_test::io::Builder::new().read(&buf).build();
+        let peer = read(&mut io).await.unwrap();
+        assert_eq!(peer, Some(addr("192.168.0.1:56324")));
+        let mut rest = vec![0u8; 3];
+        io.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest[..], b"\x16\x03\x01");
+    }
+
+    #[test]
+    fn only_reads_headers_from_trusted_peers() {
+        let config = Config {
+            ports: Some(8080).into_iter().collect(),
+            trusted_networks: Some("192.168.0.0/16".parse().unwrap())
+                .into_iter()
+                .collect(),
+        };
+        let local = addr("10.0.0.1:8080");
+        let lb = Addrs::new(local, addr("192.168.1.1:56324"), Some(local));
+        assert!(config.reads_header(&lb));
+
+        let client = Addrs::new(local, addr("172.16.0.1:56324"), Some(local));
+        assert!(!config.reads_header(&client));
+
+        let other = addr("10.0.0.1:9090");
+        let lb = Addrs::new(other, addr("192.168.1.1:56324"), Some(other));
+        assert!(!config.reads_header(&lb));
+    }
+}
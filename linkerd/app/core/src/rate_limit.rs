@@ -0,0 +1,526 @@
+//! Limits the rate of requests to destinations and routes that configure a
+//! rate limit.
+//!
+//! Each limit is a token bucket. Requests that arrive while the bucket is empty
+//! wait for a token, up to the limit's queue bound; further requests fail with
+//! an `HttpError` so that they are answered with a 429 (or a gRPC
+//! `RESOURCE_EXHAUSTED` status) and recorded in error metrics. A waiting
+//! request reserves its token, which is returned to the bucket if the request
+//! is canceled before its wait elapses.
+
+use super::dst::Route;
+use super::errors::HttpError;
+use crate::profiles::RateLimit;
+use futures::{ready, TryFuture};
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use linkerd2_stack::{NewService, Proxy, ProxyService};
+use pin_project::pin_project;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{self, Delay, Instant};
+use tower::util::{Oneshot, ServiceExt};
+use tracing::{debug, trace};
+
+/// Limits the rate of requests on routes that configure a rate limit.
+pub fn layer() -> Layer {
+    Layer(())
+}
+
+/// Limits the rate of requests to each destination with a configured rate
+/// limit, keyed by the destination's authority.
+///
+/// A destination's limit is shared by all of the services built for it.
+pub fn destination_layer(limits: IndexMap<String, RateLimit>) -> DestinationLayer {
+    let limiters = limits
+        .into_iter()
+        .map(|(dst, limit)| (dst, Arc::new(Limiter::new(limit))))
+        .collect();
+    DestinationLayer(Arc::new(limiters))
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer(());
+
+#[derive(Clone, Debug)]
+pub struct NewLimit<N> {
+    inner: N,
+}
+
+/// Limits the rate of a route's requests.
+#[derive(Clone, Debug)]
+pub struct Limit<P> {
+    limiter: Option<Arc<Limiter>>,
+    inner: P,
+}
+
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    Inner(#[pin] P::Future),
+    Rejected(Option<HttpError>),
+    Queued {
+        #[pin]
+        delay: Delay,
+        reservation: Reservation,
+        #[pin]
+        dispatch: Oneshot<ProxyService<P, S>, Req>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct DestinationLayer(Arc<IndexMap<String, Arc<Limiter>>>);
+
+#[derive(Clone, Debug)]
+pub struct MakeRateLimit<M> {
+    inner: M,
+    limiters: Arc<IndexMap<String, Arc<Limiter>>>,
+}
+
+#[pin_project]
+pub struct MakeFuture<F> {
+    #[pin]
+    future: F,
+    limiter: Option<Arc<Limiter>>,
+}
+
+/// Limits the rate of a destination's requests.
+///
+/// Readiness is withheld while a request waits for a token. When the limit's
+/// queue is full, the service becomes ready so that the next request fails.
+pub struct RateLimited<S> {
+    inner: S,
+    limiter: Option<Arc<Limiter>>,
+    state: State,
+}
+
+#[pin_project(project = RateLimitedFutureProj)]
+pub enum RateLimitedFuture<F> {
+    Inner(#[pin] F),
+    Rejected(Option<HttpError>),
+}
+
+enum State {
+    Empty,
+    Queued(Delay, Reservation),
+    Acquired,
+    Rejected(HttpError),
+}
+
+/// A token reserved by a waiting request. Unless the token is taken once the
+/// wait elapses, it is returned to the bucket when the reservation is dropped.
+#[derive(Debug)]
+struct Reservation(Option<Arc<Limiter>>);
+
+/// A token bucket, shared by all of the services that enforce a limit.
+#[derive(Debug)]
+struct Limiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// The number of tokens in the bucket. This is negative while requests
+    /// wait for tokens, each having reserved a token that is not yet
+    /// available.
+    tokens: f64,
+    updated_at: Instant,
+}
+
+// === impl Layer ===
+
+impl<N> tower::layer::Layer<N> for Layer {
+    type Service = NewLimit<N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewLimit { inner }
+    }
+}
+
+// === impl NewLimit ===
+
+impl<N> NewService<Route> for NewLimit<N>
+where
+    N: NewService<Route>,
+{
+    type Service = Limit<N::Service>;
+
+    fn new_service(&self, route: Route) -> Self::Service {
+        let limiter = route
+            .route
+            .rate_limit()
+            .map(|limit| Arc::new(Limiter::new(*limit)));
+        let inner = self.inner.new_service(route);
+        Limit { limiter, inner }
+    }
+}
+
+// === impl Limit ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for Limit<P>
+where
+    P: Proxy<http::Request<B>, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = ResponseFuture<P, S, http::Request<B>>;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        let limiter = match self.limiter.as_ref() {
+            Some(limiter) => limiter,
+            None => return ResponseFuture::Inner(self.inner.proxy(svc, req)),
+        };
+
+        match limiter.acquire() {
+            Ok(None) => ResponseFuture::Inner(self.inner.proxy(svc, req)),
+            Ok(Some(wait)) => {
+                let svc = self.inner.clone().wrap_service(svc.clone());
+                ResponseFuture::Queued {
+                    delay: time::delay_for(wait),
+                    reservation: Reservation(Some(limiter.clone())),
+                    dispatch: svc.oneshot(req),
+                }
+            }
+            Err(error) => ResponseFuture::Rejected(Some(error)),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<P, S, Req> Future for ResponseFuture<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Inner(f) => f.poll(cx).map_err(Into::into),
+            ResponseFutureProj::Rejected(error) => {
+                let error = error.take().expect("polled after ready");
+                Poll::Ready(Err(error.into()))
+            }
+            ResponseFutureProj::Queued {
+                delay,
+                reservation,
+                dispatch,
+            } => {
+                ready!(delay.poll(cx));
+                reservation.take();
+                dispatch.poll(cx)
+            }
+        }
+    }
+}
+
+// === impl DestinationLayer ===
+
+impl<M> tower::layer::Layer<M> for DestinationLayer {
+    type Service = MakeRateLimit<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeRateLimit {
+            inner,
+            limiters: self.0.clone(),
+        }
+    }
+}
+
+// === impl MakeRateLimit ===
+
+impl<T, M> tower::Service<T> for MakeRateLimit<M>
+where
+    T: fmt::Display,
+    M: tower::Service<T>,
+{
+    type Response = RateLimited<M::Response>;
+    type Error = M::Error;
+    type Future = MakeFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let limiter = self.limiters.get(&target.to_string()).cloned();
+        MakeFuture {
+            future: self.inner.call(target),
+            limiter,
+        }
+    }
+}
+
+// === impl MakeFuture ===
+
+impl<F: TryFuture> Future for MakeFuture<F> {
+    type Output = Result<RateLimited<F::Ok>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(RateLimited {
+            inner,
+            limiter: this.limiter.take(),
+            state: State::Empty,
+        }))
+    }
+}
+
+// === impl RateLimited ===
+
+impl<S, Req> tower::Service<Req> for RateLimited<S>
+where
+    S: tower::Service<Req>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = RateLimitedFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let limiter = match self.limiter.as_ref() {
+            Some(limiter) => limiter,
+            None => return self.inner.poll_ready(cx).map_err(Into::into),
+        };
+
+        loop {
+            self.state = match self.state {
+                State::Empty => match limiter.acquire() {
+                    Ok(None) => State::Acquired,
+                    Ok(Some(wait)) => {
+                        trace!(?wait, "Waiting for the rate limit");
+                        State::Queued(time::delay_for(wait), Reservation(Some(limiter.clone())))
+                    }
+                    Err(error) => State::Rejected(error),
+                },
+                State::Queued(ref mut delay, ref mut reservation) => {
+                    ready!(Pin::new(delay).poll(cx));
+                    reservation.take();
+                    State::Acquired
+                }
+                State::Acquired => return self.inner.poll_ready(cx).map_err(Into::into),
+                // The next request fails without being dispatched.
+                State::Rejected(_) => return Poll::Ready(Ok(())),
+            };
+        }
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        match std::mem::replace(&mut self.state, State::Empty) {
+            State::Rejected(error) => {
+                debug!("Rate limit exceeded");
+                RateLimitedFuture::Rejected(Some(error))
+            }
+            _ => RateLimitedFuture::Inner(self.inner.call(req)),
+        }
+    }
+}
+
+// === impl RateLimitedFuture ===
+
+impl<F> Future for RateLimitedFuture<F>
+where
+    F: TryFuture,
+    F::Error: Into<Error>,
+{
+    type Output = Result<F::Ok, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            RateLimitedFutureProj::Inner(f) => f.try_poll(cx).map_err(Into::into),
+            RateLimitedFutureProj::Rejected(error) => {
+                let error = error.take().expect("polled after ready");
+                Poll::Ready(Err(error.into()))
+            }
+        }
+    }
+}
+
+// === impl Limiter ===
+
+impl Limiter {
+    /// The bucket starts full.
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: limit.burst.max(1) as f64,
+                updated_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token from the bucket, returning how long the request must wait
+    /// for it, if at all.
+    fn acquire(&self) -> Result<Option<Duration>, HttpError> {
+        self.acquire_at(Instant::now())
+    }
+
+    fn acquire_at(&self, now: Instant) -> Result<Option<Duration>, HttpError> {
+        let mut bucket = match self.bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(_) => return Ok(None),
+        };
+
+        let rate = self.limit.rate.max(1) as f64;
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * rate).min(self.limit.burst.max(1) as f64);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(None);
+        }
+
+        // Reserve the next token that is not already reserved by a queued
+        // request, unless too many requests are already queued.
+        let tokens = bucket.tokens - 1.0;
+        if tokens < -(self.limit.max_queued as f64) {
+            return Err(HttpError::rate_limited());
+        }
+        bucket.tokens = tokens;
+        Ok(Some(Duration::from_secs_f64(-tokens / rate)))
+    }
+
+    /// Returns a reserved token to the bucket.
+    fn release(&self) {
+        if let Ok(mut bucket) = self.bucket.lock() {
+            bucket.tokens = (bucket.tokens + 1.0).min(self.limit.burst.max(1) as f64);
+        }
+    }
+}
+
+// === impl Reservation ===
+
+impl Reservation {
+    /// Takes the reserved token so that it is not returned to the bucket.
+    fn take(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(limiter) = self.0.take() {
+            trace!("Returning a canceled request's token");
+            limiter.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(rate: u32, burst: u32, max_queued: u32) -> Limiter {
+        Limiter::new(RateLimit {
+            rate,
+            burst,
+            max_queued,
+        })
+    }
+
+    #[test]
+    fn permits_bursts() {
+        let limiter = limiter(10, 2, 0);
+        let now = Instant::now();
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert!(limiter.acquire_at(now).is_err());
+
+        // Tokens are replenished at the configured rate.
+        let now = now + Duration::from_millis(100);
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert!(limiter.acquire_at(now).is_err());
+
+        // The bucket does not fill beyond its burst.
+        let now = now + Duration::from_secs(10);
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert!(limiter.acquire_at(now).is_err());
+    }
+
+    #[test]
+    fn queues_requests() {
+        let limiter = limiter(10, 1, 2);
+        let now = Instant::now();
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert_eq!(
+            limiter.acquire_at(now).unwrap(),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            limiter.acquire_at(now).unwrap(),
+            Some(Duration::from_millis(200))
+        );
+        let error = limiter.acquire_at(now).unwrap_err();
+        assert_eq!(error.status(), http::StatusCode::TOO_MANY_REQUESTS);
+
+        // Queued requests are served before new ones.
+        let now = now + Duration::from_millis(100);
+        assert_eq!(
+            limiter.acquire_at(now).unwrap(),
+            Some(Duration::from_millis(200))
+        );
+    }
+
+    #[test]
+    fn returns_canceled_reservations() {
+        let limiter = Arc::new(limiter(10, 1, 1));
+        let now = Instant::now();
+        assert_eq!(limiter.acquire_at(now).unwrap(), None);
+        assert_eq!(
+            limiter.acquire_at(now).unwrap(),
+            Some(Duration::from_millis(100))
+        );
+        assert!(limiter.acquire_at(now).is_err());
+
+        // A canceled request's token may be reserved by another request.
+        drop(Reservation(Some(limiter.clone())));
+        assert_eq!(
+            limiter.acquire_at(now).unwrap(),
+            Some(Duration::from_millis(100))
+        );
+
+        // A token that was taken is not returned.
+        let mut reservation = Reservation(Some(limiter.clone()));
+        reservation.take();
+        drop(reservation);
+        assert!(limiter.acquire_at(now).is_err());
+    }
+
+    #[tokio::test]
+    async fn dropping_a_queued_request_returns_its_token() {
+        let limiter = Arc::new(limiter(10, 1, 1));
+        let limit = Limit {
+            limiter: Some(limiter.clone()),
+            inner: (),
+        };
+        let mut svc = tower::service_fn(|_: http::Request<()>| {
+            futures::future::ok::<_, Error>(http::Response::new(()))
+        });
+
+        assert!(limit.proxy(&mut svc, http::Request::new(())).await.is_ok());
+        let queued = limit.proxy(&mut svc, http::Request::new(()));
+        assert!(limit.proxy(&mut svc, http::Request::new(())).await.is_err());
+
+        drop(queued);
+        assert!(limiter.acquire().unwrap().is_some());
+    }
+}
@@ -2,23 +2,104 @@ use super::classify;
 use super::dst::Route;
 // use super::handle_time;
 use super::http_metrics::retries::Handle;
-use super::transport::tls;
-use super::HttpRouteRetry;
+use super::transport::{connect::ConnectAddr, listen, tls};
+use super::{svc, Error, HttpRouteRetry};
+use crate::metrics::{latency, Bucket, Histogram};
 use crate::profiles;
-use futures::future;
+use futures::{future, TryFutureExt};
 use hyper::body::HttpBody;
 use linkerd2_http_classify::{Classify, ClassifyEos, ClassifyResponse};
-use linkerd2_retry::NewRetryLayer;
+pub use linkerd2_http_retry::ReplayBody;
+use linkerd2_retry::{NewHedgeLayer, NewRetryLayer};
+use linkerd2_stack::{NewService, Proxy};
+use pin_project::pin_project;
+use std::future::Future;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tower::retry::budget::Budget;
 
-pub fn layer(metrics: HttpRouteRetry) -> NewRetryLayer<NewRetry> {
-    NewRetryLayer::new(NewRetry::new(metrics))
+/// The period over which a route's latencies are observed to determine its
+/// hedging delay.
+const LATENCY_WINDOW: Duration = Duration::from_secs(10);
+
+/// The number of latencies that must be observed before requests are hedged
+/// by percentile.
+const MIN_LATENCY_SAMPLES: u64 = 10;
+
+pub fn layer(metrics: HttpRouteRetry) -> NewRetryLayer<NewRetry<CloneReplayBody>> {
+    NewRetryLayer::new(NewRetry::new(metrics).clone_requests_via())
+}
+
+/// Hedges requests on routes that have a hedging policy.
+///
+/// Must be applied within the retry layer. Hedges only withdraw from the
+/// route's budget once `distinct_endpoint_layer` admits them, so that layer
+/// must be applied to every endpoint stack that serves hedged routes.
+pub fn hedge_layer(metrics: HttpRouteRetry) -> NewHedgeLayer<NewHedge<CloneReplayBody>> {
+    NewHedgeLayer::new(NewHedge {
+        metrics,
+        _clone_request: PhantomData,
+    })
+}
+
+/// Fails hedged requests that would be served by the same endpoint as their
+/// original requests, so that a hedge is only sent to a different endpoint.
+/// Hedges withdraw from their route's budget only once they are admitted, so
+/// rejected hedges do not drain it.
+///
+/// Must be applied to an endpoint stack, below the balancer.
+pub fn distinct_endpoint_layer<M>() -> impl svc::Layer<M, Service = MakeDistinctEndpoint<M>> + Clone
+{
+    svc::layer::mk(|inner| MakeDistinctEndpoint { inner })
+}
+
+/// Buffers up to `max_body_bytes` of each request body on routes that may be
+/// retried so that requests with bodies may be replayed.
+///
+/// Must be applied before (i.e. outside of) the retry layer.
+pub fn replay_layer(max_body_bytes: usize) -> ReplayLayer {
+    ReplayLayer { max_body_bytes }
 }
 
 pub trait CloneRequest<Req> {
     fn clone_request(req: &Req) -> Option<Req>;
+
+    /// Returns true if the request's body was too large to be buffered, so the
+    /// request may not be retried.
+    fn is_body_too_long(_req: &Req) -> bool {
+        false
+    }
+
+    /// Returns true if the request's body has been read completely, so that a
+    /// clone may be sent while the original request is still in flight.
+    fn is_body_complete(_req: &Req) -> bool {
+        true
+    }
+}
+
+/// Clones requests with `ReplayBody` bodies.
+#[derive(Clone, Debug)]
+pub enum CloneReplayBody {}
+
+#[derive(Clone, Debug)]
+pub struct ReplayLayer {
+    max_body_bytes: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewReplay<N> {
+    max_body_bytes: usize,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct Replay<P> {
+    max_body_bytes: usize,
+    inner: P,
 }
 
 #[derive(Clone, Debug)]
@@ -34,6 +115,78 @@ pub struct Retry<C = ()> {
     _clone_request: PhantomData<C>,
 }
 
+#[derive(Clone, Debug)]
+pub struct NewHedge<C> {
+    metrics: HttpRouteRetry,
+    _clone_request: PhantomData<C>,
+}
+
+pub struct Hedge<C> {
+    metrics: Handle,
+    budget: Arc<Budget>,
+    delay: profiles::HedgeDelay,
+    latencies: Arc<Mutex<Latencies>>,
+
+    /// Whether successful requests should be deposited into the budget. This
+    /// is only necessary when the route is not also retried, since the retry
+    /// policy otherwise makes deposits.
+    deposit: bool,
+
+    _clone_request: PhantomData<C>,
+}
+
+/// Identifies the endpoint that serves a hedged request's original request.
+#[derive(Clone, Debug)]
+struct Hedged {
+    primary: Arc<Mutex<Option<SocketAddr>>>,
+
+    /// Set on hedges, which withdraw from the route's budget once they are
+    /// known to be served by a different endpoint.
+    hedge: Option<HedgeBudget>,
+}
+
+#[derive(Clone, Debug)]
+struct HedgeBudget {
+    budget: Arc<Budget>,
+    metrics: Handle,
+}
+
+#[derive(Clone, Debug)]
+pub struct MakeDistinctEndpoint<M> {
+    inner: M,
+}
+
+#[pin_project]
+pub struct MakeDistinctFuture<F> {
+    #[pin]
+    inner: F,
+    addr: SocketAddr,
+}
+
+#[derive(Clone, Debug)]
+pub struct DistinctEndpoint<S> {
+    addr: SocketAddr,
+    inner: S,
+}
+
+/// Indicates that a hedged request was not sent because it would have been
+/// served by the same endpoint as its original request.
+#[derive(Debug)]
+pub struct SameEndpoint(SocketAddr);
+
+/// Indicates that a hedged request was not sent because its route's budget
+/// was exhausted.
+#[derive(Debug)]
+pub struct HedgeBudgetExhausted(());
+
+/// A route's recently observed response latencies.
+#[derive(Debug)]
+struct Latencies {
+    current: Histogram<latency::Ms>,
+    prior: Histogram<latency::Ms>,
+    rotated_at: Instant,
+}
+
 impl NewRetry {
     pub fn new(metrics: super::HttpRouteRetry) -> Self {
         Self {
@@ -91,6 +244,11 @@ where
             return None;
         }
 
+        if C::is_body_too_long(req) {
+            self.metrics.incr_body_too_long();
+            return None;
+        }
+
         let withdrew = self.budget.withdraw().is_ok();
         self.metrics.incr_retryable(withdrew);
         if !withdrew {
@@ -116,27 +274,474 @@ impl<C> Clone for Retry<C> {
     }
 }
 
+// === impl NewHedge ===
+
+impl<C> linkerd2_retry::NewPolicy<Route> for NewHedge<C> {
+    type Policy = Hedge<C>;
+
+    fn new_policy(&self, route: &Route) -> Option<Self::Policy> {
+        let hedge = route.route.hedge()?;
+
+        let metrics = self.metrics.get_handle(route.clone());
+        Some(Hedge {
+            metrics,
+            budget: hedge.budget().clone(),
+            delay: hedge.delay(),
+            latencies: Arc::new(Mutex::new(Latencies::default())),
+            deposit: route.route.retries().is_none(),
+            _clone_request: self._clone_request,
+        })
+    }
+}
+
+// === impl Hedge ===
+
+impl<C, A> linkerd2_retry::HedgePolicy<http::Request<A>> for Hedge<C>
+where
+    C: CloneRequest<http::Request<A>>,
+{
+    fn delay(&self) -> Option<Duration> {
+        match self.delay {
+            profiles::HedgeDelay::Fixed(delay) => Some(delay),
+            profiles::HedgeDelay::Percentile(p) => self.latencies.lock().ok()?.percentile(p),
+        }
+    }
+
+    fn clone_request(&self, req: &mut http::Request<A>) -> Option<http::Request<A>> {
+        let mut clone = C::clone_request(req)?;
+
+        // Ensures that the hedge is not sent to the endpoint that serves the
+        // original request.
+        let primary = Arc::new(Mutex::new(None));
+        req.extensions_mut().insert(Hedged {
+            primary: primary.clone(),
+            hedge: None,
+        });
+        clone.extensions_mut().insert(Hedged {
+            primary,
+            hedge: Some(HedgeBudget {
+                budget: self.budget.clone(),
+                metrics: self.metrics.clone(),
+            }),
+        });
+
+        Some(clone)
+    }
+
+    fn can_hedge(&self, req: &http::Request<A>) -> bool {
+        // The original request's body must not be interrupted by its hedge.
+        //
+        // The budget is not withdrawn from until the hedge's endpoint is
+        // known, since hedges that would be served by the original request's
+        // endpoint are not sent.
+        C::is_body_complete(req)
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        if self.deposit {
+            self.budget.deposit();
+        }
+        if let Ok(mut latencies) = self.latencies.lock() {
+            latencies.add(latency);
+        }
+    }
+
+    fn record_hedge_won(&self) {
+        self.metrics.incr_hedge_won();
+    }
+}
+
+impl<C> Clone for Hedge<C> {
+    fn clone(&self) -> Self {
+        Self {
+            metrics: self.metrics.clone(),
+            budget: self.budget.clone(),
+            delay: self.delay,
+            latencies: self.latencies.clone(),
+            deposit: self.deposit,
+            _clone_request: self._clone_request,
+        }
+    }
+}
+
+// === impl Hedged ===
+
+impl Hedged {
+    /// Records the endpoint that serves an original request, or fails a hedge
+    /// that would be served by the same endpoint.
+    ///
+    /// Hedges that are served by a different endpoint withdraw from the
+    /// route's budget, and fail if it is exhausted.
+    fn check(&self, addr: SocketAddr) -> Result<(), Error> {
+        let hedge = match self.hedge.as_ref() {
+            Some(hedge) => hedge,
+            None => {
+                if let Ok(mut primary) = self.primary.lock() {
+                    *primary = Some(addr);
+                }
+                return Ok(());
+            }
+        };
+
+        if let Ok(primary) = self.primary.lock() {
+            if *primary == Some(addr) {
+                return Err(SameEndpoint(addr).into());
+            }
+        }
+
+        let withdrew = hedge.budget.withdraw().is_ok();
+        hedge.metrics.incr_hedgeable(withdrew);
+        if !withdrew {
+            return Err(HedgeBudgetExhausted(()).into());
+        }
+        Ok(())
+    }
+}
+
+// === impl MakeDistinctEndpoint ===
+
+impl<T, M> tower::Service<T> for MakeDistinctEndpoint<M>
+where
+    T: ConnectAddr,
+    M: tower::Service<T>,
+{
+    type Response = DistinctEndpoint<M::Response>;
+    type Error = M::Error;
+    type Future = MakeDistinctFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        MakeDistinctFuture {
+            addr: target.connect_addr(),
+            inner: self.inner.call(target),
+        }
+    }
+}
+
+impl<F, S, E> Future for MakeDistinctFuture<F>
+where
+    F: Future<Output = Result<S, E>>,
+{
+    type Output = Result<DistinctEndpoint<S>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = futures::ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(DistinctEndpoint {
+            addr: *this.addr,
+            inner,
+        }))
+    }
+}
+
+// === impl DistinctEndpoint ===
+
+impl<B, S> tower::Service<http::Request<B>> for DistinctEndpoint<S>
+where
+    S: tower::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::Ready<Result<S::Response, Error>>,
+        future::ErrInto<S::Future, Error>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(hedged) = req.extensions_mut().remove::<Hedged>() {
+            if let Err(e) = hedged.check(self.addr) {
+                return future::Either::Left(future::err(e));
+            }
+        }
+
+        future::Either::Right(self.inner.call(req).err_into())
+    }
+}
+
+// === impl SameEndpoint ===
+
+impl std::fmt::Display for SameEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "hedged request not sent to {}, which serves the original request",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for SameEndpoint {}
+
+// === impl HedgeBudgetExhausted ===
+
+impl std::fmt::Display for HedgeBudgetExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "hedged request not sent because the route's budget is exhausted"
+        )
+    }
+}
+
+impl std::error::Error for HedgeBudgetExhausted {}
+
+// === impl Latencies ===
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Self {
+            current: Histogram::default(),
+            prior: Histogram::default(),
+            rotated_at: Instant::now(),
+        }
+    }
+}
+
+impl Latencies {
+    fn add(&mut self, latency: Duration) {
+        self.rotate();
+        self.current.add(latency);
+    }
+
+    /// Estimates the given percentile of recent latencies as the upper bound
+    /// of the bucket that contains it.
+    fn percentile(&mut self, p: f64) -> Option<Duration> {
+        self.rotate();
+
+        let counts = self
+            .prior
+            .into_iter()
+            .zip(&self.current)
+            .map(|((bucket, prior), (_, current))| (*bucket, prior.value() + current.value()))
+            .collect::<Vec<_>>();
+        let total = counts.iter().map(|(_, n)| n).sum::<u64>();
+        if total < MIN_LATENCY_SAMPLES {
+            return None;
+        }
+
+        let rank = (total as f64 * p).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, n) in counts {
+            seen += n;
+            if seen >= rank {
+                return match bucket {
+                    Bucket::Le(ms) => Some(Duration::from_millis(ms)),
+                    Bucket::Inf => None,
+                };
+            }
+        }
+        None
+    }
+
+    /// Discards latencies observed more than a window ago.
+    fn rotate(&mut self) {
+        let elapsed = self.rotated_at.elapsed();
+        if elapsed < LATENCY_WINDOW {
+            return;
+        }
+
+        let current = std::mem::replace(&mut self.current, Histogram::default());
+        self.prior = if elapsed < LATENCY_WINDOW * 2 {
+            current
+        } else {
+            Histogram::default()
+        };
+        self.rotated_at = Instant::now();
+    }
+}
+
 impl<B: Default + HttpBody> CloneRequest<http::Request<B>> for () {
     fn clone_request(req: &http::Request<B>) -> Option<http::Request<B>> {
         if !req.body().is_end_stream() {
             return None;
         }
 
-        let mut clone = http::Request::new(B::default());
-        *clone.method_mut() = req.method().clone();
-        *clone.uri_mut() = req.uri().clone();
-        *clone.headers_mut() = req.headers().clone();
-        *clone.version_mut() = req.version();
+        Some(clone_parts(req, B::default()))
+    }
+}
+
+impl<B: HttpBody> CloneRequest<http::Request<ReplayBody<B>>> for CloneReplayBody {
+    fn clone_request(req: &http::Request<ReplayBody<B>>) -> Option<http::Request<ReplayBody<B>>> {
+        let body = req.body().try_clone()?;
+        Some(clone_parts(req, body))
+    }
+
+    fn is_body_too_long(req: &http::Request<ReplayBody<B>>) -> bool {
+        req.body().is_capped()
+    }
+
+    fn is_body_complete(req: &http::Request<ReplayBody<B>>) -> bool {
+        req.body().is_complete()
+    }
+}
+
+fn clone_parts<A, B>(req: &http::Request<A>, body: B) -> http::Request<B> {
+    let mut clone = http::Request::new(body);
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.headers_mut() = req.headers().clone();
+    *clone.version_mut() = req.version();
+
+    if let Some(ext) = req.extensions().get::<tls::accept::Meta>() {
+        clone.extensions_mut().insert(ext.clone());
+    }
+
+    // Preserves the client's address so that retries are balanced by it.
+    if let Some(ext) = req.extensions().get::<listen::Addrs>() {
+        clone.extensions_mut().insert(ext.clone());
+    }
+
+    // // Count retries toward the request's total handle time.
+    // if let Some(ext) = req.extensions().get::<handle_time::Tracker>() {
+    //     clone.extensions_mut().insert(ext.clone());
+    // }
 
-        if let Some(ext) = req.extensions().get::<tls::accept::Meta>() {
-            clone.extensions_mut().insert(ext.clone());
+    clone
+}
+
+// === impl ReplayLayer ===
+
+impl<N> tower::layer::Layer<N> for ReplayLayer {
+    type Service = NewReplay<N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewReplay {
+            max_body_bytes: self.max_body_bytes,
+            inner,
         }
+    }
+}
 
-        // // Count retries toward the request's total handle time.
-        // if let Some(ext) = req.extensions().get::<handle_time::Tracker>() {
-        //     clone.extensions_mut().insert(ext.clone());
-        // }
+// === impl NewReplay ===
 
-        Some(clone)
+impl<N> NewService<Route> for NewReplay<N>
+where
+    N: NewService<Route>,
+{
+    type Service = Replay<N::Service>;
+
+    fn new_service(&self, route: Route) -> Self::Service {
+        // Bodies are only buffered on routes that may be retried or hedged.
+        let max_body_bytes = if route.route.retries().is_some() || route.route.hedge().is_some() {
+            self.max_body_bytes
+        } else {
+            0
+        };
+        let inner = self.inner.new_service(route);
+        Replay {
+            max_body_bytes,
+            inner,
+        }
+    }
+}
+
+// === impl Replay ===
+
+impl<P, S, B> Proxy<http::Request<B>, S> for Replay<P>
+where
+    B: HttpBody,
+    P: Proxy<http::Request<ReplayBody<B>>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        let max_body_bytes = self.max_body_bytes;
+        self.inner
+            .proxy(svc, req.map(|body| ReplayBody::new(body, max_body_bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_percentiles() {
+        let mut latencies = Latencies::default();
+        for _ in 0..(MIN_LATENCY_SAMPLES - 1) {
+            latencies.add(Duration::from_millis(3));
+        }
+        assert_eq!(latencies.percentile(0.5), None, "too few samples");
+
+        for _ in 0..89 {
+            latencies.add(Duration::from_millis(3));
+        }
+        for _ in 0..2 {
+            latencies.add(Duration::from_millis(150));
+        }
+        assert_eq!(latencies.percentile(0.5), Some(Duration::from_millis(3)));
+        assert_eq!(latencies.percentile(0.98), Some(Duration::from_millis(3)));
+        assert_eq!(latencies.percentile(0.99), Some(Duration::from_millis(200)));
+    }
+
+    /// Builds an original request's `Hedged` extension and its hedge's,
+    /// which may withdraw from `budget`.
+    fn hedged(budget: Budget) -> (Hedged, Hedged) {
+        let primary = Arc::new(Mutex::new(None));
+        let orig = Hedged {
+            primary: primary.clone(),
+            hedge: None,
+        };
+        let hedge = Hedged {
+            primary,
+            hedge: Some(HedgeBudget {
+                budget: Arc::new(budget),
+                metrics: crate::http_metrics::Retries::<()>::default().get_handle(()),
+            }),
+        };
+        (orig, hedge)
+    }
+
+    #[test]
+    fn hedges_avoid_the_primary_endpoint() {
+        let (orig, hedge) = hedged(Budget::new(Duration::from_secs(10), 10, 0.2));
+
+        let a = SocketAddr::from(([10, 0, 0, 1], 8080));
+        let b = SocketAddr::from(([10, 0, 0, 2], 8080));
+        assert!(hedge.check(a).is_ok(), "the primary has not been sent");
+        orig.check(a).expect("the primary must be sent");
+        assert!(hedge.check(a).unwrap_err().is::<SameEndpoint>());
+        assert!(hedge.check(b).is_ok());
+    }
+
+    #[test]
+    fn rejected_hedges_do_not_withdraw_from_the_budget() {
+        // The budget permits exactly one withdrawal.
+        let (orig, hedge) = hedged(Budget::new(Duration::from_secs(1), 1, 0.0));
+
+        let a = SocketAddr::from(([10, 0, 0, 1], 8080));
+        let b = SocketAddr::from(([10, 0, 0, 2], 8080));
+        orig.check(a).expect("the primary must be sent");
+        for _ in 0..3 {
+            assert!(hedge.check(a).unwrap_err().is::<SameEndpoint>());
+        }
+
+        hedge.check(b).expect("the budget must not be exhausted");
+        assert!(hedge.check(b).unwrap_err().is::<HedgeBudgetExhausted>());
+    }
+
+    #[test]
+    fn clones_preserve_client_addrs() {
+        let local = SocketAddr::from(([127, 0, 0, 1], 4140));
+        let peer = SocketAddr::from(([10, 0, 0, 1], 40000));
+        let mut req = http::Request::new(());
+        req.extensions_mut()
+            .insert(listen::Addrs::new(local, peer, None));
+
+        let clone = clone_parts(&req, ());
+        let addrs = clone.extensions().get::<listen::Addrs>();
+        assert_eq!(addrs.map(|a| a.peer()), Some(peer));
     }
 }
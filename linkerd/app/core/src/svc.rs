@@ -1,7 +1,7 @@
 // Possibly unused, but useful during development.
 
 pub use crate::proxy::http;
-use crate::transport::Connect;
+use crate::transport::{Connect, ConnectUnix};
 use crate::{cache, Error};
 pub use linkerd2_buffer as buffer;
 use linkerd2_concurrency_limit as concurrency_limit;
@@ -35,6 +35,10 @@ pub fn connect(keepalive: Option<Duration>) -> Stack<Connect> {
     Stack(Connect::new(keepalive))
 }
 
+pub fn connect_unix(keepalive: Option<Duration>) -> Stack<ConnectUnix> {
+    Stack(ConnectUnix::new(keepalive))
+}
+
 pub fn proxies() -> Stack<IdentityProxy> {
     Stack(IdentityProxy(()))
 }
@@ -42,6 +46,17 @@ pub fn proxies() -> Stack<IdentityProxy> {
 #[derive(Copy, Clone, Debug)]
 pub struct IdentityProxy(());
 
+/// Limits the number of in-flight requests, either to a fixed maximum or to a
+/// limit that adapts to the inner service's latency and failures.
+///
+/// Response bodies are boxed, since the adaptive limit holds its permits until
+/// response streams complete.
+#[derive(Clone, Debug)]
+pub enum ConcurrencyLimitLayer<C> {
+    Fixed(concurrency_limit::Layer),
+    Adaptive(concurrency_limit::adaptive::Layer<C>),
+}
+
 impl<T> NewService<T> for IdentityProxy {
     type Service = ();
     fn new_service(&self, _: T) -> Self::Service {
@@ -142,8 +157,14 @@ impl<L> Layers<L> {
         self.push(SpawnReadyLayer::new())
     }
 
-    pub fn push_concurrency_limit(self, max: usize) -> Layers<Pair<L, concurrency_limit::Layer>> {
-        self.push(concurrency_limit::Layer::new(max))
+    /// Limits the number of in-flight requests to `max`, or to an adaptive
+    /// limit if one is provided.
+    pub fn push_concurrency_limit<C>(
+        self,
+        max: usize,
+        adaptive: Option<concurrency_limit::adaptive::Layer<C>>,
+    ) -> Layers<Pair<L, ConcurrencyLimitLayer<C>>> {
+        self.push(ConcurrencyLimitLayer::new(max, adaptive))
     }
 
     pub fn push_make_ready<Req>(self) -> Layers<Pair<L, stack::MakeReadyLayer<Req>>> {
@@ -237,11 +258,17 @@ impl<S> Stack<S> {
         self.push(SpawnReadyLayer::new())
     }
 
-    pub fn push_concurrency_limit(
+    pub fn push_concurrency_limit<C: Clone>(
         self,
         max: usize,
-    ) -> Stack<concurrency_limit::ConcurrencyLimit<S>> {
-        self.push(concurrency_limit::Layer::new(max))
+        adaptive: Option<concurrency_limit::adaptive::Layer<C>>,
+    ) -> Stack<
+        Either<
+            http::boxed::BoxResponse<concurrency_limit::ConcurrencyLimit<S>>,
+            http::boxed::BoxResponse<concurrency_limit::adaptive::AdaptiveLimit<S, C>>,
+        >,
+    > {
+        self.push(ConcurrencyLimitLayer::new(max, adaptive))
     }
 
     pub fn push_timeout(self, timeout: Duration) -> Stack<tower::timeout::Timeout<S>> {
@@ -438,6 +465,30 @@ where
     }
 }
 
+impl<C> ConcurrencyLimitLayer<C> {
+    pub fn new(max: usize, adaptive: Option<concurrency_limit::adaptive::Layer<C>>) -> Self {
+        match adaptive {
+            Some(layer) => ConcurrencyLimitLayer::Adaptive(layer),
+            None => ConcurrencyLimitLayer::Fixed(concurrency_limit::Layer::new(max)),
+        }
+    }
+}
+
+impl<S, C: Clone> Layer<S> for ConcurrencyLimitLayer<C> {
+    type Service = Either<
+        http::boxed::BoxResponse<concurrency_limit::ConcurrencyLimit<S>>,
+        http::boxed::BoxResponse<concurrency_limit::adaptive::AdaptiveLimit<S, C>>,
+    >;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let boxed = http::boxed::response::Layer::new();
+        match self {
+            ConcurrencyLimitLayer::Fixed(layer) => Either::A(boxed.layer(layer.layer(inner))),
+            ConcurrencyLimitLayer::Adaptive(layer) => Either::B(boxed.layer(layer.layer(inner))),
+        }
+    }
+}
+
 pub mod make_response {
     use super::Oneshot;
     use crate::Error;
@@ -1,4 +1,5 @@
 use super::tls;
+use crate::proxy::identity;
 use linkerd2_conditional::Conditional;
 use linkerd2_metrics::FmtLabels;
 use std::fmt;
@@ -8,11 +9,13 @@ use std::fmt;
 /// A `Metrics` type exists for each unique `Key`.
 ///
 /// Implements `FmtLabels`.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Key {
     direction: Direction,
     peer: Peer,
     tls_status: TlsStatus,
+    unix: bool,
+    server_name: Option<identity::Name>,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -37,6 +40,8 @@ impl Key {
             direction: Direction(direction),
             tls_status: TlsStatus(tls.map(|_| ())),
             peer: Peer::Src,
+            unix: false,
+            server_name: None,
         }
     }
 
@@ -45,13 +50,42 @@ impl Key {
             direction: Direction(direction),
             tls_status: TlsStatus(tls.map(|_| ())),
             peer: Peer::Dst,
+            unix: false,
+            server_name: None,
+        }
+    }
+
+    /// Describes connections to a Unix domain socket.
+    pub fn connect_unix(direction: &'static str) -> Self {
+        Self {
+            unix: true,
+            ..Self::connect::<()>(
+                direction,
+                tls::Conditional::None(tls::ReasonForNoPeerName::Loopback),
+            )
+        }
+    }
+
+    /// Describes connections whose server name was read from the client's
+    /// TLS ClientHello.
+    pub fn with_server_name(self, server_name: Option<identity::Name>) -> Self {
+        Self {
+            server_name,
+            ..self
         }
     }
 }
 
 impl FmtLabels for Key {
     fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        ((self.direction, self.peer), self.tls_status).fmt_labels(f)
+        ((self.direction, self.peer), self.tls_status).fmt_labels(f)?;
+        if self.unix {
+            write!(f, ",transport=\"unix\"")?;
+        }
+        if let Some(name) = self.server_name.as_ref() {
+            write!(f, ",server_name=\"{}\"", name)?;
+        }
+        Ok(())
     }
 }
 
@@ -9,6 +9,7 @@ use linkerd2_app_core::{
 };
 use std::fmt;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::debug;
 
@@ -27,11 +28,19 @@ pub struct Profile(Addr);
 pub struct HttpEndpoint {
     pub port: u16,
     pub settings: http::Settings,
+    /// Set when the application accepts the port's connections on a Unix
+    /// domain socket.
+    pub unix_socket: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TcpEndpoint {
     pub port: u16,
+    /// Set when the application accepts the port's connections on a Unix
+    /// domain socket.
+    pub unix_socket: Option<PathBuf>,
+    /// The logical target named by a tunneled stream's client, if any.
+    pub logical: Option<Addr>,
 }
 
 #[derive(Clone, Debug)]
@@ -44,12 +53,25 @@ pub struct ProfileTarget;
 
 // === impl HttpEndpoint ===
 
+impl HttpEndpoint {
+    pub fn with_unix_socket(mut self, sockets: &IndexMap<u16, PathBuf>) -> Self {
+        self.unix_socket = sockets.get(&self.port).cloned();
+        self
+    }
+}
+
 impl connect::ConnectAddr for HttpEndpoint {
     fn connect_addr(&self) -> SocketAddr {
         ([127, 0, 0, 1], self.port).into()
     }
 }
 
+impl connect::ConnectPath for HttpEndpoint {
+    fn connect_path(&self) -> Option<&Path> {
+        self.unix_socket.as_deref()
+    }
+}
+
 impl http::settings::HasSettings for HttpEndpoint {
     fn http_settings(&self) -> http::Settings {
         self.settings
@@ -61,6 +83,7 @@ impl From<Target> for HttpEndpoint {
         Self {
             port: target.addr.port(),
             settings: target.http_settings,
+            unix_socket: None,
         }
     }
 }
@@ -73,10 +96,19 @@ impl tls::HasPeerIdentity for HttpEndpoint {
 
 // === TcpEndpoint ===
 
+impl TcpEndpoint {
+    pub fn with_unix_socket(mut self, sockets: &IndexMap<u16, PathBuf>) -> Self {
+        self.unix_socket = sockets.get(&self.port).cloned();
+        self
+    }
+}
+
 impl From<listen::Addrs> for TcpEndpoint {
     fn from(addrs: listen::Addrs) -> Self {
         Self {
             port: addrs.target_addr().port(),
+            unix_socket: None,
+            logical: None,
         }
     }
 }
@@ -85,6 +117,17 @@ impl From<tls::accept::Meta> for TcpEndpoint {
     fn from(meta: tls::accept::Meta) -> Self {
         Self {
             port: meta.addrs.target_addr().port(),
+            unix_socket: None,
+            logical: None,
+        }
+    }
+}
+
+impl From<crate::tunnel::Accept> for TcpEndpoint {
+    fn from(accept: crate::tunnel::Accept) -> Self {
+        Self {
+            logical: Some(accept.logical),
+            ..Self::from(accept.meta)
         }
     }
 }
@@ -95,6 +138,12 @@ impl connect::ConnectAddr for TcpEndpoint {
     }
 }
 
+impl connect::ConnectPath for TcpEndpoint {
+    fn connect_path(&self) -> Option<&Path> {
+        self.unix_socket.as_deref()
+    }
+}
+
 impl tls::HasPeerIdentity for TcpEndpoint {
     fn peer_identity(&self) -> tls::PeerIdentity {
         Conditional::None(tls::ReasonForNoPeerName::Loopback.into())
@@ -0,0 +1,267 @@
+use linkerd2_app_core::{
+    proxy::http::{
+        self,
+        header::{HeaderName, HeaderValue, FORWARDED},
+        strip_header,
+    },
+    svc::{self, Layer as _, NewService},
+    transport::tls,
+};
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Informs the application of the client's address and identity, since
+/// connections from the proxy are always from the loopback interface.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The header that carries the client's verified identity.
+    pub client_id_header: HeaderName,
+}
+
+#[derive(Clone, Debug)]
+pub struct Layer(Option<Config>);
+
+#[derive(Clone, Debug)]
+pub struct NewForwarded<N> {
+    config: Option<Config>,
+    inner: N,
+}
+
+/// Appends the client's address to each request's `Forwarded` and
+/// `X-Forwarded-For` headers and sets its identity header.
+#[derive(Clone, Debug)]
+pub struct Forwarded<S> {
+    inner: S,
+    headers: Option<Arc<Headers>>,
+}
+
+type Strip<S> = strip_header::Service<HeaderName, S, strip_header::request::ReqHeader>;
+
+#[derive(Debug)]
+struct Headers {
+    forwarded: HeaderValue,
+    x_forwarded_for: HeaderValue,
+    client_id: Option<(HeaderName, HeaderValue)>,
+}
+
+fn x_forwarded_for() -> HeaderName {
+    HeaderName::from_static("x-forwarded-for")
+}
+
+// === impl Layer ===
+
+/// Builds a layer that sets forwarding headers when `config` is set.
+pub fn layer(config: Option<Config>) -> Layer {
+    Layer(config)
+}
+
+impl<N> svc::Layer<N> for Layer {
+    type Service = NewForwarded<N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewForwarded {
+            config: self.0.clone(),
+            inner,
+        }
+    }
+}
+
+// === impl NewForwarded ===
+
+impl<N> NewService<tls::accept::Meta> for NewForwarded<N>
+where
+    N: NewService<tls::accept::Meta>,
+{
+    type Service = svc::Either<Strip<Strip<Strip<Forwarded<N::Service>>>>, Forwarded<N::Service>>;
+
+    fn new_service(&self, meta: tls::accept::Meta) -> Self::Service {
+        let config = match self.config.as_ref() {
+            Some(config) => config,
+            None => {
+                let inner = self.inner.new_service(meta);
+                return svc::Either::B(Forwarded {
+                    inner,
+                    headers: None,
+                });
+            }
+        };
+
+        let client_id = meta.peer_identity.value().and_then(|id| {
+            let value = HeaderValue::from_str(id.as_ref()).ok()?;
+            Some((config.client_id_header.clone(), value))
+        });
+        let authenticated = client_id.is_some();
+        let headers = Headers {
+            forwarded: forwarded_for(meta.addrs.peer().ip()),
+            x_forwarded_for: HeaderValue::from_str(&meta.addrs.peer().ip().to_string())
+                .expect("IP addresses must be valid header values"),
+            client_id,
+        };
+
+        let inner = Forwarded {
+            inner: self.inner.new_service(meta),
+            headers: Some(Arc::new(headers)),
+        };
+        if authenticated {
+            return svc::Either::B(inner);
+        }
+
+        // Unauthenticated clients may not set forwarding headers, as they
+        // can't be distinguished from spoofed values.
+        let strip = svc::layers()
+            .push(strip_header::request::layer(
+                config.client_id_header.clone(),
+            ))
+            .push(strip_header::request::layer(x_forwarded_for()))
+            .push(strip_header::request::layer(FORWARDED));
+        svc::Either::A(strip.layer(inner))
+    }
+}
+
+fn forwarded_for(ip: IpAddr) -> HeaderValue {
+    // IPv6 addresses must be quoted, since they contain colons.
+    let value = match ip {
+        IpAddr::V4(ip) => format!("for={}", ip),
+        IpAddr::V6(ip) => format!("for=\"[{}]\"", ip),
+    };
+    HeaderValue::from_str(&value).expect("forwarded header must be valid")
+}
+
+// === impl Forwarded ===
+
+impl<S, B> tower::Service<http::Request<B>> for Forwarded<S>
+where
+    S: tower::Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(headers) = self.headers.as_ref() {
+            let h = req.headers_mut();
+            h.append(FORWARDED, headers.forwarded.clone());
+            h.append(x_forwarded_for(), headers.x_forwarded_for.clone());
+            if let Some((name, value)) = headers.client_id.as_ref() {
+                h.insert(name.clone(), value.clone());
+            }
+        }
+
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use linkerd2_app_core::{proxy::identity, transport::listen, Conditional, Error};
+    use std::net::SocketAddr;
+    use tower::{layer::Layer as _, util::ServiceExt};
+
+    fn config() -> Config {
+        Config {
+            client_id_header: HeaderName::from_static("l5d-client-id"),
+        }
+    }
+
+    fn meta(peer_identity: tls::PeerIdentity) -> tls::accept::Meta {
+        let addr = SocketAddr::from(([10, 1, 2, 3], 8080));
+        tls::accept::Meta {
+            peer_identity,
+            addrs: listen::Addrs::new(addr, SocketAddr::from(([10, 4, 5, 6], 40000)), Some(addr)),
+        }
+    }
+
+    fn meshed() -> tls::PeerIdentity {
+        let id = b"foo.ns.serviceaccount.identity.linkerd.cluster.local";
+        Conditional::Some(identity::Name::from_hostname(id).unwrap())
+    }
+
+    fn unmeshed() -> tls::PeerIdentity {
+        Conditional::None(tls::ReasonForNoPeerName::NoPeerIdFromRemote.into())
+    }
+
+    /// Sends a request that already carries forwarding headers, returning the
+    /// headers seen by the application.
+    async fn forward(config: Option<Config>, meta: tls::accept::Meta) -> http::header::HeaderMap {
+        let inner = |_: tls::accept::Meta| {
+            tower::service_fn(|req: http::Request<()>| {
+                future::ok::<_, Error>(req.headers().clone())
+            })
+        };
+        let req = http::Request::builder()
+            .header(FORWARDED, "for=192.0.2.1")
+            .header("x-forwarded-for", "192.0.2.1")
+            .header(
+                "l5d-client-id",
+                "spoofed.ns.serviceaccount.identity.linkerd.cluster.local",
+            )
+            .body(())
+            .unwrap();
+        layer(config)
+            .layer(inner)
+            .new_service(meta)
+            .oneshot(req)
+            .await
+            .unwrap()
+    }
+
+    fn values<'h>(headers: &'h http::header::HeaderMap, name: &str) -> Vec<&'h str> {
+        headers
+            .get_all(name)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn appends_to_headers_from_authenticated_clients() {
+        let headers = forward(Some(config()), meta(meshed())).await;
+        assert_eq!(
+            values(&headers, "forwarded"),
+            vec!["for=192.0.2.1", "for=10.4.5.6"]
+        );
+        assert_eq!(
+            values(&headers, "x-forwarded-for"),
+            vec!["192.0.2.1", "10.4.5.6"]
+        );
+        assert_eq!(
+            values(&headers, "l5d-client-id"),
+            vec!["foo.ns.serviceaccount.identity.linkerd.cluster.local"]
+        );
+    }
+
+    #[tokio::test]
+    async fn strips_headers_from_unauthenticated_clients() {
+        let headers = forward(Some(config()), meta(unmeshed())).await;
+        assert_eq!(values(&headers, "forwarded"), vec!["for=10.4.5.6"]);
+        assert_eq!(values(&headers, "x-forwarded-for"), vec!["10.4.5.6"]);
+        assert!(values(&headers, "l5d-client-id").is_empty());
+    }
+
+    #[tokio::test]
+    async fn leaves_headers_unmodified_when_disabled() {
+        let headers = forward(None, meta(unmeshed())).await;
+        assert_eq!(values(&headers, "forwarded"), vec!["for=192.0.2.1"]);
+        assert_eq!(values(&headers, "x-forwarded-for"), vec!["192.0.2.1"]);
+        assert_eq!(
+            values(&headers, "l5d-client-id"),
+            vec!["spoofed.ns.serviceaccount.identity.linkerd.cluster.local"]
+        );
+    }
+
+    #[test]
+    fn quotes_ipv6_addresses() {
+        assert_eq!(forwarded_for([10, 4, 5, 6].into()), "for=10.4.5.6");
+        assert_eq!(
+            forwarded_for("2001:db8::1".parse().unwrap()),
+            "for=\"[2001:db8::1]\""
+        );
+    }
+}
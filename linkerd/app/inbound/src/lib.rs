@@ -11,8 +11,9 @@ pub use self::endpoint::{
 use self::prevent_loop::PreventLoop;
 use self::require_identity_for_ports::RequireIdentityForPorts;
 use futures::{future, prelude::*};
+use indexmap::IndexMap;
 use linkerd2_app_core::{
-    admit, classify,
+    admit, authz, classify,
     config::{ProxyConfig, ServerConfig},
     drain, dst, errors, metric_labels,
     opencensus::proto::trace::v1 as oc,
@@ -21,24 +22,40 @@ use linkerd2_app_core::{
         http::{self, normalize_uri, orig_proto, strip_header, DetectHttp},
         identity, tap, tcp, SkipDetect,
     },
-    reconnect, router, serve,
+    proxy_protocol, reconnect, router, serve,
     spans::SpanConverter,
     svc::{self, NewService},
     transport::{self, io::BoxedIo, listen, tls},
     Error, ProxyMetrics, TraceContextLayer, DST_OVERRIDE_HEADER,
 };
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use tracing::{info, info_span};
 
 pub mod endpoint;
+pub mod forwarded;
 mod prevent_loop;
 mod require_identity_for_ports;
+mod tunnel;
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub proxy: ProxyConfig,
     pub require_identity_for_inbound_ports: RequireIdentityForPorts,
+    pub authorization: authz::Config,
+    /// Unix domain sockets on which the application accepts connections for
+    /// each port, instead of on the loopback interface.
+    pub unix_sockets: IndexMap<u16, PathBuf>,
+    /// Ports on which connections from load balancers begin with a PROXY
+    /// protocol header that describes the client's address.
+    pub proxy_protocol: proxy_protocol::Config,
+    /// Informs the application of each request's client, if set.
+    pub forwarded: Option<forwarded::Config>,
+    /// Terminates HTTP/2 `CONNECT` tunnels from meshed clients, forwarding
+    /// their streams to the application.
+    pub tcp_tunnel: bool,
 }
 
 impl Config {
@@ -112,7 +129,7 @@ impl Config {
            + Send {
         // Establishes connections to remote peers (for both TCP
         // forwarding and HTTP proxying).
-        svc::connect(self.proxy.connect.keepalive)
+        svc::connect_unix(self.proxy.connect.keepalive)
             .push_map_response(BoxedIo::new) // Ensures the transport propagates shutdown properly.
             // Limits the time we wait for a connection to be established.
             .push_timeout(self.proxy.connect.timeout)
@@ -172,8 +189,10 @@ impl Config {
                     dispatch_timeout,
                     ..
                 },
+            unix_sockets,
             ..
         } = self.clone();
+        let unix_sockets = Arc::new(unix_sockets);
 
         let prevent_loop = prevent_loop.into();
 
@@ -210,7 +229,7 @@ impl Config {
 
         // An HTTP client is created for each target via the endpoint stack.
         let http_target_cache = http_endpoint
-            .push_map_target(HttpEndpoint::from)
+            .push_map_target(move |t: Target| HttpEndpoint::from(t).with_unix_socket(&unix_sockets))
             // Normalizes the URI, i.e. if it was originally in
             // absolute-form on the outbound side.
             .push(normalize_uri::layer())
@@ -315,19 +334,31 @@ impl Config {
         let ProxyConfig {
             server: ServerConfig { h2_settings, .. },
             disable_protocol_detection_for_ports: skip_detect,
+            port_protocols,
             dispatch_timeout,
             max_in_flight_requests,
+            adaptive_concurrency_limit,
             detect_protocol_timeout,
             ..
         } = self.proxy;
         let require_identity = self.require_identity_for_inbound_ports;
+        let authorize = authz::Authorize::new(self.authorization, metrics.authz.clone());
+        let unix_sockets = Arc::new(self.unix_sockets);
+
+        let adaptive_concurrency_limit = adaptive_concurrency_limit.map(|config| {
+            metrics.concurrency_limit.layer(
+                metric_labels::Direction::In,
+                config,
+                classify::Request::Default,
+            )
+        });
 
         // Handles requests as they are initially received by the proxy.
         let http_admit_request = svc::layers()
             // Downgrades the protocol if upgraded by an outbound proxy.
             .push(svc::layer::mk(orig_proto::Downgrade::new))
             // Limits the number of in-flight requests.
-            .push_concurrency_limit(max_in_flight_requests)
+            .push_concurrency_limit(max_in_flight_requests, adaptive_concurrency_limit)
             // Eagerly fail requests when the proxy is out of capacity for a
             // dispatch_timeout.
             .push_failfast(dispatch_timeout)
@@ -343,6 +374,24 @@ impl Config {
             // .push(metrics.http_handle_time.layer())
             ;
 
+        // Forwards TCP streams that cannot be decoded as HTTP.
+        let tcp_forward = svc::stack(tcp::Forward::new(tcp_connect))
+            .push(admit::AdmitLayer::new(prevent_loop.into()));
+        let tcp_forward_meta = tcp_forward.clone().push_map_target({
+            let unix_sockets = unix_sockets.clone();
+            move |meta: tls::accept::Meta| TcpEndpoint::from(meta).with_unix_socket(&unix_sockets)
+        });
+        let tunnel_forward = if self.tcp_tunnel {
+            Some(tcp_forward.clone().push_map_target({
+                let unix_sockets = unix_sockets.clone();
+                move |accept: tunnel::Accept| {
+                    TcpEndpoint::from(accept).with_unix_socket(&unix_sockets)
+                }
+            }))
+        } else {
+            None
+        };
+
         let http_server = svc::stack(http_router)
             // Ensures that the built service is ready before it is returned
             // to the router to dispatch a request.
@@ -360,6 +409,13 @@ impl Config {
             // Used by tap.
             .push_http_insert_target()
             .check_new_service::<tls::accept::Meta>()
+            // Sets headers that describe the client's address and identity.
+            .push(forwarded::layer(self.forwarded))
+            // Forwards the streams of tunnels opened by meshed clients.
+            .push(tunnel::layer(tunnel_forward))
+            // Denies requests that are not authorized by the port's path
+            // policies.
+            .push(authorize.http_layer())
             .push_on_response(
                 svc::layers()
                     .push(http_admit_request)
@@ -381,30 +437,41 @@ impl Config {
         // The stack is served lazily since some layers (notably buffer) spawn
         // tasks from their constructor. This helps to ensure that tasks are
         // spawned on the same runtime as the proxy.
-        // Forwards TCP streams that cannot be decoded as HTTP.
-        let tcp_forward = svc::stack(tcp::Forward::new(tcp_connect))
-            .push(admit::AdmitLayer::new(prevent_loop.into()));
-
         let http = DetectHttp::new(
             h2_settings,
             detect_protocol_timeout,
             http_server,
-            tcp_forward.clone().push_map_target(TcpEndpoint::from),
+            tcp_forward_meta,
             drain.clone(),
-        );
+        )
+        // Serves connections on ports with a declared protocol without
+        // detecting it.
+        .with_known_protocols(port_protocols);
 
         let tls = svc::stack(http)
             .push(admit::AdmitLayer::new(require_identity))
+            // Drops connections that are not authorized by the port's policy.
+            .push(admit::AdmitLayer::new(authorize))
             .push(metrics.transport.layer_accept(TransportLabels))
             .push(svc::layer::mk(|inner| {
                 tls::DetectTls::new(local_identity.clone(), inner, detect_protocol_timeout)
             }));
 
         let accept_fwd = tcp_forward
-            .push_map_target(TcpEndpoint::from)
+            .push_map_target(move |addrs: listen::Addrs| {
+                TcpEndpoint::from(addrs).with_unix_socket(&unix_sockets)
+            })
             .push(metrics.transport.layer_accept(TransportLabels))
             .into_inner();
         let accept = SkipDetect::new(skip_detect, tls, accept_fwd);
+        // Reads the client's address from a PROXY protocol header before
+        // the connection is detected.
+        let accept = proxy_protocol::ProxyProtocol::new(
+            self.proxy_protocol,
+            detect_protocol_timeout,
+            metrics.proxy_protocol.clone(),
+            accept,
+        );
 
         info!(addr = %listen_addr, "Serving");
         serve::serve(listen, accept, drain.signal()).await
@@ -417,7 +484,10 @@ struct TransportLabels;
 impl transport::metrics::TransportLabels<HttpEndpoint> for TransportLabels {
     type Labels = transport::labels::Key;
 
-    fn transport_labels(&self, _: &HttpEndpoint) -> Self::Labels {
+    fn transport_labels(&self, endpoint: &HttpEndpoint) -> Self::Labels {
+        if endpoint.unix_socket.is_some() {
+            return transport::labels::Key::connect_unix("inbound");
+        }
         transport::labels::Key::connect::<()>(
             "inbound",
             tls::Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
@@ -428,7 +498,10 @@ impl transport::metrics::TransportLabels<HttpEndpoint> for TransportLabels {
 impl transport::metrics::TransportLabels<TcpEndpoint> for TransportLabels {
     type Labels = transport::labels::Key;
 
-    fn transport_labels(&self, _: &TcpEndpoint) -> Self::Labels {
+    fn transport_labels(&self, endpoint: &TcpEndpoint) -> Self::Labels {
+        if endpoint.unix_socket.is_some() {
+            return transport::labels::Key::connect_unix("inbound");
+        }
         transport::labels::Key::connect::<()>(
             "inbound",
             tls::Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
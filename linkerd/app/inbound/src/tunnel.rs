@@ -0,0 +1,260 @@
+//! Terminates HTTP/2 `CONNECT` tunnels from meshed clients.
+//!
+//! Each tunnel's stream is forwarded to the application as though it were a
+//! TCP connection to the port on which the tunnel was accepted. Clients must
+//! name the tunnel's logical target in the `l5d-dst-canonical` header so
+//! that it is visible to the forwarding stack.
+
+use futures::{future, prelude::*};
+use linkerd2_app_core::{
+    errors::HttpError,
+    proxy::http::{self, tunnel},
+    svc::NewService,
+    transport::tls,
+    Addr, Error, CANONICAL_DST_HEADER,
+};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::util::ServiceExt;
+use tracing::{debug, info_span};
+use tracing_futures::Instrument;
+
+#[derive(Clone, Debug)]
+pub struct Layer<F> {
+    forward: Option<F>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewTunnel<N, F> {
+    inner: N,
+    forward: Option<F>,
+}
+
+/// Describes a tunnel accepted from a meshed client.
+#[derive(Clone, Debug)]
+pub struct Accept {
+    pub meta: tls::accept::Meta,
+    /// The logical target named by the client.
+    pub logical: Addr,
+}
+
+/// Forwards tunnels through `F` and dispatches all other requests to `S`.
+#[derive(Clone, Debug)]
+pub struct Terminate<S, F> {
+    inner: S,
+    forward: Option<(F, tls::accept::Meta)>,
+}
+
+type ResponseFuture =
+    Pin<Box<dyn Future<Output = Result<http::Response<http::boxed::Payload>, Error>> + Send>>;
+
+// === impl Layer ===
+
+/// Builds a layer that terminates tunnels through `forward` when it is set.
+pub fn layer<F>(forward: Option<F>) -> Layer<F> {
+    Layer { forward }
+}
+
+impl<N, F: Clone> tower::layer::Layer<N> for Layer<F> {
+    type Service = NewTunnel<N, F>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        NewTunnel {
+            inner,
+            forward: self.forward.clone(),
+        }
+    }
+}
+
+// === impl NewTunnel ===
+
+impl<N, F> NewService<tls::accept::Meta> for NewTunnel<N, F>
+where
+    N: NewService<tls::accept::Meta>,
+    F: Clone,
+{
+    type Service = Terminate<N::Service, F>;
+
+    fn new_service(&self, meta: tls::accept::Meta) -> Self::Service {
+        // Only peers with a verified identity may open tunnels.
+        let forward = match self.forward.as_ref() {
+            Some(forward) if meta.peer_identity.value().is_some() => {
+                Some((forward.clone(), meta.clone()))
+            }
+            _ => None,
+        };
+
+        Terminate {
+            inner: self.inner.new_service(meta),
+            forward,
+        }
+    }
+}
+
+// === impl Terminate ===
+
+impl<S, F, A, B> tower::Service<http::Request<B>> for Terminate<S, F>
+where
+    S: tower::Service<
+        http::Request<B>,
+        Response = http::Response<http::boxed::Payload>,
+        Error = Error,
+    >,
+    F: tower::Service<Accept, Response = A> + Clone + Send + 'static,
+    F::Error: Into<Error>,
+    F::Future: Send + 'static,
+    A: tower::Service<tunnel::TunnelIo<B>, Response = ()> + Send + 'static,
+    A::Error: Into<Error>,
+    A::Future: Send + 'static,
+    B: http::HttpBody + Unpin + Send + 'static,
+    B::Data: Unpin + Send + 'static,
+    B::Error: Into<Error>,
+{
+    type Response = http::Response<http::boxed::Payload>;
+    type Error = Error;
+    type Future = future::Either<S::Future, ResponseFuture>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let (forward, meta) = match self.forward.as_ref() {
+            Some(forward) if tunnel::is_tunnel(&req) => forward,
+            _ => return future::Either::Left(self.inner.call(req)),
+        };
+
+        let logical = match logical(&req) {
+            Some(logical) => logical,
+            None => {
+                let error = HttpError::not_found("tunnels must name their logical target");
+                return future::Either::Right(Box::pin(future::err(error.into())));
+            }
+        };
+
+        let span = info_span!("tunnel", %logical);
+        let (rsp, io) = tunnel::accept(req);
+        let accept = forward.clone().oneshot(Accept {
+            meta: meta.clone(),
+            logical,
+        });
+        future::Either::Right(Box::pin(async move {
+            let accept = accept.await.map_err(Into::into)?;
+            debug!(parent: &span, "Tunnel accepted");
+            tokio::spawn(
+                accept
+                    .oneshot(io)
+                    .err_into::<Error>()
+                    .map_err(|error| debug!(%error, "Tunnel failed"))
+                    .instrument(span),
+            );
+            Ok(rsp)
+        }))
+    }
+}
+
+fn logical<B>(req: &http::Request<B>) -> Option<Addr> {
+    let value = req.headers().get(CANONICAL_DST_HEADER)?.to_str().ok()?;
+    Addr::from_str(value).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_app_core::{
+        proxy::{http::boxed::Payload, identity},
+        transport::listen,
+        Conditional,
+    };
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use tower::layer::Layer as _;
+
+    fn meta(peer_identity: tls::PeerIdentity) -> tls::accept::Meta {
+        let addr = SocketAddr::from(([10, 1, 2, 3], 5432));
+        tls::accept::Meta {
+            peer_identity,
+            addrs: listen::Addrs::new(addr, SocketAddr::from(([10, 4, 5, 6], 40000)), Some(addr)),
+        }
+    }
+
+    fn meshed() -> tls::PeerIdentity {
+        let id = b"foo.ns.serviceaccount.identity.linkerd.cluster.local";
+        Conditional::Some(identity::Name::from_hostname(id).unwrap())
+    }
+
+    fn tunnel_request(logical: Option<&str>) -> http::Request<Payload> {
+        let (mut req, _tx) = tunnel::request("10.1.2.3:5432".parse().unwrap());
+        if let Some(logical) = logical {
+            req.headers_mut()
+                .insert(CANONICAL_DST_HEADER, logical.parse().unwrap());
+        }
+        req
+    }
+
+    /// Builds a tunnel-terminating service for `meta`, recording the targets
+    /// of forwarded tunnels.
+    fn terminate(
+        meta: tls::accept::Meta,
+    ) -> (
+        impl tower::Service<http::Request<Payload>, Response = http::Response<Payload>, Error = Error>,
+        Arc<Mutex<Vec<Accept>>>,
+    ) {
+        let accepted = Arc::new(Mutex::new(Vec::new()));
+        let forward = {
+            let accepted = accepted.clone();
+            tower::service_fn(move |accept: Accept| {
+                accepted.lock().unwrap().push(accept);
+                future::ok::<_, Error>(tower::service_fn(|_: tunnel::TunnelIo<Payload>| {
+                    future::ok::<(), Error>(())
+                }))
+            })
+        };
+        let inner = |_: tls::accept::Meta| {
+            tower::service_fn(|_: http::Request<Payload>| {
+                let mut rsp = http::Response::new(Payload::default());
+                *rsp.status_mut() = http::StatusCode::NO_CONTENT;
+                future::ok::<_, Error>(rsp)
+            })
+        };
+        let svc = layer(Some(forward)).layer(inner).new_service(meta);
+        (svc, accepted)
+    }
+
+    #[tokio::test]
+    async fn forwards_tunnels_with_their_logical_target() {
+        let (svc, accepted) = terminate(meta(meshed()));
+        let rsp = svc
+            .oneshot(tunnel_request(Some("db.ns.svc.cluster.local:5432")))
+            .await
+            .unwrap();
+        assert_eq!(rsp.status(), http::StatusCode::OK);
+
+        let accepted = accepted.lock().unwrap();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(
+            accepted[0].logical,
+            Addr::from_str("db.ns.svc.cluster.local:5432").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_tunnels_without_a_logical_target() {
+        let (svc, accepted) = terminate(meta(meshed()));
+        let error = svc.oneshot(tunnel_request(None)).await.unwrap_err();
+        assert!(error.is::<HttpError>());
+        assert!(accepted.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn does_not_terminate_tunnels_from_unidentified_peers() {
+        let no_identity = Conditional::None(tls::ReasonForNoPeerName::NoPeerIdFromRemote.into());
+        let (svc, accepted) = terminate(meta(no_identity));
+        let rsp = svc
+            .oneshot(tunnel_request(Some("db.ns.svc.cluster.local:5432")))
+            .await
+            .unwrap();
+        assert_eq!(rsp.status(), http::StatusCode::NO_CONTENT);
+        assert!(accepted.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use super::endpoint::{HttpEndpoint, Target, TcpEndpoint};
+use linkerd2_app_core::{
+    dns,
+    egress::{Dst, HasDst},
+};
+use std::convert::TryFrom;
+
+/// Requests are named by their host, if any.
+impl HasDst for Target<HttpEndpoint> {
+    fn dst(&self) -> Dst {
+        Dst {
+            name: self.addr.name_addr().map(|n| n.name().clone()),
+            addr: self.inner.addr,
+        }
+    }
+}
+
+/// Connections are named by their TLS server name, if any.
+impl HasDst for TcpEndpoint {
+    fn dst(&self) -> Dst {
+        Dst {
+            name: self
+                .server_name
+                .as_ref()
+                .and_then(|n| dns::Name::try_from(n.as_ref().as_bytes()).ok()),
+            addr: self.addr,
+        }
+    }
+}
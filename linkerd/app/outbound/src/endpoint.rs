@@ -1,11 +1,12 @@
 use crate::http::uri::Authority;
 use indexmap::IndexMap;
 use linkerd2_app_core::{
-    dst, metric_labels,
+    dst, fault, http_locality, http_outlier, metric_labels,
     metric_labels::{prefix_labels, EndpointLabels},
     profiles,
     proxy::{
         api_resolve::{Metadata, ProtocolHint},
+        core::{HasWeight, Weight},
         http::override_authority::CanOverrideAuthority,
         http::{self, identity_from_header, Settings},
         identity,
@@ -49,10 +50,17 @@ pub struct HttpEndpoint {
     pub metadata: Metadata,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct TcpEndpoint {
     pub addr: SocketAddr,
     pub identity: tls::PeerIdentity,
+    /// The logical target through which the endpoint was discovered, if any.
+    pub logical: Option<Addr>,
+    /// The server name from the client's TLS ClientHello, if any.
+    pub server_name: Option<identity::Name>,
+    /// Set when discovery indicates that the endpoint's proxy accepts
+    /// tunneled TCP streams.
+    pub tcp_tunnel: bool,
 }
 
 // === impl Target ===
@@ -253,10 +261,25 @@ impl tap::Inspect for HttpEndpoint {
         self.identity.as_ref()
     }
 
+    /// Includes the endpoint's outlier status, if outlier detection is
+    /// enabled, in the route labels.
     fn route_labels<B>(&self, req: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
-        req.extensions()
-            .get::<dst::Route>()
-            .map(|r| r.route.labels().clone())
+        let labels = fault::route_labels(req);
+        match req.extensions().get::<http_outlier::Status>() {
+            None => labels,
+            Some(status) => {
+                let mut labels = labels.map(|l| (*l).clone()).unwrap_or_default();
+                labels.insert(
+                    "outlier_ejections".to_string(),
+                    status.ejections.to_string(),
+                );
+                labels.insert(
+                    "outlier_consecutive_failures".to_string(),
+                    status.consecutive_failures.to_string(),
+                );
+                Some(Arc::new(labels))
+            }
+        }
     }
 
     fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
@@ -301,6 +324,18 @@ impl CanOverrideAuthority for Target<HttpEndpoint> {
     }
 }
 
+impl HasWeight for Target<HttpEndpoint> {
+    fn weight(&self) -> &Weight {
+        self.inner.metadata.weight()
+    }
+}
+
+impl http_locality::HasLocality for Target<HttpEndpoint> {
+    fn locality(&self, label: &str) -> Option<&str> {
+        self.inner.metadata.labels().get(label).map(String::as_str)
+    }
+}
+
 impl Into<EndpointLabels> for Target<HttpEndpoint> {
     fn into(self) -> EndpointLabels {
         use linkerd2_app_core::metric_labels::{Direction, TlsId};
@@ -317,13 +352,53 @@ impl Into<EndpointLabels> for Target<HttpEndpoint> {
     }
 }
 
+impl MapEndpoint<Logical<TcpEndpoint>, Metadata> for FromMetadata {
+    type Out = TcpEndpoint;
+
+    fn map_endpoint(
+        &self,
+        logical: &Logical<TcpEndpoint>,
+        addr: SocketAddr,
+        metadata: Metadata,
+    ) -> Self::Out {
+        tracing::trace!(%addr, ?metadata, "Resolved endpoint");
+        let identity = metadata
+            .identity()
+            .cloned()
+            .map(Conditional::Some)
+            .unwrap_or_else(|| {
+                Conditional::None(tls::ReasonForNoPeerName::NotProvidedByServiceDiscovery.into())
+            });
+
+        TcpEndpoint {
+            addr,
+            identity,
+            logical: Some(logical.addr.clone()),
+            server_name: logical.inner.server_name.clone(),
+            tcp_tunnel: metadata.tcp_tunnel(),
+        }
+    }
+}
+
 // === impl TcpEndpoint ===
 
+impl From<listen::Addrs> for Logical<TcpEndpoint> {
+    fn from(addrs: listen::Addrs) -> Self {
+        Target {
+            addr: addrs.target_addr().into(),
+            inner: TcpEndpoint::from(addrs),
+        }
+    }
+}
+
 impl From<listen::Addrs> for TcpEndpoint {
     fn from(addrs: listen::Addrs) -> Self {
         Self {
             addr: addrs.target_addr(),
             identity: Conditional::None(tls::ReasonForNoPeerName::NotHttp.into()),
+            logical: None,
+            server_name: None,
+            tcp_tunnel: false,
         }
     }
 }
@@ -395,9 +470,17 @@ impl<B> router::Recognize<http::Request<B>> for LogicalPerRequest {
 
         tracing::debug!(headers = ?req.headers(), uri = %req.uri(), target.addr = %addr, http.settings = ?settings, "Setting target for request");
 
+        // Connections without an original destination (i.e. from the Unix
+        // socket) are forwarded to the request's target when it is an IP.
+        let endpoint_addr = self
+            .0
+            .orig_dst()
+            .or_else(|| addr.socket_addr())
+            .unwrap_or_else(|| self.0.target_addr());
+
         let inner = HttpEndpoint {
             settings,
-            addr: self.0.target_addr(),
+            addr: endpoint_addr,
             metadata: Metadata::empty(),
             identity: identity_from_header(req, L5D_REQUIRE_ID)
                 .map(Conditional::Some)
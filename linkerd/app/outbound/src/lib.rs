@@ -10,22 +10,24 @@ pub use self::endpoint::{
     TcpEndpoint,
 };
 use ::http::header::HOST;
-use futures::{future, prelude::*};
+use futures::{future, prelude::*, stream};
+use indexmap::IndexMap;
 use linkerd2_app_core::{
     admit, classify,
     config::{ProxyConfig, ServerConfig},
-    dns, drain, dst, errors, metric_labels,
+    dns, drain, dst, egress, errors, failover, fault, http_health, http_locality, http_outlier,
+    metric_labels, mirror,
     opencensus::proto::trace::v1 as oc,
     profiles,
     proxy::{
         self, core::resolve::Resolve, discover, http, identity, resolve::map_endpoint, tap, tcp,
         SkipDetect,
     },
-    reconnect, retry, router, serve,
+    rate_limit, reconnect, retry, router, serve,
     spans::SpanConverter,
     svc::{self, NewService},
-    transport::{self, listen, tls},
-    Conditional, DiscoveryRejected, Error, ProxyMetrics, StackMetrics, TraceContextLayer,
+    transport::{self, io::EitherIo, listen, tls},
+    Addr, Conditional, DiscoveryRejected, Error, ProxyMetrics, StackMetrics, TraceContextLayer,
     CANONICAL_DST_HEADER, DST_OVERRIDE_HEADER, L5D_REQUIRE_ID,
 };
 use std::collections::HashMap;
@@ -34,10 +36,14 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{info, info_span};
 
+mod egress_policy;
 pub mod endpoint;
 mod orig_proto_upgrade;
+mod preface;
 mod prevent_loop;
 mod require_identity_on_endpoint;
+pub mod sni;
+mod tunnel;
 
 use self::orig_proto_upgrade::OrigProtoUpgradeLayer;
 use self::prevent_loop::PreventLoop;
@@ -50,6 +56,26 @@ const EWMA_DECAY: Duration = Duration::from_secs(10);
 pub struct Config {
     pub proxy: ProxyConfig,
     pub canonicalize_timeout: Duration,
+    pub outlier_detection: http_outlier::Config,
+    pub locality: Option<http_locality::Config>,
+    pub health_checks: http_health::Config,
+    pub hash_keys: IndexMap<Addr, http::balance::HashKey>,
+    pub slow_start: Duration,
+    pub failover: failover::Config,
+    pub rate_limits: IndexMap<String, profiles::RateLimit>,
+    pub retry_max_body_bytes: usize,
+    pub mirror_max_body_bytes: usize,
+    /// Accepts application traffic on a Unix domain socket, in addition to
+    /// the TCP listener.
+    pub unix_listen: Option<listen::BindUnix>,
+    /// Carries opaque TCP streams to meshed endpoints over HTTP/2 `CONNECT`
+    /// streams rather than establishing a connection for each stream.
+    pub tcp_tunnel: bool,
+    /// Reads the server name from TLS ClientHellos on connections that are
+    /// not HTTP.
+    pub tls_sni: Option<sni::Config>,
+    /// Restricts the external destinations that may be reached.
+    pub egress: Option<egress::Config>,
 }
 
 impl Config {
@@ -81,6 +107,169 @@ impl Config {
             .into_inner()
     }
 
+    /// Builds a stack that establishes connections to discovered endpoints
+    /// for opaque TCP streams.
+    ///
+    /// When tunneling is enabled, streams to meshed endpoints are carried over
+    /// an HTTP/2 connection that is shared by all streams to the endpoint.
+    /// Otherwise, a connection is established for each stream.
+    pub fn build_tcp_tunnel<C>(
+        &self,
+        tcp_connect: C,
+        metrics: &ProxyMetrics,
+    ) -> impl tower::Service<
+        TcpEndpoint,
+        Error = Error,
+        Future = impl Unpin + Send,
+        Response = impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    > + Unpin
+           + Clone
+           + Send
+           + Sync
+    where
+        C: tower::Service<TcpEndpoint, Error = Error> + Unpin + Clone + Send + Sync + 'static,
+        C::Response: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        C::Future: Unpin + Send + 'static,
+    {
+        let ProxyConfig {
+            buffer_capacity,
+            cache_max_idle_age,
+            dispatch_timeout,
+            ..
+        } = self.proxy.clone();
+
+        // Caches an HTTP/2 client for each meshed endpoint.
+        let clients = svc::stack(http::h2::Connect::new(
+            tcp_connect.clone(),
+            self.proxy.connect.h2_settings,
+        ))
+        // Re-establishes a connection when the client fails.
+        .push(reconnect::layer({
+            let backoff = self.proxy.connect.backoff.clone();
+            move |_| Ok(backoff.stream())
+        }))
+        .check_make_service::<TcpEndpoint, http::Request<http::boxed::Payload>>()
+        .into_new_service()
+        .cache(
+            svc::layers().push_on_response(
+                svc::layers()
+                    // If the endpoint has been unavailable for an extended time, eagerly
+                    // fail connections.
+                    .push_failfast(dispatch_timeout)
+                    .push_spawn_buffer_with_idle_timeout(buffer_capacity, cache_max_idle_age)
+                    .push(metrics.stack.layer(stack_labels("tcp.tunnel"))),
+            ),
+        )
+        .spawn_buffer(buffer_capacity)
+        .instrument(|ep: &TcpEndpoint| info_span!("tunnel", peer.addr = %ep.addr))
+        .check_service::<TcpEndpoint>()
+        .into_inner();
+
+        // Each tunneled stream is recorded as a connection to the endpoint.
+        let tunnel = svc::stack(tunnel::Connect::new(clients))
+            .push(metrics.transport.layer_connect(TransportLabels))
+            .into_inner();
+
+        svc::stack(tunnel::Tunnel::new(self.tcp_tunnel, tcp_connect, tunnel))
+            .check_service::<TcpEndpoint>()
+            .into_inner()
+    }
+
+    /// Builds the egress policy, which is enforced wherever traffic is
+    /// forwarded to its original destination address.
+    pub fn build_egress(
+        &self,
+        dns_resolver: dns::Resolver,
+        metrics: &ProxyMetrics,
+    ) -> egress::Egress {
+        egress::Egress::new(self.egress.clone(), dns_resolver, metrics.egress.clone())
+    }
+
+    /// Builds a stack that establishes connections for opaque TCP streams.
+    ///
+    /// Each logical target is resolved through the destination service and new
+    /// connections are balanced over its endpoints, preferring endpoints with
+    /// the fewest open connections. When discovery is rejected for a target,
+    /// connections are established to the original destination address if the
+    /// egress policy allows it.
+    pub fn build_tcp_balance<C, R>(
+        &self,
+        tcp_connect: C,
+        resolve: R,
+        egress: egress::Egress,
+        metrics: &ProxyMetrics,
+    ) -> impl tower::Service<
+        Logical<TcpEndpoint>,
+        Error = Error,
+        Future = impl Unpin + Send,
+        Response = impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    > + Unpin
+           + Clone
+           + Send
+    where
+        C: tower::Service<TcpEndpoint, Error = Error> + Unpin + Clone + Send + Sync + 'static,
+        C::Response: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        C::Future: Unpin + Send,
+        R: Resolve<Logical<TcpEndpoint>, Endpoint = proxy::api_resolve::Metadata>
+            + Unpin
+            + Clone
+            + Send
+            + 'static,
+        R::Future: Unpin + Send,
+        R::Resolution: Unpin + Send,
+    {
+        let ProxyConfig {
+            buffer_capacity,
+            cache_max_idle_age,
+            dispatch_timeout,
+            ..
+        } = self.proxy.clone();
+
+        // Resolves each target via the control plane on a background task, buffering results.
+        let discover = {
+            const BUFFER_CAPACITY: usize = 1_000;
+            let resolve = map_endpoint::Resolve::new(endpoint::FromMetadata, resolve);
+            discover::Layer::new(BUFFER_CAPACITY, cache_max_idle_age, resolve)
+        };
+
+        // Connects directly to the original destination when the target cannot be resolved.
+        let tcp_forward = svc::stack(tcp_connect.clone())
+            // Drops connections to external destinations that are denied by
+            // the egress policy.
+            .push(egress.layer())
+            .push_map_target(|l: Logical<TcpEndpoint>| l.inner)
+            .into_inner();
+
+        // Builds a balancer for each logical destination.
+        svc::stack(tcp::balance::MakeEndpoint::new(tcp_connect))
+            .check_service::<TcpEndpoint>()
+            .push(discover)
+            .push_on_response(tcp::balance::layer())
+            .into_new_service()
+            .cache(
+                svc::layers().push_on_response(
+                    svc::layers()
+                        // If the balancer has been empty/unavailable for an extended time,
+                        // eagerly fail connections.
+                        .push_failfast(dispatch_timeout)
+                        // Shares the balancer, ensuring discovery errors are propagated.
+                        .push_spawn_buffer_with_idle_timeout(buffer_capacity, cache_max_idle_age)
+                        .push(metrics.stack.layer(stack_labels("tcp.balance"))),
+                ),
+            )
+            .spawn_buffer(buffer_capacity)
+            .instrument(|l: &Logical<TcpEndpoint>| info_span!("tcp.balance", addr = %l.addr))
+            // Obtains the balancer and establishes a connection through it.
+            .push(svc::make_response::Layer)
+            .push_fallback_with_predicate(tcp_forward, is_discovery_rejected)
+            .push_map_response(|io: svc::Either<_, _>| match io {
+                svc::Either::A(io) => transport::io::EitherIo::Left(io),
+                svc::Either::B(io) => transport::io::EitherIo::Right(io),
+            })
+            .check_service::<Logical<TcpEndpoint>>()
+            .into_inner()
+    }
+
     pub fn build_dns_refine(
         &self,
         dns_resolver: dns::Resolver,
@@ -179,6 +368,10 @@ impl Config {
                 move |_| Ok(backoff.stream())
             }))
             .push(admit::AdmitLayer::new(prevent_loop.into()))
+            .push(admit::AdmitLayer::new(preface::RequireTarget))
+            // Fails hedged requests that would be served by the same endpoint
+            // as their original request, before they are recorded.
+            .push(retry::distinct_endpoint_layer())
             .push(observability.clone())
             .push(identity_headers.clone())
             .push(http::override_authority::Layer::new(vec![HOST.as_str(), CANONICAL_DST_HEADER]))
@@ -196,11 +389,65 @@ impl Config {
         })
     }
 
-    pub fn build_http_router<B, E, S, R, P>(
+    /// Builds clients that probe the endpoints of health-checked
+    /// destinations.
+    ///
+    /// Probes are not application traffic, so they are not recorded by tap,
+    /// metrics, or tracing.
+    pub fn build_http_probe_client<C>(
+        &self,
+        prevent_loop: impl Into<PreventLoop>,
+        tcp_connect: C,
+    ) -> impl tower::Service<
+        Target<HttpEndpoint>,
+        Error = Error,
+        Future = impl Unpin + Send,
+        Response = impl tower::Service<
+            http::Request<http::boxed::Payload>,
+            Response = http::Response<http::boxed::Payload>,
+            Error = Error,
+            Future = impl Send,
+        > + Send,
+    > + Unpin
+           + Clone
+           + Send
+    where
+        C: tower::Service<Target<HttpEndpoint>, Error = Error>
+            + Unpin
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        C::Response: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+        C::Future: Unpin + Send,
+    {
+        svc::stack(tcp_connect)
+            .push(http::MakeClientLayer::new(self.proxy.connect.h2_settings))
+            .push(reconnect::layer({
+                let backoff = self.proxy.connect.backoff.clone();
+                move |_| Ok(backoff.stream())
+            }))
+            .push(admit::AdmitLayer::new(prevent_loop.into()))
+            .push(admit::AdmitLayer::new(preface::RequireTarget))
+            .push(http::override_authority::Layer::new(vec![HOST.as_str(), CANONICAL_DST_HEADER]))
+            .push(http::normalize_uri::layer())
+            .push(OrigProtoUpgradeLayer::new())
+            .push_on_response(svc::layers().box_http_response())
+            .check_service::<Target<HttpEndpoint>>()
+            .instrument(|endpoint: &Target<HttpEndpoint>| {
+                info_span!("probe", peer.addr = %endpoint.inner.addr)
+            })
+            .into_inner()
+    }
+
+    pub fn build_http_router<B, E, S, H, R, P>(
         &self,
         http_endpoint: E,
+        http_probe_client: H,
         resolve: R,
         profiles_client: P,
+        egress: egress::Egress,
+        tap_layer: tap::Layer,
         metrics: ProxyMetrics,
     ) -> impl tower::Service<
         Target<HttpEndpoint>,
@@ -232,6 +479,20 @@ impl Config {
             > + Send
             + 'static,
         S::Future: Send,
+        H: tower::Service<Target<HttpEndpoint>, Error = Error>
+            + Unpin
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        H::Future: Unpin + Send,
+        H::Response: tower::Service<
+                http::Request<http::boxed::Payload>,
+                Response = http::Response<http::boxed::Payload>,
+                Error = Error,
+            > + Send
+            + 'static,
+        <H::Response as tower::Service<http::Request<http::boxed::Payload>>>::Future: Send,
         R: Resolve<Concrete<http::Settings>, Endpoint = proxy::api_resolve::Metadata>
             + Unpin
             + Clone
@@ -261,7 +522,7 @@ impl Config {
         };
 
         // Builds a balancer for each concrete destination.
-        let http_balancer = svc::stack(http_endpoint.clone())
+        let http_balance = svc::stack(http_endpoint.clone())
             .check_make_service::<Target<HttpEndpoint>, http::Request<http::boxed::Payload>>()
             .push_on_response(
                 svc::layers()
@@ -269,9 +530,57 @@ impl Config {
                     .box_http_request(),
             )
             .push_spawn_ready()
+            // Exposes each endpoint's weight, which may be updated by
+            // discovery, to the balancer.
+            .push(http::balance::weight_layer())
+            // Records whether each endpoint is in the proxy's locality.
+            .push(http_locality::TagLayer::new(self.locality.clone()))
+            // Builds a client that probes each endpoint of health-checked
+            // destinations.
+            .push(http_health::ClientLayer::new(
+                &self.health_checks,
+                http_probe_client,
+            ))
             .check_service::<Target<HttpEndpoint>>()
             .push(discover)
-            .push_on_response(http::balance::layer(EWMA_DEFAULT_RTT, EWMA_DECAY))
+            // Withholds endpoints from the balancer while they fail their
+            // health checks.
+            .push(http_health::Layer::new(
+                self.health_checks.clone(),
+                metric_labels::Direction::Out,
+                metrics.http_health.clone(),
+            ))
+            // Ejects endpoints from the balancer when their responses are
+            // observed to be failing.
+            .push(http_outlier::Layer::<_, classify::Response>::new(
+                self.outlier_detection.clone(),
+                metric_labels::Direction::Out,
+                metrics.http_outlier.clone(),
+            ))
+            // Prefers endpoints in the proxy's locality, spilling over to
+            // other localities when local endpoints are unavailable or
+            // overloaded.
+            .push(http_locality::Layer::new(
+                self.locality.clone(),
+                metric_labels::Direction::Out,
+                metrics.http_locality.clone(),
+            ))
+            // Balances requests over endpoints, either by load or, for
+            // destinations with a configured hash key, by a consistent hash.
+            // Newly discovered endpoints are slow-started.
+            .push(http::balance::layer(
+                EWMA_DEFAULT_RTT,
+                EWMA_DECAY,
+                self.slow_start,
+                self.hash_keys.clone(),
+                metric_labels::Direction::Out,
+                metrics.http_balance.clone(),
+            ));
+
+        // Caches balancers so that each is shared by its destination's traffic
+        // and by the destinations that it backs up.
+        let http_balancer = http_balance
+            .clone()
             .into_new_service()
             .cache(
                 svc::layers().push_on_response(
@@ -287,6 +596,37 @@ impl Config {
             .spawn_buffer(buffer_capacity)
             .instrument(|c: &Concrete<http::Settings>| info_span!("balance", addr = %c.addr));
 
+        // Fails over to backup destinations, in order, while the destination's
+        // balancer has no available endpoints. The destination's own balancer is
+        // owned by the failover service so that its readiness is observed
+        // directly, while backups are obtained from the shared balancer cache.
+        //
+        // Destinations without backups fall back to the shared balancer cache.
+        let http_failover = http_balance
+            .push(failover::layer(
+                self.failover.clone(),
+                http_balancer.clone().into_inner(),
+                metric_labels::Direction::Out,
+                metrics.http_failover.clone(),
+            ))
+            .into_new_service()
+            .cache(
+                svc::layers().push_on_response(
+                    svc::layers()
+                        // If no tier has been available for 10s, eagerly fail
+                        // requests.
+                        .push_failfast(dispatch_timeout)
+                        .push_spawn_buffer_with_idle_timeout(buffer_capacity, cache_max_idle_age)
+                        .push(metrics.stack.layer(stack_labels("failover"))),
+                ),
+            )
+            .spawn_buffer(buffer_capacity)
+            .instrument(|c: &Concrete<http::Settings>| info_span!("failover", addr = %c.addr))
+            .push(admit::AdmitLayer::new(failover::HasBackups::from(
+                self.failover.clone(),
+            )))
+            .push_fallback_with_predicate(http_balancer.into_inner(), failover::is_no_backups);
+
         // Caches clients that bypass discovery/balancing.
         //
         // This is effectively the same as the endpoint stack; but the client layer captures the
@@ -294,6 +634,9 @@ impl Config {
         let http_forward_cache = svc::stack(http_endpoint)
             .check_make_service::<Target<HttpEndpoint>, http::Request<http::boxed::Payload>>()
             .into_new_service()
+            // Fails requests to external destinations that are denied by the
+            // egress policy. Each cached client makes its own decision.
+            .push(egress.new_service_layer())
             .cache(
                 svc::layers()
                     .push_on_response(
@@ -319,7 +662,7 @@ impl Config {
 
         // If the balancer fails to be created, i.e., because it is unresolvable, fall back to
         // using a router that dispatches request to the application-selected original destination.
-        let http_concrete = http_balancer
+        let http_concrete = http_failover
             .push_map_target(|c: Concrete<HttpEndpoint>| c.map(|l| l.map(|e| e.settings)))
             .check_service::<Concrete<HttpEndpoint>>()
             .push_on_response(svc::layers().box_http_response())
@@ -335,14 +678,31 @@ impl Config {
         let http_profile_route_proxy = svc::proxies()
             .check_new_clone_service::<dst::Route>()
             .push(metrics.http_route_actual.into_layer::<classify::Response>())
+            // Sets an optional hedging policy.
+            .push(retry::hedge_layer(metrics.http_route_retry.clone()))
             // Sets an optional retry policy.
             .push(retry::layer(metrics.http_route_retry))
+            // Buffers request bodies so that they may be retried or hedged.
+            .push(retry::replay_layer(self.retry_max_body_bytes))
+            // Sends copies of an optional share of requests to a mirror
+            // destination.
+            .push(mirror::layer(
+                metrics.http_route_mirror,
+                self.mirror_max_body_bytes,
+            ))
             .check_new_clone_service::<dst::Route>()
             // Sets an optional request timeout.
             .push(http::MakeTimeoutLayer::default())
             .check_new_clone_service::<dst::Route>()
-            // Records per-route metrics.
-            .push(metrics.http_route.into_layer::<classify::Response>())
+            // Limits the rate of requests on routes that configure a rate
+            // limit. Requests that wait for the limit are not subject to the
+            // route's timeout.
+            .push(rate_limit::layer())
+            .check_new_clone_service::<dst::Route>()
+            // Injects optional delays and aborts, and records per-route
+            // metrics, with a `fault` label for requests with injected faults.
+            // Injected delays are not subject to the route's timeout.
+            .push(fault::layer(metrics.http_route, tap_layer))
             .check_new_clone_service::<dst::Route>()
             // Sets the per-route response classifier as a request
             // extension.
@@ -353,6 +713,8 @@ impl Config {
         // resolutions are shared even as the type of request may vary.
         let http_logical_profile_cache = http_concrete
             .clone()
+            // Dispatches mirrored requests through the concrete stack.
+            .push(mirror::dispatch_layer())
             .push_on_response(svc::layers().box_http_request())
             .check_service::<Concrete<HttpEndpoint>>()
             // Provides route configuration. The profile service operates
@@ -407,6 +769,9 @@ impl Config {
                 is_discovery_rejected,
             )
             .check_service::<Logical<HttpEndpoint>>()
+            // Limits the rate of requests to destinations that configure a
+            // rate limit.
+            .push(rate_limit::destination_layer(self.rate_limits.clone()))
             .push(http::header_from_target::layer(CANONICAL_DST_HEADER))
             // Strips headers that may be set by this proxy.
             .push_on_response(http::strip_header::request::layer(DST_OVERRIDE_HEADER))
@@ -419,9 +784,13 @@ impl Config {
         self,
         listen_addr: std::net::SocketAddr,
         listen: impl Stream<Item = std::io::Result<listen::Connection>> + Send + 'static,
+        unix_listen: Option<
+            impl Stream<Item = std::io::Result<listen::UnixConnection>> + Send + 'static,
+        >,
         refine: R,
-        tcp_connect: C,
+        tcp_balance: C,
         http_router: H,
+        tap_layer: tap::Layer,
         metrics: ProxyMetrics,
         span_sink: Option<mpsc::Sender<oc::Span>>,
         drain: drain::Watch,
@@ -433,7 +802,12 @@ impl Config {
             + Send
             + 'static,
         R::Future: Unpin + Send,
-        C: tower::Service<TcpEndpoint, Error = Error> + Unpin + Clone + Send + Sync + 'static,
+        C: tower::Service<Logical<TcpEndpoint>, Error = Error>
+            + Unpin
+            + Clone
+            + Send
+            + Sync
+            + 'static,
         C::Response: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
         C::Future: Unpin + Send,
         H: tower::Service<Target<HttpEndpoint>, Error = Error, Response = S>
@@ -453,17 +827,27 @@ impl Config {
         let ProxyConfig {
             server: ServerConfig { h2_settings, .. },
             disable_protocol_detection_for_ports: skip_detect,
+            port_protocols,
             dispatch_timeout,
             max_in_flight_requests,
+            adaptive_concurrency_limit,
             detect_protocol_timeout,
             ..
         } = self.proxy;
         let canonicalize_timeout = self.canonicalize_timeout;
         let prevent_loop = PreventLoop::from(listen_addr.port());
 
+        let adaptive_concurrency_limit = adaptive_concurrency_limit.map(|config| {
+            metrics.concurrency_limit.layer(
+                metric_labels::Direction::Out,
+                config,
+                classify::Request::Default,
+            )
+        });
+
         let http_admit_request = svc::layers()
             // Limits the number of in-flight requests.
-            .push_concurrency_limit(max_in_flight_requests)
+            .push_concurrency_limit(max_in_flight_requests, adaptive_concurrency_limit)
             // Eagerly fail requests when the proxy is out of capacity for a
             // dispatch_timeout.
             .push_failfast(dispatch_timeout)
@@ -506,10 +890,17 @@ impl Config {
         // The stack is served lazily since caching layers spawn tasks from
         // their constructor. This helps to ensure that tasks are spawned on the
         // same runtime as the proxy.
-        // Forwards TCP streams that cannot be decoded as HTTP.
-        let tcp_forward = svc::stack(tcp::Forward::new(tcp_connect))
+        // Forwards TCP streams that cannot be decoded as HTTP, balancing
+        // connections over the target's discovered endpoints.
+        let tcp_forward = svc::stack(tcp::Forward::new(tcp_balance))
             .push(admit::AdmitLayer::new(prevent_loop))
-            .push_map_target(TcpEndpoint::from);
+            .push(admit::AdmitLayer::new(preface::RequireTarget));
+
+        // Reads the server name from connections that begin with a TLS
+        // ClientHello. This applies both to connections that are not HTTP and
+        // to connections on ports that skip protocol detection, including TLS
+        // passthrough ports.
+        let tcp_forward = sni::DetectSni::new(tcp_forward, self.tls_sni, tap_layer);
 
         let http = http::DetectHttp::new(
             h2_settings,
@@ -517,12 +908,31 @@ impl Config {
             http_server,
             tcp_forward.clone(),
             drain.clone(),
-        );
+        )
+        // Serves connections on ports with a declared protocol without
+        // detecting it.
+        .with_known_protocols(port_protocols);
 
         let accept = svc::stack(SkipDetect::new(skip_detect, http, tcp_forward))
-            .push(metrics.transport.layer_accept(TransportLabels));
+            .push(metrics.transport.layer_accept(TransportLabels))
+            // Reads the original destination of Unix domain socket
+            // connections from their preface.
+            .push(svc::layer::mk(|inner| {
+                preface::Preface::new(inner, detect_protocol_timeout)
+            }));
+
+        // Connections from both listeners are served by the same stack.
+        let listen = stream::select(
+            listen.map_ok(|(addrs, tcp)| (addrs, EitherIo::Left(tcp))),
+            stream::iter(unix_listen)
+                .flatten()
+                .map_ok(|(addrs, uds)| (addrs, EitherIo::Right(uds))),
+        );
 
         info!(addr = %listen_addr, "Serving");
+        if let Some(bind) = self.unix_listen.as_ref() {
+            info!(path = %bind.path().display(), "Serving");
+        }
         serve::serve(listen, accept, drain.signal()).await
     }
 }
@@ -547,6 +957,7 @@ impl transport::metrics::TransportLabels<TcpEndpoint> for TransportLabels {
 
     fn transport_labels(&self, endpoint: &TcpEndpoint) -> Self::Labels {
         transport::labels::Key::connect("outbound", endpoint.identity.as_ref())
+            .with_server_name(endpoint.server_name.clone())
     }
 }
 
@@ -0,0 +1,294 @@
+//! Determines the original destination of connections accepted on the
+//! outbound Unix domain socket.
+//!
+//! These connections are not redirected by iptables, so their original
+//! destination can't be read from the socket. HTTP requests are routed by
+//! their authority as usual; other clients must begin each connection with a
+//! preface that names the destination, e.g. `L5D-DST 10.1.2.3:5432\r\n`.
+//! Connections and requests that name no destination are rejected by
+//! [`RequireTarget`] rather than being forwarded to the listener's own
+//! (portless) address.
+
+use super::endpoint::{HttpEndpoint, Target, TcpEndpoint};
+use bytes::BytesMut;
+use futures::prelude::*;
+use linkerd2_app_core::{
+    admit,
+    transport::{
+        io::{EitherIo, PrefixedIo},
+        listen,
+    },
+    Error,
+};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tower::util::ServiceExt;
+use tracing::debug;
+
+const PREFIX: &[u8] = b"L5D-DST ";
+
+/// The longest preface that is read before the connection is rejected.
+const MAX_LEN: usize = 64;
+
+/// Wraps an accept stack so that it serves both TCP connections (`Left`) and
+/// Unix domain socket connections (`Right`).
+#[derive(Clone, Debug)]
+pub struct Preface<M> {
+    inner: M,
+    timeout: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct Accept<M> {
+    addrs: listen::Addrs,
+    inner: M,
+    timeout: Duration,
+}
+
+/// Rejects endpoints that have no target port, i.e. Unix socket connections
+/// that were not prefaced and requests that name no IP destination.
+#[derive(Copy, Clone, Debug)]
+pub struct RequireTarget;
+
+#[derive(Copy, Clone, Debug)]
+pub struct NoTarget(());
+
+#[derive(Debug)]
+pub struct InvalidPreface(());
+
+#[derive(Debug)]
+pub struct PrefaceTimeout(Duration);
+
+// === impl Preface ===
+
+impl<M> Preface<M> {
+    pub fn new(inner: M, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl<M: Clone> tower::Service<listen::Addrs> for Preface<M> {
+    type Response = Accept<M>;
+    type Error = Error;
+    type Future = future::Ready<Result<Accept<M>, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, addrs: listen::Addrs) -> Self::Future {
+        future::ok(Accept {
+            addrs,
+            inner: self.inner.clone(),
+            timeout: self.timeout,
+        })
+    }
+}
+
+// === impl Accept ===
+
+impl<M, A, T, U> tower::Service<EitherIo<T, U>> for Accept<M>
+where
+    T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    U: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    M: tower::Service<listen::Addrs, Response = A> + Clone + Send + 'static,
+    M::Error: Into<Error>,
+    M::Future: Send,
+    A: tower::Service<EitherIo<T, PrefixedIo<U>>, Response = ()> + Send + 'static,
+    A::Error: Into<Error>,
+    A::Future: Send,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, io: EitherIo<T, U>) -> Self::Future {
+        let addrs = self.addrs.clone();
+        let inner = self.inner.clone();
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let (addrs, io) = match io {
+                EitherIo::Left(tcp) => (addrs, EitherIo::Left(tcp)),
+                EitherIo::Right(uds) => {
+                    let (orig_dst, io) = tokio::time::timeout(timeout, read(uds))
+                        .await
+                        .map_err(|_| PrefaceTimeout(timeout))??;
+                    let addrs = match orig_dst {
+                        Some(orig_dst) => {
+                            debug!(%orig_dst, "Read connection preface");
+                            listen::Addrs::new(addrs.local(), addrs.peer(), Some(orig_dst))
+                        }
+                        None => addrs,
+                    };
+                    (addrs, EitherIo::Right(io))
+                }
+            };
+
+            let accept = inner.oneshot(addrs).await.map_err(Into::into)?;
+            accept.oneshot(io).await.map_err(Into::into)
+        })
+    }
+}
+
+// === impl RequireTarget ===
+
+impl RequireTarget {
+    fn check(addr: SocketAddr) -> Result<(), NoTarget> {
+        if addr.port() == 0 {
+            return Err(NoTarget(()));
+        }
+
+        Ok(())
+    }
+}
+
+impl admit::Admit<Target<HttpEndpoint>> for RequireTarget {
+    type Error = NoTarget;
+
+    fn admit(&mut self, ep: &Target<HttpEndpoint>) -> Result<(), Self::Error> {
+        Self::check(ep.inner.addr)
+    }
+}
+
+impl admit::Admit<TcpEndpoint> for RequireTarget {
+    type Error = NoTarget;
+
+    fn admit(&mut self, ep: &TcpEndpoint) -> Result<(), Self::Error> {
+        Self::check(ep.addr)
+    }
+}
+
+impl admit::Admit<Target<TcpEndpoint>> for RequireTarget {
+    type Error = NoTarget;
+
+    fn admit(&mut self, ep: &Target<TcpEndpoint>) -> Result<(), Self::Error> {
+        Self::check(ep.inner.addr)
+    }
+}
+
+/// Reads a connection preface, if one is present, returning the connection
+/// with any other bytes that were read.
+async fn read<I>(mut io: I) -> Result<(Option<SocketAddr>, PrefixedIo<I>), Error>
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buf = BytesMut::with_capacity(MAX_LEN);
+    loop {
+        // Stop reading as soon as the connection can't start with a preface.
+        let n = buf.len().min(PREFIX.len());
+        if buf[..n] != PREFIX[..n] {
+            return Ok((None, PrefixedIo::new(buf.freeze(), io)));
+        }
+
+        if let Some(idx) = buf.iter().position(|b| *b == b'\n') {
+            let line = buf.split_to(idx + 1);
+            let orig_dst = std::str::from_utf8(&line[PREFIX.len()..])
+                .ok()
+                .and_then(|addr| addr.trim_end().parse().ok())
+                .ok_or(InvalidPreface(()))?;
+            return Ok((Some(orig_dst), PrefixedIo::new(buf.freeze(), io)));
+        }
+
+        if buf.len() >= MAX_LEN {
+            return Err(InvalidPreface(()).into());
+        }
+        if io.read_buf(&mut buf).await? == 0 {
+            return Ok((None, PrefixedIo::new(buf.freeze(), io)));
+        }
+    }
+}
+
+// === impl NoTarget ===
+
+impl std::fmt::Display for NoTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no destination; Unix socket clients must send an `L5D-DST` preface or an HTTP request with an IP authority"
+        )
+    }
+}
+
+impl std::error::Error for NoTarget {}
+
+// === impl InvalidPreface ===
+
+impl std::fmt::Display for InvalidPreface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invalid connection preface; expected `L5D-DST <ip>:<port>`"
+        )
+    }
+}
+
+impl std::error::Error for InvalidPreface {}
+
+// === impl PrefaceTimeout ===
+
+impl std::fmt::Display for PrefaceTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "connection preface not read after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for PrefaceTimeout {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_preface() {
+        let io = tokio_test::io::Builder::new()
+            .read(b"L5D-DST 10.1.2.3:5432\r\nhello")
+            .build();
+        let (orig_dst, mut io) = read(io).await.unwrap();
+        assert_eq!(orig_dst, Some(SocketAddr::from(([10, 1, 2, 3], 5432))));
+
+        let mut rest = String::new();
+        io.read_to_string(&mut rest).await.unwrap();
+        assert_eq!(rest, "hello");
+    }
+
+    #[tokio::test]
+    async fn passes_through_http() {
+        let io = tokio_test::io::Builder::new()
+            .read(b"GET / HTTP/1.1\r\nhost: example.com\r\n\r\n")
+            .build();
+        let (orig_dst, io) = read(io).await.unwrap();
+        assert_eq!(orig_dst, None);
+        assert_eq!(
+            &io.prefix()[..],
+            &b"GET / HTTP/1.1\r\nhost: example.com\r\n\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn requires_target_port() {
+        use linkerd2_app_core::admit::Admit;
+
+        let local = SocketAddr::from(([127, 0, 0, 1], 0));
+        let unix = TcpEndpoint::from(listen::Addrs::new(local, local, None));
+        assert!(RequireTarget.admit(&unix).is_err());
+
+        let dst = SocketAddr::from(([10, 1, 2, 3], 5432));
+        let prefaced = TcpEndpoint::from(listen::Addrs::new(local, local, Some(dst)));
+        assert!(RequireTarget.admit(&prefaced).is_ok());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_preface() {
+        let io = tokio_test::io::Builder::new()
+            .read(b"L5D-DST example.com\r\n")
+            .build();
+        assert!(read(io).await.is_err());
+    }
+}
@@ -44,6 +44,14 @@ impl admit::Admit<TcpEndpoint> for PreventLoop {
     }
 }
 
+impl admit::Admit<Target<TcpEndpoint>> for PreventLoop {
+    type Error = LoopPrevented;
+
+    fn admit(&mut self, ep: &Target<TcpEndpoint>) -> Result<(), Self::Error> {
+        admit::Admit::<TcpEndpoint>::admit(self, &ep.inner)
+    }
+}
+
 impl std::fmt::Display for LoopPrevented {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
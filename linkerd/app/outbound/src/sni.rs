@@ -0,0 +1,417 @@
+//! Reads the server name from outbound connections that begin with a TLS
+//! ClientHello.
+//!
+//! Applications that initiate TLS themselves appear to the proxy as opaque
+//! streams. The ClientHello is read without terminating TLS so that its SNI
+//! extension can label the connection's metrics, inform the egress policy
+//! and, optionally, route the connection by name rather than by IP.
+//! Connections that do not present a server name before the timeout are
+//! forwarded as-is.
+//!
+//! Tap only observes HTTP messages, so connections with a server name are
+//! reported to tap as `CONNECT` requests to that name. The response is
+//! recorded when the connection is forwarded and ends when the connection
+//! closes.
+
+use crate::endpoint::{Logical, TcpEndpoint};
+use bytes::{Bytes, BytesMut};
+use futures::{prelude::*, ready};
+use indexmap::IndexMap;
+use linkerd2_app_core::{
+    dns,
+    proxy::{
+        http::{boxed::Payload, HttpBody},
+        identity, tap,
+    },
+    transport::{io::PrefixedIo, listen, tls, tls::conditional_accept},
+    Conditional, Error, NameAddr,
+};
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tower::util::ServiceExt;
+use tracing::debug;
+
+/// The most bytes that are read while waiting for a complete ClientHello.
+const MAX_LEN: usize = 16 * 1024;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// How long to wait for a ClientHello before forwarding the connection.
+    pub timeout: Duration,
+    /// Whether connections are routed to the endpoints discovered for their
+    /// server name rather than to their original destination.
+    pub discover: bool,
+}
+
+/// Builds an accept service for each connection that reads its server name
+/// when `config` is set.
+#[derive(Clone, Debug)]
+pub struct DetectSni<M> {
+    inner: M,
+    config: Option<Config>,
+    tap: tap::Layer,
+}
+
+#[derive(Clone, Debug)]
+pub struct Accept<M> {
+    addrs: listen::Addrs,
+    inner: M,
+    config: Option<Config>,
+    tap: tap::Layer,
+}
+
+/// Describes a connection with a server name to tap.
+#[derive(Clone, Debug)]
+struct Tunnel {
+    addrs: listen::Addrs,
+    authority: String,
+    labels: IndexMap<String, String>,
+}
+
+type Forward = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+/// Responds to a tapped `CONNECT` request by forwarding the connection.
+struct Connect(Option<Forward>);
+
+/// A response body that completes when the connection is closed.
+struct Connection(Option<Forward>);
+
+// === impl DetectSni ===
+
+impl<M> DetectSni<M> {
+    pub fn new(inner: M, config: Option<Config>, tap: tap::Layer) -> Self {
+        Self { inner, config, tap }
+    }
+}
+
+impl<M: Clone> tower::Service<listen::Addrs> for DetectSni<M> {
+    type Response = Accept<M>;
+    type Error = Error;
+    type Future = future::Ready<Result<Accept<M>, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, addrs: listen::Addrs) -> Self::Future {
+        future::ok(Accept {
+            addrs,
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            tap: self.tap.clone(),
+        })
+    }
+}
+
+// === impl Accept ===
+
+impl<M, A, I> tower::Service<I> for Accept<M>
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    M: tower::Service<Logical<TcpEndpoint>, Response = A> + Clone + Send + 'static,
+    M::Error: Into<Error>,
+    M::Future: Send,
+    A: tower::Service<PrefixedIo<I>, Response = ()> + Send + 'static,
+    A::Error: Into<Error>,
+    A::Future: Send,
+{
+    type Response = ();
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut io: I) -> Self::Future {
+        let addrs = self.addrs.clone();
+        let inner = self.inner.clone();
+        let config = self.config.clone();
+        let tap = self.tap.clone();
+        Box::pin(async move {
+            let mut target = Logical::<TcpEndpoint>::from(addrs.clone());
+            let mut buf = BytesMut::with_capacity(1024);
+            if let Some(config) = config {
+                let server_name = tokio::time::timeout(config.timeout, read(&mut io, &mut buf))
+                    .await
+                    .unwrap_or_else(|_| {
+                        debug!("Timed out waiting for a ClientHello");
+                        Ok(None)
+                    })?;
+                if let Some(server_name) = server_name {
+                    debug!(%server_name, "Read server name");
+                    target = config.target(target, server_name);
+                }
+            }
+
+            let server_name = target.inner.server_name.clone();
+            let forward = Box::pin(async move {
+                let accept = inner.oneshot(target).await.map_err(Into::into)?;
+                accept
+                    .oneshot(PrefixedIo::new(buf.freeze(), io))
+                    .await
+                    .map_err(Into::into)
+            });
+
+            match server_name {
+                Some(server_name) => Tunnel::new(addrs, &server_name).forward(tap, forward).await,
+                None => forward.await,
+            }
+        })
+    }
+}
+
+/// Reads from `io` until the buffered bytes either do or do not contain a
+/// ClientHello with a server name.
+async fn read<I>(io: &mut I, buf: &mut BytesMut) -> std::io::Result<Option<identity::Name>>
+where
+    I: AsyncRead + Unpin,
+{
+    loop {
+        match conditional_accept::read_server_name(buf.as_ref()) {
+            conditional_accept::ServerName::Name(name) => return Ok(Some(name)),
+            conditional_accept::ServerName::None => return Ok(None),
+            conditional_accept::ServerName::Incomplete if buf.len() >= MAX_LEN => return Ok(None),
+            conditional_accept::ServerName::Incomplete => {}
+        }
+
+        if io.read_buf(buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
+
+// === impl Tunnel ===
+
+impl Tunnel {
+    fn new(addrs: listen::Addrs, server_name: &identity::Name) -> Self {
+        let authority = format!("{}:{}", server_name, addrs.target_addr().port());
+        let mut labels = IndexMap::with_capacity(1);
+        labels.insert("server_name".to_string(), server_name.to_string());
+        Self {
+            addrs,
+            authority,
+            labels,
+        }
+    }
+
+    /// Forwards the connection, reporting it to `tap` as a `CONNECT` request
+    /// to the server name.
+    async fn forward(self, tap: tap::Layer, forward: Forward) -> Result<(), Error> {
+        use tower::layer::Layer;
+
+        let req = http::Request::builder()
+            .method(http::Method::CONNECT)
+            .uri(self.authority.as_str())
+            .body(Payload::default())?;
+
+        let mut connect = Some(Connect(Some(forward)));
+        let tapped = tap
+            .layer(tower::service_fn(move |_: Tunnel| {
+                future::ok::<_, Error>(connect.take().unwrap_or(Connect(None)))
+            }))
+            .oneshot(self)
+            .await?;
+        let mut body = tapped.oneshot(req).await?.into_body();
+        while let Some(res) = body.data().await {
+            res?;
+        }
+        Ok(())
+    }
+}
+
+impl tap::Inspect for Tunnel {
+    fn src_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        Some(self.addrs.peer())
+    }
+
+    fn src_tls<'a, B>(
+        &self,
+        _: &'a http::Request<B>,
+    ) -> Conditional<&'a identity::Name, tls::ReasonForNoPeerName> {
+        Conditional::None(tls::ReasonForNoPeerName::Loopback)
+    }
+
+    fn dst_addr<B>(&self, _: &http::Request<B>) -> Option<SocketAddr> {
+        Some(self.addrs.target_addr())
+    }
+
+    fn dst_labels<B>(&self, _: &http::Request<B>) -> Option<&IndexMap<String, String>> {
+        Some(&self.labels)
+    }
+
+    fn dst_tls<B>(
+        &self,
+        _: &http::Request<B>,
+    ) -> Conditional<&identity::Name, tls::ReasonForNoPeerName> {
+        Conditional::None(tls::ReasonForNoPeerName::NotHttp)
+    }
+
+    fn route_labels<B>(&self, _: &http::Request<B>) -> Option<Arc<IndexMap<String, String>>> {
+        None
+    }
+
+    fn is_outbound<B>(&self, _: &http::Request<B>) -> bool {
+        true
+    }
+}
+
+// === impl Connect ===
+
+impl<B> tower::Service<http::Request<B>> for Connect {
+    type Response = http::Response<Connection>;
+    type Error = Error;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: http::Request<B>) -> Self::Future {
+        future::ok(http::Response::new(Connection(self.0.take())))
+    }
+}
+
+// === impl Connection ===
+
+impl HttpBody for Connection {
+    type Data = Bytes;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let res = match self.0.as_mut() {
+            Some(forward) => ready!(forward.as_mut().poll(cx)),
+            None => return Poll::Ready(None),
+        };
+        self.0 = None;
+        Poll::Ready(res.err().map(Err))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Annotates the target with the server name.
+    fn target(
+        &self,
+        mut target: Logical<TcpEndpoint>,
+        server_name: identity::Name,
+    ) -> Logical<TcpEndpoint> {
+        if self.discover {
+            if let Ok(name) = dns::Name::try_from(server_name.as_ref().as_bytes()) {
+                target.addr = NameAddr::new(name, target.inner.addr.port()).into();
+            }
+        }
+
+        target.inner.server_name = Some(server_name);
+        target
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_app_core::Addr;
+    use tokio::sync::mpsc;
+
+    static CLIENT_HELLO: &[u8] =
+        include_bytes!("../../../proxy/transport/src/tls/testdata/example-com-client-hello.bin");
+
+    fn addrs() -> listen::Addrs {
+        let addr = ([10, 0, 0, 1], 443).into();
+        listen::Addrs::new(addr, addr, Some(addr))
+    }
+
+    fn target() -> Logical<TcpEndpoint> {
+        Logical::from(addrs())
+    }
+
+    /// Forwards a connection with `bytes`, returning the target and the
+    /// bytes that were forwarded.
+    async fn forward(bytes: &[u8], config: Config) -> (Logical<TcpEndpoint>, Vec<u8>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let inner = tower::service_fn(move |target: Logical<TcpEndpoint>| {
+            let tx = tx.clone();
+            future::ok::<_, Error>(tower::service_fn(
+                move |mut io: PrefixedIo<tokio_test::io::Mock>| {
+                    let tx = tx.clone();
+                    let target = target.clone();
+                    async move {
+                        let mut buf = Vec::new();
+                        io.read_to_end(&mut buf).await?;
+                        let _ = tx.send((target, buf));
+                        Ok::<_, Error>(())
+                    }
+                },
+            ))
+        });
+
+        let (_, tap, _) = tap::new();
+        let accept = DetectSni::new(inner, Some(config), tap)
+            .oneshot(addrs())
+            .await
+            .expect("accept");
+        let io = tokio_test::io::Builder::new().read(bytes).build();
+        accept.oneshot(io).await.expect("forward");
+        rx.recv().await.expect("forwarded")
+    }
+
+    fn config(discover: bool) -> Config {
+        Config {
+            timeout: Duration::from_secs(1),
+            discover,
+        }
+    }
+
+    fn name(s: &str) -> identity::Name {
+        identity::Name::from_hostname(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn labels_target_with_server_name() {
+        let t = config(false).target(target(), name("api.example.com"));
+        assert_eq!(t.inner.server_name, Some(name("api.example.com")));
+        assert_eq!(t.addr, Addr::from(t.inner.addr));
+    }
+
+    #[tokio::test]
+    async fn forwards_connections_with_server_names() {
+        let (t, buf) = forward(CLIENT_HELLO, config(false)).await;
+        assert_eq!(t.inner.server_name, Some(name("example.com")));
+        assert_eq!(buf, CLIENT_HELLO);
+    }
+
+    #[tokio::test]
+    async fn forwards_connections_without_server_names() {
+        let bytes = b"GET / HTTP/1.1\r\n\r\n";
+        let (t, buf) = forward(bytes, config(false)).await;
+        assert_eq!(t.inner.server_name, None);
+        assert_eq!(buf, &bytes[..]);
+    }
+
+    #[test]
+    fn discovers_server_name() {
+        let t = config(true).target(target(), name("api.example.com"));
+        assert_eq!(t.addr.to_string(), "api.example.com:443");
+        assert_eq!(t.inner.addr, ([10, 0, 0, 1], 443).into());
+    }
+}
@@ -0,0 +1,201 @@
+//! Tunnels opaque TCP streams to meshed endpoints over HTTP/2 `CONNECT`.
+//!
+//! Each stream is carried on the HTTP/2 connection that is shared by all
+//! tunnels to the endpoint, so that only one mTLS handshake is needed per
+//! peer. The request's authority names the endpoint and the logical target
+//! is sent in the `l5d-dst-canonical` header.
+//!
+//! Streams are only tunneled to endpoints that discovery advertises as
+//! accepting tunnels; all other endpoints are connected to directly.
+
+use crate::endpoint::TcpEndpoint;
+use futures::prelude::*;
+use linkerd2_app_core::{
+    proxy::http::{self, tunnel},
+    transport::io::EitherIo,
+    Error, CANONICAL_DST_HEADER,
+};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::util::ServiceExt;
+use tracing::debug;
+
+/// Establishes connections directly or, for meshed endpoints, through a
+/// tunnel.
+#[derive(Clone, Debug)]
+pub struct Tunnel<C, T> {
+    direct: C,
+    tunnel: T,
+    enabled: bool,
+}
+
+/// Opens tunnels on clients obtained from an HTTP/2 client cache.
+#[derive(Clone, Debug)]
+pub struct Connect<H> {
+    clients: H,
+}
+
+#[derive(Debug)]
+pub struct TunnelRejected(http::StatusCode);
+
+// === impl Tunnel ===
+
+impl<C, T> Tunnel<C, T> {
+    pub fn new(enabled: bool, direct: C, tunnel: T) -> Self {
+        Self {
+            direct,
+            tunnel,
+            enabled,
+        }
+    }
+}
+
+impl<C, T> tower::Service<TcpEndpoint> for Tunnel<C, T>
+where
+    C: tower::Service<TcpEndpoint, Error = Error>,
+    C::Future: Send + 'static,
+    T: tower::Service<TcpEndpoint, Error = Error> + Clone + Send + 'static,
+    T::Future: Send + 'static,
+{
+    type Response = EitherIo<C::Response, T::Response>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.direct.poll_ready(cx)
+    }
+
+    fn call(&mut self, endpoint: TcpEndpoint) -> Self::Future {
+        // Only endpoints whose proxies are known to terminate tunnels are
+        // tunneled to, and only over mTLS.
+        if self.enabled && endpoint.tcp_tunnel && endpoint.identity.value().is_some() {
+            let tunnel = self.tunnel.clone().oneshot(endpoint);
+            return Box::pin(tunnel.map_ok(EitherIo::Right));
+        }
+
+        Box::pin(self.direct.call(endpoint).map_ok(EitherIo::Left))
+    }
+}
+
+// === impl Connect ===
+
+impl<H> Connect<H> {
+    pub fn new(clients: H) -> Self {
+        Self { clients }
+    }
+}
+
+impl<H, S> tower::Service<TcpEndpoint> for Connect<H>
+where
+    H: tower::Service<TcpEndpoint, Response = S, Error = Error> + Clone + Send + 'static,
+    H::Future: Send + 'static,
+    S: tower::Service<
+            http::Request<http::boxed::Payload>,
+            Response = http::Response<http::glue::Body>,
+            Error = Error,
+        > + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = tunnel::TunnelIo<http::glue::Body>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, endpoint: TcpEndpoint) -> Self::Future {
+        let authority = endpoint
+            .addr
+            .to_string()
+            .parse::<http::uri::Authority>()
+            .expect("socket addresses must be valid authorities");
+        let (mut req, tx) = tunnel::request(authority);
+        if let Some(logical) = endpoint.logical.as_ref() {
+            if let Ok(value) = http::header::HeaderValue::from_str(&logical.to_string()) {
+                req.headers_mut().insert(CANONICAL_DST_HEADER, value);
+            }
+        }
+
+        // Tunnels to an endpoint share a client, regardless of their logical
+        // target.
+        let clients = self.clients.clone().oneshot(TcpEndpoint {
+            logical: None,
+            server_name: None,
+            ..endpoint
+        });
+        Box::pin(async move {
+            let client = clients.await?;
+            let rsp = client.oneshot(req).await?;
+            if !rsp.status().is_success() {
+                return Err(TunnelRejected(rsp.status()).into());
+            }
+            debug!("Tunnel established");
+            Ok(tunnel::TunnelIo::new(rsp.into_body(), tx))
+        })
+    }
+}
+
+// === impl TunnelRejected ===
+
+impl std::fmt::Display for TunnelRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "tunnel rejected by peer with status {}", self.0)
+    }
+}
+
+impl std::error::Error for TunnelRejected {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_app_core::{proxy::identity, transport::tls, Conditional};
+    use std::net::SocketAddr;
+
+    fn endpoint(tcp_tunnel: bool, identity: tls::PeerIdentity) -> TcpEndpoint {
+        TcpEndpoint {
+            addr: SocketAddr::from(([10, 1, 2, 3], 5432)),
+            identity,
+            logical: None,
+            server_name: None,
+            tcp_tunnel,
+        }
+    }
+
+    fn meshed() -> tls::PeerIdentity {
+        let id = b"foo.ns.serviceaccount.identity.linkerd.cluster.local";
+        Conditional::Some(identity::Name::from_hostname(id).unwrap())
+    }
+
+    fn unmeshed() -> tls::PeerIdentity {
+        Conditional::None(tls::ReasonForNoPeerName::NotProvidedByServiceDiscovery.into())
+    }
+
+    fn tunnel(
+        enabled: bool,
+    ) -> impl tower::Service<TcpEndpoint, Response = EitherIo<(), ()>, Error = Error> {
+        let direct = tower::service_fn(|_: TcpEndpoint| future::ok::<(), Error>(()));
+        let tunnel = tower::service_fn(|_: TcpEndpoint| future::ok::<(), Error>(()));
+        Tunnel::new(enabled, direct, tunnel)
+    }
+
+    async fn is_tunneled(enabled: bool, ep: TcpEndpoint) -> bool {
+        match tunnel(enabled).oneshot(ep).await.unwrap() {
+            EitherIo::Left(()) => false,
+            EitherIo::Right(()) => true,
+        }
+    }
+
+    #[tokio::test]
+    async fn tunnels_to_endpoints_that_accept_tunnels() {
+        assert!(is_tunneled(true, endpoint(true, meshed())).await);
+    }
+
+    #[tokio::test]
+    async fn connects_directly_unless_the_endpoint_accepts_tunnels() {
+        assert!(!is_tunneled(true, endpoint(false, meshed())).await);
+        assert!(!is_tunneled(true, endpoint(true, unmeshed())).await);
+        assert!(!is_tunneled(false, endpoint(true, meshed())).await);
+    }
+}
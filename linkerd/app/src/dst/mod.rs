@@ -20,6 +20,9 @@ pub struct Config {
     pub get_networks: IndexSet<ipnet::IpNet>,
     pub profile_suffixes: IndexSet<dns::Suffix>,
     pub initial_profile_timeout: Duration,
+    /// Policies that are applied to profile routes in addition to those
+    /// configured by the destination service.
+    pub route_policies: profiles::RoutePolicies,
 }
 
 /// Handles to destination service clients.
@@ -56,7 +59,8 @@ impl Config {
             self.initial_profile_timeout,
             self.context,
             self.profile_suffixes,
-        );
+        )
+        .with_route_policies(self.route_policies);
 
         Ok(Dst {
             addr: self.control.addr,
@@ -1,12 +1,15 @@
 use crate::core::{
-    addr,
+    addr, authz,
     config::*,
-    proxy::http::h2,
+    egress, failover, http_health, http_locality, http_outlier,
+    profiles::{self, RateLimit},
+    proxy::http::{balance::HashKey, h2, header::HeaderName, uri::PathAndQuery},
+    proxy_protocol,
     transport::{listen, tls},
-    Addr,
+    Addr, NameAddr,
 };
 use crate::{dns, gateway, identity, inbound, oc_collector, outbound};
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::iter::FromIterator;
@@ -46,6 +49,16 @@ pub enum ParseError {
     NameError,
     InvalidTokenSource,
     InvalidTrustAnchors,
+    NotAHashKey,
+    NotAHealthCheck,
+    NotAFailover,
+    NotARateLimit,
+    NotAnAuthorization,
+    NotAUnixSocket,
+    NotAHeaderName,
+    NotAPortProtocol,
+    NotAnEgressRule,
+    NotARoutePolicy,
 }
 
 // Environment variables to look at when loading the configuration
@@ -54,6 +67,68 @@ pub const ENV_INBOUND_LISTEN_ADDR: &str = "LINKERD2_PROXY_INBOUND_LISTEN_ADDR";
 pub const ENV_CONTROL_LISTEN_ADDR: &str = "LINKERD2_PROXY_CONTROL_LISTEN_ADDR";
 pub const ENV_ADMIN_LISTEN_ADDR: &str = "LINKERD2_PROXY_ADMIN_LISTEN_ADDR";
 
+/// Accepts outbound application traffic on a Unix domain socket at this path,
+/// in addition to the outbound TCP listener.
+///
+/// Connections on the socket have no original destination, so HTTP requests
+/// are routed by their authority and other connections must begin with an
+/// `L5D-DST <ip>:<port>\r\n` preface.
+pub const ENV_OUTBOUND_UNIX_LISTEN_PATH: &str = "LINKERD2_PROXY_OUTBOUND_UNIX_LISTEN_PATH";
+
+/// If set to a non-empty value, opaque TCP streams to meshed endpoints are
+/// carried as HTTP/2 `CONNECT` streams over a connection that is shared by all
+/// streams to the endpoint. Only endpoints that the destination service labels
+/// with `tcp_tunnel="true"` are tunneled to; their proxies must accept tunnels
+/// (see `LINKERD2_PROXY_INBOUND_TCP_TUNNEL`).
+pub const ENV_OUTBOUND_TCP_TUNNEL: &str = "LINKERD2_PROXY_OUTBOUND_TCP_TUNNEL";
+
+/// If set to a non-empty value, HTTP/2 `CONNECT` requests from meshed clients
+/// are terminated by the inbound proxy and their streams are forwarded to the
+/// application as TCP connections.
+pub const ENV_INBOUND_TCP_TUNNEL: &str = "LINKERD2_PROXY_INBOUND_TCP_TUNNEL";
+
+/// If set to a non-empty value, the server name is read from outbound
+/// connections that are not HTTP, or that are on ports that skip protocol
+/// detection, and begin with a TLS ClientHello. The connection's TCP metrics
+/// are labeled with the name, the name is checked against the egress policy,
+/// and the connection is reported to tap as a `CONNECT` request to the name.
+///
+/// Server-speaks-first connections on ports that skip protocol detection are
+/// forwarded once `LINKERD2_PROXY_OUTBOUND_TLS_SNI_TIMEOUT` elapses.
+pub const ENV_OUTBOUND_TLS_SNI: &str = "LINKERD2_PROXY_OUTBOUND_TLS_SNI";
+
+/// How long to wait for a ClientHello before forwarding a connection without
+/// a server name.
+pub const ENV_OUTBOUND_TLS_SNI_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_TLS_SNI_TIMEOUT";
+
+/// If set to a non-empty value, connections are routed to the endpoints
+/// discovered for their server name rather than their original destination.
+pub const ENV_OUTBOUND_TLS_SNI_DISCOVER: &str = "LINKERD2_PROXY_OUTBOUND_TLS_SNI_DISCOVER";
+
+/// Restricts outbound traffic to external destinations, i.e. traffic that is
+/// not resolved by the destination service and is forwarded to an address
+/// outside of `LINKERD2_PROXY_DESTINATION_GET_NETWORKS`.
+///
+/// A comma-separated list of `<action>=<match>[;<match>]` rules, where the
+/// action is `allow` or `deny` and each match is one of `name:<suffix>`,
+/// `net:<network>`, `port:<port>`, or `*`, e.g.
+/// `deny=name:evil.example.com,allow=name:example.com;port:443`. Requests are
+/// matched by their host and connections by their TLS server name, if any.
+/// A name only matches an `allow` rule if it resolves to the destination's
+/// address. The first matching rule applies.
+pub const ENV_OUTBOUND_EGRESS_RULES: &str = "LINKERD2_PROXY_OUTBOUND_EGRESS_RULES";
+
+/// The action, `allow` or `deny`, for external destinations that match no
+/// egress rule. Defaults to `allow`.
+pub const ENV_OUTBOUND_EGRESS_DEFAULT: &str = "LINKERD2_PROXY_OUTBOUND_EGRESS_DEFAULT";
+
+/// If set to a non-empty value, egress denials are recorded and logged but
+/// not enforced.
+pub const ENV_OUTBOUND_EGRESS_DRY_RUN: &str = "LINKERD2_PROXY_OUTBOUND_EGRESS_DRY_RUN";
+
+/// If set to a non-empty value, every egress decision is logged.
+pub const ENV_OUTBOUND_EGRESS_LOG: &str = "LINKERD2_PROXY_OUTBOUND_EGRESS_LOG";
+
 // When compiled with the `mock-orig-dst` flag, these environment variables are required to
 // configure the proxy's behavior.
 
@@ -85,6 +160,187 @@ pub const ENV_OUTBOUND_ROUTER_MAX_IDLE_AGE: &str = "LINKERD2_PROXY_OUTBOUND_ROUT
 pub const ENV_INBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT";
 pub const ENV_OUTBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_OUTBOUND_MAX_IN_FLIGHT";
 
+/// Enables adaptive limiting of in-flight requests, starting from this limit.
+///
+/// The limit grows and shrinks based on observed latency and failures, and
+/// never exceeds the `MAX_IN_FLIGHT` limit. Requests that exceed the limit
+/// fail immediately. If unspecified, the fixed `MAX_IN_FLIGHT` limit is used.
+pub const ENV_INBOUND_ADAPTIVE_CONCURRENCY_INITIAL_LIMIT: &str =
+    "LINKERD2_PROXY_INBOUND_ADAPTIVE_CONCURRENCY_INITIAL_LIMIT";
+pub const ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_INITIAL_LIMIT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ADAPTIVE_CONCURRENCY_INITIAL_LIMIT";
+
+/// Ejects an outbound endpoint from its balancer after this many consecutive
+/// failed responses.
+///
+/// If unspecified, endpoints are not ejected due to consecutive failures.
+pub const ENV_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES";
+
+/// Ejects an outbound endpoint from its balancer when the ratio of its failed
+/// responses to all responses, between 0.0 and 1.0, exceeds this value.
+///
+/// If unspecified, endpoints are not ejected due to their failure rate.
+pub const ENV_OUTBOUND_OUTLIER_FAILURE_RATE: &str = "LINKERD2_PROXY_OUTBOUND_OUTLIER_FAILURE_RATE";
+pub const ENV_OUTBOUND_OUTLIER_FAILURE_RATE_MIN_REQUESTS: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_FAILURE_RATE_MIN_REQUESTS";
+pub const ENV_OUTBOUND_OUTLIER_FAILURE_RATE_WINDOW: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_FAILURE_RATE_WINDOW";
+
+/// Configures how long an ejected outbound endpoint is removed from its
+/// balancer. Each subsequent ejection doubles this time, up to the maximum.
+pub const ENV_OUTBOUND_OUTLIER_BASE_EJECTION_TIME: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_BASE_EJECTION_TIME";
+pub const ENV_OUTBOUND_OUTLIER_MAX_EJECTION_TIME: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_MAX_EJECTION_TIME";
+
+/// Limits the percentage of a balancer's endpoints that may be ejected at once.
+pub const ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT";
+
+/// The proxy's own locality, e.g. its zone. When set, outbound balancers
+/// prefer endpoints whose locality label matches this value.
+///
+/// If unspecified, endpoints are balanced without regard to their locality.
+pub const ENV_OUTBOUND_LOCALITY: &str = "LINKERD2_PROXY_OUTBOUND_LOCALITY";
+
+/// The destination endpoint label that holds an endpoint's locality.
+pub const ENV_OUTBOUND_LOCALITY_LABEL: &str = "LINKERD2_PROXY_OUTBOUND_LOCALITY_LABEL";
+
+/// Spills traffic over to other localities when fewer than this many local
+/// endpoints are available.
+pub const ENV_OUTBOUND_LOCALITY_MIN_ENDPOINTS: &str =
+    "LINKERD2_PROXY_OUTBOUND_LOCALITY_MIN_ENDPOINTS";
+
+/// Spills traffic over to other localities when local endpoints have, on
+/// average, at least this many requests in flight.
+pub const ENV_OUTBOUND_LOCALITY_MAX_LOAD: &str = "LINKERD2_PROXY_OUTBOUND_LOCALITY_MAX_LOAD";
+
+/// Destinations whose requests are balanced by a consistent hash of a request
+/// key, so that requests with the same key are sent to the same endpoint.
+/// Each endpoint owns a share of keys in proportion to its weight.
+///
+/// A comma-separated list of `<authority>=<key>` entries, where each key is
+/// one of `header:<name>`, `cookie:<name>`, or `source` (the client's IP
+/// address), e.g. `cache.ns.svc.cluster.local:11211=header:x-user-id`.
+pub const ENV_OUTBOUND_HASH_KEYS: &str = "LINKERD2_PROXY_OUTBOUND_HASH_KEYS";
+
+/// Destinations whose endpoints are actively health checked. Endpoints that
+/// fail their health checks are withheld from their balancers.
+///
+/// A comma-separated list of `<authority>=<probe>` entries, where each probe is
+/// one of `http:<path>` (a `GET` request that expects a 2xx response) or
+/// `grpc[:<service>]` (a `grpc.health.v1.Health/Check` request), e.g.
+/// `web.ns.svc.cluster.local:8080=http:/healthz`.
+pub const ENV_OUTBOUND_HEALTH_CHECKS: &str = "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECKS";
+
+/// Configures the time between health check probes of an endpoint, and up to
+/// how much time is randomly added to each interval.
+pub const ENV_OUTBOUND_HEALTH_CHECK_INTERVAL: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_INTERVAL";
+pub const ENV_OUTBOUND_HEALTH_CHECK_JITTER: &str = "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_JITTER";
+
+/// A health check probe fails if it does not complete within this time.
+pub const ENV_OUTBOUND_HEALTH_CHECK_TIMEOUT: &str = "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_TIMEOUT";
+
+/// Marks an endpoint unhealthy after this many consecutive failed probes, and
+/// healthy again after this many consecutive successful probes.
+pub const ENV_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD";
+pub const ENV_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD";
+
+/// The duration over which a newly discovered endpoint's share of traffic is
+/// ramped up to its full weight.
+///
+/// If unspecified, endpoints receive their full share of traffic as soon as
+/// they are discovered.
+pub const ENV_OUTBOUND_SLOW_START: &str = "LINKERD2_PROXY_OUTBOUND_SLOW_START";
+
+/// Destinations whose traffic fails over to backup destinations while they
+/// have no available endpoints.
+///
+/// A comma-separated list of `<authority>=<backup>[|<backup>...]` entries,
+/// where backups are listed in order of preference, e.g.
+/// `web.ns.svc.cluster.local:8080=web.ns.svc.west.example.com:8080`.
+pub const ENV_OUTBOUND_FAILOVER: &str = "LINKERD2_PROXY_OUTBOUND_FAILOVER";
+
+/// Configures how long a destination must be unavailable before its traffic
+/// fails over to its next backup.
+///
+/// This should be less than the dispatch timeout, after which requests fail
+/// instead.
+pub const ENV_OUTBOUND_FAILOVER_THRESHOLD: &str = "LINKERD2_PROXY_OUTBOUND_FAILOVER_THRESHOLD";
+
+/// Destinations whose requests are rate limited.
+///
+/// A comma-separated list of `<authority>=<rate>[:<burst>]` entries, where the
+/// rate is the number of requests permitted per second and the burst is the
+/// number of requests that may be sent at once (by default, the rate), e.g.
+/// `web.ns.svc.cluster.local:8080=100:200`.
+pub const ENV_OUTBOUND_RATE_LIMITS: &str = "LINKERD2_PROXY_OUTBOUND_RATE_LIMITS";
+
+/// Configures how many requests to a rate-limited destination or route may wait
+/// for the limit. Further requests fail with a 429 status.
+pub const ENV_OUTBOUND_RATE_LIMIT_MAX_QUEUED: &str =
+    "LINKERD2_PROXY_OUTBOUND_RATE_LIMIT_MAX_QUEUED";
+
+/// Profile routes whose requests are hedged: a request that has not completed
+/// after a delay is sent again, to a different endpoint, and the first response
+/// is used. Hedges draw from the profile's retry budget.
+///
+/// A comma-separated list of `<authority>/<route>=<delay>` entries, where the
+/// route is named by its profile and the delay is either a duration or a
+/// percentile of the route's recent latencies, e.g.
+/// `web.ns.svc.cluster.local:8080/GET /users/{id}=p95`.
+pub const ENV_OUTBOUND_ROUTE_HEDGES: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_HEDGES";
+
+/// Profile routes that send copies of a share of their requests to another
+/// destination. Mirrored responses are discarded.
+///
+/// A comma-separated list of `<authority>/<route>=<mirror>@<ratio>` entries,
+/// where the ratio is between 0 and 1, e.g.
+/// `web.ns.svc.cluster.local:8080/GET /users/{id}=web-next.ns.svc.cluster.local:8080@0.1`.
+pub const ENV_OUTBOUND_ROUTE_MIRRORS: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_MIRRORS";
+
+/// Profile routes that inject faults into a share of their requests.
+///
+/// A comma-separated list of `<authority>/<route>=<fault>` entries, where the
+/// fault is either `delay@<ratio>:<duration>[-<duration>]`, which delays
+/// requests by a fixed or random duration, or `abort@<ratio>:<status>`, which
+/// fails requests with an HTTP status or a gRPC status like `grpc:14`. A route
+/// may have one delay and one abort, e.g.
+/// `web.ns.svc.cluster.local:8080/list=delay@0.1:100ms-1s,web.ns.svc.cluster.local:8080/list=abort@0.01:503`.
+pub const ENV_OUTBOUND_ROUTE_FAULTS: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_FAULTS";
+
+/// Profile routes whose requests are rate limited.
+///
+/// A comma-separated list of `<authority>/<route>=<rate>[:<burst>]` entries,
+/// like `LINKERD2_PROXY_OUTBOUND_RATE_LIMITS`, e.g.
+/// `web.ns.svc.cluster.local:8080/POST /orders=10:20`.
+pub const ENV_OUTBOUND_ROUTE_RATE_LIMITS: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_RATE_LIMITS";
+
+/// Header and query parameter conditions that requests must also satisfy to
+/// match a profile route.
+///
+/// A comma-separated list of `<authority>/<route>=<match>` entries, where the
+/// match is `header:<name>` or `query:<name>`, which match requests that have
+/// the header or parameter, optionally followed by `:<value>`, which requires
+/// an exact value, or by `~<regex>`, which requires a value matching the
+/// regex. A route's entries must all match, e.g.
+/// `web.ns.svc.cluster.local:8080/list=header:content-type~^application/grpc,web.ns.svc.cluster.local:8080/list=query:tenant:acme`.
+/// Values may not contain `,` or `=`.
+pub const ENV_OUTBOUND_ROUTE_MATCHES: &str = "LINKERD2_PROXY_OUTBOUND_ROUTE_MATCHES";
+
+/// Limits the number of bytes of each request body that are buffered so that
+/// the request may be retried. Requests with larger bodies are not retried.
+pub const ENV_OUTBOUND_RETRY_MAX_BODY_BYTES: &str = "LINKERD2_PROXY_OUTBOUND_RETRY_MAX_BODY_BYTES";
+
+/// Limits the number of bytes of each request body that are buffered so that
+/// the request may be mirrored. Mirrors of requests with larger bodies fail.
+pub const ENV_OUTBOUND_MIRROR_MAX_BODY_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_MIRROR_MAX_BODY_BYTES";
+
 pub const ENV_TRACE_ATTRIBUTES_PATH: &str = "LINKERD2_PROXY_TRACE_ATTRIBUTES_PATH";
 
 /// Constrains which destination names are resolved through the destination
@@ -133,9 +389,64 @@ pub const ENV_INBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
 pub const ENV_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION: &str =
     "LINKERD2_PROXY_OUTBOUND_PORTS_DISABLE_PROTOCOL_DETECTION";
 
+/// Declares the protocol of connections to each port, so that it need not be
+/// detected.
+///
+/// A comma-separated list of `<port>=<protocol>` entries, where the protocol
+/// is one of `opaque`, `http1`, `http2` (or its alias, `grpc`), or
+/// `tls-passthrough`, e.g. `8080=http1,9090=grpc`. Declaring an HTTP protocol
+/// takes precedence over the ports for which protocol detection is disabled.
+/// Connections to `tls-passthrough` ports are forwarded without terminating
+/// TLS. Ports that are declared `opaque` are not detected as HTTP, but
+/// server-speaks-first protocols must still disable protocol detection.
+pub const ENV_INBOUND_PORT_PROTOCOLS: &str = "LINKERD2_PROXY_INBOUND_PORT_PROTOCOLS";
+pub const ENV_OUTBOUND_PORT_PROTOCOLS: &str = "LINKERD2_PROXY_OUTBOUND_PORT_PROTOCOLS";
+
 pub const ENV_INBOUND_PORTS_REQUIRE_IDENTITY: &str =
     "LINKERD2_PROXY_INBOUND_PORTS_REQUIRE_IDENTITY";
 
+/// Authorizes the clients of inbound ports.
+///
+/// A comma-separated list of `<port>[<path-prefix>]=<client>[|<client>...]`
+/// entries, where each client is one of `id:<name>`, `id:*.<suffix>` (or
+/// `id:*`, matching any identity), or `net:<network>` (matching
+/// unauthenticated clients in the network). An entry without a path prefix
+/// authorizes connections to the port; entries with a path prefix further
+/// authorize requests whose paths start with the prefix's segments, e.g.
+/// `8080=id:*.ns.serviceaccount.identity.linkerd.cluster.local|net:10.0.0.0/8,8080/admin=id:admin.ns.serviceaccount.identity.linkerd.cluster.local`.
+///
+/// Ports without an entry are not restricted. Ports that skip protocol
+/// detection may not have an entry, since their connections are not inspected.
+pub const ENV_INBOUND_AUTHORIZATION: &str = "LINKERD2_PROXY_INBOUND_AUTHORIZATION";
+
+/// Inbound ports on which connections begin with a PROXY protocol (v1 or v2)
+/// header, e.g. because they are accepted from a layer 4 load balancer. The
+/// header's source address replaces the connection's peer address.
+/// Connections to these ports from load balancers without a valid header are
+/// rejected.
+pub const ENV_INBOUND_PORTS_PROXY_PROTOCOL: &str = "LINKERD2_PROXY_INBOUND_PORTS_PROXY_PROTOCOL";
+
+/// Networks of the load balancers whose PROXY protocol headers are trusted.
+/// Connections from other peers are served without reading a header. Must be
+/// set if `LINKERD2_PROXY_INBOUND_PORTS_PROXY_PROTOCOL` is set.
+pub const ENV_INBOUND_PROXY_PROTOCOL_TRUSTED_NETWORKS: &str =
+    "LINKERD2_PROXY_INBOUND_PROXY_PROTOCOL_TRUSTED_NETWORKS";
+
+/// If set to a non-empty value, inbound HTTP requests are annotated with
+/// `Forwarded` and `X-Forwarded-For` headers describing the client's address.
+/// Requests from clients with a verified identity also carry that identity in
+/// the header named by `LINKERD2_PROXY_INBOUND_CLIENT_ID_HEADER`. These headers
+/// are stripped from requests from unauthenticated clients.
+pub const ENV_INBOUND_FORWARDED_HEADERS: &str = "LINKERD2_PROXY_INBOUND_FORWARDED_HEADERS";
+pub const ENV_INBOUND_CLIENT_ID_HEADER: &str = "LINKERD2_PROXY_INBOUND_CLIENT_ID_HEADER";
+
+/// Forwards inbound connections for a port to a Unix domain socket on which
+/// the application listens, rather than to the port on the loopback interface.
+///
+/// A comma-separated list of `<port>=<absolute path>` entries, e.g.
+/// `8080=/var/run/app/http.sock`.
+pub const ENV_INBOUND_UNIX_SOCKETS: &str = "LINKERD2_PROXY_INBOUND_UNIX_SOCKETS";
+
 pub const ENV_IDENTITY_DISABLED: &str = "LINKERD2_PROXY_IDENTITY_DISABLED";
 pub const ENV_IDENTITY_DIR: &str = "LINKERD2_PROXY_IDENTITY_DIR";
 pub const ENV_IDENTITY_TRUST_ANCHORS: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS";
@@ -144,6 +455,17 @@ pub const ENV_IDENTITY_TOKEN_FILE: &str = "LINKERD2_PROXY_IDENTITY_TOKEN_FILE";
 pub const ENV_IDENTITY_MIN_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MIN_REFRESH";
 pub const ENV_IDENTITY_MAX_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MAX_REFRESH";
 
+/// Paths to a PEM-encoded certificate chain and PKCS#8 private key from which
+/// the local identity is loaded, instead of from the Identity service. The
+/// files are reloaded when they change. The trust anchors and local name must
+/// also be set.
+pub const ENV_IDENTITY_CRT_FILE: &str = "LINKERD2_PROXY_IDENTITY_CRT_FILE";
+pub const ENV_IDENTITY_KEY_FILE: &str = "LINKERD2_PROXY_IDENTITY_KEY_FILE";
+
+/// How often the identity's certificate and key files are checked for
+/// changes.
+pub const ENV_IDENTITY_FILES_POLL_INTERVAL: &str = "LINKERD2_PROXY_IDENTITY_FILES_POLL_INTERVAL";
+
 pub const ENV_IDENTITY_SVC_BASE: &str = "LINKERD2_PROXY_IDENTITY_SVC";
 
 pub const ENV_DESTINATION_SVC_BASE: &str = "LINKERD2_PROXY_DESTINATION_SVC";
@@ -188,6 +510,7 @@ const DEFAULT_ADMIN_LISTEN_ADDR: &str = "127.0.0.1:4191";
 const DEFAULT_METRICS_RETAIN_IDLE: Duration = Duration::from_secs(10 * 60);
 const DEFAULT_INBOUND_DISPATCH_TIMEOUT: Duration = Duration::from_secs(1);
 const DEFAULT_INBOUND_CONNECT_TIMEOUT: Duration = Duration::from_millis(100);
+const DEFAULT_INBOUND_CLIENT_ID_HEADER: &str = "l5d-client-id";
 const DEFAULT_INBOUND_CONNECT_BACKOFF: ExponentialBackoff = ExponentialBackoff {
     min: Duration::from_millis(100),
     max: Duration::from_millis(500),
@@ -201,6 +524,7 @@ const DEFAULT_OUTBOUND_CONNECT_BACKOFF: ExponentialBackoff = ExponentialBackoff
     jitter: 0.1,
 };
 const DEFAULT_DNS_CANONICALIZE_TIMEOUT: Duration = Duration::from_millis(100);
+const DEFAULT_OUTBOUND_TLS_SNI_TIMEOUT: Duration = Duration::from_millis(100);
 const DEFAULT_RESOLV_CONF: &str = "/etc/resolv.conf";
 
 const DEFAULT_INITIAL_STREAM_WINDOW_SIZE: u32 = 65_535; // Protocol default
@@ -216,12 +540,39 @@ const DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE: Duration = Duration::from_secs(60);
 const DEFAULT_INBOUND_MAX_IN_FLIGHT: usize = DEFAULT_BUFFER_CAPACITY;
 const DEFAULT_OUTBOUND_MAX_IN_FLIGHT: usize = DEFAULT_BUFFER_CAPACITY;
 
+const DEFAULT_ADAPTIVE_CONCURRENCY_MIN_LIMIT: usize = 10;
+const DEFAULT_ADAPTIVE_CONCURRENCY_BACKOFF_RATIO: f64 = 0.9;
+const DEFAULT_ADAPTIVE_CONCURRENCY_LATENCY_TOLERANCE: f64 = 2.0;
+
+const DEFAULT_OUTBOUND_RETRY_MAX_BODY_BYTES: usize = 64 * 1024;
+
+const DEFAULT_OUTBOUND_MIRROR_MAX_BODY_BYTES: usize = 64 * 1024;
+
+const DEFAULT_OUTBOUND_OUTLIER_FAILURE_RATE_MIN_REQUESTS: usize = 10;
+const DEFAULT_OUTBOUND_OUTLIER_FAILURE_RATE_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_OUTLIER_BASE_EJECTION_TIME: Duration = Duration::from_secs(30);
+const DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_TIME: Duration = Duration::from_secs(300);
+const DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT: u8 = 50;
+
+const DEFAULT_OUTBOUND_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_HEALTH_CHECK_JITTER: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD: usize = 3;
+const DEFAULT_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD: usize = 2;
+const DEFAULT_OUTBOUND_FAILOVER_THRESHOLD: Duration = Duration::from_secs(1);
+const DEFAULT_OUTBOUND_RATE_LIMIT_MAX_QUEUED: u32 = 10;
+
+const DEFAULT_OUTBOUND_LOCALITY_LABEL: &str = "zone";
+const DEFAULT_OUTBOUND_LOCALITY_MIN_ENDPOINTS: usize = 1;
+const DEFAULT_OUTBOUND_LOCALITY_MAX_LOAD: usize = 100;
+
 const DEFAULT_DESTINATION_GET_SUFFIXES: &str = "svc.cluster.local.";
 const DEFAULT_DESTINATION_PROFILE_SUFFIXES: &str = "svc.cluster.local.";
 const DEFAULT_DESTINATION_PROFILE_INITIAL_TIMEOUT: Duration = Duration::from_millis(500);
 
 const DEFAULT_IDENTITY_MIN_REFRESH: Duration = Duration::from_secs(10);
 const DEFAULT_IDENTITY_MAX_REFRESH: Duration = Duration::from_secs(60 * 60 * 24);
+const DEFAULT_IDENTITY_FILES_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 // By default, we keep a list of known assigned ports of server-first protocols.
 //
@@ -242,6 +593,9 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
     let outbound_listener_addr = parse(strings, ENV_OUTBOUND_LISTEN_ADDR, parse_socket_addr);
     let inbound_listener_addr = parse(strings, ENV_INBOUND_LISTEN_ADDR, parse_socket_addr);
     let admin_listener_addr = parse(strings, ENV_ADMIN_LISTEN_ADDR, parse_socket_addr);
+    let outbound_unix_listener_path = parse(strings, ENV_OUTBOUND_UNIX_LISTEN_PATH, |s| {
+        Ok(PathBuf::from(s))
+    });
 
     let inbound_dispatch_timeout = parse(strings, ENV_INBOUND_DISPATCH_TIMEOUT, parse_duration);
     let inbound_connect_timeout = parse(strings, ENV_INBOUND_CONNECT_TIMEOUT, parse_duration);
@@ -272,6 +626,16 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         parse_port_set,
     );
 
+    let inbound_port_protocols =
+        parse(strings, ENV_INBOUND_PORT_PROTOCOLS, parse_port_protocols)?.unwrap_or_default();
+    let outbound_port_protocols =
+        parse(strings, ENV_OUTBOUND_PORT_PROTOCOLS, parse_port_protocols)?.unwrap_or_default();
+
+    let outbound_tls_sni_timeout = parse(strings, ENV_OUTBOUND_TLS_SNI_TIMEOUT, parse_duration);
+
+    let outbound_egress_rules = parse(strings, ENV_OUTBOUND_EGRESS_RULES, parse_egress_rules);
+    let outbound_egress_default = parse(strings, ENV_OUTBOUND_EGRESS_DEFAULT, parse_egress_action);
+
     let buffer_capacity = parse(strings, ENV_BUFFER_CAPACITY, parse_number);
 
     let inbound_cache_max_idle_age =
@@ -282,6 +646,39 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
     let inbound_max_in_flight = parse(strings, ENV_INBOUND_MAX_IN_FLIGHT, parse_number);
     let outbound_max_in_flight = parse(strings, ENV_OUTBOUND_MAX_IN_FLIGHT, parse_number);
 
+    let inbound_adaptive_concurrency = parse(
+        strings,
+        ENV_INBOUND_ADAPTIVE_CONCURRENCY_INITIAL_LIMIT,
+        parse_number,
+    );
+    let outbound_adaptive_concurrency = parse(
+        strings,
+        ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_INITIAL_LIMIT,
+        parse_number,
+    );
+
+    let outbound_outlier_detection = parse_outlier_config(strings);
+
+    let outbound_locality = parse_locality_config(strings);
+
+    let outbound_hash_keys = parse(strings, ENV_OUTBOUND_HASH_KEYS, parse_hash_keys);
+
+    let outbound_health_checks = parse_health_check_config(strings);
+
+    let outbound_slow_start = parse(strings, ENV_OUTBOUND_SLOW_START, parse_duration);
+
+    let outbound_failover = parse_failover_config(strings);
+
+    let outbound_rate_limits = parse_rate_limit_config(strings);
+
+    let route_policies = parse_route_policy_config(strings);
+
+    let outbound_retry_max_body_bytes =
+        parse(strings, ENV_OUTBOUND_RETRY_MAX_BODY_BYTES, parse_number);
+
+    let outbound_mirror_max_body_bytes =
+        parse(strings, ENV_OUTBOUND_MIRROR_MAX_BODY_BYTES, parse_number);
+
     let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
 
     // DNS
@@ -293,12 +690,20 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
 
     let dns_canonicalize_timeout = parse(strings, ENV_DNS_CANONICALIZE_TIMEOUT, parse_duration);
 
-    let identity_config = parse_identity_config(strings);
+    let identity_files_config = parse_identity_files_config(strings);
+    let identity_config = match identity_files_config {
+        Ok(Some(_)) => Ok(None),
+        _ => parse_identity_config(strings),
+    };
 
-    let id_disabled = identity_config
+    let id_disabled = identity_files_config
         .as_ref()
         .map(|c| c.is_none())
-        .unwrap_or(false);
+        .unwrap_or(false)
+        && identity_config
+            .as_ref()
+            .map(|c| c.is_none())
+            .unwrap_or(false);
 
     let dst_addr = if id_disabled {
         parse_control_addr_disable_identity(strings, ENV_DESTINATION_SVC_BASE)
@@ -393,21 +798,92 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         let dispatch_timeout =
             outbound_dispatch_timeout?.unwrap_or(DEFAULT_OUTBOUND_DISPATCH_TIMEOUT);
 
+        let max_in_flight_requests =
+            outbound_max_in_flight?.unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT);
+
+        let failover = outbound_failover?;
+        if !failover.backups.is_empty() && failover.threshold >= dispatch_timeout {
+            warn!(
+                "{} should be less than {}; requests will fail before failing over",
+                ENV_OUTBOUND_FAILOVER_THRESHOLD, ENV_OUTBOUND_DISPATCH_TIMEOUT,
+            );
+        }
+
+        let tls_sni = if strings
+            .get(ENV_OUTBOUND_TLS_SNI)?
+            .map(|e| !e.is_empty())
+            .unwrap_or(false)
+        {
+            Some(outbound::sni::Config {
+                timeout: outbound_tls_sni_timeout?.unwrap_or(DEFAULT_OUTBOUND_TLS_SNI_TIMEOUT),
+                discover: strings
+                    .get(ENV_OUTBOUND_TLS_SNI_DISCOVER)?
+                    .map(|e| !e.is_empty())
+                    .unwrap_or(false),
+            })
+        } else {
+            None
+        };
+
+        let egress = match (outbound_egress_rules?, outbound_egress_default?) {
+            (None, None) => None,
+            (rules, default) => Some(egress::Config {
+                rules: rules.unwrap_or_default(),
+                default: default.unwrap_or(egress::Action::Allow),
+                internal_networks: dst_get_networks
+                    .clone()?
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+                dry_run: strings
+                    .get(ENV_OUTBOUND_EGRESS_DRY_RUN)?
+                    .map(|e| !e.is_empty())
+                    .unwrap_or(false),
+                log: strings
+                    .get(ENV_OUTBOUND_EGRESS_LOG)?
+                    .map(|e| !e.is_empty())
+                    .unwrap_or(false),
+            }),
+        };
+
         outbound::Config {
             canonicalize_timeout: dns_canonicalize_timeout?
                 .unwrap_or(DEFAULT_DNS_CANONICALIZE_TIMEOUT),
+            outlier_detection: outbound_outlier_detection?,
+            locality: outbound_locality?,
+            hash_keys: outbound_hash_keys?.unwrap_or_default(),
+            health_checks: outbound_health_checks?,
+            slow_start: outbound_slow_start?.unwrap_or_default(),
+            failover,
+            rate_limits: outbound_rate_limits?,
+            retry_max_body_bytes: outbound_retry_max_body_bytes?
+                .unwrap_or(DEFAULT_OUTBOUND_RETRY_MAX_BODY_BYTES),
+            mirror_max_body_bytes: outbound_mirror_max_body_bytes?
+                .unwrap_or(DEFAULT_OUTBOUND_MIRROR_MAX_BODY_BYTES),
+            unix_listen: outbound_unix_listener_path?.map(listen::BindUnix::new),
+            tcp_tunnel: strings
+                .get(ENV_OUTBOUND_TCP_TUNNEL)?
+                .map(|e| !e.is_empty())
+                .unwrap_or(false),
+            tls_sni,
+            egress,
             proxy: ProxyConfig {
                 server,
                 connect,
-                disable_protocol_detection_for_ports: outbound_disable_ports?
-                    .unwrap_or_else(|| default_disable_ports_protocol_detection())
-                    .into(),
+                disable_protocol_detection_for_ports: skip_detect_ports(
+                    outbound_disable_ports?
+                        .unwrap_or_else(|| default_disable_ports_protocol_detection()),
+                    &outbound_port_protocols,
+                )
+                .into(),
+                port_protocols: outbound_port_protocols.into(),
                 cache_max_idle_age: outbound_cache_max_idle_age?
                     .unwrap_or(DEFAULT_OUTBOUND_ROUTER_MAX_IDLE_AGE),
                 buffer_capacity,
                 dispatch_timeout,
-                max_in_flight_requests: outbound_max_in_flight?
-                    .unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT),
+                max_in_flight_requests,
+                adaptive_concurrency_limit: outbound_adaptive_concurrency?
+                    .map(|initial| adaptive_concurrency_limit(initial, max_in_flight_requests)),
                 detect_protocol_timeout: dispatch_timeout,
             },
         }
@@ -441,6 +917,9 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         let dispatch_timeout =
             inbound_dispatch_timeout?.unwrap_or(DEFAULT_INBOUND_DISPATCH_TIMEOUT);
 
+        let max_in_flight_requests =
+            inbound_max_in_flight?.unwrap_or(DEFAULT_INBOUND_MAX_IN_FLIGHT);
+
         let require_identity_for_inbound_ports =
             parse(strings, ENV_INBOUND_PORTS_REQUIRE_IDENTITY, parse_port_set)?
                 .unwrap_or_else(|| IndexSet::new());
@@ -453,22 +932,90 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
             return Err(EnvError::InvalidEnvVar);
         }
 
+        let disable_protocol_detection_for_ports = skip_detect_ports(
+            inbound_disable_ports?.unwrap_or_else(|| default_disable_ports_protocol_detection()),
+            &inbound_port_protocols,
+        );
+
+        let authorization =
+            parse(strings, ENV_INBOUND_AUTHORIZATION, parse_authorization)?.unwrap_or_default();
+        // Connections to ports that skip protocol detection are forwarded
+        // without terminating TLS, so their policies can't be enforced.
+        let unenforced = authorization
+            .keys()
+            .filter(|port| disable_protocol_detection_for_ports.contains(*port))
+            .collect::<Vec<_>>();
+        if !unenforced.is_empty() {
+            error!(
+                "{} must not authorize ports that skip protocol detection: {:?}",
+                ENV_INBOUND_AUTHORIZATION, unenforced
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+        let authorizes_identities = authorization.values().any(|policy| {
+            policy
+                .connections
+                .iter()
+                .chain(policy.paths.iter().map(|(_, authz)| authz))
+                .any(|authz| !authz.identities.is_empty())
+        });
+        if id_disabled && authorizes_identities {
+            error!(
+                "if {} is true, {} must not authorize identities",
+                ENV_IDENTITY_DISABLED, ENV_INBOUND_AUTHORIZATION
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+
+        let unix_sockets =
+            parse(strings, ENV_INBOUND_UNIX_SOCKETS, parse_unix_sockets)?.unwrap_or_default();
+
+        let proxy_protocol = proxy_protocol::Config {
+            ports: parse(strings, ENV_INBOUND_PORTS_PROXY_PROTOCOL, parse_port_set)?
+                .unwrap_or_default(),
+            trusted_networks: parse(
+                strings,
+                ENV_INBOUND_PROXY_PROTOCOL_TRUSTED_NETWORKS,
+                parse_networks,
+            )?
+            .unwrap_or_default(),
+        };
+        if !proxy_protocol.ports.is_empty() && proxy_protocol.trusted_networks.is_empty() {
+            error!(
+                "{} must be set when {} is set",
+                ENV_INBOUND_PROXY_PROTOCOL_TRUSTED_NETWORKS, ENV_INBOUND_PORTS_PROXY_PROTOCOL
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+
+        let forwarded = parse_forwarded_config(strings)?;
+
+        let tcp_tunnel = strings
+            .get(ENV_INBOUND_TCP_TUNNEL)?
+            .map(|e| !e.is_empty())
+            .unwrap_or(false);
+
         inbound::Config {
             proxy: ProxyConfig {
                 server,
                 connect,
-                disable_protocol_detection_for_ports: inbound_disable_ports?
-                    .unwrap_or_else(|| default_disable_ports_protocol_detection())
-                    .into(),
+                disable_protocol_detection_for_ports: disable_protocol_detection_for_ports.into(),
+                port_protocols: inbound_port_protocols.into(),
                 cache_max_idle_age: inbound_cache_max_idle_age?
                     .unwrap_or(DEFAULT_INBOUND_ROUTER_MAX_IDLE_AGE),
                 buffer_capacity,
                 dispatch_timeout,
-                max_in_flight_requests: inbound_max_in_flight?
-                    .unwrap_or(DEFAULT_INBOUND_MAX_IN_FLIGHT),
+                max_in_flight_requests,
+                adaptive_concurrency_limit: inbound_adaptive_concurrency?
+                    .map(|initial| adaptive_concurrency_limit(initial, max_in_flight_requests)),
                 detect_protocol_timeout: dispatch_timeout,
             },
             require_identity_for_inbound_ports: require_identity_for_inbound_ports.into(),
+            authorization,
+            unix_sockets,
+            proxy_protocol,
+            forwarded,
+            tcp_tunnel,
         }
     };
 
@@ -488,6 +1035,7 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 .unwrap_or(parse_dns_suffixes(DEFAULT_DESTINATION_PROFILE_SUFFIXES).unwrap()),
             initial_profile_timeout: dst_profile_initial_timeout?
                 .unwrap_or(DEFAULT_DESTINATION_PROFILE_INITIAL_TIMEOUT),
+            route_policies: route_policies?,
             control: ControlConfig {
                 addr,
                 connect,
@@ -554,24 +1102,27 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         })
         .unwrap_or(super::tap::Config::Disabled);
 
-    let identity = identity_config?
-        .map(|(addr, certify)| {
-            // If the address doesn't have a server identity, then we're on localhost.
-            let connect = if addr.identity.is_none() {
-                inbound.proxy.connect.clone()
-            } else {
-                outbound.proxy.connect.clone()
-            };
-            identity::Config::Enabled {
-                certify,
-                control: ControlConfig {
-                    addr,
-                    connect,
-                    buffer_capacity: 1,
-                },
-            }
-        })
-        .unwrap_or(identity::Config::Disabled);
+    let identity = match identity_files_config? {
+        Some(files) => identity::Config::Files(files),
+        None => identity_config?
+            .map(|(addr, certify)| {
+                // If the address doesn't have a server identity, then we're on localhost.
+                let connect = if addr.identity.is_none() {
+                    inbound.proxy.connect.clone()
+                } else {
+                    outbound.proxy.connect.clone()
+                };
+                identity::Config::Enabled {
+                    certify,
+                    control: ControlConfig {
+                        addr,
+                        connect,
+                        buffer_capacity: 1,
+                    },
+                }
+            })
+            .unwrap_or(identity::Config::Disabled),
+    };
 
     Ok(super::Config {
         admin,
@@ -617,6 +1168,28 @@ fn default_disable_ports_protocol_detection() -> IndexSet<u16> {
     IndexSet::from_iter(DEFAULT_PORTS_DISABLE_PROTOCOL_DETECTION.iter().cloned())
 }
 
+/// Updates the ports that skip detection entirely with the declared port
+/// protocols: TLS passthrough ports skip detection, ports with a declared HTTP
+/// protocol are served as HTTP, and opaque ports are left as they are.
+fn skip_detect_ports(
+    mut ports: IndexSet<u16>,
+    protocols: &IndexMap<u16, PortProtocol>,
+) -> IndexSet<u16> {
+    for (port, protocol) in protocols.iter() {
+        match protocol {
+            PortProtocol::TlsPassthrough => {
+                ports.insert(*port);
+            }
+            // Opaque ports in the set may be server-speaks-first.
+            PortProtocol::Opaque => {}
+            PortProtocol::Http1 | PortProtocol::Http2 => {
+                ports.remove(port);
+            }
+        }
+    }
+    ports
+}
+
 // ===== impl Env =====
 
 impl Strings for Env {
@@ -829,120 +1402,957 @@ fn parse_networks(list: &str) -> Result<IndexSet<ipnet::IpNet>, ParseError> {
     Ok(nets)
 }
 
-pub fn parse_backoff<S: Strings>(
-    strings: &S,
-    base: &str,
-    default: ExponentialBackoff,
-) -> Result<ExponentialBackoff, EnvError> {
-    let min_env = format!("LINKERD2_PROXY_{}_EXP_BACKOFF_MIN", base);
-    let min = parse(strings, &min_env, parse_duration);
-    let max_env = format!("LINKERD2_PROXY_{}_EXP_BACKOFF_MAX", base);
-    let max = parse(strings, &max_env, parse_duration);
-    let jitter_env = format!("LINKERD2_PROXY_{}_EXP_BACKOFF_JITTER", base);
-    let jitter = parse(strings, &jitter_env, parse_number::<f64>);
-
-    match (min?, max?, jitter?) {
-        (None, None, None) => Ok(default),
-        (Some(min), Some(max), jitter) => {
-            ExponentialBackoff::new(min, max, jitter.unwrap_or_default()).map_err(|error| {
-                error!(message="Invalid backoff", %error, %min_env, ?min, %max_env, ?max, %jitter_env, ?jitter);
-                EnvError::InvalidEnvVar
-            })
+fn parse_hash_keys(list: &str) -> Result<IndexMap<Addr, HashKey>, ParseError> {
+    let mut keys = IndexMap::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
         }
+
+        let mut parts = entry.splitn(2, '=');
+        let (dst, key) = match (parts.next(), parts.next()) {
+            (Some(dst), Some(key)) => (parse_addr(dst.trim())?, parse_hash_key(key.trim())?),
+            _ => {
+                error!(%entry, "Expected <authority>=<key>");
+                return Err(ParseError::NotAHashKey);
+            }
+        };
+        keys.insert(dst, key);
+    }
+    Ok(keys)
+}
+
+fn parse_hash_key(s: &str) -> Result<HashKey, ParseError> {
+    if s == "source" {
+        return Ok(HashKey::SourceAddr);
+    }
+
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("header"), Some(name)) => HeaderName::from_bytes(name.as_bytes())
+            .map(HashKey::Header)
+            .map_err(|_| {
+                error!(%name, "Invalid header name");
+                ParseError::NotAHashKey
+            }),
+        (Some("cookie"), Some(name)) if !name.is_empty() => Ok(HashKey::Cookie(name.to_string())),
         _ => {
-            error!("You need to specify either all of {} {} {} or none of them to use the default backoff", min_env, max_env,jitter_env );
-            Err(EnvError::InvalidEnvVar)
+            error!(key = %s, "Expected header:<name>, cookie:<name>, or source");
+            Err(ParseError::NotAHashKey)
         }
     }
 }
 
-pub fn parse_control_addr<S: Strings>(
-    strings: &S,
-    base: &str,
-) -> Result<Option<ControlAddr>, EnvError> {
-    let a_env = format!("{}_ADDR", base);
-    let a = parse(strings, &a_env, parse_addr);
-    let n_env = format!("{}_NAME", base);
-    let n = parse(strings, &n_env, parse_identity);
-    match (a?, n?) {
-        (None, None) => Ok(None),
-        (Some(ref addr), _) if addr.is_loopback() => Ok(Some(ControlAddr {
-            addr: addr.clone(),
-            identity: tls::Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
-        })),
-        (Some(addr), Some(name)) => Ok(Some(ControlAddr {
-            addr,
-            identity: tls::Conditional::Some(name),
-        })),
-        (Some(_), None) => {
-            error!("{} must be specified when {} is set", n_env, a_env);
-            Err(EnvError::InvalidEnvVar)
+fn parse_health_checks(list: &str) -> Result<IndexMap<String, http_health::Probe>, ParseError> {
+    let mut probes = IndexMap::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
         }
-        (None, Some(_)) => {
-            error!("{} must be specified when {} is set", a_env, n_env);
-            Err(EnvError::InvalidEnvVar)
+
+        let mut parts = entry.splitn(2, '=');
+        let (dst, probe) = match (parts.next(), parts.next()) {
+            (Some(dst), Some(probe)) => (parse_addr(dst.trim())?, parse_probe(probe.trim())?),
+            _ => {
+                error!(%entry, "Expected <authority>=<probe>");
+                return Err(ParseError::NotAHealthCheck);
+            }
+        };
+        probes.insert(dst.to_string(), probe);
+    }
+    Ok(probes)
+}
+
+fn parse_probe(s: &str) -> Result<http_health::Probe, ParseError> {
+    let mut parts = s.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("http"), Some(path)) if path.starts_with('/') => PathAndQuery::from_str(path)
+            .map(http_health::Probe::Http)
+            .map_err(|_| {
+                error!(%path, "Invalid path");
+                ParseError::NotAHealthCheck
+            }),
+        (Some("grpc"), service) => Ok(http_health::Probe::Grpc(
+            service.unwrap_or_default().to_string(),
+        )),
+        _ => {
+            error!(probe = %s, "Expected http:<path> or grpc[:<service>]");
+            Err(ParseError::NotAHealthCheck)
         }
     }
 }
 
-pub fn parse_control_addr_disable_identity<S: Strings>(
-    strings: &S,
-    base: &str,
-) -> Result<Option<ControlAddr>, EnvError> {
-    let a = parse(strings, &format!("{}_ADDR", base), parse_addr)?;
-    let identity = tls::Conditional::None(tls::ReasonForNoPeerName::LocalIdentityDisabled);
-    Ok(a.map(|addr| ControlAddr { addr, identity }))
+fn parse_failovers(list: &str) -> Result<IndexMap<String, Vec<NameAddr>>, ParseError> {
+    let mut failovers = IndexMap::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut parts = entry.splitn(2, '=');
+        let (dst, backups) = match (parts.next(), parts.next()) {
+            (Some(dst), Some(backups)) if !backups.trim().is_empty() => {
+                let backups = backups
+                    .split('|')
+                    .map(|b| {
+                        NameAddr::from_str(b.trim()).map_err(|e| {
+                            error!("Not a valid backup name: {}", b);
+                            ParseError::AddrError(e)
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                (parse_addr(dst.trim())?, backups)
+            }
+            _ => {
+                error!(%entry, "Expected <authority>=<backup>[|<backup>...]");
+                return Err(ParseError::NotAFailover);
+            }
+        };
+        failovers.insert(dst.to_string(), backups);
+    }
+    Ok(failovers)
 }
 
-pub fn parse_identity_config<S: Strings>(
-    strings: &S,
-) -> Result<Option<(ControlAddr, identity::certify::Config)>, EnvError> {
-    let control = parse_control_addr(strings, ENV_IDENTITY_SVC_BASE);
-    let ta = parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
-        identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
-    });
-    let dir = parse(strings, ENV_IDENTITY_DIR, |ref s| Ok(PathBuf::from(s)));
-    let tok = parse(strings, ENV_IDENTITY_TOKEN_FILE, |ref s| {
-        identity::TokenSource::if_nonempty_file(s.to_string()).map_err(|e| {
-            error!("Could not read {}: {}", ENV_IDENTITY_TOKEN_FILE, e);
-            ParseError::InvalidTokenSource
-        })
-    });
-    let li = parse(strings, ENV_IDENTITY_IDENTITY_LOCAL_NAME, parse_identity);
-    let min_refresh = parse(strings, ENV_IDENTITY_MIN_REFRESH, parse_duration);
-    let max_refresh = parse(strings, ENV_IDENTITY_MAX_REFRESH, parse_duration);
+fn parse_rate_limits(list: &str) -> Result<IndexMap<String, (u32, u32)>, ParseError> {
+    let mut limits = IndexMap::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
 
-    let disabled = strings
-        .get(ENV_IDENTITY_DISABLED)?
-        .map(|d| !d.is_empty())
-        .unwrap_or(false);
+        let mut parts = entry.splitn(2, '=');
+        let (dst, limit) = match (parts.next(), parts.next()) {
+            (Some(dst), Some(limit)) => (parse_addr(dst.trim())?, parse_rate_limit(limit.trim())?),
+            _ => {
+                error!(%entry, "Expected <authority>=<rate>[:<burst>]");
+                return Err(ParseError::NotARateLimit);
+            }
+        };
+        limits.insert(dst.to_string(), limit);
+    }
+    Ok(limits)
+}
 
-    match (
-        disabled,
-        control?,
-        ta?,
-        dir?,
-        li?,
-        tok?,
-        min_refresh?,
-        max_refresh?,
-    ) {
-        (disabled, None, None, None, None, None, None, None) => {
-            if !disabled {
-                error!(
-                    "{} must be set or identity configuration must be specified.",
-                    ENV_IDENTITY_DISABLED
-                );
-                Err(EnvError::InvalidEnvVar)
-            } else {
-                Ok(None)
+/// Parses a rate and an optional burst, which defaults to the rate.
+fn parse_rate_limit(s: &str) -> Result<(u32, u32), ParseError> {
+    let mut parts = s.splitn(2, ':');
+    let rate = parse_number::<u32>(parts.next().unwrap_or_default())?;
+    let burst = match parts.next() {
+        Some(burst) => parse_number::<u32>(burst)?,
+        None => rate,
+    };
+    if rate == 0 || burst == 0 {
+        error!(limit = %s, "Rate limits must be positive");
+        return Err(ParseError::NotARateLimit);
+    }
+    Ok((rate, burst))
+}
+
+/// Parses a comma-separated list of `<authority>/<route>=<value>` entries.
+fn parse_route_entries<T>(
+    list: &str,
+    parse_value: impl Fn(&str) -> Result<T, ParseError>,
+) -> Result<Vec<(NameAddr, String, T)>, ParseError> {
+    let mut entries = Vec::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        // Route names may contain slashes, but authorities may not, and route
+        // values do not contain `=`.
+        let (route, value) = {
+            let mut parts = entry.rsplitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(value), Some(route)) => (route, value.trim()),
+                _ => {
+                    error!(%entry, "Expected <authority>/<route>=<value>");
+                    return Err(ParseError::NotARoutePolicy);
+                }
+            }
+        };
+        let mut parts = route.splitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(dst), Some(route)) if !route.trim().is_empty() => {
+                let dst = NameAddr::from_str(dst.trim()).map_err(|e| {
+                    error!(%dst, "Not a valid name");
+                    ParseError::AddrError(e)
+                })?;
+                entries.push((dst, route.trim().to_string(), parse_value(value)?));
+            }
+            _ => {
+                error!(%entry, "Expected <authority>/<route>=<value>");
+                return Err(ParseError::NotARoutePolicy);
             }
         }
-        (
-            false,
-            Some(control),
-            Some(trust_anchors),
-            Some(dir),
+    }
+    Ok(entries)
+}
+
+/// Parses a hedging delay, either a duration or a percentile like `p99.9`.
+fn parse_hedge_delay(s: &str) -> Result<profiles::HedgeDelay, ParseError> {
+    if s.starts_with('p') {
+        let p = parse_number::<f64>(&s[1..])?;
+        if !(0.0 < p && p < 100.0) {
+            error!(delay = %s, "Hedging percentiles must be between 0 and 100");
+            return Err(ParseError::NotARoutePolicy);
+        }
+        return Ok(profiles::HedgeDelay::Percentile(p / 100.0));
+    }
+
+    parse_duration(s).map(profiles::HedgeDelay::Fixed)
+}
+
+/// Parses a mirror destination and the ratio of requests it receives, like
+/// `web-next.ns.svc.cluster.local:8080@0.1`.
+fn parse_mirror(s: &str) -> Result<(NameAddr, f64), ParseError> {
+    let mut parts = s.rsplitn(2, '@');
+    let (ratio, addr) = match (parts.next(), parts.next()) {
+        (Some(ratio), Some(addr)) => (ratio.trim(), addr.trim()),
+        _ => {
+            error!(mirror = %s, "Expected <authority>@<ratio>");
+            return Err(ParseError::NotARoutePolicy);
+        }
+    };
+    let addr = NameAddr::from_str(addr).map_err(|e| {
+        error!(%addr, "Not a valid name");
+        ParseError::AddrError(e)
+    })?;
+    let ratio = parse_number::<f64>(ratio)?;
+    if !(0.0..=1.0).contains(&ratio) {
+        error!(mirror = %s, "Mirror ratios must be between 0 and 1");
+        return Err(ParseError::NotARoutePolicy);
+    }
+    Ok((addr, ratio))
+}
+
+/// Parses a header or query parameter match, like `header:x-tenant`,
+/// `query:tenant:acme`, or `header:content-type~^application/grpc`.
+fn parse_request_match(s: &str) -> Result<profiles::RequestMatch, ParseError> {
+    use regex::Regex;
+
+    let mut parts = s.splitn(2, ':');
+    let (kind, rest) = match (parts.next(), parts.next()) {
+        (Some(kind), Some(rest)) => (kind.trim(), rest.trim()),
+        _ => {
+            error!(request_match = %s, "Expected header:<name> or query:<name>");
+            return Err(ParseError::NotARoutePolicy);
+        }
+    };
+
+    let (name, value) = match rest.find(|c| c == ':' || c == '~') {
+        None => (rest, None),
+        Some(i) => {
+            let value = &rest[i + 1..];
+            let value = if rest[i..].starts_with('~') {
+                let re = Regex::new(value).map_err(|e| {
+                    error!(regex = %value, "Invalid regex: {}", e);
+                    ParseError::NotARoutePolicy
+                })?;
+                profiles::ValueMatch::Regex(re)
+            } else {
+                profiles::ValueMatch::Exact(value.to_string())
+            };
+            (&rest[..i], Some(value))
+        }
+    };
+    if name.is_empty() {
+        error!(request_match = %s, "Expected a header or parameter name");
+        return Err(ParseError::NotARoutePolicy);
+    }
+
+    match kind {
+        "header" => {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                error!(%name, "Not a valid header name");
+                ParseError::NotARoutePolicy
+            })?;
+            Ok(profiles::RequestMatch::Header { name, value })
+        }
+        "query" => Ok(profiles::RequestMatch::Query {
+            name: name.to_string(),
+            value,
+        }),
+        _ => {
+            error!(request_match = %s, "Expected header:<name> or query:<name>");
+            Err(ParseError::NotARoutePolicy)
+        }
+    }
+}
+
+/// Parses an injected fault, like `delay@0.1:100ms-1s` or `abort@0.01:503`.
+fn parse_fault(s: &str) -> Result<profiles::Faults, ParseError> {
+    let mut parts = s.splitn(2, '@');
+    let (kind, rest) = match (parts.next(), parts.next()) {
+        (Some(kind), Some(rest)) => (kind.trim(), rest),
+        _ => {
+            error!(fault = %s, "Expected delay@<ratio>:<duration> or abort@<ratio>:<status>");
+            return Err(ParseError::NotARoutePolicy);
+        }
+    };
+    let mut parts = rest.splitn(2, ':');
+    let (ratio, value) = match (parts.next(), parts.next()) {
+        (Some(ratio), Some(value)) => (parse_number::<f64>(ratio.trim())?, value.trim()),
+        _ => {
+            error!(fault = %s, "Expected <kind>@<ratio>:<value>");
+            return Err(ParseError::NotARoutePolicy);
+        }
+    };
+    if !(0.0..=1.0).contains(&ratio) {
+        error!(fault = %s, "Fault ratios must be between 0 and 1");
+        return Err(ParseError::NotARoutePolicy);
+    }
+
+    match kind {
+        "delay" => {
+            let mut parts = value.splitn(2, '-');
+            let min = parse_duration(parts.next().unwrap_or_default())?;
+            let max = match parts.next() {
+                Some(max) => parse_duration(max)?,
+                None => min,
+            };
+            if max < min {
+                error!(fault = %s, "Delays must not be shorter than their minimum");
+                return Err(ParseError::NotARoutePolicy);
+            }
+            Ok(profiles::Faults {
+                delay: Some(profiles::DelayFault { ratio, min, max }),
+                abort: None,
+            })
+        }
+        "abort" => {
+            let status = if value.starts_with("grpc:") {
+                let code = parse_number::<u16>(&value["grpc:".len()..])?;
+                if code == 0 || code > 16 {
+                    error!(fault = %s, "gRPC aborts must have an error status between 1 and 16");
+                    return Err(ParseError::NotARoutePolicy);
+                }
+                profiles::AbortStatus::Grpc(code)
+            } else {
+                let status = parse_number::<u16>(value)?;
+                match http::StatusCode::from_u16(status) {
+                    Ok(status) if status.is_client_error() || status.is_server_error() => {
+                        profiles::AbortStatus::Http(status)
+                    }
+                    _ => {
+                        error!(fault = %s, "HTTP aborts must have an error status");
+                        return Err(ParseError::NotARoutePolicy);
+                    }
+                }
+            };
+            Ok(profiles::Faults {
+                delay: None,
+                abort: Some(profiles::AbortFault { ratio, status }),
+            })
+        }
+        _ => {
+            error!(fault = %s, "Faults must be either delays or aborts");
+            Err(ParseError::NotARoutePolicy)
+        }
+    }
+}
+
+pub fn parse_forwarded_config<S: Strings>(
+    strings: &S,
+) -> Result<Option<inbound::forwarded::Config>, EnvError> {
+    let enabled = strings
+        .get(ENV_INBOUND_FORWARDED_HEADERS)?
+        .map(|e| !e.is_empty())
+        .unwrap_or(false);
+    let client_id_header = parse(strings, ENV_INBOUND_CLIENT_ID_HEADER, |name| {
+        HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+            error!(%name, "Invalid header name");
+            ParseError::NotAHeaderName
+        })
+    })?;
+    if !enabled {
+        return Ok(None);
+    }
+
+    Ok(Some(inbound::forwarded::Config {
+        client_id_header: client_id_header
+            .unwrap_or_else(|| HeaderName::from_static(DEFAULT_INBOUND_CLIENT_ID_HEADER)),
+    }))
+}
+
+fn parse_unix_sockets(list: &str) -> Result<IndexMap<u16, PathBuf>, ParseError> {
+    let mut sockets = IndexMap::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut parts = entry.splitn(2, '=');
+        match (parts.next(), parts.next().map(|p| PathBuf::from(p.trim()))) {
+            (Some(port), Some(path)) if path.is_absolute() => {
+                sockets.insert(parse_number::<u16>(port.trim())?, path);
+            }
+            _ => {
+                error!(%entry, "Expected <port>=<absolute path>");
+                return Err(ParseError::NotAUnixSocket);
+            }
+        }
+    }
+    Ok(sockets)
+}
+
+fn parse_port_protocols(list: &str) -> Result<IndexMap<u16, PortProtocol>, ParseError> {
+    let mut protocols = IndexMap::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut parts = entry.splitn(2, '=');
+        let (port, protocol) = match (parts.next(), parts.next()) {
+            (Some(port), Some(protocol)) => (port.trim(), protocol.trim()),
+            _ => {
+                error!(%entry, "Expected <port>=<protocol>");
+                return Err(ParseError::NotAPortProtocol);
+            }
+        };
+        let protocol = match protocol.to_ascii_lowercase().as_str() {
+            "opaque" => PortProtocol::Opaque,
+            "http1" => PortProtocol::Http1,
+            "http2" | "grpc" => PortProtocol::Http2,
+            "tls-passthrough" => PortProtocol::TlsPassthrough,
+            _ => {
+                error!(%protocol, "Expected opaque, http1, http2, grpc, or tls-passthrough");
+                return Err(ParseError::NotAPortProtocol);
+            }
+        };
+        protocols.insert(parse_number::<u16>(port)?, protocol);
+    }
+    Ok(protocols)
+}
+
+fn parse_authorization(list: &str) -> Result<authz::Config, ParseError> {
+    let mut ports = authz::Config::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut parts = entry.splitn(2, '=');
+        let (target, clients) = match (parts.next(), parts.next()) {
+            (Some(target), Some(clients)) => (target.trim(), parse_authorized_clients(clients)?),
+            _ => {
+                error!(%entry, "Expected <port>[<path-prefix>]=<client>[|<client>...]");
+                return Err(ParseError::NotAnAuthorization);
+            }
+        };
+
+        let (port, path) = match target.find('/') {
+            Some(idx) => (&target[..idx], Some(&target[idx..])),
+            None => (target, None),
+        };
+        let policy = ports.entry(parse_number::<u16>(port)?).or_default();
+        match path {
+            Some(path) => policy.paths.push((path.to_string(), clients)),
+            None if policy.connections.is_none() => policy.connections = Some(clients),
+            None => {
+                error!(%port, "Port authorized more than once");
+                return Err(ParseError::NotAnAuthorization);
+            }
+        }
+    }
+    Ok(ports)
+}
+
+fn parse_authorized_clients(list: &str) -> Result<authz::Authorization, ParseError> {
+    let mut authz = authz::Authorization::default();
+    for client in list.split('|') {
+        let client = client.trim();
+        let mut parts = client.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("id"), Some("*")) => authz
+                .identities
+                .push(authz::IdentityMatch::Suffix(String::new())),
+            (Some("id"), Some(name)) if name.starts_with("*.") => authz
+                .identities
+                .push(authz::IdentityMatch::Suffix(name[1..].to_string())),
+            (Some("id"), Some(name)) => {
+                let name = identity::Name::from_hostname(name.as_bytes()).map_err(|_| {
+                    error!(%name, "Not a valid identity name");
+                    ParseError::NameError
+                })?;
+                authz.identities.push(authz::IdentityMatch::Exact(name));
+            }
+            (Some("net"), Some(net)) => {
+                let net = ipnet::IpNet::from_str(net).map_err(|_| {
+                    error!(%net, "Not a valid network");
+                    ParseError::NotANetwork
+                })?;
+                authz.networks.push(net);
+            }
+            _ => {
+                error!(%client, "Expected id:<name>, id:*.<suffix>, or net:<network>");
+                return Err(ParseError::NotAnAuthorization);
+            }
+        }
+    }
+    Ok(authz)
+}
+
+fn parse_egress_rules(list: &str) -> Result<Vec<egress::Rule>, ParseError> {
+    let mut rules = Vec::new();
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut parts = entry.splitn(2, '=');
+        let (action, matches) = match (parts.next(), parts.next()) {
+            (Some(action), Some(matches)) => (parse_egress_action(action.trim())?, matches),
+            _ => {
+                error!(%entry, "Expected <action>=<match>[;<match>]");
+                return Err(ParseError::NotAnEgressRule);
+            }
+        };
+
+        let mut rule = egress::Rule {
+            action,
+            dst: None,
+            port: None,
+        };
+        for m in matches.split(';') {
+            let m = m.trim();
+            let mut parts = m.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some("*"), None) => {}
+                (Some("name"), Some(name)) if rule.dst.is_none() => {
+                    rule.dst = Some(egress::DstMatch::Name(parse_dns_suffix(name)?));
+                }
+                (Some("net"), Some(net)) if rule.dst.is_none() => {
+                    let net = ipnet::IpNet::from_str(net).map_err(|_| {
+                        error!(%net, "Not a valid network");
+                        ParseError::NotANetwork
+                    })?;
+                    rule.dst = Some(egress::DstMatch::Network(net));
+                }
+                (Some("port"), Some(port)) if rule.port.is_none() => {
+                    rule.port = Some(parse_number(port)?);
+                }
+                _ => {
+                    error!(%m, "Expected one each of name:<suffix> or net:<network>, and port:<port>");
+                    return Err(ParseError::NotAnEgressRule);
+                }
+            }
+        }
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+fn parse_egress_action(s: &str) -> Result<egress::Action, ParseError> {
+    match s {
+        "allow" => Ok(egress::Action::Allow),
+        "deny" => Ok(egress::Action::Deny),
+        _ => {
+            error!(action = %s, "Expected allow or deny");
+            Err(ParseError::NotAnEgressRule)
+        }
+    }
+}
+
+pub fn parse_backoff<S: Strings>(
+    strings: &S,
+    base: &str,
+    default: ExponentialBackoff,
+) -> Result<ExponentialBackoff, EnvError> {
+    let min_env = format!("LINKERD2_PROXY_{}_EXP_BACKOFF_MIN", base);
+    let min = parse(strings, &min_env, parse_duration);
+    let max_env = format!("LINKERD2_PROXY_{}_EXP_BACKOFF_MAX", base);
+    let max = parse(strings, &max_env, parse_duration);
+    let jitter_env = format!("LINKERD2_PROXY_{}_EXP_BACKOFF_JITTER", base);
+    let jitter = parse(strings, &jitter_env, parse_number::<f64>);
+
+    match (min?, max?, jitter?) {
+        (None, None, None) => Ok(default),
+        (Some(min), Some(max), jitter) => {
+            ExponentialBackoff::new(min, max, jitter.unwrap_or_default()).map_err(|error| {
+                error!(message="Invalid backoff", %error, %min_env, ?min, %max_env, ?max, %jitter_env, ?jitter);
+                EnvError::InvalidEnvVar
+            })
+        }
+        _ => {
+            error!("You need to specify either all of {} {} {} or none of them to use the default backoff", min_env, max_env,jitter_env );
+            Err(EnvError::InvalidEnvVar)
+        }
+    }
+}
+
+pub fn parse_outlier_config<S: Strings>(strings: &S) -> Result<http_outlier::Config, EnvError> {
+    let consecutive_failures = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES,
+        parse_number::<usize>,
+    );
+    let failure_rate = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_FAILURE_RATE,
+        parse_number::<f64>,
+    );
+    let min_requests = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_FAILURE_RATE_MIN_REQUESTS,
+        parse_number::<usize>,
+    );
+    let window = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_FAILURE_RATE_WINDOW,
+        parse_duration,
+    );
+    let base_ejection_time = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_BASE_EJECTION_TIME,
+        parse_duration,
+    );
+    let max_ejection_time = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_MAX_EJECTION_TIME,
+        parse_duration,
+    );
+    let max_ejection_percent = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT,
+        parse_number::<u8>,
+    );
+
+    let (min_requests, window) = (min_requests?, window?);
+    let failure_rate = match failure_rate? {
+        None => None,
+        Some(threshold) if threshold >= 0.0 && threshold <= 1.0 => {
+            Some(http_outlier::FailureRate {
+                threshold,
+                min_requests: min_requests
+                    .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_FAILURE_RATE_MIN_REQUESTS),
+                window: window.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_FAILURE_RATE_WINDOW),
+            })
+        }
+        Some(threshold) => {
+            error!(
+                "{} must be between 0.0 and 1.0; found {}",
+                ENV_OUTBOUND_OUTLIER_FAILURE_RATE, threshold
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+    };
+
+    let base_ejection_time =
+        base_ejection_time?.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_BASE_EJECTION_TIME);
+    let max_ejection_time =
+        max_ejection_time?.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_TIME);
+    if max_ejection_time < base_ejection_time {
+        error!(
+            "{} must not be less than {}",
+            ENV_OUTBOUND_OUTLIER_MAX_EJECTION_TIME, ENV_OUTBOUND_OUTLIER_BASE_EJECTION_TIME
+        );
+        return Err(EnvError::InvalidEnvVar);
+    }
+
+    let max_ejection_percent =
+        max_ejection_percent?.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT);
+    if max_ejection_percent > 100 {
+        error!(
+            "{} must not exceed 100; found {}",
+            ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT, max_ejection_percent
+        );
+        return Err(EnvError::InvalidEnvVar);
+    }
+
+    Ok(http_outlier::Config {
+        consecutive_failures: consecutive_failures?.filter(|n| *n > 0),
+        failure_rate,
+        base_ejection_time,
+        max_ejection_time,
+        max_ejection_percent,
+    })
+}
+
+pub fn parse_health_check_config<S: Strings>(strings: &S) -> Result<http_health::Config, EnvError> {
+    let probes = parse(strings, ENV_OUTBOUND_HEALTH_CHECKS, parse_health_checks);
+    let interval = parse(strings, ENV_OUTBOUND_HEALTH_CHECK_INTERVAL, parse_duration);
+    let jitter = parse(strings, ENV_OUTBOUND_HEALTH_CHECK_JITTER, parse_duration);
+    let timeout = parse(strings, ENV_OUTBOUND_HEALTH_CHECK_TIMEOUT, parse_duration);
+    let unhealthy_threshold = parse(
+        strings,
+        ENV_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD,
+        parse_number::<usize>,
+    );
+    let healthy_threshold = parse(
+        strings,
+        ENV_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD,
+        parse_number::<usize>,
+    );
+
+    let interval = interval?.unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_INTERVAL);
+    if interval == Duration::from_secs(0) {
+        error!("{} must be positive", ENV_OUTBOUND_HEALTH_CHECK_INTERVAL);
+        return Err(EnvError::InvalidEnvVar);
+    }
+
+    let unhealthy_threshold =
+        unhealthy_threshold?.unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD);
+    let healthy_threshold =
+        healthy_threshold?.unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD);
+    if unhealthy_threshold == 0 || healthy_threshold == 0 {
+        error!(
+            "{} and {} must be positive",
+            ENV_OUTBOUND_HEALTH_CHECK_UNHEALTHY_THRESHOLD,
+            ENV_OUTBOUND_HEALTH_CHECK_HEALTHY_THRESHOLD
+        );
+        return Err(EnvError::InvalidEnvVar);
+    }
+
+    Ok(http_health::Config {
+        probes: probes?.unwrap_or_default(),
+        interval,
+        jitter: jitter?.unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_JITTER),
+        timeout: timeout?.unwrap_or(DEFAULT_OUTBOUND_HEALTH_CHECK_TIMEOUT),
+        unhealthy_threshold,
+        healthy_threshold,
+    })
+}
+
+pub fn parse_failover_config<S: Strings>(strings: &S) -> Result<failover::Config, EnvError> {
+    let backups = parse(strings, ENV_OUTBOUND_FAILOVER, parse_failovers);
+    let threshold = parse(strings, ENV_OUTBOUND_FAILOVER_THRESHOLD, parse_duration);
+
+    Ok(failover::Config {
+        backups: backups?.unwrap_or_default(),
+        threshold: threshold?.unwrap_or(DEFAULT_OUTBOUND_FAILOVER_THRESHOLD),
+    })
+}
+
+pub fn parse_rate_limit_config<S: Strings>(
+    strings: &S,
+) -> Result<IndexMap<String, RateLimit>, EnvError> {
+    let limits = parse(strings, ENV_OUTBOUND_RATE_LIMITS, parse_rate_limits);
+    let max_queued = parse(
+        strings,
+        ENV_OUTBOUND_RATE_LIMIT_MAX_QUEUED,
+        parse_number::<u32>,
+    );
+
+    let max_queued = max_queued?.unwrap_or(DEFAULT_OUTBOUND_RATE_LIMIT_MAX_QUEUED);
+    Ok(limits?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(dst, (rate, burst))| {
+            let limit = RateLimit {
+                rate,
+                burst,
+                max_queued,
+            };
+            (dst, limit)
+        })
+        .collect())
+}
+
+/// Parses the policies that are configured locally for profile routes.
+pub fn parse_route_policy_config<S: Strings>(
+    strings: &S,
+) -> Result<profiles::RoutePolicies, EnvError> {
+    let hedges = parse(strings, ENV_OUTBOUND_ROUTE_HEDGES, |s| {
+        parse_route_entries(s, parse_hedge_delay)
+    });
+    let mirrors = parse(strings, ENV_OUTBOUND_ROUTE_MIRRORS, |s| {
+        parse_route_entries(s, parse_mirror)
+    });
+    let faults = parse(strings, ENV_OUTBOUND_ROUTE_FAULTS, |s| {
+        parse_route_entries(s, parse_fault)
+    });
+    let rate_limits = parse(strings, ENV_OUTBOUND_ROUTE_RATE_LIMITS, |s| {
+        parse_route_entries(s, parse_rate_limit)
+    });
+    let matches = parse(strings, ENV_OUTBOUND_ROUTE_MATCHES, |s| {
+        parse_route_entries(s, parse_request_match)
+    });
+    let max_queued = parse(
+        strings,
+        ENV_OUTBOUND_RATE_LIMIT_MAX_QUEUED,
+        parse_number::<u32>,
+    );
+
+    let mut policies = IndexMap::<NameAddr, IndexMap<String, profiles::RoutePolicy>>::new();
+    for (dst, route, delay) in hedges?.unwrap_or_default() {
+        let policy = policies.entry(dst).or_default().entry(route).or_default();
+        policy.hedge = Some(delay);
+    }
+    for (dst, route, mirror) in mirrors?.unwrap_or_default() {
+        let policy = policies.entry(dst).or_default().entry(route).or_default();
+        policy.mirror = Some(mirror);
+    }
+    for (dst, route, fault) in faults?.unwrap_or_default() {
+        let policy = policies.entry(dst).or_default().entry(route).or_default();
+        let faults = policy.faults.get_or_insert_with(Default::default);
+        if fault.delay.is_some() {
+            faults.delay = fault.delay;
+        }
+        if fault.abort.is_some() {
+            faults.abort = fault.abort;
+        }
+    }
+    for (dst, route, m) in matches?.unwrap_or_default() {
+        let policy = policies.entry(dst).or_default().entry(route).or_default();
+        policy.matches.push(m);
+    }
+    let max_queued = max_queued?.unwrap_or(DEFAULT_OUTBOUND_RATE_LIMIT_MAX_QUEUED);
+    for (dst, route, (rate, burst)) in rate_limits?.unwrap_or_default() {
+        let policy = policies.entry(dst).or_default().entry(route).or_default();
+        policy.rate_limit = Some(RateLimit {
+            rate,
+            burst,
+            max_queued,
+        });
+    }
+
+    Ok(profiles::RoutePolicies::new(policies))
+}
+
+fn adaptive_concurrency_limit(initial_limit: usize, max_limit: usize) -> AdaptiveConcurrencyLimit {
+    AdaptiveConcurrencyLimit {
+        min_limit: DEFAULT_ADAPTIVE_CONCURRENCY_MIN_LIMIT.min(initial_limit),
+        initial_limit,
+        max_limit,
+        backoff_ratio: DEFAULT_ADAPTIVE_CONCURRENCY_BACKOFF_RATIO,
+        latency_tolerance: DEFAULT_ADAPTIVE_CONCURRENCY_LATENCY_TOLERANCE,
+    }
+}
+
+pub fn parse_locality_config<S: Strings>(
+    strings: &S,
+) -> Result<Option<http_locality::Config>, EnvError> {
+    let locality = strings.get(ENV_OUTBOUND_LOCALITY);
+    let label = strings.get(ENV_OUTBOUND_LOCALITY_LABEL);
+    let min_local_endpoints = parse(
+        strings,
+        ENV_OUTBOUND_LOCALITY_MIN_ENDPOINTS,
+        parse_number::<usize>,
+    );
+    let max_local_load = parse(
+        strings,
+        ENV_OUTBOUND_LOCALITY_MAX_LOAD,
+        parse_number::<usize>,
+    );
+
+    let locality = match locality? {
+        Some(locality) if !locality.is_empty() => locality,
+        _ => return Ok(None),
+    };
+
+    let max_local_load = max_local_load?.unwrap_or(DEFAULT_OUTBOUND_LOCALITY_MAX_LOAD);
+    if max_local_load == 0 {
+        error!("{} must be greater than 0", ENV_OUTBOUND_LOCALITY_MAX_LOAD);
+        return Err(EnvError::InvalidEnvVar);
+    }
+
+    Ok(Some(http_locality::Config {
+        locality,
+        label: label?.unwrap_or_else(|| DEFAULT_OUTBOUND_LOCALITY_LABEL.to_string()),
+        min_local_endpoints: min_local_endpoints?
+            .unwrap_or(DEFAULT_OUTBOUND_LOCALITY_MIN_ENDPOINTS),
+        max_local_load,
+    }))
+}
+
+pub fn parse_control_addr<S: Strings>(
+    strings: &S,
+    base: &str,
+) -> Result<Option<ControlAddr>, EnvError> {
+    let a_env = format!("{}_ADDR", base);
+    let a = parse(strings, &a_env, parse_addr);
+    let n_env = format!("{}_NAME", base);
+    let n = parse(strings, &n_env, parse_identity);
+    match (a?, n?) {
+        (None, None) => Ok(None),
+        (Some(ref addr), _) if addr.is_loopback() => Ok(Some(ControlAddr {
+            addr: addr.clone(),
+            identity: tls::Conditional::None(tls::ReasonForNoPeerName::Loopback.into()),
+        })),
+        (Some(addr), Some(name)) => Ok(Some(ControlAddr {
+            addr,
+            identity: tls::Conditional::Some(name),
+        })),
+        (Some(_), None) => {
+            error!("{} must be specified when {} is set", n_env, a_env);
+            Err(EnvError::InvalidEnvVar)
+        }
+        (None, Some(_)) => {
+            error!("{} must be specified when {} is set", a_env, n_env);
+            Err(EnvError::InvalidEnvVar)
+        }
+    }
+}
+
+pub fn parse_control_addr_disable_identity<S: Strings>(
+    strings: &S,
+    base: &str,
+) -> Result<Option<ControlAddr>, EnvError> {
+    let a = parse(strings, &format!("{}_ADDR", base), parse_addr)?;
+    let identity = tls::Conditional::None(tls::ReasonForNoPeerName::LocalIdentityDisabled);
+    Ok(a.map(|addr| ControlAddr { addr, identity }))
+}
+
+pub fn parse_identity_config<S: Strings>(
+    strings: &S,
+) -> Result<Option<(ControlAddr, identity::certify::Config)>, EnvError> {
+    let control = parse_control_addr(strings, ENV_IDENTITY_SVC_BASE);
+    let ta = parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
+        identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
+    });
+    let dir = parse(strings, ENV_IDENTITY_DIR, |ref s| Ok(PathBuf::from(s)));
+    let tok = parse(strings, ENV_IDENTITY_TOKEN_FILE, |ref s| {
+        identity::TokenSource::if_nonempty_file(s.to_string()).map_err(|e| {
+            error!("Could not read {}: {}", ENV_IDENTITY_TOKEN_FILE, e);
+            ParseError::InvalidTokenSource
+        })
+    });
+    let li = parse(strings, ENV_IDENTITY_IDENTITY_LOCAL_NAME, parse_identity);
+    let min_refresh = parse(strings, ENV_IDENTITY_MIN_REFRESH, parse_duration);
+    let max_refresh = parse(strings, ENV_IDENTITY_MAX_REFRESH, parse_duration);
+
+    let disabled = strings
+        .get(ENV_IDENTITY_DISABLED)?
+        .map(|d| !d.is_empty())
+        .unwrap_or(false);
+
+    match (
+        disabled,
+        control?,
+        ta?,
+        dir?,
+        li?,
+        tok?,
+        min_refresh?,
+        max_refresh?,
+    ) {
+        (disabled, None, None, None, None, None, None, None) => {
+            if !disabled {
+                error!(
+                    "{} must be set or identity configuration must be specified.",
+                    ENV_IDENTITY_DISABLED
+                );
+                Err(EnvError::InvalidEnvVar)
+            } else {
+                Ok(None)
+            }
+        }
+        (
+            false,
+            Some(control),
+            Some(trust_anchors),
+            Some(dir),
             Some(local_name),
             Some(token),
             min_refresh,
@@ -1025,6 +2435,75 @@ pub fn parse_identity_config<S: Strings>(
     }
 }
 
+/// Reads the local identity's configuration when it is loaded from files.
+pub fn parse_identity_files_config<S: Strings>(
+    strings: &S,
+) -> Result<Option<identity::files::Config>, EnvError> {
+    let crt = parse(strings, ENV_IDENTITY_CRT_FILE, |ref s| Ok(PathBuf::from(s)))?;
+    let key = parse(strings, ENV_IDENTITY_KEY_FILE, |ref s| Ok(PathBuf::from(s)))?;
+    let (crt_path, key_path) = match (crt, key) {
+        (None, None) => return Ok(None),
+        (Some(crt), Some(key)) => (crt, key),
+        _ => {
+            error!(
+                "{} and {} must be set together.",
+                ENV_IDENTITY_CRT_FILE, ENV_IDENTITY_KEY_FILE
+            );
+            return Err(EnvError::InvalidEnvVar);
+        }
+    };
+
+    let ta = parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
+        identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
+    });
+    let li = parse(strings, ENV_IDENTITY_IDENTITY_LOCAL_NAME, parse_identity);
+    let poll_interval = parse(strings, ENV_IDENTITY_FILES_POLL_INTERVAL, parse_duration);
+
+    // The Identity service is not used, so its configuration must be unset.
+    let svc_addr = format!("{}_ADDR", ENV_IDENTITY_SVC_BASE);
+    let mut conflicts = false;
+    for name in &[
+        ENV_IDENTITY_DISABLED,
+        ENV_IDENTITY_DIR,
+        ENV_IDENTITY_TOKEN_FILE,
+        svc_addr.as_str(),
+    ] {
+        if strings.get(name)?.is_some() {
+            error!(
+                "{} must be unset when {} is set.",
+                name, ENV_IDENTITY_CRT_FILE
+            );
+            conflicts = true;
+        }
+    }
+
+    match (ta?, li?) {
+        (Some(trust_anchors), Some(local_name)) if !conflicts => {
+            Ok(Some(identity::files::Config {
+                trust_anchors,
+                local_name,
+                crt_path,
+                key_path,
+                poll_interval: poll_interval?.unwrap_or(DEFAULT_IDENTITY_FILES_POLL_INTERVAL),
+            }))
+        }
+        (trust_anchors, local_name) => {
+            for (unset, name) in &[
+                (trust_anchors.is_none(), ENV_IDENTITY_TRUST_ANCHORS),
+                (local_name.is_none(), ENV_IDENTITY_IDENTITY_LOCAL_NAME),
+            ] {
+                if *unset {
+                    error!(
+                        "{} must be set when {} is set.",
+                        name, ENV_IDENTITY_CRT_FILE
+                    );
+                }
+            }
+            Err(EnvError::InvalidEnvVar)
+        }
+    }
+}
+
 impl fmt::Display for EnvError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1196,4 +2675,531 @@ mod tests {
             "names are coerced to lowercase"
         );
     }
+
+    #[test]
+    fn hash_keys() {
+        let keys = parse_hash_keys(
+            "a.ns.svc.cluster.local:80=header:x-user-id, b.ns.svc.cluster.local:80=cookie:session,\
+             10.1.1.1:8080=source",
+        )
+        .unwrap();
+        let addr = |s| Addr::from_str(s).unwrap();
+        assert_eq!(
+            keys.get(&addr("a.ns.svc.cluster.local:80")),
+            Some(&HashKey::Header(HeaderName::from_static("x-user-id")))
+        );
+        assert_eq!(
+            keys.get(&addr("b.ns.svc.cluster.local:80")),
+            Some(&HashKey::Cookie("session".to_string()))
+        );
+        assert_eq!(keys.get(&addr("10.1.1.1:8080")), Some(&HashKey::SourceAddr));
+
+        assert_eq!(parse_hash_keys(""), Ok(IndexMap::new()));
+        assert_eq!(
+            parse_hash_keys("a.ns.svc.cluster.local:80"),
+            Err(ParseError::NotAHashKey)
+        );
+        assert_eq!(
+            parse_hash_keys("a.ns.svc.cluster.local:80=path"),
+            Err(ParseError::NotAHashKey)
+        );
+        assert_eq!(
+            parse_hash_keys("a.ns.svc.cluster.local:80=cookie:"),
+            Err(ParseError::NotAHashKey)
+        );
+    }
+
+    #[test]
+    fn health_checks() {
+        let probes = parse_health_checks(
+            "a.ns.svc.cluster.local:80=http:/healthz?full=1, b.ns.svc.cluster.local:80=grpc,\
+             10.1.1.1:8080=grpc:svc",
+        )
+        .unwrap();
+        assert_eq!(
+            probes.get("a.ns.svc.cluster.local:80"),
+            Some(&http_health::Probe::Http(PathAndQuery::from_static(
+                "/healthz?full=1"
+            )))
+        );
+        assert_eq!(
+            probes.get("b.ns.svc.cluster.local:80"),
+            Some(&http_health::Probe::Grpc(String::new()))
+        );
+        assert_eq!(
+            probes.get("10.1.1.1:8080"),
+            Some(&http_health::Probe::Grpc("svc".to_string()))
+        );
+
+        assert_eq!(parse_health_checks(""), Ok(IndexMap::new()));
+        assert_eq!(
+            parse_health_checks("a.ns.svc.cluster.local:80"),
+            Err(ParseError::NotAHealthCheck)
+        );
+        assert_eq!(
+            parse_health_checks("a.ns.svc.cluster.local:80=http:healthz"),
+            Err(ParseError::NotAHealthCheck)
+        );
+        assert_eq!(
+            parse_health_checks("a.ns.svc.cluster.local:80=tcp"),
+            Err(ParseError::NotAHealthCheck)
+        );
+    }
+
+    #[test]
+    fn failovers() {
+        let failovers = parse_failovers(
+            "a.ns.svc.cluster.local:80=a.ns.svc.west.example.com:80|a.ns.svc.east.example.com:80,\
+             10.1.1.1:8080=b.ns.svc.cluster.local:8080",
+        )
+        .unwrap();
+        assert_eq!(
+            failovers.get("a.ns.svc.cluster.local:80"),
+            Some(&vec![
+                NameAddr::from_str("a.ns.svc.west.example.com:80").unwrap(),
+                NameAddr::from_str("a.ns.svc.east.example.com:80").unwrap(),
+            ])
+        );
+        assert_eq!(
+            failovers.get("10.1.1.1:8080"),
+            Some(&vec![
+                NameAddr::from_str("b.ns.svc.cluster.local:8080").unwrap()
+            ])
+        );
+
+        assert_eq!(parse_failovers(""), Ok(IndexMap::new()));
+        assert_eq!(
+            parse_failovers("a.ns.svc.cluster.local:80"),
+            Err(ParseError::NotAFailover)
+        );
+        assert_eq!(
+            parse_failovers("a.ns.svc.cluster.local:80="),
+            Err(ParseError::NotAFailover)
+        );
+        assert_eq!(
+            parse_failovers("a.ns.svc.cluster.local:80=b.ns.svc.cluster.local"),
+            Err(ParseError::AddrError(addr::Error::MissingPort))
+        );
+    }
+
+    #[test]
+    fn route_hedges() {
+        let hedges = parse_route_entries(
+            "a.ns.svc.cluster.local:80/GET /users/{id}=p99.9, b.ns.svc.cluster.local:80/list=50ms",
+            parse_hedge_delay,
+        )
+        .unwrap();
+        assert_eq!(
+            hedges,
+            vec![
+                (
+                    NameAddr::from_str("a.ns.svc.cluster.local:80").unwrap(),
+                    "GET /users/{id}".to_string(),
+                    profiles::HedgeDelay::Percentile(0.999),
+                ),
+                (
+                    NameAddr::from_str("b.ns.svc.cluster.local:80").unwrap(),
+                    "list".to_string(),
+                    profiles::HedgeDelay::Fixed(Duration::from_millis(50)),
+                ),
+            ]
+        );
+
+        assert_eq!(parse_route_entries("", parse_hedge_delay), Ok(vec![]));
+        assert_eq!(
+            parse_route_entries("a.ns.svc.cluster.local:80=p99", parse_hedge_delay),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_route_entries("a.ns.svc.cluster.local:80/=p99", parse_hedge_delay),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_route_entries("a.ns.svc.cluster.local:80/list=p100", parse_hedge_delay),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_route_entries("a.ns.svc.cluster.local:80/list=soon", parse_hedge_delay),
+            Err(ParseError::NotADuration)
+        );
+    }
+
+    #[test]
+    fn route_mirrors() {
+        let mirrors = parse_route_entries(
+            "a.ns.svc.cluster.local:80/list=b.ns.svc.cluster.local:80@0.25",
+            parse_mirror,
+        )
+        .unwrap();
+        assert_eq!(
+            mirrors,
+            vec![(
+                NameAddr::from_str("a.ns.svc.cluster.local:80").unwrap(),
+                "list".to_string(),
+                (
+                    NameAddr::from_str("b.ns.svc.cluster.local:80").unwrap(),
+                    0.25
+                ),
+            )]
+        );
+
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local:80@1").map(|(_, r)| r),
+            Ok(1.0)
+        );
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local:80@0").map(|(_, r)| r),
+            Ok(0.0)
+        );
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local:80@1.5"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local:80@-0.1"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local:80@NaN"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local:80"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_mirror("b.ns.svc.cluster.local@0.5"),
+            Err(ParseError::AddrError(addr::Error::MissingPort))
+        );
+    }
+
+    #[test]
+    fn route_faults() {
+        assert_eq!(
+            parse_fault("delay@0.1:100ms-1s"),
+            Ok(profiles::Faults {
+                delay: Some(profiles::DelayFault {
+                    ratio: 0.1,
+                    min: Duration::from_millis(100),
+                    max: Duration::from_secs(1),
+                }),
+                abort: None,
+            })
+        );
+        assert_eq!(
+            parse_fault("delay@1:5s"),
+            Ok(profiles::Faults {
+                delay: Some(profiles::DelayFault {
+                    ratio: 1.0,
+                    min: Duration::from_secs(5),
+                    max: Duration::from_secs(5),
+                }),
+                abort: None,
+            })
+        );
+        assert_eq!(
+            parse_fault("abort@0.01:503"),
+            Ok(profiles::Faults {
+                delay: None,
+                abort: Some(profiles::AbortFault {
+                    ratio: 0.01,
+                    status: profiles::AbortStatus::Http(http::StatusCode::SERVICE_UNAVAILABLE),
+                }),
+            })
+        );
+        assert_eq!(
+            parse_fault("abort@0.5:grpc:14"),
+            Ok(profiles::Faults {
+                delay: None,
+                abort: Some(profiles::AbortFault {
+                    ratio: 0.5,
+                    status: profiles::AbortStatus::Grpc(14),
+                }),
+            })
+        );
+
+        assert_eq!(
+            parse_fault("delay@1.5:1s"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_fault("delay@0.1:1s-100ms"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_fault("abort@0.1:200"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(
+            parse_fault("abort@0.1:grpc:0"),
+            Err(ParseError::NotARoutePolicy)
+        );
+        assert_eq!(parse_fault("reset@0.1:1"), Err(ParseError::NotARoutePolicy));
+        assert_eq!(parse_fault("abort:503"), Err(ParseError::NotARoutePolicy));
+    }
+
+    #[test]
+    fn route_matches() {
+        let matches = parse_route_entries(
+            "a.ns.svc.cluster.local:80/list=header:x-tenant, a.ns.svc.cluster.local:80/list=query:tenant:acme",
+            parse_request_match,
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 2);
+        match &matches[0].2 {
+            profiles::RequestMatch::Header { name, value: None } => {
+                assert_eq!(name, "x-tenant");
+            }
+            m => panic!("unexpected match: {:?}", m),
+        }
+        match &matches[1].2 {
+            profiles::RequestMatch::Query {
+                name,
+                value: Some(profiles::ValueMatch::Exact(value)),
+            } => {
+                assert_eq!(name, "tenant");
+                assert_eq!(value, "acme");
+            }
+            m => panic!("unexpected match: {:?}", m),
+        }
+
+        match parse_request_match("header:content-type~^application/grpc").unwrap() {
+            profiles::RequestMatch::Header {
+                name,
+                value: Some(profiles::ValueMatch::Regex(re)),
+            } => {
+                assert_eq!(name, http::header::CONTENT_TYPE);
+                assert_eq!(re.as_str(), "^application/grpc");
+            }
+            m => panic!("unexpected match: {:?}", m),
+        }
+
+        assert!(parse_request_match("header:").is_err());
+        assert!(parse_request_match("header:bad name").is_err());
+        assert!(parse_request_match("query:v~(").is_err());
+        assert!(parse_request_match("cookie:session").is_err());
+        assert!(parse_request_match("x-tenant").is_err());
+    }
+
+    #[test]
+    fn route_rate_limits() {
+        let limits = parse_route_entries(
+            "a.ns.svc.cluster.local:80/POST /orders=10:20, a.ns.svc.cluster.local:80/list=5",
+            parse_rate_limit,
+        )
+        .unwrap();
+        let dst = NameAddr::from_str("a.ns.svc.cluster.local:80").unwrap();
+        assert_eq!(
+            limits,
+            vec![
+                (dst.clone(), "POST /orders".to_string(), (10, 20)),
+                (dst, "list".to_string(), (5, 5)),
+            ]
+        );
+        assert_eq!(
+            parse_route_entries("a.ns.svc.cluster.local:80/list=0", parse_rate_limit),
+            Err(ParseError::NotARateLimit)
+        );
+    }
+
+    #[test]
+    fn rate_limits() {
+        let limits =
+            parse_rate_limits("a.ns.svc.cluster.local:80=100, 10.1.1.1:8080=10:50").unwrap();
+        assert_eq!(limits.get("a.ns.svc.cluster.local:80"), Some(&(100, 100)));
+        assert_eq!(limits.get("10.1.1.1:8080"), Some(&(10, 50)));
+
+        assert_eq!(parse_rate_limits(""), Ok(IndexMap::new()));
+        assert_eq!(
+            parse_rate_limits("a.ns.svc.cluster.local:80"),
+            Err(ParseError::NotARateLimit)
+        );
+        assert_eq!(
+            parse_rate_limits("a.ns.svc.cluster.local:80=0"),
+            Err(ParseError::NotARateLimit)
+        );
+        assert_eq!(
+            parse_rate_limits("a.ns.svc.cluster.local:80=ten"),
+            Err(ParseError::NotANumber)
+        );
+    }
+
+    #[test]
+    fn authorization() {
+        let ports = parse_authorization(
+            "8080=id:*.ns.serviceaccount.identity.linkerd.cluster.local|net:10.0.0.0/8,\
+             8080/admin=id:admin.ns.serviceaccount.identity.linkerd.cluster.local, 9090/=id:*",
+        )
+        .unwrap();
+
+        let policy = ports.get(&8080).unwrap();
+        assert_eq!(
+            policy.connections,
+            Some(authz::Authorization {
+                identities: vec![authz::IdentityMatch::Suffix(
+                    ".ns.serviceaccount.identity.linkerd.cluster.local".to_string()
+                )],
+                networks: vec!["10.0.0.0/8".parse().unwrap()],
+            })
+        );
+        assert_eq!(
+            policy.paths,
+            vec![(
+                "/admin".to_string(),
+                authz::Authorization {
+                    identities: vec![authz::IdentityMatch::Exact(
+                        identity::Name::from_hostname(
+                            b"admin.ns.serviceaccount.identity.linkerd.cluster.local"
+                        )
+                        .unwrap()
+                    )],
+                    networks: vec![],
+                }
+            )]
+        );
+
+        let policy = ports.get(&9090).unwrap();
+        assert_eq!(policy.connections, None);
+        assert_eq!(
+            policy.paths,
+            vec![(
+                "/".to_string(),
+                authz::Authorization {
+                    identities: vec![authz::IdentityMatch::Suffix(String::new())],
+                    networks: vec![],
+                }
+            )]
+        );
+
+        assert_eq!(parse_authorization(""), Ok(authz::Config::new()));
+        assert_eq!(
+            parse_authorization("8080"),
+            Err(ParseError::NotAnAuthorization)
+        );
+        assert_eq!(
+            parse_authorization("8080=dns:foo"),
+            Err(ParseError::NotAnAuthorization)
+        );
+        assert_eq!(
+            parse_authorization("8080=net:10.0.0.0/8,8080=net:10.0.0.0/8"),
+            Err(ParseError::NotAnAuthorization)
+        );
+        assert_eq!(
+            parse_authorization("8080=net:10.0.0.0"),
+            Err(ParseError::NotANetwork)
+        );
+        assert_eq!(
+            parse_authorization("http=net:10.0.0.0/8"),
+            Err(ParseError::NotANumber)
+        );
+    }
+
+    #[test]
+    fn unix_sockets() {
+        let sockets =
+            parse_unix_sockets("8080=/var/run/app/http.sock, 9090 = /var/run/app/grpc.sock")
+                .unwrap();
+        assert_eq!(
+            sockets.get(&8080),
+            Some(&PathBuf::from("/var/run/app/http.sock"))
+        );
+        assert_eq!(
+            sockets.get(&9090),
+            Some(&PathBuf::from("/var/run/app/grpc.sock"))
+        );
+
+        assert_eq!(parse_unix_sockets(""), Ok(IndexMap::new()));
+        assert_eq!(parse_unix_sockets("8080"), Err(ParseError::NotAUnixSocket));
+        assert_eq!(
+            parse_unix_sockets("8080=app.sock"),
+            Err(ParseError::NotAUnixSocket)
+        );
+        assert_eq!(
+            parse_unix_sockets("http=/var/run/app/http.sock"),
+            Err(ParseError::NotANumber)
+        );
+    }
+
+    #[test]
+    fn port_protocols() {
+        let protocols =
+            parse_port_protocols("8080=http1, 9090=grpc,3306=opaque,443=tls-passthrough,25=http2")
+                .unwrap();
+        assert_eq!(protocols.get(&8080), Some(&PortProtocol::Http1));
+        assert_eq!(protocols.get(&9090), Some(&PortProtocol::Http2));
+        assert_eq!(protocols.get(&3306), Some(&PortProtocol::Opaque));
+        assert_eq!(protocols.get(&443), Some(&PortProtocol::TlsPassthrough));
+
+        let skip = skip_detect_ports(default_disable_ports_protocol_detection(), &protocols);
+        assert!(skip.contains(&443));
+        assert!(skip.contains(&3306));
+        assert!(skip.contains(&587));
+        assert!(!skip.contains(&25));
+        assert!(!skip.contains(&8080));
+
+        assert_eq!(parse_port_protocols(""), Ok(IndexMap::new()));
+        assert_eq!(
+            parse_port_protocols("8080"),
+            Err(ParseError::NotAPortProtocol)
+        );
+        assert_eq!(
+            parse_port_protocols("8080=http3"),
+            Err(ParseError::NotAPortProtocol)
+        );
+        assert_eq!(
+            parse_port_protocols("http=http1"),
+            Err(ParseError::NotANumber)
+        );
+    }
+
+    #[test]
+    fn egress_rules() {
+        let rules = parse_egress_rules(
+            "deny=name:evil.example.com, allow=name:example.com;port:443,allow=net:203.0.113.0/24,deny=*",
+        )
+        .unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                egress::Rule {
+                    action: egress::Action::Deny,
+                    dst: Some(egress::DstMatch::Name(
+                        parse_dns_suffix("evil.example.com").unwrap()
+                    )),
+                    port: None,
+                },
+                egress::Rule {
+                    action: egress::Action::Allow,
+                    dst: Some(egress::DstMatch::Name(
+                        parse_dns_suffix("example.com").unwrap()
+                    )),
+                    port: Some(443),
+                },
+                egress::Rule {
+                    action: egress::Action::Allow,
+                    dst: Some(egress::DstMatch::Network("203.0.113.0/24".parse().unwrap())),
+                    port: None,
+                },
+                egress::Rule {
+                    action: egress::Action::Deny,
+                    dst: None,
+                    port: None,
+                },
+            ]
+        );
+
+        assert_eq!(parse_egress_rules(""), Ok(vec![]));
+        assert_eq!(
+            parse_egress_rules("block=*"),
+            Err(ParseError::NotAnEgressRule)
+        );
+        assert_eq!(
+            parse_egress_rules("deny=name:example.com;net:10.0.0.0/8"),
+            Err(ParseError::NotAnEgressRule)
+        );
+        assert_eq!(
+            parse_egress_rules("deny=port:smtp"),
+            Err(ParseError::NotANumber)
+        );
+    }
 }
@@ -1,5 +1,5 @@
 pub use linkerd2_app_core::proxy::identity::{
-    certify, Crt, CrtKey, Csr, InvalidName, Key, Local, Name, TokenSource, TrustAnchors,
+    certify, files, Crt, CrtKey, Csr, InvalidName, Key, Local, Name, TokenSource, TrustAnchors,
 };
 use linkerd2_app_core::{
     classify,
@@ -22,12 +22,17 @@ pub enum Config {
         control: ControlConfig,
         certify: certify::Config,
     },
+    /// Loads the certificate and key from files instead of the Identity
+    /// service.
+    Files(files::Config),
 }
 
 pub enum Identity {
     Disabled,
     Enabled {
-        addr: ControlAddr,
+        /// The Identity service's address, unless identity is loaded from
+        /// files.
+        addr: Option<ControlAddr>,
         local: Local,
         task: Task,
     },
@@ -70,7 +75,23 @@ impl Config {
                     })
                 };
 
-                Ok(Identity::Enabled { addr, local, task })
+                Ok(Identity::Enabled {
+                    addr: Some(addr),
+                    local,
+                    task,
+                })
+            }
+            Config::Files(config) => {
+                let (local, crt_store) = config.local();
+                let task = Box::pin(async move {
+                    debug!(crt = %config.crt_path.display(), "watching");
+                    files::daemon(config, crt_store).await
+                });
+                Ok(Identity::Enabled {
+                    addr: None,
+                    local,
+                    task,
+                })
             }
         }
     }
@@ -147,6 +147,11 @@ impl Config {
         let inbound_metrics = metrics.inbound;
 
         let (outbound_addr, outbound_listen) = outbound.proxy.server.bind.bind()?;
+        let outbound_unix_listen = outbound
+            .unix_listen
+            .as_ref()
+            .map(|b| b.bind())
+            .transpose()?;
         let outbound_metrics = metrics.outbound;
 
         let resolver = dns.resolver;
@@ -158,6 +163,7 @@ impl Config {
             let outbound_connect =
                 outbound.build_tcp_connect(local_identity.clone(), &outbound_metrics);
 
+            let egress = outbound.build_egress(resolver.clone(), &outbound_metrics);
             let refine = outbound.build_dns_refine(resolver, &outbound_metrics.stack);
 
             let outbound_http_endpoint = outbound.build_http_endpoint(
@@ -168,10 +174,26 @@ impl Config {
                 oc_span_sink.clone(),
             );
 
+            let outbound_http_probe_client =
+                outbound.build_http_probe_client(outbound_addr.port(), outbound_connect.clone());
+
+            let outbound_tcp_tunnel =
+                outbound.build_tcp_tunnel(outbound_connect, &outbound_metrics);
+
+            let outbound_tcp_balance = outbound.build_tcp_balance(
+                outbound_tcp_tunnel,
+                dst.resolve.clone(),
+                egress.clone(),
+                &outbound_metrics,
+            );
+
             let outbound_http = outbound.build_http_router(
                 outbound_http_endpoint,
+                outbound_http_probe_client,
                 dst.resolve,
                 dst.profiles.clone(),
+                egress,
+                tap_layer.clone(),
                 outbound_metrics.clone(),
             );
 
@@ -180,11 +202,13 @@ impl Config {
                     .build_server(
                         outbound_addr,
                         outbound_listen,
+                        outbound_unix_listen,
                         svc::stack(refine.clone())
                             .push_map_response(|(n, _)| n)
                             .into_inner(),
-                        outbound_connect,
+                        outbound_tcp_balance,
                         outbound_http.clone(),
+                        tap_layer.clone(),
                         outbound_metrics,
                         oc_span_sink.clone(),
                         drain_rx.clone(),
@@ -268,7 +292,7 @@ impl App {
     pub fn identity_addr(&self) -> Option<&ControlAddr> {
         match self.identity {
             identity::Identity::Disabled => None,
-            identity::Identity::Enabled { ref addr, .. } => Some(addr),
+            identity::Identity::Enabled { ref addr, .. } => addr.as_ref(),
         }
     }
 
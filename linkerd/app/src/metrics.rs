@@ -1,9 +1,12 @@
 pub use linkerd2_app_core::{
+    authz,
     classify::Class,
-    errors, handle_time, http_metrics as metrics,
+    concurrency_limit, egress, errors, failover, handle_time, http_health, http_locality,
+    http_metrics as metrics, http_outlier,
     metric_labels::{ControlLabels, EndpointLabels, RouteLabels},
     metrics::FmtMetrics,
-    opencensus, proxy, stack_metrics, telemetry, transport, ControlHttpMetrics, ProxyMetrics,
+    opencensus, proxy, proxy_protocol, stack_metrics, telemetry, transport, ControlHttpMetrics,
+    ProxyMetrics,
 };
 use std::time::{Duration, SystemTime};
 
@@ -53,6 +56,15 @@ impl Metrics {
             (m, r.without_latencies())
         };
 
+        let (http_route_mirror, mirror_report) = {
+            let m = metrics::Requests::<RouteLabels, Class>::default();
+            let r = m
+                .clone()
+                .into_report(retain_idle)
+                .with_prefix("route_mirror");
+            (m, r)
+        };
+
         let http_errors = errors::Metrics::default();
 
         let handle_time_report = handle_time::Metrics::new();
@@ -61,6 +73,24 @@ impl Metrics {
 
         let stack = stack_metrics::Registry::default();
 
+        let http_outlier = http_outlier::Registry::default();
+
+        let http_locality = http_locality::Registry::default();
+
+        let http_health = http_health::Registry::default();
+
+        let http_balance = proxy::http::balance::Registry::default();
+
+        let http_failover = failover::Registry::default();
+
+        let authz = authz::Registry::default();
+
+        let egress = egress::Registry::default();
+
+        let proxy_protocol = proxy_protocol::Registry::default();
+
+        let concurrency_limit = concurrency_limit::adaptive::Registry::default();
+
         let (transport, transport_report) = transport::metrics::new();
 
         let (opencensus, opencensus_report) = opencensus::metrics::new();
@@ -71,8 +101,18 @@ impl Metrics {
                 http_endpoint: http_endpoint.clone(),
                 http_route: http_route.clone(),
                 http_route_actual: http_route_actual.clone(),
+                http_route_mirror: http_route_mirror.clone(),
                 http_route_retry: http_route_retry.clone(),
                 http_errors: http_errors.inbound(),
+                http_outlier: http_outlier.clone(),
+                http_locality: http_locality.clone(),
+                http_health: http_health.clone(),
+                http_balance: http_balance.clone(),
+                http_failover: http_failover.clone(),
+                authz: authz.clone(),
+                egress: egress.clone(),
+                proxy_protocol: proxy_protocol.clone(),
+                concurrency_limit: concurrency_limit.clone(),
                 stack: stack.clone(),
                 transport: transport.clone(),
             },
@@ -82,7 +122,17 @@ impl Metrics {
                 http_route,
                 http_route_retry,
                 http_route_actual,
+                http_route_mirror,
                 http_errors: http_errors.outbound(),
+                http_outlier: http_outlier.clone(),
+                http_locality: http_locality.clone(),
+                http_health: http_health.clone(),
+                http_balance: http_balance.clone(),
+                http_failover: http_failover.clone(),
+                authz: authz.clone(),
+                egress: egress.clone(),
+                proxy_protocol: proxy_protocol.clone(),
+                concurrency_limit: concurrency_limit.clone(),
                 stack: stack.clone(),
                 transport,
             },
@@ -95,11 +145,21 @@ impl Metrics {
             .and_then(route_report)
             .and_then(retry_report)
             .and_then(actual_report)
+            .and_then(mirror_report)
             .and_then(control_report)
             .and_then(handle_time_report)
             .and_then(transport_report)
             .and_then(opencensus_report)
             .and_then(stack)
+            .and_then(http_outlier)
+            .and_then(http_locality)
+            .and_then(http_health)
+            .and_then(http_balance)
+            .and_then(http_failover)
+            .and_then(authz)
+            .and_then(egress)
+            .and_then(proxy_protocol)
+            .and_then(concurrency_limit)
             .and_then(process)
             .and_then(build_info);
 
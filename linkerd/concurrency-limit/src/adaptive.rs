@@ -0,0 +1,689 @@
+//! A concurrency limit that adapts to the observed latency and failures of
+//! the inner service.
+//!
+//! The limit is managed with an additive-increase/multiplicative-decrease
+//! (AIMD) policy: while requests complete successfully and their latencies
+//! are within a tolerance of the lowest latency observed, the limit grows by
+//! roughly one permit for each `limit` requests. When a request fails or is
+//! slow, the limit is reduced by the backoff ratio.
+//!
+//! Responses are classified when their streams complete, so that failures
+//! that are only indicated by trailers, i.e. gRPC statuses, reduce the limit.
+//! A request's permit is held until its response stream completes.
+//!
+//! Unlike the fixed limit, requests are not queued once the limit is reached.
+//! They fail immediately with a `LimitExceeded` error so that load is shed.
+
+use futures::{ready, TryFuture};
+use http_body::Body;
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use linkerd2_http_classify::{Classify, ClassifyEos, ClassifyResponse};
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetric, FmtMetrics, Gauge};
+use pin_project::{pin_project, pinned_drop};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower::Service;
+use tracing::{debug, trace};
+
+metrics! {
+    concurrency_limit: Gauge {
+        "The current adaptive limit on the number of in-flight requests"
+    },
+    concurrency_in_flight: Gauge {
+        "The number of requests currently in flight under an adaptive concurrency limit"
+    },
+    concurrency_limit_rejected_total: Counter {
+        "Total number of requests rejected because the adaptive concurrency limit was reached"
+    }
+}
+
+/// Determines whether a response classification indicates a failure that
+/// should reduce the limit.
+pub trait IsFailure {
+    fn is_failure(&self) -> bool;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    pub min_limit: usize,
+    pub initial_limit: usize,
+    pub max_limit: usize,
+
+    /// The factor by which the limit is multiplied when a request fails or is
+    /// slow.
+    pub backoff_ratio: f64,
+
+    /// A request is considered slow when its latency exceeds the lowest
+    /// observed latency by this factor.
+    pub latency_tolerance: f64,
+}
+
+/// Records adaptive concurrency limit metrics for each scope.
+#[derive(Debug)]
+pub struct Registry<L: Hash + Eq>(Arc<Mutex<IndexMap<L, Arc<Limiter>>>>);
+
+#[derive(Clone, Debug)]
+pub struct Layer<C> {
+    limiter: Arc<Limiter>,
+    classify: C,
+}
+
+/// Enforces an adaptive limit on the number of concurrent requests to the
+/// inner service.
+#[derive(Clone, Debug)]
+pub struct AdaptiveLimit<S, C> {
+    inner: S,
+    limiter: Arc<Limiter>,
+    classify: C,
+}
+
+#[pin_project(project = ResponseFutureProj)]
+#[derive(Debug)]
+pub enum ResponseFuture<F, C> {
+    Admitted {
+        #[pin]
+        inner: F,
+        permit: Option<Permit>,
+        classify: Option<C>,
+    },
+    Rejected(Option<LimitExceeded>),
+}
+
+/// Holds a request's permit until the response stream completes.
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct ResponseBody<B, C: ClassifyEos>
+where
+    C::Class: IsFailure,
+{
+    #[pin]
+    inner: B,
+    permit: Option<Permit>,
+    classify: Option<C>,
+}
+
+/// Holds one of the limiter's permits until it is dropped.
+#[derive(Debug)]
+pub struct Permit {
+    limiter: Arc<Limiter>,
+    started_at: Instant,
+}
+
+/// Indicates that a request was rejected because the concurrency limit was
+/// reached.
+#[derive(Clone, Debug)]
+pub struct LimitExceeded {
+    limit: usize,
+}
+
+#[derive(Debug)]
+struct Limiter {
+    config: Config,
+    state: Mutex<State>,
+    metrics: Metrics,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: f64,
+    in_flight: usize,
+    min_latency: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    in_flight: Gauge,
+    rejected: Counter,
+}
+
+// === impl Config ===
+
+impl Config {
+    fn clamp(&self, limit: f64) -> f64 {
+        limit
+            .max(self.min_limit.max(1) as f64)
+            .min(self.max_limit.max(1) as f64)
+    }
+}
+
+// === impl Registry ===
+
+impl<L: Hash + Eq> Registry<L> {
+    /// Builds a layer whose limit is shared by all services it produces.
+    ///
+    /// Responses are classified with `classify` unless a response classifier
+    /// has already been set on the request.
+    pub fn layer<C>(&self, scope: L, config: Config, classify: C) -> Layer<C> {
+        let limiter = Arc::new(Limiter::new(config));
+        if let Ok(mut limiters) = self.0.lock() {
+            limiters.insert(scope, limiter.clone());
+        }
+        Layer { limiter, classify }
+    }
+}
+
+impl<L: Hash + Eq> Default for Registry<L> {
+    fn default() -> Self {
+        Registry(Default::default())
+    }
+}
+
+impl<L: Hash + Eq> Clone for Registry<L> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+impl<L: FmtLabels + Hash + Eq> FmtMetrics for Registry<L> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limiters = match self.0.lock() {
+            Ok(limiters) => limiters,
+            Err(_) => return Ok(()),
+        };
+        if limiters.is_empty() {
+            return Ok(());
+        }
+
+        concurrency_limit.fmt_help(f)?;
+        for (scope, limiter) in limiters.iter() {
+            Gauge::from(limiter.limit() as u64).fmt_metric_labeled(
+                f,
+                &concurrency_limit.name,
+                scope,
+            )?;
+        }
+
+        concurrency_in_flight.fmt_help(f)?;
+        concurrency_in_flight.fmt_scopes(f, limiters.iter(), |l| &l.metrics.in_flight)?;
+
+        concurrency_limit_rejected_total.fmt_help(f)?;
+        concurrency_limit_rejected_total.fmt_scopes(f, limiters.iter(), |l| &l.metrics.rejected)?;
+
+        Ok(())
+    }
+}
+
+// === impl Layer ===
+
+impl<S, C: Clone> tower::layer::Layer<S> for Layer<C> {
+    type Service = AdaptiveLimit<S, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AdaptiveLimit {
+            inner,
+            limiter: self.limiter.clone(),
+            classify: self.classify.clone(),
+        }
+    }
+}
+
+// === impl AdaptiveLimit ===
+
+impl<S, C, A, B> Service<http::Request<A>> for AdaptiveLimit<S, C>
+where
+    S: Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+    B: Body,
+    C: Classify,
+    C::Class: IsFailure,
+{
+    type Response = http::Response<ResponseBody<B, C::ClassifyEos>>;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future, C::ClassifyResponse>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        match Limiter::acquire(&self.limiter) {
+            Ok(permit) => {
+                // Prefer a classifier that has already been set on the request.
+                let classify = req
+                    .extensions()
+                    .get::<C::ClassifyResponse>()
+                    .cloned()
+                    .unwrap_or_else(|| self.classify.classify(&req));
+                ResponseFuture::Admitted {
+                    inner: self.inner.call(req),
+                    permit: Some(permit),
+                    classify: Some(classify),
+                }
+            }
+            Err(e) => ResponseFuture::Rejected(Some(e)),
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, C, B> Future for ResponseFuture<F, C>
+where
+    F: TryFuture<Ok = http::Response<B>>,
+    F::Error: Into<Error>,
+    B: Body,
+    C: ClassifyResponse,
+    C::Class: IsFailure,
+{
+    type Output = Result<http::Response<ResponseBody<B, C::ClassifyEos>>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Admitted {
+                inner,
+                permit,
+                classify,
+            } => {
+                let rsp = ready!(inner.try_poll(cx));
+                let permit = permit.take();
+                let classify = classify.take();
+                Poll::Ready(match rsp {
+                    Ok(rsp) => {
+                        let classify = classify.map(|c| c.start(&rsp));
+                        let (head, inner) = rsp.into_parts();
+                        let body = ResponseBody {
+                            inner,
+                            permit,
+                            classify,
+                        };
+                        Ok(http::Response::from_parts(head, body))
+                    }
+                    Err(e) => {
+                        let e = e.into();
+                        if let (Some(permit), Some(classify)) = (permit, classify) {
+                            permit.complete(classify.error(&e).is_failure());
+                        }
+                        Err(e)
+                    }
+                })
+            }
+            ResponseFutureProj::Rejected(error) => {
+                let error = error.take().expect("polled after ready");
+                Poll::Ready(Err(error.into()))
+            }
+        }
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B, C> ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn complete(self: Pin<&mut Self>, class: impl FnOnce(C) -> C::Class) {
+        let this = self.project();
+        if let (Some(permit), Some(classify)) = (this.permit.take(), this.classify.take()) {
+            permit.complete(class(classify).is_failure());
+        }
+    }
+}
+
+impl<B, C> Body for ResponseBody<B, C>
+where
+    B: Body,
+    B::Error: Into<Error>,
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let frame = ready!(self.as_mut().project().inner.poll_data(cx));
+        Poll::Ready(frame.map(|res| {
+            res.map_err(|e| {
+                let e = e.into();
+                self.as_mut().complete(|c| c.error(&e));
+                e
+            })
+        }))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let trls = ready!(self.as_mut().project().inner.poll_trailers(cx)).map_err(|e| {
+            let e = e.into();
+            self.as_mut().complete(|c| c.error(&e));
+            e
+        })?;
+        self.complete(|c| c.eos(trls.as_ref()));
+        Poll::Ready(Ok(trls))
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B: Default, C> Default for ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn default() -> Self {
+        Self {
+            inner: B::default(),
+            permit: None,
+            classify: None,
+        }
+    }
+}
+
+#[pinned_drop]
+impl<B, C> PinnedDrop for ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn drop(self: Pin<&mut Self>) {
+        self.complete(|c| c.eos(None));
+    }
+}
+
+// === impl Permit ===
+
+impl Permit {
+    fn complete(self, failed: bool) {
+        let latency = self.started_at.elapsed();
+        self.limiter.update(latency, failed);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        // Canceled requests release their permit without updating the limit.
+        self.limiter.release();
+    }
+}
+
+// === impl Limiter ===
+
+impl Limiter {
+    fn new(config: Config) -> Self {
+        let state = State {
+            limit: config.clamp(config.initial_limit as f64),
+            in_flight: 0,
+            min_latency: None,
+        };
+        Self {
+            config,
+            state: Mutex::new(state),
+            metrics: Metrics::default(),
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.state
+            .lock()
+            .map(|s| s.limit as usize)
+            .unwrap_or(self.config.min_limit)
+    }
+
+    fn acquire(this: &Arc<Self>) -> Result<Permit, LimitExceeded> {
+        let mut state = this.state.lock().expect("limiter lock poisoned");
+        let limit = state.limit as usize;
+        if state.in_flight >= limit {
+            trace!(limit, in_flight = state.in_flight, "Rejecting request");
+            this.metrics.rejected.incr();
+            return Err(LimitExceeded { limit });
+        }
+
+        state.in_flight += 1;
+        this.metrics.in_flight.incr();
+        Ok(Permit {
+            limiter: this.clone(),
+            started_at: Instant::now(),
+        })
+    }
+
+    fn release(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.in_flight = state.in_flight.saturating_sub(1);
+            self.metrics.in_flight.decr();
+        }
+    }
+
+    fn update(&self, latency: Duration, failed: bool) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        // The baseline latency drifts slowly toward higher observations so
+        // that it follows lasting changes in the inner service's latency.
+        let min_latency = match state.min_latency {
+            Some(min) if min <= latency => min + (latency - min) / 100,
+            _ => latency,
+        };
+        state.min_latency = Some(min_latency);
+
+        let slow =
+            latency.as_secs_f64() > min_latency.as_secs_f64() * self.config.latency_tolerance;
+        let limit = if failed || slow {
+            state.limit * self.config.backoff_ratio
+        } else if (state.in_flight as f64) * 2.0 >= state.limit {
+            // Only grow the limit while it is being utilized.
+            state.limit + 1.0 / state.limit
+        } else {
+            state.limit
+        };
+        let limit = self.config.clamp(limit);
+
+        if limit as usize != state.limit as usize {
+            debug!(
+                limit = limit as usize,
+                failed,
+                ?latency,
+                ?min_latency,
+                "Adjusted concurrency limit"
+            );
+        }
+        state.limit = limit;
+    }
+}
+
+// === impl LimitExceeded ===
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "concurrency limit of {} exceeded", self.limit)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use tower::ServiceExt;
+
+    /// Classifies responses by the `grpc-status` in their trailers.
+    #[derive(Clone, Debug)]
+    struct Grpc;
+
+    #[derive(Debug)]
+    struct GrpcEos;
+
+    #[derive(Debug)]
+    struct Failed(bool);
+
+    impl IsFailure for Failed {
+        fn is_failure(&self) -> bool {
+            self.0
+        }
+    }
+
+    impl Classify for Grpc {
+        type Class = Failed;
+        type ClassifyEos = GrpcEos;
+        type ClassifyResponse = Grpc;
+
+        fn classify<B>(&self, _: &http::Request<B>) -> Grpc {
+            Grpc
+        }
+    }
+
+    impl ClassifyResponse for Grpc {
+        type Class = Failed;
+        type ClassifyEos = GrpcEos;
+
+        fn start<B>(self, _: &http::Response<B>) -> GrpcEos {
+            GrpcEos
+        }
+
+        fn error(self, _: &Error) -> Failed {
+            Failed(true)
+        }
+    }
+
+    impl ClassifyEos for GrpcEos {
+        type Class = Failed;
+
+        fn eos(self, trailers: Option<&http::HeaderMap>) -> Failed {
+            let status = trailers.and_then(|t| t.get("grpc-status"));
+            Failed(status.map(|s| s != "0").unwrap_or(false))
+        }
+
+        fn error(self, _: &Error) -> Failed {
+            Failed(true)
+        }
+    }
+
+    /// A response body that only carries trailers.
+    #[derive(Debug, Default)]
+    struct Trailers(Option<http::HeaderMap>);
+
+    impl Body for Trailers {
+        type Data = &'static [u8];
+        type Error = Error;
+
+        fn poll_data(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+            Poll::Ready(None)
+        }
+
+        fn poll_trailers(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+            Poll::Ready(Ok(self.0.take()))
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            min_limit: 2,
+            initial_limit: 4,
+            max_limit: 8,
+            backoff_ratio: 0.5,
+            latency_tolerance: 2.0,
+        }
+    }
+
+    #[test]
+    fn rejects_beyond_limit() {
+        let limiter = Arc::new(Limiter::new(config()));
+        let permits = (0..4)
+            .map(|_| Limiter::acquire(&limiter).expect("must be admitted"))
+            .collect::<Vec<_>>();
+        assert!(Limiter::acquire(&limiter).is_err());
+        assert_eq!(limiter.metrics.rejected.value(), 1);
+
+        drop(permits);
+        assert!(Limiter::acquire(&limiter).is_ok());
+    }
+
+    #[test]
+    fn backs_off_on_failure() {
+        let limiter = Limiter::new(config());
+        limiter.update(Duration::from_millis(10), true);
+        assert_eq!(limiter.limit(), 2);
+
+        // The limit is never reduced below the minimum.
+        limiter.update(Duration::from_millis(10), true);
+        assert_eq!(limiter.limit(), 2);
+    }
+
+    #[test]
+    fn backs_off_on_slow_responses() {
+        let limiter = Limiter::new(config());
+        limiter.update(Duration::from_millis(10), false);
+        assert_eq!(limiter.limit(), 4);
+        limiter.update(Duration::from_millis(100), false);
+        assert_eq!(limiter.limit(), 2);
+    }
+
+    #[test]
+    fn grows_while_utilized() {
+        let limiter = Arc::new(Limiter::new(config()));
+        let _permits = (0..4)
+            .map(|_| Limiter::acquire(&limiter).expect("must be admitted"))
+            .collect::<Vec<_>>();
+        for _ in 0..5 {
+            limiter.update(Duration::from_millis(10), false);
+        }
+        assert_eq!(limiter.limit(), 5);
+
+        // The limit is never raised above the maximum.
+        for _ in 0..100 {
+            limiter.update(Duration::from_millis(10), false);
+        }
+        assert_eq!(limiter.limit(), 8);
+    }
+
+    #[tokio::test]
+    async fn backs_off_on_failures_in_trailers() {
+        let limiter = Arc::new(Limiter::new(config()));
+        let layer = Layer {
+            limiter: limiter.clone(),
+            classify: Grpc,
+        };
+        let mut svc = tower::layer::Layer::layer(
+            &layer,
+            tower::service_fn(|_: http::Request<()>| {
+                let mut trailers = http::HeaderMap::new();
+                trailers.insert("grpc-status", http::HeaderValue::from_static("14"));
+                future::ok::<_, Error>(http::Response::new(Trailers(Some(trailers))))
+            }),
+        );
+
+        let rsp = svc
+            .ready_and()
+            .await
+            .unwrap()
+            .call(http::Request::new(()))
+            .await
+            .expect("request must succeed");
+        // The permit is held until the response stream completes.
+        assert_eq!(limiter.limit(), 4);
+        assert_eq!(limiter.metrics.in_flight.value(), 1);
+
+        // A gRPC failure is only indicated by the response's trailers.
+        let mut body = rsp.into_body();
+        assert!(body.data().await.is_none());
+        assert!(body.trailers().await.unwrap().is_some());
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.metrics.in_flight.value(), 0);
+    }
+}
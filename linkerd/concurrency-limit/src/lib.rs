@@ -6,6 +6,8 @@
 
 #![deny(warnings, rust_2018_idioms)]
 
+pub mod adaptive;
+
 use pin_project::pin_project;
 use std::future::Future;
 use std::pin::Pin;
@@ -6,6 +6,7 @@ pub use self::refine::{MakeRefine, Refine};
 pub use linkerd2_dns_name::{InvalidName, Name, Suffix};
 use std::future::Future;
 use std::pin::Pin;
+use std::time::Instant;
 use std::{fmt, net};
 use tokio::sync::{mpsc, oneshot};
 use tracing::{info_span, trace, Span};
@@ -121,6 +122,22 @@ impl Resolver {
         })
     }
 
+    /// Resolves all of a name's addresses, and the time until which they are
+    /// valid.
+    pub fn resolve_ips(
+        &self,
+        name: &Name,
+    ) -> Pin<Box<dyn Future<Output = Result<(Vec<net::IpAddr>, Instant), Error>> + Send + 'static>>
+    {
+        let name = name.clone();
+        let resolver = self.clone();
+        Box::pin(async move {
+            let span = info_span!("resolve_ips", %name);
+            let ips = resolver.lookup_ip(name, span).await?;
+            Ok((ips.iter().collect(), ips.valid_until()))
+        })
+    }
+
     /// Creates a refining service.
     pub fn into_make_refine(self) -> MakeRefine {
         MakeRefine(self)
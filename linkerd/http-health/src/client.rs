@@ -0,0 +1,128 @@
+use futures::{ready, TryFuture};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::util::Oneshot;
+
+/// Builds endpoint services along with a means to build clients that probe
+/// their endpoints.
+#[derive(Clone, Debug)]
+pub struct MakeProbeClient<M, P> {
+    inner: M,
+    probe: P,
+    enabled: bool,
+}
+
+#[pin_project]
+pub struct MakeProbeClientFuture<F, P> {
+    #[pin]
+    future: F,
+    client: Option<P>,
+}
+
+/// An endpoint service and, if its endpoint may be health checked, a future
+/// that builds a distinct client that is used to probe it.
+///
+/// Probe clients are built by their own stack, so that probes are neither
+/// recorded as application traffic nor queued behind the balanced service's
+/// requests. The client is only built if the endpoint's destination has a
+/// probe.
+#[derive(Debug)]
+pub struct WithProbeClient<S, P> {
+    pub(crate) inner: S,
+    pub(crate) client: Option<P>,
+}
+
+// === impl MakeProbeClient ===
+
+impl<M, P> MakeProbeClient<M, P> {
+    pub(crate) fn new(inner: M, probe: P, enabled: bool) -> Self {
+        Self {
+            inner,
+            probe,
+            enabled,
+        }
+    }
+}
+
+impl<T, M, P> tower::Service<T> for MakeProbeClient<M, P>
+where
+    T: Clone,
+    M: tower::Service<T>,
+    P: tower::Service<T> + Clone,
+{
+    type Response = WithProbeClient<M::Response, Oneshot<P, T>>;
+    type Error = M::Error;
+    type Future = MakeProbeClientFuture<M::Future, Oneshot<P, T>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        // The client is not built until the future is polled.
+        let client = if self.enabled {
+            Some(Oneshot::new(self.probe.clone(), target.clone()))
+        } else {
+            None
+        };
+        MakeProbeClientFuture {
+            future: self.inner.call(target),
+            client,
+        }
+    }
+}
+
+// === impl MakeProbeClientFuture ===
+
+impl<F, P> Future for MakeProbeClientFuture<F, P>
+where
+    F: TryFuture,
+{
+    type Output = Result<WithProbeClient<F::Ok, P>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(WithProbeClient {
+            inner,
+            client: this.client.take(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, future};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::Service;
+
+    #[test]
+    fn builds_probe_clients_when_polled() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let probe = {
+            let built = built.clone();
+            tower::service_fn(move |()| {
+                built.fetch_add(1, Ordering::SeqCst);
+                future::ok::<_, ()>(())
+            })
+        };
+        let inner = || tower::service_fn(|()| future::ok::<_, ()>(()));
+
+        let mut enabled = MakeProbeClient::new(inner(), probe.clone(), true);
+        let WithProbeClient { client, .. } = block_on(enabled.call(())).unwrap();
+        assert_eq!(built.load(Ordering::SeqCst), 0);
+        block_on(client.expect("client must be built")).unwrap();
+        assert_eq!(built.load(Ordering::SeqCst), 1);
+
+        let mut disabled = MakeProbeClient::new(inner(), probe, false);
+        let WithProbeClient { client, .. } = block_on(disabled.call(())).unwrap();
+        assert!(client.is_none());
+        assert_eq!(built.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{
+    client::WithProbeClient,
+    endpoint::Endpoint,
+    metrics::{Metrics, Registry},
+    probe::Prober,
+    state::Health,
+    Config, Probe,
+};
+use futures::{ready, Stream, TryFuture, TryFutureExt};
+use http_body::Body;
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use linkerd2_http_box::Payload;
+use pin_project::{pin_project, pinned_drop};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::discover::{self, Change};
+use tracing::{debug, info_span, warn};
+use tracing_futures::Instrument;
+
+/// Wraps a discovery-producing service so that each discovered endpoint of a
+/// health-checked destination is probed.
+#[derive(Debug)]
+pub struct MakeDiscover<M, L: Hash + Eq> {
+    inner: M,
+    scope: L,
+    config: Arc<Config>,
+    registry: Registry<L>,
+}
+
+#[pin_project]
+pub struct DiscoverFuture<F, L: Hash + Eq> {
+    #[pin]
+    future: F,
+    dst: String,
+    probe: Option<Probe>,
+    scope: L,
+    config: Arc<Config>,
+    registry: Registry<L>,
+}
+
+/// Probes the endpoints of a single balancer.
+#[pin_project(PinnedDrop)]
+pub struct Discover<D: discover::Discover, L: Hash + Eq> {
+    #[pin]
+    inner: D,
+    dst: String,
+    probe: Option<Probe>,
+    scope: L,
+    config: Arc<Config>,
+    registry: Registry<L>,
+    endpoints: IndexMap<D::Key, Arc<Metrics>>,
+}
+
+// === impl MakeDiscover ===
+
+impl<M, L: Hash + Eq> MakeDiscover<M, L> {
+    pub(crate) fn new(inner: M, scope: L, config: Arc<Config>, registry: Registry<L>) -> Self {
+        Self {
+            inner,
+            scope,
+            config,
+            registry,
+        }
+    }
+}
+
+impl<M: Clone, L: Clone + Hash + Eq> Clone for MakeDiscover<M, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            scope: self.scope.clone(),
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<T, M, L> tower::Service<T> for MakeDiscover<M, L>
+where
+    T: fmt::Display,
+    M: tower::Service<T>,
+    M::Response: discover::Discover,
+    L: Clone + Hash + Eq,
+{
+    type Response = Discover<M::Response, L>;
+    type Error = M::Error;
+    type Future = DiscoverFuture<M::Future, L>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.to_string();
+        let probe = self.config.probes.get(&dst).cloned();
+        if let Some(ref probe) = probe {
+            debug!(%dst, ?probe, "Health checking endpoints");
+        }
+        DiscoverFuture {
+            future: self.inner.call(target),
+            dst,
+            probe,
+            scope: self.scope.clone(),
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+// === impl DiscoverFuture ===
+
+impl<F, L> Future for DiscoverFuture<F, L>
+where
+    F: TryFuture,
+    F::Ok: discover::Discover,
+    L: Clone + Hash + Eq,
+{
+    type Output = Result<Discover<F::Ok, L>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(Discover {
+            inner,
+            dst: std::mem::take(this.dst),
+            probe: this.probe.take(),
+            scope: this.scope.clone(),
+            config: this.config.clone(),
+            registry: this.registry.clone(),
+            endpoints: IndexMap::default(),
+        }))
+    }
+}
+
+// === impl Discover ===
+
+impl<D, S, P, C, B, L> Stream for Discover<D, L>
+where
+    D: discover::Discover<Service = WithProbeClient<S, P>>,
+    D::Key: Hash + Clone + fmt::Display,
+    D::Error: Into<Error>,
+    P: TryFuture<Ok = C> + Send + 'static,
+    P::Error: Into<Error>,
+    C: tower::Service<http::Request<Payload>, Response = http::Response<B>> + Send + 'static,
+    C::Error: Into<Error>,
+    C::Future: Send,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Error>,
+    L: Clone + Hash + Eq,
+{
+    type Item = Result<Change<D::Key, Endpoint<S>>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.inner.poll_discover(cx)) {
+            Some(Ok(change)) => change,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            None => return Poll::Ready(None),
+        };
+
+        let change = match change {
+            Change::Insert(key, WithProbeClient { inner, client }) => {
+                match (this.probe.as_ref(), client) {
+                    (Some(probe), Some(client)) => {
+                        let endpoint = key.to_string();
+                        let metrics = this
+                            .registry
+                            .metrics(this.scope, this.dst, endpoint.clone());
+                        this.endpoints.insert(key.clone(), metrics.clone());
+
+                        // The prober holds a weak reference to the endpoint's
+                        // health, so that it stops once the balancer drops
+                        // the endpoint.
+                        let health = Arc::new(Health::new(metrics));
+                        let weak = Arc::downgrade(&health);
+                        let probe = probe.clone();
+                        let dst = this.dst.clone();
+                        let config = this.config.clone();
+                        let span = info_span!("health", dst = %this.dst, %endpoint);
+                        tokio::spawn(
+                            async move {
+                                let client = match client.into_future().await {
+                                    Ok(client) => client,
+                                    Err(error) => {
+                                        let error: Error = error.into();
+                                        warn!(%error, "Failed to build probe client");
+                                        return;
+                                    }
+                                };
+                                Prober::new(client, probe, dst, config, weak).run().await
+                            }
+                            .instrument(span),
+                        );
+
+                        Change::Insert(key, Endpoint::new(inner, Some(health)))
+                    }
+                    _ => Change::Insert(key, Endpoint::new(inner, None)),
+                }
+            }
+            Change::Remove(key) => {
+                if let Some(metrics) = this.endpoints.remove(&key) {
+                    this.registry.remove(&metrics);
+                }
+                Change::Remove(key)
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+#[pinned_drop]
+impl<D, L> PinnedDrop for Discover<D, L>
+where
+    D: discover::Discover,
+    L: Hash + Eq,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        for (_, metrics) in std::mem::take(this.endpoints) {
+            this.registry.remove(&metrics);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::state::Health;
+use futures::ready;
+use linkerd2_proxy_core::{HasWeight, IsLocal, Weight};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// A balanced endpoint that is not ready while it is unhealthy.
+#[derive(Debug)]
+pub struct Endpoint<S> {
+    inner: S,
+    health: Option<Arc<Health>>,
+}
+
+// === impl Endpoint ===
+
+impl<S> Endpoint<S> {
+    pub(crate) fn new(inner: S, health: Option<Arc<Health>>) -> Self {
+        Self { inner, health }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, Req> tower::Service<Req> for Endpoint<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // While the endpoint is unhealthy, it does not become ready. The
+        // balancer is woken when a probe finds the endpoint healthy again.
+        if let Some(health) = self.health.as_ref() {
+            ready!(health.poll_healthy(cx));
+        }
+
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+impl<S: HasWeight> HasWeight for Endpoint<S> {
+    fn weight(&self) -> &Weight {
+        self.inner.weight()
+    }
+}
+
+impl<S: IsLocal> IsLocal for Endpoint<S> {
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+}
@@ -0,0 +1,161 @@
+//! Active health checking for balanced HTTP endpoints.
+//!
+//! When a balancer's destination has a configured probe, a dedicated probe
+//! client is built for each of its endpoints and the endpoint is probed
+//! periodically on a background task. An endpoint that fails too many
+//! consecutive probes is marked unhealthy: its service reports that it is not
+//! ready, so that the balancer skips it, until it passes enough consecutive
+//! probes to be marked healthy again.
+
+#![deny(warnings, rust_2018_idioms)]
+
+use indexmap::IndexMap;
+use std::{hash::Hash, sync::Arc, time::Duration};
+
+mod client;
+mod discover;
+mod endpoint;
+mod metrics;
+mod probe;
+mod state;
+
+pub use self::client::{MakeProbeClient, MakeProbeClientFuture, WithProbeClient};
+pub use self::discover::{Discover, DiscoverFuture, MakeDiscover};
+pub use self::endpoint::Endpoint;
+pub use self::metrics::Registry;
+
+/// Describes how an endpoint is probed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// A `GET` request on a path, which succeeds when the endpoint responds
+    /// with a 2xx status.
+    Http(http::uri::PathAndQuery),
+
+    /// A `grpc.health.v1.Health/Check` request for the named service, which
+    /// succeeds when the endpoint reports that the service is serving. An
+    /// empty name checks the server's overall health.
+    Grpc(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The probe used for each destination, keyed by the destination's
+    /// authority. Endpoints of other destinations are not health checked.
+    pub probes: IndexMap<String, Probe>,
+
+    /// The time between an endpoint's probes.
+    pub interval: Duration,
+
+    /// Up to this much time is randomly added to each interval, so that
+    /// endpoints are not all probed at once.
+    pub jitter: Duration,
+
+    /// A probe fails if it does not complete within this time.
+    pub timeout: Duration,
+
+    /// A healthy endpoint is marked unhealthy after this many consecutive
+    /// failed probes.
+    pub unhealthy_threshold: usize,
+
+    /// An unhealthy endpoint is marked healthy after this many consecutive
+    /// successful probes.
+    pub healthy_threshold: usize,
+}
+
+/// Pairs each endpoint built by an inner `MakeService` with a future that
+/// builds its probe client with the `P`-typed `MakeService`.
+///
+/// When no destinations are health checked, probe clients are not built.
+#[derive(Clone, Debug)]
+pub struct ClientLayer<P> {
+    probe: P,
+    enabled: bool,
+}
+
+/// Wraps endpoint discovery so that balanced endpoints are withheld from the
+/// balancer while they fail their health checks.
+///
+/// Must wrap a discovery stack whose endpoints are built by `ClientLayer`.
+#[derive(Debug)]
+pub struct Layer<L: Hash + Eq> {
+    config: Arc<Config>,
+    scope: L,
+    registry: Registry<L>,
+}
+
+// === impl Config ===
+
+impl Config {
+    pub fn is_enabled(&self) -> bool {
+        !self.probes.is_empty()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            probes: IndexMap::new(),
+            interval: Duration::from_secs(10),
+            jitter: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            unhealthy_threshold: 3,
+            healthy_threshold: 2,
+        }
+    }
+}
+
+// === impl ClientLayer ===
+
+impl<P> ClientLayer<P> {
+    pub fn new(config: &Config, probe: P) -> Self {
+        Self {
+            probe,
+            enabled: config.is_enabled(),
+        }
+    }
+}
+
+impl<M, P: Clone> tower::layer::Layer<M> for ClientLayer<P> {
+    type Service = MakeProbeClient<M, P>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeProbeClient::new(inner, self.probe.clone(), self.enabled)
+    }
+}
+
+// === impl Layer ===
+
+impl<L: Hash + Eq> Layer<L> {
+    /// Creates a layer that records metrics in `registry`, labeled with
+    /// `scope`.
+    pub fn new(config: Config, scope: L, registry: Registry<L>) -> Self {
+        Self {
+            config: config.into(),
+            scope,
+            registry,
+        }
+    }
+}
+
+impl<L: Clone + Hash + Eq> Clone for Layer<L> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            scope: self.scope.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+impl<L: Clone + Hash + Eq, M> tower::layer::Layer<M> for Layer<L> {
+    type Service = MakeDiscover<M, L>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeDiscover::new(
+            inner,
+            self.scope.clone(),
+            self.config.clone(),
+            self.registry.clone(),
+        )
+    }
+}
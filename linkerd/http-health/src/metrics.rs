@@ -0,0 +1,106 @@
+use indexmap::IndexMap;
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetrics, Gauge};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+metrics! {
+    health_probe_successes_total: Counter {
+        "Total number of an endpoint's health check probes that succeeded"
+    },
+    health_probe_failures_total: Counter {
+        "Total number of an endpoint's health check probes that failed"
+    },
+    health_unhealthy: Gauge {
+        "Whether an endpoint is currently withheld from a balancer by failing health checks"
+    }
+}
+
+/// Records health check results for each balanced endpoint.
+#[derive(Debug)]
+pub struct Registry<L: Hash + Eq>(Arc<Mutex<IndexMap<Labels<L>, Arc<Metrics>>>>);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Labels<L> {
+    scope: L,
+    dst: String,
+    endpoint: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    pub(crate) successes_total: Counter,
+    pub(crate) failures_total: Counter,
+    pub(crate) unhealthy: Gauge,
+}
+
+// === impl Registry ===
+
+impl<L: Clone + Hash + Eq> Registry<L> {
+    pub(crate) fn metrics(&self, scope: &L, dst: &str, endpoint: String) -> Arc<Metrics> {
+        let labels = Labels {
+            scope: scope.clone(),
+            dst: dst.to_string(),
+            endpoint,
+        };
+        match self.0.lock() {
+            Ok(mut metrics) => metrics
+                .entry(labels)
+                .or_insert_with(Default::default)
+                .clone(),
+            Err(_) => Arc::new(Metrics::default()),
+        }
+    }
+
+    /// Stops reporting an endpoint's metrics once it has been removed from
+    /// its balancer.
+    pub(crate) fn remove(&self, metrics: &Arc<Metrics>) {
+        if let Ok(mut registry) = self.0.lock() {
+            registry.retain(|_, m| !Arc::ptr_eq(m, metrics));
+        }
+    }
+}
+
+impl<L: Hash + Eq> Default for Registry<L> {
+    fn default() -> Self {
+        Registry(Default::default())
+    }
+}
+
+impl<L: Hash + Eq> Clone for Registry<L> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+impl<L: FmtLabels + Hash + Eq> FmtMetrics for Registry<L> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = match self.0.lock() {
+            Ok(metrics) => metrics,
+            Err(_) => return Ok(()),
+        };
+        if metrics.is_empty() {
+            return Ok(());
+        }
+
+        health_probe_successes_total.fmt_help(f)?;
+        health_probe_successes_total.fmt_scopes(f, metrics.iter(), |m| &m.successes_total)?;
+
+        health_probe_failures_total.fmt_help(f)?;
+        health_probe_failures_total.fmt_scopes(f, metrics.iter(), |m| &m.failures_total)?;
+
+        health_unhealthy.fmt_help(f)?;
+        health_unhealthy.fmt_scopes(f, metrics.iter(), |m| &m.unhealthy)?;
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl<L: FmtLabels> FmtLabels for Labels<L> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.scope.fmt_labels(f)?;
+        write!(f, ",dst=\"{}\",endpoint=\"{}\"", self.dst, self.endpoint)
+    }
+}
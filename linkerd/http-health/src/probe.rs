@@ -0,0 +1,279 @@
+use crate::{state::Health, Config, Probe};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::future;
+use http::header::{CONTENT_TYPE, TE};
+use http_body::Body;
+use linkerd2_error::Error;
+use linkerd2_http_box::Payload;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use tokio::time;
+use tracing::{debug, info, trace};
+
+/// The `grpc.health.v1.HealthCheckResponse.ServingStatus` of a serving server.
+const GRPC_SERVING: u64 = 1;
+
+/// Periodically probes an endpoint, recording the outcomes in its `Health`.
+///
+/// Probing stops once the endpoint's balanced service has been dropped.
+pub(crate) struct Prober<S> {
+    client: S,
+    probe: Probe,
+    dst: String,
+    config: Arc<Config>,
+    health: Weak<Health>,
+}
+
+/// A request body that holds a single chunk of data.
+#[derive(Debug, Default)]
+struct Once(Option<Bytes>);
+
+// === impl Prober ===
+
+impl<S, B> Prober<S>
+where
+    S: tower::Service<http::Request<Payload>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+    B: Body,
+    B::Error: Into<Error>,
+{
+    pub(crate) fn new(
+        client: S,
+        probe: Probe,
+        dst: String,
+        config: Arc<Config>,
+        health: Weak<Health>,
+    ) -> Self {
+        Self {
+            client,
+            probe,
+            dst,
+            config,
+            health,
+        }
+    }
+
+    pub(crate) async fn run(mut self) {
+        loop {
+            if self.health.strong_count() == 0 {
+                trace!("Endpoint dropped; stopping probes");
+                return;
+            }
+
+            let result = match time::timeout(self.config.timeout, self.probe()).await {
+                Ok(result) => result,
+                Err(_) => Err("probe timed out".into()),
+            };
+            match result {
+                Ok(()) => trace!("Probe succeeded"),
+                Err(ref error) => debug!(%error, "Probe failed"),
+            }
+
+            let health = match self.health.upgrade() {
+                Some(health) => health,
+                None => return,
+            };
+            match health.record(&self.config, result.is_ok()) {
+                Some(true) => info!("Endpoint passed its health checks"),
+                Some(false) => info!("Endpoint failed its health checks; withholding it"),
+                None => {}
+            }
+            drop(health);
+
+            let jitter = self.config.jitter.mul_f64(rand::random::<f64>());
+            time::delay_for(self.config.interval + jitter).await;
+        }
+    }
+
+    async fn probe(&mut self) -> Result<(), Error> {
+        let req = self.probe.request(&self.dst)?;
+        let client = &mut self.client;
+        future::poll_fn(|cx| client.poll_ready(cx))
+            .await
+            .map_err(Into::into)?;
+        let rsp = client.call(req).await.map_err(Into::into)?;
+        self.probe.check(rsp).await
+    }
+}
+
+// === impl Probe ===
+
+impl Probe {
+    fn request(&self, dst: &str) -> Result<http::Request<Payload>, Error> {
+        let req = match self {
+            Probe::Http(path) => http::Request::get(&format!("http://{}{}", dst, path)[..])
+                .body(Payload::default())?,
+            Probe::Grpc(service) => {
+                http::Request::post(&format!("http://{}/grpc.health.v1.Health/Check", dst)[..])
+                    .header(CONTENT_TYPE, "application/grpc")
+                    .header(TE, "trailers")
+                    .body(Payload::new(Once(Some(grpc_request(service)))))?
+            }
+        };
+        Ok(req)
+    }
+
+    async fn check<B>(&self, rsp: http::Response<B>) -> Result<(), Error>
+    where
+        B: Body,
+        B::Error: Into<Error>,
+    {
+        if !rsp.status().is_success() {
+            return Err(format!("unexpected status: {}", rsp.status()).into());
+        }
+        if let Probe::Http(_) = self {
+            return Ok(());
+        }
+
+        let (head, body) = rsp.into_parts();
+        let mut body = Box::pin(body);
+        let mut msg = BytesMut::new();
+        while let Some(data) = future::poll_fn(|cx| body.as_mut().poll_data(cx)).await {
+            msg.put(data.map_err(Into::into)?);
+        }
+        let trailers = future::poll_fn(|cx| body.as_mut().poll_trailers(cx))
+            .await
+            .map_err(Into::into)?;
+
+        // A response without a message carries its status in its headers.
+        let grpc_status = trailers
+            .as_ref()
+            .and_then(|t| t.get("grpc-status"))
+            .or_else(|| head.headers.get("grpc-status"))
+            .and_then(|v| v.to_str().ok());
+        if grpc_status != Some("0") {
+            return Err(format!("unexpected grpc-status: {:?}", grpc_status).into());
+        }
+        match grpc_serving_status(&msg) {
+            Some(GRPC_SERVING) => Ok(()),
+            status => Err(format!("not serving: {:?}", status).into()),
+        }
+    }
+}
+
+/// Encodes a length-prefixed `grpc.health.v1.HealthCheckRequest` message.
+fn grpc_request(service: &str) -> Bytes {
+    let mut msg = BytesMut::new();
+    if !service.is_empty() {
+        // Field 1 (`service`), length-delimited.
+        msg.put_u8(0x0a);
+        put_varint(&mut msg, service.len() as u64);
+        msg.put_slice(service.as_bytes());
+    }
+
+    let mut frame = BytesMut::with_capacity(5 + msg.len());
+    frame.put_u8(0); // Uncompressed.
+    frame.put_u32(msg.len() as u32);
+    frame.put_slice(&msg);
+    frame.freeze()
+}
+
+/// Decodes the serving status from a length-prefixed
+/// `grpc.health.v1.HealthCheckResponse` message.
+fn grpc_serving_status(frame: &[u8]) -> Option<u64> {
+    if frame.len() < 5 || frame[0] != 0 {
+        return None;
+    }
+    let len = u32::from_be_bytes([frame[1], frame[2], frame[3], frame[4]]) as usize;
+    let mut msg = frame.get(5..5 + len)?;
+
+    let mut status = 0;
+    while !msg.is_empty() {
+        let key = varint(&mut msg)?;
+        match key & 0x7 {
+            0 => {
+                let value = varint(&mut msg)?;
+                // Field 1 (`status`).
+                if key >> 3 == 1 {
+                    status = value;
+                }
+            }
+            1 => msg = msg.get(8..)?,
+            2 => {
+                let len = varint(&mut msg)? as usize;
+                msg = msg.get(len..)?;
+            }
+            5 => msg = msg.get(4..)?,
+            _ => return None,
+        }
+    }
+    Some(status)
+}
+
+fn put_varint(buf: &mut BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+fn varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+// === impl Once ===
+
+impl Body for Once {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.0.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_grpc_requests() {
+        assert_eq!(&grpc_request("")[..], &[0, 0, 0, 0, 0][..]);
+        assert_eq!(
+            &grpc_request("svc")[..],
+            &[0, 0, 0, 0, 5, 0x0a, 3, b's', b'v', b'c'][..]
+        );
+    }
+
+    #[test]
+    fn decodes_grpc_serving_status() {
+        assert_eq!(grpc_serving_status(&[0, 0, 0, 0, 2, 0x08, 1]), Some(1));
+        assert_eq!(grpc_serving_status(&[0, 0, 0, 0, 2, 0x08, 2]), Some(2));
+        // An empty message has the default (unknown) status.
+        assert_eq!(grpc_serving_status(&[0, 0, 0, 0, 0]), Some(0));
+        // Unknown fields are skipped.
+        assert_eq!(
+            grpc_serving_status(&[0, 0, 0, 0, 5, 0x12, 1, b'x', 0x08, 1]),
+            Some(1)
+        );
+        // Truncated and compressed messages are not decoded.
+        assert_eq!(grpc_serving_status(&[0, 0, 0, 0, 2, 0x08]), None);
+        assert_eq!(grpc_serving_status(&[1, 0, 0, 0, 2, 0x08, 1]), None);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{metrics::Metrics, Config};
+use futures::task::AtomicWaker;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// An endpoint's health, shared between its balanced service and the task that
+/// probes it.
+#[derive(Debug)]
+pub(crate) struct Health {
+    state: Mutex<State>,
+    task: AtomicWaker,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug)]
+struct State {
+    healthy: bool,
+    successes: usize,
+    failures: usize,
+}
+
+// === impl Health ===
+
+impl Health {
+    /// Endpoints are considered healthy until they fail their probes.
+    pub(crate) fn new(metrics: Arc<Metrics>) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            task: AtomicWaker::new(),
+            metrics,
+        }
+    }
+
+    /// Returns ready once the endpoint is healthy.
+    pub(crate) fn poll_healthy(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_healthy() {
+            return Poll::Ready(());
+        }
+
+        // Register before checking again so that a concurrent transition is
+        // not missed.
+        self.task.register(cx.waker());
+        if self.is_healthy() {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// Records the outcome of a probe, returning the endpoint's new health if
+    /// it changed.
+    pub(crate) fn record(&self, config: &Config, success: bool) -> Option<bool> {
+        if success {
+            self.metrics.successes_total.incr();
+        } else {
+            self.metrics.failures_total.incr();
+        }
+
+        let healthy = self.state.lock().ok()?.record(config, success)?;
+        if healthy {
+            self.metrics.unhealthy.decr();
+            self.task.wake();
+        } else {
+            self.metrics.unhealthy.incr();
+        }
+        Some(healthy)
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.state.lock().map(|s| s.healthy).unwrap_or(true)
+    }
+}
+
+impl Drop for Health {
+    fn drop(&mut self) {
+        if !self.is_healthy() {
+            self.metrics.unhealthy.decr();
+        }
+    }
+}
+
+// === impl State ===
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            successes: 0,
+            failures: 0,
+        }
+    }
+}
+
+impl State {
+    /// Records the outcome of a probe, returning the endpoint's new health if
+    /// it crossed a threshold.
+    fn record(&mut self, config: &Config, success: bool) -> Option<bool> {
+        if success {
+            self.failures = 0;
+            self.successes += 1;
+            if !self.healthy && self.successes >= config.healthy_threshold.max(1) {
+                self.healthy = true;
+                return Some(true);
+            }
+        } else {
+            self.successes = 0;
+            self.failures += 1;
+            if self.healthy && self.failures >= config.unhealthy_threshold.max(1) {
+                self.healthy = false;
+                return Some(false);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            unhealthy_threshold: 3,
+            healthy_threshold: 2,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn marks_unhealthy_after_consecutive_failures() {
+        let config = config();
+        let mut state = State::default();
+
+        assert_eq!(state.record(&config, false), None);
+        assert_eq!(state.record(&config, false), None);
+        assert_eq!(state.record(&config, true), None, "success resets");
+        assert_eq!(state.record(&config, false), None);
+        assert_eq!(state.record(&config, false), None);
+        assert_eq!(state.record(&config, false), Some(false));
+        assert_eq!(state.record(&config, false), None, "already unhealthy");
+    }
+
+    #[test]
+    fn marks_healthy_after_consecutive_successes() {
+        let config = config();
+        let mut state = State {
+            healthy: false,
+            ..State::default()
+        };
+
+        assert_eq!(state.record(&config, true), None);
+        assert_eq!(state.record(&config, false), None, "failure resets");
+        assert_eq!(state.record(&config, true), None);
+        assert_eq!(state.record(&config, true), Some(true));
+        assert_eq!(state.record(&config, true), None, "already healthy");
+    }
+
+    #[test]
+    fn health_gates_readiness() {
+        let config = config();
+        let health = Health::new(Arc::new(Metrics::default()));
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(health.poll_healthy(&mut cx).is_ready());
+
+        for _ in 0..2 {
+            assert_eq!(health.record(&config, false), None);
+        }
+        assert_eq!(health.record(&config, false), Some(false));
+        assert!(health.poll_healthy(&mut cx).is_pending());
+
+        assert_eq!(health.record(&config, true), None);
+        assert_eq!(health.record(&config, true), Some(true));
+        assert!(health.poll_healthy(&mut cx).is_ready());
+    }
+}
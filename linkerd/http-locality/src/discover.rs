@@ -0,0 +1,146 @@
+use crate::{
+    endpoint::{Endpoint, Pool},
+    metrics::Registry,
+    Config, IsLocal,
+};
+use futures::{ready, Stream, TryFuture};
+use linkerd2_error::Error;
+use pin_project::{pin_project, pinned_drop};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::discover::{self, Change};
+
+/// Wraps a discovery-producing service so that each balancer prefers its
+/// local endpoints.
+#[derive(Clone, Debug)]
+pub struct MakeDiscover<M, L: Hash + Eq> {
+    inner: M,
+    scope: L,
+    config: Option<Arc<Config>>,
+    registry: Registry<L>,
+}
+
+#[pin_project]
+pub struct DiscoverFuture<F, L: Hash + Eq> {
+    #[pin]
+    future: F,
+    dst: String,
+    scope: L,
+    config: Option<Arc<Config>>,
+    registry: Registry<L>,
+}
+
+/// Tracks the locality of a single balancer's endpoints.
+#[pin_project(PinnedDrop)]
+pub struct Discover<D, L: Hash + Eq> {
+    #[pin]
+    inner: D,
+    pool: Arc<Pool>,
+    registry: Registry<L>,
+}
+
+// === impl MakeDiscover ===
+
+impl<M, L: Hash + Eq> MakeDiscover<M, L> {
+    pub(crate) fn new(
+        inner: M,
+        scope: L,
+        config: Option<Arc<Config>>,
+        registry: Registry<L>,
+    ) -> Self {
+        Self {
+            inner,
+            scope,
+            config,
+            registry,
+        }
+    }
+}
+
+impl<T, M, L> tower::Service<T> for MakeDiscover<M, L>
+where
+    T: fmt::Display,
+    M: tower::Service<T>,
+    M::Response: discover::Discover,
+    L: Clone + Hash + Eq,
+{
+    type Response = Discover<M::Response, L>;
+    type Error = M::Error;
+    type Future = DiscoverFuture<M::Future, L>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.to_string();
+        DiscoverFuture {
+            future: self.inner.call(target),
+            dst,
+            scope: self.scope.clone(),
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+// === impl DiscoverFuture ===
+
+impl<F, L> Future for DiscoverFuture<F, L>
+where
+    F: TryFuture,
+    F::Ok: discover::Discover,
+    L: Clone + Hash + Eq,
+{
+    type Output = Result<Discover<F::Ok, L>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        let metrics = this.registry.metrics(this.scope, this.dst);
+        Poll::Ready(Ok(Discover {
+            inner,
+            pool: Arc::new(Pool::new(this.config.clone(), metrics)),
+            registry: this.registry.clone(),
+        }))
+    }
+}
+
+// === impl Discover ===
+
+impl<D, L> Stream for Discover<D, L>
+where
+    D: discover::Discover,
+    D::Service: IsLocal,
+    D::Error: Into<Error>,
+    L: Hash + Eq,
+{
+    type Item = Result<Change<D::Key, Endpoint<D::Service>>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.inner.poll_discover(cx)) {
+            Some(Ok(Change::Insert(key, svc))) => {
+                let local = svc.is_local();
+                Change::Insert(key, Endpoint::new(svc, local, this.pool.clone()))
+            }
+            Some(Ok(Change::Remove(key))) => Change::Remove(key),
+            Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            None => return Poll::Ready(None),
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+#[pinned_drop]
+impl<D, L: Hash + Eq> PinnedDrop for Discover<D, L> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        this.registry.remove(this.pool.metrics());
+    }
+}
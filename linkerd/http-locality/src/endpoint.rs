@@ -0,0 +1,322 @@
+use crate::{metrics::Metrics, Config};
+use futures::{task::AtomicWaker, TryFuture};
+use linkerd2_error::Error;
+use linkerd2_proxy_core::{HasWeight, Weight};
+use pin_project::{pin_project, pinned_drop};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use tracing::{debug, trace};
+
+/// A balanced endpoint that, if it is remote, is only ready while traffic
+/// spills over from the balancer's local endpoints.
+#[derive(Debug)]
+pub struct Endpoint<S> {
+    inner: S,
+    local: bool,
+    ready: bool,
+    pool: Arc<Pool>,
+
+    /// Notified, if the endpoint is remote, when traffic spills over.
+    spill_waiter: Arc<AtomicWaker>,
+}
+
+#[pin_project(PinnedDrop)]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    pool: Option<Arc<Pool>>,
+}
+
+/// Tracks the local endpoints of a single balancer.
+#[derive(Debug)]
+pub(crate) struct Pool {
+    config: Option<Arc<Config>>,
+    state: Mutex<State>,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    local_endpoints: usize,
+    local_ready: usize,
+    local_in_flight: usize,
+
+    /// Wakes remote endpoints that are waiting for traffic to spill over.
+    ///
+    /// Each endpoint is registered at most once, and dropped endpoints are
+    /// pruned as new endpoints register.
+    remote_waiters: Vec<Weak<AtomicWaker>>,
+}
+
+// === impl Endpoint ===
+
+impl<S> Endpoint<S> {
+    pub(crate) fn new(inner: S, local: bool, pool: Arc<Pool>) -> Self {
+        if local {
+            pool.update(|s| s.local_endpoints += 1);
+        }
+        Self {
+            inner,
+            local,
+            ready: false,
+            pool,
+            spill_waiter: Arc::new(AtomicWaker::new()),
+        }
+    }
+}
+
+impl<S, Req> tower::Service<Req> for Endpoint<S>
+where
+    S: tower::Service<Req>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if !self.local {
+            if !self.pool.poll_spill(cx, &self.spill_waiter) {
+                trace!("Withholding remote endpoint");
+                return Poll::Pending;
+            }
+            return self.inner.poll_ready(cx).map_err(Into::into);
+        }
+
+        let poll = self.inner.poll_ready(cx);
+        let ready = match poll {
+            Poll::Ready(Ok(())) => true,
+            _ => false,
+        };
+        if ready != self.ready {
+            self.ready = ready;
+            self.pool.update(|s| {
+                if ready {
+                    s.local_ready += 1;
+                } else {
+                    s.local_ready -= 1;
+                }
+            });
+        }
+        poll.map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        let pool = if self.local {
+            self.pool.metrics.local.incr();
+            self.pool.update(|s| s.local_in_flight += 1);
+            Some(self.pool.clone())
+        } else {
+            self.pool.metrics.remote.incr();
+            None
+        };
+        ResponseFuture {
+            inner: self.inner.call(req),
+            pool,
+        }
+    }
+}
+
+impl<S: HasWeight> HasWeight for Endpoint<S> {
+    fn weight(&self) -> &Weight {
+        self.inner.weight()
+    }
+}
+
+impl<S> Drop for Endpoint<S> {
+    fn drop(&mut self) {
+        if self.local {
+            let ready = self.ready;
+            self.pool.update(|s| {
+                s.local_endpoints -= 1;
+                if ready {
+                    s.local_ready -= 1;
+                }
+            });
+        }
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F> Future for ResponseFuture<F>
+where
+    F: TryFuture,
+    F::Error: Into<Error>,
+{
+    type Output = Result<F::Ok, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project().inner.try_poll(cx).map_err(Into::into)
+    }
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for ResponseFuture<F> {
+    fn drop(self: Pin<&mut Self>) {
+        // A local request is considered in flight until its response headers
+        // are received.
+        if let Some(pool) = self.project().pool.take() {
+            pool.update(|s| s.local_in_flight -= 1);
+        }
+    }
+}
+
+// === impl Pool ===
+
+impl Pool {
+    pub(crate) fn new(config: Option<Arc<Config>>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+            metrics,
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Returns true if remote endpoints may be used. Otherwise, the task is
+    /// notified when traffic begins to spill over.
+    fn poll_spill(&self, cx: &mut Context<'_>, waiter: &Arc<AtomicWaker>) -> bool {
+        let config = match self.config.as_ref() {
+            Some(config) => config,
+            None => return true,
+        };
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return true,
+        };
+        if state.is_spilling(config) {
+            return true;
+        }
+
+        waiter.register(cx.waker());
+        let waiter = Arc::downgrade(waiter);
+        if !state
+            .remote_waiters
+            .iter()
+            .any(|w| Weak::ptr_eq(w, &waiter))
+        {
+            state.remote_waiters.retain(|w| w.strong_count() > 0);
+            state.remote_waiters.push(waiter);
+        }
+        false
+    }
+
+    /// Updates the pool's state, waking remote endpoints if the update causes
+    /// traffic to spill over.
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        let config = match self.config.as_ref() {
+            Some(config) => config,
+            None => return f(&mut *state),
+        };
+
+        let was_spilling = state.is_spilling(config);
+        f(&mut *state);
+        if !was_spilling && state.is_spilling(config) {
+            debug!(
+                local.endpoints = state.local_endpoints,
+                local.ready = state.local_ready,
+                local.in_flight = state.local_in_flight,
+                "Spilling over to remote endpoints"
+            );
+            for waiter in state.remote_waiters.drain(..) {
+                if let Some(waiter) = waiter.upgrade() {
+                    waiter.wake();
+                }
+            }
+        }
+    }
+}
+
+// === impl State ===
+
+impl State {
+    fn is_spilling(&self, config: &Config) -> bool {
+        self.local_ready == 0
+            || self.local_ready < config.min_local_endpoints
+            || self.local_in_flight >= self.local_ready * config.max_local_load.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            locality: "zone-a".to_string(),
+            label: "zone".to_string(),
+            min_local_endpoints: 2,
+            max_local_load: 2,
+        }
+    }
+
+    #[test]
+    fn spills_below_min_local_endpoints() {
+        let config = config();
+        let mut state = State::default();
+        assert!(state.is_spilling(&config));
+
+        state.local_endpoints = 2;
+        state.local_ready = 1;
+        assert!(state.is_spilling(&config));
+
+        state.local_ready = 2;
+        assert!(!state.is_spilling(&config));
+    }
+
+    #[test]
+    fn spills_when_local_endpoints_are_overloaded() {
+        let config = config();
+        let mut state = State {
+            local_endpoints: 2,
+            local_ready: 2,
+            ..State::default()
+        };
+
+        state.local_in_flight = 3;
+        assert!(!state.is_spilling(&config));
+
+        state.local_in_flight = 4;
+        assert!(state.is_spilling(&config));
+    }
+
+    #[test]
+    fn remote_waiters_are_bounded() {
+        let pool = Pool::new(Some(Arc::new(config())), Arc::default());
+        pool.update(|s| {
+            s.local_endpoints = 2;
+            s.local_ready = 2;
+        });
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // Repeated polls of the same endpoint register it once.
+        let waiter = Arc::new(AtomicWaker::new());
+        for _ in 0..10 {
+            assert!(!pool.poll_spill(&mut cx, &waiter));
+        }
+        assert_eq!(pool.state.lock().unwrap().remote_waiters.len(), 1);
+
+        // Dropped endpoints are pruned as other endpoints register.
+        for _ in 0..10 {
+            let dropped = Arc::new(AtomicWaker::new());
+            assert!(!pool.poll_spill(&mut cx, &dropped));
+        }
+        assert_eq!(pool.state.lock().unwrap().remote_waiters.len(), 2);
+
+        // Waiters are released once traffic spills over.
+        pool.update(|s| s.local_ready = 1);
+        assert!(pool.state.lock().unwrap().remote_waiters.is_empty());
+        assert!(pool.poll_spill(&mut cx, &waiter));
+    }
+}
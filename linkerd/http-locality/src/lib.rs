@@ -0,0 +1,111 @@
+//! Locality-aware balancing for HTTP endpoints.
+//!
+//! Each endpoint is tagged, as it is built, with whether its locality label
+//! matches the proxy's own locality. Remote endpoints are withheld from the
+//! balancer — their services report that they are not ready — while the
+//! balancer's local endpoints can serve its traffic. Traffic spills over to
+//! remote endpoints when too few local endpoints are ready (i.e., because
+//! they are unavailable or ejected as outliers) or when the local endpoints
+//! are overloaded.
+
+#![deny(warnings, rust_2018_idioms)]
+
+pub use linkerd2_proxy_core::IsLocal;
+use std::{hash::Hash, sync::Arc};
+
+mod discover;
+mod endpoint;
+mod metrics;
+mod tag;
+
+pub use self::discover::{Discover, DiscoverFuture, MakeDiscover};
+pub use self::endpoint::{Endpoint, ResponseFuture};
+pub use self::metrics::Registry;
+pub use self::tag::{MakeTag, MakeTagFuture, Tagged};
+
+/// Exposes an endpoint target's locality.
+pub trait HasLocality {
+    /// Returns the value of the given locality label, if the endpoint has one.
+    fn locality(&self, label: &str) -> Option<&str>;
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The proxy's own locality.
+    pub locality: String,
+
+    /// The endpoint label that holds an endpoint's locality.
+    pub label: String,
+
+    /// Traffic spills over to remote endpoints when fewer than this many
+    /// local endpoints are ready.
+    pub min_local_endpoints: usize,
+
+    /// Traffic spills over to remote endpoints when the local endpoints have,
+    /// on average, at least this many requests in flight.
+    pub max_local_load: usize,
+}
+
+/// Tags the endpoints built by an inner `MakeService` with their locality.
+///
+/// When locality-aware balancing is disabled, all endpoints are treated as
+/// local.
+#[derive(Clone, Debug)]
+pub struct TagLayer {
+    config: Option<Arc<Config>>,
+}
+
+/// Wraps endpoint discovery so that balancers prefer local endpoints.
+///
+/// Must wrap a discovery stack whose endpoints are tagged by `TagLayer`.
+#[derive(Clone, Debug)]
+pub struct Layer<L: Hash + Eq> {
+    config: Option<Arc<Config>>,
+    scope: L,
+    registry: Registry<L>,
+}
+
+// === impl TagLayer ===
+
+impl TagLayer {
+    pub fn new(config: Option<Config>) -> Self {
+        Self {
+            config: config.map(Arc::new),
+        }
+    }
+}
+
+impl<M> tower::layer::Layer<M> for TagLayer {
+    type Service = MakeTag<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeTag::new(inner, self.config.clone())
+    }
+}
+
+// === impl Layer ===
+
+impl<L: Hash + Eq> Layer<L> {
+    /// Creates a layer that records metrics in `registry`, labeled with
+    /// `scope`.
+    pub fn new(config: Option<Config>, scope: L, registry: Registry<L>) -> Self {
+        Self {
+            config: config.map(Arc::new),
+            scope,
+            registry,
+        }
+    }
+}
+
+impl<L: Clone + Hash + Eq, M> tower::layer::Layer<M> for Layer<L> {
+    type Service = MakeDiscover<M, L>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeDiscover::new(
+            inner,
+            self.scope.clone(),
+            self.config.clone(),
+            self.registry.clone(),
+        )
+    }
+}
@@ -0,0 +1,117 @@
+use indexmap::IndexMap;
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetrics};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+metrics! {
+    balancer_locality_requests_total: Counter {
+        "Total number of requests a balancer has dispatched to local and remote endpoints"
+    }
+}
+
+/// Records the requests dispatched to local and remote endpoints by each
+/// balancer.
+#[derive(Debug)]
+pub struct Registry<L: Hash + Eq>(Arc<Mutex<IndexMap<Labels<L>, Arc<Metrics>>>>);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Labels<L> {
+    scope: L,
+    dst: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    pub(crate) local: Counter,
+    pub(crate) remote: Counter,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Locality {
+    Local,
+    Remote,
+}
+
+// === impl Registry ===
+
+impl<L: Clone + Hash + Eq> Registry<L> {
+    pub(crate) fn metrics(&self, scope: &L, dst: &str) -> Arc<Metrics> {
+        let labels = Labels {
+            scope: scope.clone(),
+            dst: dst.to_string(),
+        };
+        match self.0.lock() {
+            Ok(mut metrics) => metrics
+                .entry(labels)
+                .or_insert_with(Default::default)
+                .clone(),
+            Err(_) => Arc::new(Metrics::default()),
+        }
+    }
+}
+
+impl<L: Hash + Eq> Registry<L> {
+    /// Stops reporting a balancer's metrics once it has been dropped.
+    pub(crate) fn remove(&self, metrics: &Arc<Metrics>) {
+        if let Ok(mut registry) = self.0.lock() {
+            registry.retain(|_, m| !Arc::ptr_eq(m, metrics));
+        }
+    }
+}
+
+impl<L: Hash + Eq> Default for Registry<L> {
+    fn default() -> Self {
+        Registry(Default::default())
+    }
+}
+
+impl<L: Hash + Eq> Clone for Registry<L> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+impl<L: FmtLabels + Hash + Eq> FmtMetrics for Registry<L> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics = match self.0.lock() {
+            Ok(metrics) => metrics,
+            Err(_) => return Ok(()),
+        };
+        if metrics.is_empty() {
+            return Ok(());
+        }
+
+        balancer_locality_requests_total.fmt_help(f)?;
+        balancer_locality_requests_total.fmt_scopes(
+            f,
+            metrics.iter().map(|(l, m)| ((l, Locality::Local), m)),
+            |m| &m.local,
+        )?;
+        balancer_locality_requests_total.fmt_scopes(
+            f,
+            metrics.iter().map(|(l, m)| ((l, Locality::Remote), m)),
+            |m| &m.remote,
+        )?;
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl<L: FmtLabels> FmtLabels for Labels<L> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.scope.fmt_labels(f)?;
+        write!(f, ",dst=\"{}\"", self.dst)
+    }
+}
+
+impl FmtLabels for Locality {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Locality::Local => write!(f, "locality=\"local\""),
+            Locality::Remote => write!(f, "locality=\"remote\""),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{Config, HasLocality, IsLocal};
+use futures::{ready, TryFuture};
+use linkerd2_proxy_core::{HasWeight, Weight};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Builds endpoint services tagged with their locality.
+#[derive(Clone, Debug)]
+pub struct MakeTag<M> {
+    inner: M,
+    config: Option<Arc<Config>>,
+}
+
+#[pin_project]
+pub struct MakeTagFuture<F> {
+    #[pin]
+    future: F,
+    local: bool,
+}
+
+/// An endpoint service that knows whether it is in the proxy's locality.
+#[derive(Clone, Debug)]
+pub struct Tagged<S> {
+    inner: S,
+    local: bool,
+}
+
+// === impl MakeTag ===
+
+impl<M> MakeTag<M> {
+    pub(crate) fn new(inner: M, config: Option<Arc<Config>>) -> Self {
+        Self { inner, config }
+    }
+}
+
+impl<T, M> tower::Service<T> for MakeTag<M>
+where
+    T: HasLocality,
+    M: tower::Service<T>,
+{
+    type Response = Tagged<M::Response>;
+    type Error = M::Error;
+    type Future = MakeTagFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let local = match self.config.as_ref() {
+            Some(config) => target.locality(&config.label) == Some(config.locality.as_str()),
+            None => true,
+        };
+        MakeTagFuture {
+            future: self.inner.call(target),
+            local,
+        }
+    }
+}
+
+// === impl MakeTagFuture ===
+
+impl<F: TryFuture> Future for MakeTagFuture<F> {
+    type Output = Result<Tagged<F::Ok>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(Tagged {
+            inner,
+            local: *this.local,
+        }))
+    }
+}
+
+// === impl Tagged ===
+
+impl<S> IsLocal for Tagged<S> {
+    fn is_local(&self) -> bool {
+        self.local
+    }
+}
+
+impl<S: HasWeight> HasWeight for Tagged<S> {
+    fn weight(&self) -> &Weight {
+        self.inner.weight()
+    }
+}
+
+impl<S, Req> tower::Service<Req> for Tagged<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
@@ -19,10 +19,17 @@ pub struct Metrics {
     last_update: Instant,
     retryable: Counter,
     no_budget: Counter,
+    body_too_long: Counter,
+    hedgeable: Counter,
+    hedge_no_budget: Counter,
+    hedges: Counter,
+    hedge_wins: Counter,
 }
 
 struct NoBudgetLabel;
 
+struct BodyTooLongLabel;
+
 // === impl Retries ===
 
 impl<T: Hash + Eq> Default for Retries<T> {
@@ -65,6 +72,39 @@ impl Handle {
             }
         }
     }
+
+    /// Records a retryable response that could not be retried because its
+    /// request body was too large to be buffered.
+    pub fn incr_body_too_long(&self) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.retryable.incr();
+            m.body_too_long.incr();
+        }
+    }
+
+    /// Records a request that was slow enough to be hedged, once its hedge is
+    /// found to be served by a different endpoint.
+    pub fn incr_hedgeable(&self, has_budget: bool) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.hedgeable.incr();
+            if has_budget {
+                m.hedges.incr();
+            } else {
+                m.hedge_no_budget.incr();
+            }
+        }
+    }
+
+    /// Records a hedged request whose response was used because it completed
+    /// before the original request.
+    pub fn incr_hedge_won(&self) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.hedge_wins.incr();
+        }
+    }
 }
 
 // === impl Metrics ===
@@ -75,6 +115,11 @@ impl Default for Metrics {
             last_update: Instant::now(),
             retryable: Counter::default(),
             no_budget: Counter::default(),
+            body_too_long: Counter::default(),
+            hedgeable: Counter::default(),
+            hedge_no_budget: Counter::default(),
+            hedges: Counter::default(),
+            hedge_wins: Counter::default(),
         }
     }
 }
@@ -97,6 +142,27 @@ where
             "Total count of retryable HTTP responses.",
         )
     }
+
+    fn hedgeable_total(&self) -> Metric<'_, Prefixed<'_, &'static str>, Counter> {
+        Metric::new(
+            self.prefix_key("hedgeable_total"),
+            "Total count of HTTP requests that were slow enough to be hedged to a different endpoint.",
+        )
+    }
+
+    fn hedges_total(&self) -> Metric<'_, Prefixed<'_, &'static str>, Counter> {
+        Metric::new(
+            self.prefix_key("hedges_total"),
+            "Total count of hedged HTTP requests that were sent.",
+        )
+    }
+
+    fn hedge_wins_total(&self) -> Metric<'_, Prefixed<'_, &'static str>, Counter> {
+        Metric::new(
+            self.prefix_key("hedge_wins_total"),
+            "Total count of hedged HTTP requests that completed before the original request.",
+        )
+    }
 }
 
 impl<T> FmtMetrics for Report<T, Metrics>
@@ -125,6 +191,34 @@ where
                 m.retryable.fmt_metric_labeled(f, &metric.name, tgt)?;
                 m.no_budget
                     .fmt_metric_labeled(f, &metric.name, (tgt, NoBudgetLabel))?;
+                m.body_too_long
+                    .fmt_metric_labeled(f, &metric.name, (tgt, BodyTooLongLabel))?;
+            }
+        }
+
+        let metric = self.hedgeable_total();
+        metric.fmt_help(f)?;
+        for (tgt, tm) in &registry.by_target {
+            if let Ok(m) = tm.lock() {
+                m.hedgeable.fmt_metric_labeled(f, &metric.name, tgt)?;
+                m.hedge_no_budget
+                    .fmt_metric_labeled(f, &metric.name, (tgt, NoBudgetLabel))?;
+            }
+        }
+
+        let metric = self.hedges_total();
+        metric.fmt_help(f)?;
+        for (tgt, tm) in &registry.by_target {
+            if let Ok(m) = tm.lock() {
+                m.hedges.fmt_metric_labeled(f, &metric.name, tgt)?;
+            }
+        }
+
+        let metric = self.hedge_wins_total();
+        metric.fmt_help(f)?;
+        for (tgt, tm) in &registry.by_target {
+            if let Ok(m) = tm.lock() {
+                m.hedge_wins.fmt_metric_labeled(f, &metric.name, tgt)?;
             }
         }
 
@@ -139,3 +233,9 @@ impl FmtLabels for NoBudgetLabel {
         write!(f, "skipped=\"no_budget\"")
     }
 }
+
+impl FmtLabels for BodyTooLongLabel {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped=\"body_too_long\"")
+    }
+}
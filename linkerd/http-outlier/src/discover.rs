@@ -0,0 +1,201 @@
+use crate::{
+    endpoint::Endpoint,
+    metrics::Registry,
+    state::{Pool, Tracker},
+    Config,
+};
+use futures::{ready, Stream, TryFuture};
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use pin_project::{pin_project, pinned_drop};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower::discover::{self, Change};
+
+/// Wraps a discovery-producing service so that each discovered endpoint is
+/// tracked for outlier ejection.
+#[derive(Debug)]
+pub struct MakeDiscover<M, L: Hash + Eq, C> {
+    inner: M,
+    scope: L,
+    config: Arc<Config>,
+    registry: Registry<L>,
+    _marker: std::marker::PhantomData<fn() -> C>,
+}
+
+#[pin_project]
+pub struct DiscoverFuture<F, L: Hash + Eq, C> {
+    #[pin]
+    future: F,
+    dst: String,
+    scope: L,
+    config: Arc<Config>,
+    registry: Registry<L>,
+    _marker: std::marker::PhantomData<fn() -> C>,
+}
+
+/// Tracks the endpoints of a single balancer.
+#[pin_project(PinnedDrop)]
+pub struct Discover<D: discover::Discover, L: Hash + Eq, C> {
+    #[pin]
+    inner: D,
+    dst: String,
+    scope: L,
+    config: Arc<Config>,
+    registry: Registry<L>,
+    pool: Arc<Mutex<Pool>>,
+    endpoints: IndexMap<D::Key, Tracker>,
+    _marker: std::marker::PhantomData<fn() -> C>,
+}
+
+// === impl MakeDiscover ===
+
+impl<M, L: Hash + Eq, C> MakeDiscover<M, L, C> {
+    pub(crate) fn new(inner: M, scope: L, config: Arc<Config>, registry: Registry<L>) -> Self {
+        Self {
+            inner,
+            scope,
+            config,
+            registry,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<M: Clone, L: Clone + Hash + Eq, C> Clone for MakeDiscover<M, L, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            scope: self.scope.clone(),
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            _marker: self._marker,
+        }
+    }
+}
+
+impl<T, M, L, C> tower::Service<T> for MakeDiscover<M, L, C>
+where
+    T: fmt::Display,
+    M: tower::Service<T>,
+    M::Response: discover::Discover,
+    L: Clone + Hash + Eq,
+{
+    type Response = Discover<M::Response, L, C>;
+    type Error = M::Error;
+    type Future = DiscoverFuture<M::Future, L, C>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.to_string();
+        DiscoverFuture {
+            future: self.inner.call(target),
+            dst,
+            scope: self.scope.clone(),
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+// === impl DiscoverFuture ===
+
+impl<F, L, C> Future for DiscoverFuture<F, L, C>
+where
+    F: TryFuture,
+    F::Ok: discover::Discover,
+    L: Clone + Hash + Eq,
+{
+    type Output = Result<Discover<F::Ok, L, C>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        Poll::Ready(Ok(Discover {
+            inner,
+            dst: std::mem::take(this.dst),
+            scope: this.scope.clone(),
+            config: this.config.clone(),
+            registry: this.registry.clone(),
+            pool: Arc::new(Mutex::new(Pool::default())),
+            endpoints: IndexMap::default(),
+            _marker: std::marker::PhantomData,
+        }))
+    }
+}
+
+// === impl Discover ===
+
+impl<D, L, C> Stream for Discover<D, L, C>
+where
+    D: discover::Discover,
+    D::Key: Hash + Clone + fmt::Display,
+    D::Error: Into<Error>,
+    L: Clone + Hash + Eq,
+{
+    type Item = Result<Change<D::Key, Endpoint<D::Service, C>>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.inner.poll_discover(cx)) {
+            Some(Ok(change)) => change,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            None => return Poll::Ready(None),
+        };
+
+        let change = match change {
+            Change::Insert(key, svc) => {
+                if !this.config.is_enabled() {
+                    return Poll::Ready(Some(Ok(Change::Insert(key, Endpoint::new(svc, None)))));
+                }
+
+                let endpoint = key.to_string();
+                let metrics = this
+                    .registry
+                    .metrics(this.scope, this.dst, endpoint.clone());
+                let tracker = Tracker::new(
+                    endpoint.into(),
+                    this.config.clone(),
+                    this.pool.clone(),
+                    metrics,
+                );
+                if let Some(prior) = this.endpoints.insert(key.clone(), tracker.clone()) {
+                    prior.retire();
+                }
+                Change::Insert(key, Endpoint::new(svc, Some(tracker)))
+            }
+            Change::Remove(key) => {
+                if let Some(tracker) = this.endpoints.remove(&key) {
+                    tracker.retire();
+                    this.registry.remove(tracker.metrics());
+                }
+                Change::Remove(key)
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+#[pinned_drop]
+impl<D, L, C> PinnedDrop for Discover<D, L, C>
+where
+    D: discover::Discover,
+    L: Hash + Eq,
+{
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        for (_, tracker) in std::mem::take(this.endpoints) {
+            tracker.retire();
+            this.registry.remove(tracker.metrics());
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{state::Tracker, IsFailure};
+use futures::{ready, TryFuture};
+use http_body::Body;
+use linkerd2_error::Error;
+use linkerd2_http_classify::{ClassifyEos, ClassifyResponse};
+use linkerd2_proxy_core::{HasWeight, IsLocal, Weight};
+use pin_project::{pin_project, pinned_drop};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::time::{self, Delay};
+
+/// A balanced endpoint that is not ready while it is ejected.
+#[derive(Debug)]
+pub struct Endpoint<S, C> {
+    inner: S,
+    tracker: Option<Tracker>,
+    ejection: Option<Delay>,
+    _marker: std::marker::PhantomData<fn() -> C>,
+}
+
+#[pin_project]
+pub struct ResponseFuture<F, C> {
+    #[pin]
+    inner: F,
+    classify: Option<C>,
+    tracker: Option<Tracker>,
+}
+
+#[pin_project(PinnedDrop)]
+#[derive(Debug)]
+pub struct ResponseBody<B, C: ClassifyEos>
+where
+    C::Class: IsFailure,
+{
+    #[pin]
+    inner: B,
+    classify: Option<C>,
+    tracker: Option<Tracker>,
+}
+
+// === impl Endpoint ===
+
+impl<S, C> Endpoint<S, C> {
+    pub(crate) fn new(inner: S, tracker: Option<Tracker>) -> Self {
+        Self {
+            inner,
+            tracker,
+            ejection: None,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, C, A, B> tower::Service<http::Request<A>> for Endpoint<S, C>
+where
+    S: tower::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+    B: Body,
+    C: ClassifyResponse + Clone + Default + Send + Sync + 'static,
+    C::Class: IsFailure,
+{
+    type Response = http::Response<ResponseBody<B, C::ClassifyEos>>;
+    type Error = Error;
+    type Future = ResponseFuture<S::Future, C>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(tracker) = self.tracker.as_ref() {
+            // While the endpoint is ejected, it does not become ready. A timer
+            // is used to wake the balancer once the ejection expires.
+            if !tracker.restore_if_expired() {
+                if let Some(until) = tracker.ejected_until() {
+                    let ejection = self
+                        .ejection
+                        .get_or_insert_with(|| time::delay_until(until));
+                    if ejection.deadline() != until {
+                        ejection.reset(until);
+                    }
+                    ready!(Pin::new(ejection).poll(cx));
+                    tracker.restore_if_expired();
+                }
+            }
+            self.ejection = None;
+        }
+
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<A>) -> Self::Future {
+        let classify = self.tracker.as_ref().map(|tracker| {
+            req.extensions_mut().insert(tracker.status());
+            req.extensions().get::<C>().cloned().unwrap_or_default()
+        });
+        ResponseFuture {
+            inner: self.inner.call(req),
+            classify,
+            tracker: self.tracker.clone(),
+        }
+    }
+}
+
+impl<S: HasWeight, C> HasWeight for Endpoint<S, C> {
+    fn weight(&self) -> &Weight {
+        self.inner.weight()
+    }
+}
+
+impl<S: IsLocal, C> IsLocal for Endpoint<S, C> {
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, C, B> Future for ResponseFuture<F, C>
+where
+    F: TryFuture<Ok = http::Response<B>>,
+    F::Error: Into<Error>,
+    B: Body,
+    C: ClassifyResponse,
+    C::Class: IsFailure,
+{
+    type Output = Result<http::Response<ResponseBody<B, C::ClassifyEos>>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let rsp = ready!(this.inner.try_poll(cx));
+
+        let classify = this.classify.take();
+        let tracker = this.tracker.take();
+        Poll::Ready(match rsp {
+            Ok(rsp) => {
+                let classify = classify.map(|c| c.start(&rsp));
+                let (head, inner) = rsp.into_parts();
+                let body = ResponseBody {
+                    inner,
+                    classify,
+                    tracker,
+                };
+                Ok(http::Response::from_parts(head, body))
+            }
+            Err(e) => {
+                let e = e.into();
+                if let (Some(classify), Some(tracker)) = (classify, tracker) {
+                    tracker.record(classify.error(&e).is_failure());
+                }
+                Err(e)
+            }
+        })
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B, C> ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn record(self: Pin<&mut Self>, class: impl FnOnce(C) -> C::Class) {
+        let this = self.project();
+        if let (Some(classify), Some(tracker)) = (this.classify.take(), this.tracker.take()) {
+            tracker.record(class(classify).is_failure());
+        }
+    }
+}
+
+impl<B, C> Body for ResponseBody<B, C>
+where
+    B: Body,
+    B::Error: Into<Error>,
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let frame = ready!(self.as_mut().project().inner.poll_data(cx));
+        Poll::Ready(frame.map(|res| {
+            res.map_err(|e| {
+                let e = e.into();
+                self.as_mut().record(|c| c.error(&e));
+                e
+            })
+        }))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<http::HeaderMap>, Self::Error>> {
+        let trls = ready!(self.as_mut().project().inner.poll_trailers(cx)).map_err(|e| {
+            let e = e.into();
+            self.as_mut().record(|c| c.error(&e));
+            e
+        })?;
+        self.record(|c| c.eos(trls.as_ref()));
+        Poll::Ready(Ok(trls))
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B: Default, C> Default for ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn default() -> Self {
+        Self {
+            inner: B::default(),
+            classify: None,
+            tracker: None,
+        }
+    }
+}
+
+#[pinned_drop]
+impl<B, C> PinnedDrop for ResponseBody<B, C>
+where
+    C: ClassifyEos,
+    C::Class: IsFailure,
+{
+    fn drop(self: Pin<&mut Self>) {
+        self.record(|c| c.eos(None));
+    }
+}
@@ -0,0 +1,135 @@
+//! Passive outlier detection for balanced HTTP endpoints.
+//!
+//! Each endpoint's responses are classified as they complete. When an
+//! endpoint accumulates too many consecutive failures, or its failure rate
+//! exceeds a threshold over a window, it is ejected from the balancer: its
+//! service reports that it is not ready until the ejection expires. Each
+//! subsequent ejection lasts longer than the last, up to a maximum.
+//!
+//! Each request dispatched to an endpoint is annotated with the endpoint's
+//! outlier `Status`, so that it may be reported by the endpoint's tap.
+
+#![deny(warnings, rust_2018_idioms)]
+
+use std::{hash::Hash, sync::Arc, time::Duration};
+
+mod discover;
+mod endpoint;
+mod metrics;
+mod state;
+
+pub use self::discover::{Discover, DiscoverFuture, MakeDiscover};
+pub use self::endpoint::{Endpoint, ResponseBody, ResponseFuture};
+pub use self::metrics::Registry;
+pub use self::state::Status;
+
+/// Determines whether a response classification indicates a failure.
+pub trait IsFailure {
+    fn is_failure(&self) -> bool;
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Ejects an endpoint after this many consecutive failures.
+    pub consecutive_failures: Option<usize>,
+
+    /// Ejects an endpoint when its failure rate exceeds a threshold.
+    pub failure_rate: Option<FailureRate>,
+
+    /// The duration of an endpoint's first ejection. Subsequent ejections
+    /// double in length.
+    pub base_ejection_time: Duration,
+
+    /// The maximum duration of an ejection.
+    pub max_ejection_time: Duration,
+
+    /// The maximum percentage of a balancer's endpoints that may be ejected at
+    /// once.
+    pub max_ejection_percent: u8,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FailureRate {
+    /// The ratio of failures to requests, between 0.0 and 1.0, above which an
+    /// endpoint is ejected.
+    pub threshold: f64,
+
+    /// The minimum number of requests that must be observed in a window
+    /// before the failure rate is considered.
+    pub min_requests: usize,
+
+    /// The window over which the failure rate is computed.
+    pub window: Duration,
+}
+
+/// Wraps endpoint discovery so that balanced endpoints are ejected when they
+/// are observed to be failing.
+///
+/// Responses are classified with the `C`-typed classifier found in each
+/// request's extensions.
+#[derive(Debug)]
+pub struct Layer<L: Hash + Eq, C> {
+    config: Arc<Config>,
+    scope: L,
+    registry: Registry<L>,
+    _marker: std::marker::PhantomData<fn() -> C>,
+}
+
+// === impl Config ===
+
+impl Config {
+    pub fn is_enabled(&self) -> bool {
+        self.consecutive_failures.is_some() || self.failure_rate.is_some()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: None,
+            failure_rate: None,
+            base_ejection_time: Duration::from_secs(30),
+            max_ejection_time: Duration::from_secs(300),
+            max_ejection_percent: 50,
+        }
+    }
+}
+
+// === impl Layer ===
+
+impl<L: Hash + Eq, C> Layer<L, C> {
+    /// Creates a layer that records metrics in `registry`, labeled with
+    /// `scope`.
+    pub fn new(config: Config, scope: L, registry: Registry<L>) -> Self {
+        Self {
+            config: config.into(),
+            scope,
+            registry,
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<L: Clone + Hash + Eq, C> Clone for Layer<L, C> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            scope: self.scope.clone(),
+            registry: self.registry.clone(),
+            _marker: self._marker,
+        }
+    }
+}
+
+impl<L: Clone + Hash + Eq, C, M> tower::layer::Layer<M> for Layer<L, C> {
+    type Service = MakeDiscover<M, L, C>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeDiscover::new(
+            inner,
+            self.scope.clone(),
+            self.config.clone(),
+            self.registry.clone(),
+        )
+    }
+}
@@ -0,0 +1,394 @@
+use crate::{metrics::Metrics, Config};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Tracks the number of endpoints in a balancer and how many are ejected.
+#[derive(Debug, Default)]
+pub(crate) struct Pool {
+    endpoints: usize,
+    ejected: usize,
+}
+
+/// Tracks the outcomes of an endpoint's responses to determine whether it
+/// should be ejected.
+#[derive(Clone, Debug)]
+pub(crate) struct Tracker {
+    config: Arc<Config>,
+    pool: Arc<Mutex<Pool>>,
+    state: Arc<Mutex<State>>,
+    metrics: Arc<Metrics>,
+    endpoint: Arc<str>,
+}
+
+/// A request extension describing the outlier state of the endpoint to which
+/// the request was dispatched.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// The number of times the endpoint has been ejected since its ejection
+    /// backoff was last reset.
+    pub ejections: u32,
+
+    /// The number of consecutive failures observed since the endpoint last
+    /// succeeded or was restored.
+    pub consecutive_failures: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    consecutive_failures: usize,
+    window_start: Instant,
+    window_requests: usize,
+    window_failures: usize,
+
+    /// The number of times this endpoint has been ejected, used to back off
+    /// ejection times.
+    ejections: u32,
+    ejected_until: Option<Instant>,
+    last_restored: Option<Instant>,
+}
+
+// === impl Pool ===
+
+impl Pool {
+    fn can_eject(&self, max_percent: u8) -> bool {
+        let max = self.endpoints * usize::from(max_percent.min(100)) / 100;
+        self.ejected < max
+    }
+}
+
+// === impl Tracker ===
+
+impl Tracker {
+    pub(crate) fn new(
+        endpoint: Arc<str>,
+        config: Arc<Config>,
+        pool: Arc<Mutex<Pool>>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        if let Ok(mut pool) = pool.lock() {
+            pool.endpoints += 1;
+        }
+        Self {
+            state: Arc::new(Mutex::new(State::new(Instant::now()))),
+            config,
+            pool,
+            metrics,
+            endpoint,
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    pub(crate) fn status(&self) -> Status {
+        match self.state.lock() {
+            Ok(state) => Status {
+                ejections: state.ejections,
+                consecutive_failures: state.consecutive_failures,
+            },
+            Err(_) => Status::default(),
+        }
+    }
+
+    /// Returns the time at which the endpoint's ejection expires, if it is
+    /// currently ejected.
+    pub(crate) fn ejected_until(&self) -> Option<Instant> {
+        self.state.lock().ok()?.ejected_until
+    }
+
+    /// Records the outcome of a response, ejecting the endpoint if it is
+    /// determined to be an outlier.
+    pub(crate) fn record(&self, is_failure: bool) {
+        let now = Instant::now();
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(_) => return,
+        };
+        if !state.record(&self.config, is_failure, now) {
+            return;
+        }
+
+        let mut pool = match self.pool.lock() {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        if !pool.can_eject(self.config.max_ejection_percent) {
+            debug!(
+                endpoint = %self.endpoint,
+                ejected = pool.ejected,
+                endpoints = pool.endpoints,
+                "Not ejecting outlier; too many endpoints are already ejected"
+            );
+            state.reset(now);
+            return;
+        }
+
+        let duration = state.eject(&self.config, now);
+        pool.ejected += 1;
+        self.metrics.ejections_total.incr();
+        self.metrics.ejected.incr();
+        info!(endpoint = %self.endpoint, ?duration, "Ejecting outlier endpoint");
+    }
+
+    /// Returns the endpoint to the balancer if its ejection has expired.
+    ///
+    /// Returns true if the endpoint is not ejected.
+    pub(crate) fn restore_if_expired(&self) -> bool {
+        let now = Instant::now();
+        let mut state = match self.state.lock() {
+            Ok(s) => s,
+            Err(_) => return true,
+        };
+        match state.ejected_until {
+            None => true,
+            Some(until) if until > now => false,
+            Some(_) => {
+                state.restore(now);
+                self.release();
+                info!(endpoint = %self.endpoint, "Restoring ejected endpoint");
+                true
+            }
+        }
+    }
+
+    /// Removes the endpoint from its pool when it is no longer discovered.
+    pub(crate) fn retire(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.ejected_until.is_some() {
+                state.restore(Instant::now());
+                self.release();
+            }
+        }
+        if let Ok(mut pool) = self.pool.lock() {
+            pool.endpoints = pool.endpoints.saturating_sub(1);
+        }
+    }
+
+    fn release(&self) {
+        if let Ok(mut pool) = self.pool.lock() {
+            pool.ejected = pool.ejected.saturating_sub(1);
+        }
+        self.metrics.ejected.decr();
+    }
+}
+
+// === impl State ===
+
+impl State {
+    fn new(now: Instant) -> Self {
+        Self {
+            consecutive_failures: 0,
+            window_start: now,
+            window_requests: 0,
+            window_failures: 0,
+            ejections: 0,
+            ejected_until: None,
+            last_restored: None,
+        }
+    }
+
+    /// Records a response outcome, returning true if the endpoint should be
+    /// ejected.
+    fn record(&mut self, config: &Config, is_failure: bool, now: Instant) -> bool {
+        // Responses that complete while the endpoint is ejected were
+        // dispatched before the ejection and are not considered.
+        if self.ejected_until.is_some() {
+            return false;
+        }
+
+        if is_failure {
+            self.consecutive_failures += 1;
+        } else {
+            self.consecutive_failures = 0;
+        }
+        if let Some(max) = config.consecutive_failures {
+            if self.consecutive_failures >= max {
+                return true;
+            }
+        }
+
+        if let Some(rate) = config.failure_rate {
+            if now.saturating_duration_since(self.window_start) >= rate.window {
+                self.window_start = now;
+                self.window_requests = 0;
+                self.window_failures = 0;
+            }
+            self.window_requests += 1;
+            if is_failure {
+                self.window_failures += 1;
+            }
+            if self.window_requests >= rate.min_requests.max(1) {
+                let observed = self.window_failures as f64 / self.window_requests as f64;
+                if observed > rate.threshold {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Ejects the endpoint, returning the duration of the ejection.
+    fn eject(&mut self, config: &Config, now: Instant) -> Duration {
+        // If the endpoint has been healthy for longer than the maximum
+        // ejection time since it was last restored, start backing off anew.
+        if let Some(restored) = self.last_restored {
+            if now.saturating_duration_since(restored) >= config.max_ejection_time {
+                self.ejections = 0;
+            }
+        }
+
+        let duration = config
+            .base_ejection_time
+            .checked_mul(2u32.saturating_pow(self.ejections))
+            .unwrap_or(config.max_ejection_time)
+            .min(config.max_ejection_time);
+        self.ejections = self.ejections.saturating_add(1);
+        self.ejected_until = Some(now + duration);
+        self.reset(now);
+        duration
+    }
+
+    fn restore(&mut self, now: Instant) {
+        self.ejected_until = None;
+        self.last_restored = Some(now);
+        self.reset(now);
+    }
+
+    fn reset(&mut self, now: Instant) {
+        self.consecutive_failures = 0;
+        self.window_start = now;
+        self.window_requests = 0;
+        self.window_failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FailureRate;
+
+    fn config() -> Config {
+        Config {
+            consecutive_failures: Some(3),
+            failure_rate: None,
+            base_ejection_time: Duration::from_secs(10),
+            max_ejection_time: Duration::from_secs(30),
+            max_ejection_percent: 50,
+        }
+    }
+
+    #[test]
+    fn ejects_after_consecutive_failures() {
+        let config = config();
+        let now = Instant::now();
+        let mut state = State::new(now);
+
+        assert!(!state.record(&config, true, now));
+        assert!(!state.record(&config, true, now));
+        assert!(!state.record(&config, false, now), "success resets");
+        assert!(!state.record(&config, true, now));
+        assert!(!state.record(&config, true, now));
+        assert!(state.record(&config, true, now));
+    }
+
+    #[test]
+    fn ejects_on_failure_rate() {
+        let config = Config {
+            consecutive_failures: None,
+            failure_rate: Some(FailureRate {
+                threshold: 0.5,
+                min_requests: 4,
+                window: Duration::from_secs(10),
+            }),
+            ..config()
+        };
+        let now = Instant::now();
+        let mut state = State::new(now);
+
+        assert!(!state.record(&config, true, now));
+        assert!(!state.record(&config, false, now));
+        assert!(!state.record(&config, true, now));
+        assert!(!state.record(&config, false, now), "rate is not exceeded");
+
+        // The window expires, so earlier failures are not counted.
+        let later = now + Duration::from_secs(10);
+        assert!(!state.record(&config, true, later));
+        assert!(!state.record(&config, true, later));
+        assert!(!state.record(&config, false, later));
+        assert!(state.record(&config, true, later));
+    }
+
+    #[test]
+    fn ejection_time_backs_off() {
+        let config = config();
+        let now = Instant::now();
+        let mut state = State::new(now);
+
+        assert_eq!(state.eject(&config, now), Duration::from_secs(10));
+        assert!(!state.record(&config, true, now), "ignored while ejected");
+        state.restore(now);
+        assert_eq!(state.eject(&config, now), Duration::from_secs(20));
+        state.restore(now);
+        assert_eq!(state.eject(&config, now), Duration::from_secs(30));
+        state.restore(now);
+        assert_eq!(state.eject(&config, now), Duration::from_secs(30));
+
+        // After a healthy period, ejections start from the base time again.
+        let later = now + Duration::from_secs(60);
+        state.restore(now);
+        assert_eq!(state.eject(&config, later), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn limits_ejected_endpoints() {
+        let mut pool = Pool::default();
+        pool.endpoints = 1;
+        assert!(!pool.can_eject(50));
+        pool.endpoints = 4;
+        assert!(pool.can_eject(50));
+        pool.ejected = 2;
+        assert!(!pool.can_eject(50));
+        assert!(pool.can_eject(100));
+    }
+
+    #[test]
+    fn reports_status() {
+        let pool = Arc::new(Mutex::new(Pool::default()));
+        let tracker = Tracker::new(
+            "10.0.0.1:8080".into(),
+            Arc::new(config()),
+            pool.clone(),
+            Arc::default(),
+        );
+        let _other = Tracker::new(
+            "10.0.0.2:8080".into(),
+            Arc::new(config()),
+            pool,
+            Arc::default(),
+        );
+        assert_eq!(tracker.status(), Status::default());
+
+        tracker.record(true);
+        tracker.record(true);
+        assert_eq!(
+            tracker.status(),
+            Status {
+                ejections: 0,
+                consecutive_failures: 2,
+            }
+        );
+
+        tracker.record(true);
+        assert!(tracker.ejected_until().is_some());
+        assert_eq!(
+            tracker.status(),
+            Status {
+                ejections: 1,
+                consecutive_failures: 0,
+            }
+        );
+    }
+}
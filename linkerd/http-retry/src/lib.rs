@@ -0,0 +1,372 @@
+#![deny(warnings, rust_2018_idioms)]
+
+use bytes::{Buf, Bytes};
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use linkerd2_error::Error;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// A request body that buffers its data as it is streamed so that the request
+/// may be replayed.
+///
+/// Data is buffered up to a configured number of bytes. If the body exceeds
+/// this limit, its buffer is discarded and it can no longer be cloned.
+///
+/// Each clone replays the buffered data before resuming the stream from the
+/// inner body. Once a clone has been polled, prior bodies may no longer be
+/// polled.
+#[derive(Debug)]
+pub struct ReplayBody<B> {
+    shared: Arc<Mutex<Shared<B>>>,
+
+    /// Identifies this body so that only the most recent clone may be polled.
+    generation: usize,
+
+    /// The number of buffered chunks this body has already yielded.
+    replayed: usize,
+
+    size_hint: SizeHint,
+    is_empty: bool,
+}
+
+/// Indicates that a body's buffer was discarded because it exceeded its limit,
+/// so it cannot be replayed.
+#[derive(Debug)]
+pub struct Capped(());
+
+/// Indicates that a body was polled after it was replaced by a clone.
+#[derive(Debug)]
+pub struct Replaced(());
+
+#[derive(Debug)]
+struct Shared<B> {
+    /// The generation of the body currently reading from `inner`.
+    owner: usize,
+    next_generation: usize,
+
+    buf: Vec<Bytes>,
+    buffered_bytes: usize,
+    max_bytes: usize,
+    is_capped: bool,
+
+    /// The inner body, until its trailers have been read.
+    inner: Option<Pin<Box<B>>>,
+    trailers: Option<HeaderMap>,
+}
+
+// === impl ReplayBody ===
+
+impl<B: Body> ReplayBody<B> {
+    /// Wraps a body so that up to `max_bytes` of its data are buffered.
+    pub fn new(inner: B, max_bytes: usize) -> Self {
+        let size_hint = inner.size_hint();
+        let is_empty = inner.is_end_stream();
+        let shared = Shared {
+            owner: 0,
+            next_generation: 1,
+            buf: Vec::new(),
+            buffered_bytes: 0,
+            max_bytes,
+            is_capped: false,
+            inner: Some(Box::pin(inner)),
+            trailers: None,
+        };
+        Self {
+            shared: Arc::new(Mutex::new(shared)),
+            generation: 0,
+            replayed: 0,
+            size_hint,
+            is_empty,
+        }
+    }
+
+    /// Returns true if the inner body has been read to completion, so that a
+    /// clone may be read without interrupting this body.
+    pub fn is_complete(&self) -> bool {
+        match self.shared.lock() {
+            Ok(shared) => shared.inner.as_ref().map_or(true, |b| b.is_end_stream()),
+            Err(_) => false,
+        }
+    }
+}
+
+impl<B> ReplayBody<B> {
+    /// Returns a body that replays this body's data, unless the body has
+    /// exceeded its buffer limit.
+    pub fn try_clone(&self) -> Option<Self> {
+        let mut shared = self.shared.lock().ok()?;
+        if shared.is_capped {
+            return None;
+        }
+
+        let generation = shared.next_generation;
+        shared.next_generation += 1;
+        Some(Self {
+            shared: self.shared.clone(),
+            generation,
+            replayed: 0,
+            size_hint: self.size_hint.clone(),
+            is_empty: self.is_empty,
+        })
+    }
+
+    /// Returns true if the body has exceeded its buffer limit and can no
+    /// longer be replayed.
+    pub fn is_capped(&self) -> bool {
+        self.shared.lock().map(|s| s.is_capped).unwrap_or(true)
+    }
+
+    /// Ensures that this body may read from the shared state, taking it over
+    /// from prior bodies as necessary.
+    fn acquire(&self, shared: &mut Shared<B>) -> Result<(), Error> {
+        if self.generation < shared.owner {
+            return Err(Replaced(()).into());
+        }
+        if self.generation > shared.owner {
+            // A replacement body cannot replay data that was discarded.
+            if shared.is_capped {
+                return Err(Capped(()).into());
+            }
+            shared.owner = self.generation;
+        }
+        Ok(())
+    }
+}
+
+impl<B> Body for ReplayBody<B>
+where
+    B: Body,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        let mut shared = this.shared.lock().expect("replay body lock poisoned");
+        if let Err(e) = this.acquire(&mut shared) {
+            return Poll::Ready(Some(Err(e)));
+        }
+
+        // Replay buffered data before reading more from the inner body.
+        if let Some(chunk) = shared.buf.get(this.replayed) {
+            this.replayed += 1;
+            return Poll::Ready(Some(Ok(chunk.clone())));
+        }
+
+        let shared = &mut *shared;
+        let inner = match shared.inner.as_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(None),
+        };
+        let mut data = match inner.as_mut().poll_data(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(Some(Ok(data))) => data,
+        };
+
+        let chunk = data.to_bytes();
+        if !shared.is_capped {
+            if shared.buffered_bytes + chunk.len() > shared.max_bytes {
+                shared.is_capped = true;
+                shared.buf = Vec::new();
+                shared.buffered_bytes = 0;
+            } else {
+                shared.buffered_bytes += chunk.len();
+                shared.buf.push(chunk.clone());
+            }
+        }
+        this.replayed = shared.buf.len();
+
+        Poll::Ready(Some(Ok(chunk)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        let mut shared = this.shared.lock().expect("replay body lock poisoned");
+        this.acquire(&mut shared)?;
+
+        let trailers = match shared.inner.as_mut() {
+            None => return Poll::Ready(Ok(shared.trailers.clone())),
+            Some(inner) => match inner.as_mut().poll_trailers(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(res) => res.map_err(Into::into)?,
+            },
+        };
+
+        // The inner body is complete, so it may be dropped.
+        shared.inner = None;
+        shared.trailers = trailers.clone();
+        Poll::Ready(Ok(trailers))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        if self.is_empty {
+            return true;
+        }
+
+        let shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => return false,
+        };
+        if self.generation != shared.owner || self.replayed < shared.buf.len() {
+            return false;
+        }
+        match shared.inner.as_ref() {
+            Some(inner) => inner.is_end_stream(),
+            None => shared.trailers.is_none(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.size_hint.clone()
+    }
+}
+
+// === impl Capped ===
+
+impl std::fmt::Display for Capped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request body exceeded its replay buffer")
+    }
+}
+
+impl std::error::Error for Capped {}
+
+// === impl Replaced ===
+
+impl std::fmt::Display for Replaced {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "request body was replaced by a retry")
+    }
+}
+
+impl std::error::Error for Replaced {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[derive(Default)]
+    struct TestBody {
+        data: VecDeque<&'static str>,
+        trailers: Option<HeaderMap>,
+    }
+
+    #[tokio::test]
+    async fn replays_buffered_body() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", " world"], true), 64);
+        let mut replay = initial.try_clone().expect("body must not be capped");
+
+        assert_eq!(chunk(&mut initial).await, Some("hello".into()));
+        assert_eq!(chunk(&mut initial).await, Some(" world".into()));
+        assert_eq!(chunk(&mut initial).await, None);
+        assert!(initial.trailers().await.unwrap().is_some());
+        drop(initial);
+
+        assert_eq!(chunk(&mut replay).await, Some("hello".into()));
+        assert_eq!(chunk(&mut replay).await, Some(" world".into()));
+        assert_eq!(chunk(&mut replay).await, None);
+        assert!(replay.trailers().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn replays_partially_read_body() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", " world"], false), 64);
+        let mut replay = initial.try_clone().expect("body must not be capped");
+
+        assert_eq!(chunk(&mut initial).await, Some("hello".into()));
+
+        // The replay yields the buffered data and then reads the remainder of
+        // the inner body.
+        assert_eq!(chunk(&mut replay).await, Some("hello".into()));
+        assert_eq!(chunk(&mut replay).await, Some(" world".into()));
+        assert_eq!(chunk(&mut replay).await, None);
+        assert!(replay.trailers().await.unwrap().is_none());
+
+        // The initial body has been replaced, so it may not be polled.
+        assert!(initial.data().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn caps_buffer() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", " world"], false), 8);
+        let mut replay = initial.try_clone().expect("body must not be capped");
+
+        assert_eq!(chunk(&mut initial).await, Some("hello".into()));
+        assert!(!initial.is_capped());
+        assert_eq!(chunk(&mut initial).await, Some(" world".into()));
+        assert!(initial.is_capped());
+        assert!(initial.try_clone().is_none());
+
+        // Data exceeding the buffer cannot be replayed.
+        assert!(replay.data().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn empty_body() {
+        let initial = ReplayBody::new(TestBody::default(), 0);
+        let replay = initial.try_clone().expect("body must not be capped");
+        assert!(initial.is_end_stream());
+        assert!(replay.is_end_stream());
+    }
+
+    async fn chunk<B>(body: &mut B) -> Option<String>
+    where
+        B: Body + Unpin,
+        B::Error: std::fmt::Debug,
+    {
+        let mut data = body.data().await?.expect("data must not fail");
+        let bytes = data.to_bytes();
+        Some(String::from_utf8(bytes.to_vec()).expect("data must be utf8"))
+    }
+
+    impl TestBody {
+        fn new(data: &[&'static str], trailers: bool) -> Self {
+            let trailers = if trailers {
+                let mut trls = HeaderMap::new();
+                trls.insert("x-trailer", "done".parse().unwrap());
+                Some(trls)
+            } else {
+                None
+            };
+            Self {
+                data: data.iter().copied().collect(),
+                trailers,
+            }
+        }
+    }
+
+    impl Body for TestBody {
+        type Data = Bytes;
+        type Error = Error;
+
+        fn poll_data(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Bytes, Error>>> {
+            Poll::Ready(self.get_mut().data.pop_front().map(|d| Ok(Bytes::from(d))))
+        }
+
+        fn poll_trailers(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Error>> {
+            Poll::Ready(Ok(self.get_mut().trailers.take()))
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.data.is_empty() && self.trailers.is_none()
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{internal::Io, Poll};
+use bytes::{Buf, BufMut};
+use pin_project::pin_project;
+use std::{mem::MaybeUninit, pin::Pin, task::Context};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A transport that may be one of two types.
+#[pin_project(project = EitherIoProj)]
+#[derive(Debug)]
+pub enum EitherIo<L, R> {
+    Left(#[pin] L),
+    Right(#[pin] R),
+}
+
+impl<L: AsyncRead, R: AsyncRead> AsyncRead for EitherIo<L, R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<usize> {
+        match self.project() {
+            EitherIoProj::Left(l) => l.poll_read(cx, buf),
+            EitherIoProj::Right(r) => r.poll_read(cx, buf),
+        }
+    }
+
+    fn poll_read_buf<B: BufMut>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<usize> {
+        match self.project() {
+            EitherIoProj::Left(l) => l.poll_read_buf(cx, buf),
+            EitherIoProj::Right(r) => r.poll_read_buf(cx, buf),
+        }
+    }
+
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [MaybeUninit<u8>]) -> bool {
+        match self {
+            Self::Left(l) => l.prepare_uninitialized_buffer(buf),
+            Self::Right(r) => r.prepare_uninitialized_buffer(buf),
+        }
+    }
+}
+
+impl<L: AsyncWrite, R: AsyncWrite> AsyncWrite for EitherIo<L, R> {
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match self.project() {
+            EitherIoProj::Left(l) => l.poll_shutdown(cx),
+            EitherIoProj::Right(r) => r.poll_shutdown(cx),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match self.project() {
+            EitherIoProj::Left(l) => l.poll_flush(cx),
+            EitherIoProj::Right(r) => r.poll_flush(cx),
+        }
+    }
+
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<usize> {
+        match self.project() {
+            EitherIoProj::Left(l) => l.poll_write(cx, buf),
+            EitherIoProj::Right(r) => r.poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_buf<B: Buf>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<usize> {
+        match self.project() {
+            EitherIoProj::Left(l) => l.poll_write_buf(cx, buf),
+            EitherIoProj::Right(r) => r.poll_write_buf(cx, buf),
+        }
+    }
+}
+
+impl<L: Io, R: Io> Io for EitherIo<L, R> {
+    fn poll_write_buf_erased(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: &mut dyn Buf,
+    ) -> Poll<usize> {
+        self.poll_write_buf(cx, &mut buf)
+    }
+
+    fn poll_read_buf_erased(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        mut buf: &mut dyn BufMut,
+    ) -> Poll<usize> {
+        self.poll_read_buf(cx, &mut buf)
+    }
+}
@@ -1,10 +1,12 @@
 mod boxed;
+mod either;
 mod peek;
 mod prefixed;
 mod sensor;
 
 pub use self::{
     boxed::BoxedIo,
+    either::EitherIo,
     peek::{Peek, Peekable},
     prefixed::PrefixedIo,
     sensor::{Sensor, SensorIo},
@@ -61,6 +63,24 @@ mod internal {
         }
     }
 
+    impl Io for tokio::net::UnixStream {
+        fn poll_write_buf_erased(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            mut buf: &mut dyn Buf,
+        ) -> Poll<usize> {
+            self.poll_write_buf(cx, &mut buf)
+        }
+
+        fn poll_read_buf_erased(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            mut buf: &mut dyn BufMut,
+        ) -> Poll<usize> {
+            self.poll_read_buf(cx, &mut buf)
+        }
+    }
+
     impl<S: Io + Unpin> Io for tokio_rustls::server::TlsStream<S> {
         fn poll_write_buf_erased(
             self: Pin<&mut Self>,
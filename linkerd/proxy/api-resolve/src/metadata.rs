@@ -1,9 +1,13 @@
+use crate::core::{HasWeight, Weight};
 use crate::identity;
 use http::uri::Authority;
 use indexmap::IndexMap;
 
 /// Metadata describing an endpoint.
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// Metadata are compared without regard to their weights, since a weight may
+/// change while the endpoint is in use.
+#[derive(Clone, Debug)]
 pub struct Metadata {
     /// An endpoint's relative weight.
     ///
@@ -13,8 +17,9 @@ pub struct Metadata {
     /// The default weight, corresponding to 1.0, is 10,000. This enables us to
     /// specify weights as small as 0.0001 and as large as 400,000+.
     ///
-    /// A float is not used so that this type can implement `Eq`.
-    weight: u32,
+    /// The weight is shared by all clones of the metadata so that it may be
+    /// updated without rebuilding the endpoint.
+    weight: Weight,
 
     /// Arbitrary endpoint labels. Primarily used for telemetry.
     labels: IndexMap<String, String>,
@@ -28,6 +33,10 @@ pub struct Metadata {
 
     /// Used to override the the authority if needed
     authority_override: Option<Authority>,
+
+    /// Indicates that the endpoint's proxy terminates opaque TCP streams that
+    /// are tunneled over HTTP/2 `CONNECT`.
+    tcp_tunnel: bool,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -47,8 +56,9 @@ impl Metadata {
             labels: IndexMap::default(),
             protocol_hint: ProtocolHint::Unknown,
             identity: None,
-            weight: 10_000,
+            weight: Weight::default(),
             authority_override: None,
+            tcp_tunnel: false,
         }
     }
 
@@ -63,11 +73,17 @@ impl Metadata {
             labels,
             protocol_hint,
             identity,
-            weight,
+            weight: Weight::new(weight),
             authority_override,
+            tcp_tunnel: false,
         }
     }
 
+    /// Marks the endpoint as accepting tunneled TCP streams.
+    pub fn with_tcp_tunnel(self, tcp_tunnel: bool) -> Self {
+        Self { tcp_tunnel, ..self }
+    }
+
     /// Returns the endpoint's labels from the destination service, if it has them.
     pub fn labels(&self) -> &IndexMap<String, String> {
         &self.labels
@@ -84,4 +100,30 @@ impl Metadata {
     pub fn authority_override(&self) -> Option<&Authority> {
         self.authority_override.as_ref()
     }
+
+    pub fn weight(&self) -> &Weight {
+        &self.weight
+    }
+
+    pub fn tcp_tunnel(&self) -> bool {
+        self.tcp_tunnel
+    }
+}
+
+impl PartialEq for Metadata {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels == other.labels
+            && self.protocol_hint == other.protocol_hint
+            && self.identity == other.identity
+            && self.authority_override == other.authority_override
+            && self.tcp_tunnel == other.tcp_tunnel
+    }
+}
+
+impl Eq for Metadata {}
+
+impl HasWeight for Metadata {
+    fn weight(&self) -> &Weight {
+        &self.weight
+    }
 }
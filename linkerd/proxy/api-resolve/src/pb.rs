@@ -8,6 +8,10 @@ use http::uri::Authority;
 use indexmap::IndexMap;
 use std::{collections::HashMap, net::SocketAddr};
 
+/// The endpoint label with which the destination service advertises that an
+/// endpoint's proxy accepts tunneled TCP streams.
+const TCP_TUNNEL_LABEL: &str = "tcp_tunnel";
+
 /// Construct a new labeled `SocketAddr `from a protobuf `WeightedAddr`.
 pub(in crate) fn to_addr_meta(
     pb: WeightedAddr,
@@ -42,8 +46,14 @@ pub(in crate) fn to_addr_meta(
         }
     }
 
+    let tcp_tunnel = meta
+        .get(TCP_TUNNEL_LABEL)
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
     let tls_id = pb.tls_identity.and_then(to_id);
-    let meta = Metadata::new(meta, proto_hint, tls_id, pb.weight, authority_override);
+    let meta = Metadata::new(meta, proto_hint, tls_id, pb.weight, authority_override)
+        .with_tcp_tunnel(tcp_tunnel);
     Some((addr, meta))
 }
 
@@ -5,9 +5,11 @@ use crate::pb;
 use api::destination_client::DestinationClient;
 use futures::{ready, Stream};
 use http_body::Body as HttpBody;
+use indexmap::IndexMap;
 use pin_project::pin_project;
 use std::error::Error;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tonic::{
@@ -29,6 +31,7 @@ pub struct Resolve<S> {
 pub struct Resolution {
     #[pin]
     inner: grpc::Streaming<api::Update>,
+    active: IndexMap<SocketAddr, Metadata>,
 }
 
 // === impl Resolver ===
@@ -99,6 +102,7 @@ where
             trace!(metadata = ?rsp.metadata());
             Ok(Resolution {
                 inner: rsp.into_inner(),
+                active: IndexMap::default(),
             })
         })
     }
@@ -119,6 +123,7 @@ impl Stream for Resolution {
                         let addr_metas = addrs
                             .into_iter()
                             .filter_map(|addr| pb::to_addr_meta(addr, &metric_labels))
+                            .filter(|(addr, meta)| !reweight(this.active, *addr, meta))
                             .collect::<Vec<_>>();
                         if !addr_metas.is_empty() {
                             debug!(endpoints = %addr_metas.len(), "Add");
@@ -131,6 +136,9 @@ impl Stream for Resolution {
                             .into_iter()
                             .filter_map(pb::to_sock_addr)
                             .collect::<Vec<_>>();
+                        for addr in sock_addrs.iter() {
+                            this.active.remove(addr);
+                        }
                         if !sock_addrs.is_empty() {
                             debug!(endpoints = %sock_addrs.len(), "Remove");
                             return Poll::Ready(Some(Ok(Update::Remove(sock_addrs))));
@@ -139,6 +147,7 @@ impl Stream for Resolution {
 
                     Some(api::update::Update::NoEndpoints(api::NoEndpoints { exists })) => {
                         info!("No endpoints");
+                        this.active.clear();
                         let update = if exists {
                             Update::Empty
                         } else {
@@ -154,3 +163,28 @@ impl Stream for Resolution {
         }
     }
 }
+
+/// Records an added endpoint, returning true if the endpoint was already
+/// active and is unchanged apart from its weight. In that case, the active
+/// endpoint's weight is updated in place so that its service need not be
+/// rebuilt.
+fn reweight(
+    active: &mut IndexMap<SocketAddr, Metadata>,
+    addr: SocketAddr,
+    meta: &Metadata,
+) -> bool {
+    if let Some(prev) = active.get(&addr) {
+        // Metadata equality ignores weights.
+        if prev == meta {
+            let weight = meta.weight().get();
+            if prev.weight().get() != weight {
+                debug!(%addr, %weight, "Reweight");
+                prev.weight().set(weight);
+            }
+            return true;
+        }
+    }
+
+    active.insert(addr, meta.clone());
+    false
+}
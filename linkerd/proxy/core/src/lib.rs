@@ -1,5 +1,12 @@
 #![deny(warnings, rust_2018_idioms)]
 
+pub mod locality;
 pub mod resolve;
+pub mod weight;
 
-pub use self::{resolve::Resolve, resolve::Update};
+pub use self::{
+    locality::IsLocal,
+    resolve::Resolve,
+    resolve::Update,
+    weight::{HasWeight, Weight},
+};
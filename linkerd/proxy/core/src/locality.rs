@@ -0,0 +1,7 @@
+/// Indicates whether an endpoint's service is in the proxy's locality.
+///
+/// Implemented by balanced endpoint services so that locality-aware balancing
+/// can see through the layers that wrap them.
+pub trait IsLocal {
+    fn is_local(&self) -> bool;
+}
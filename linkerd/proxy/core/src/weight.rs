@@ -0,0 +1,100 @@
+use futures::task::AtomicWaker;
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+use std::task::Waker;
+
+/// An endpoint's relative weight.
+///
+/// A weight is shared by all clones of an endpoint, so that an endpoint that is
+/// rediscovered with a new weight may be updated in place. Because a weight may
+/// change at any time, it does not implement `PartialEq` or `Hash`.
+///
+/// A weight of 0 means that the endpoint should never be preferred over a non
+/// 0-weighted endpoint.
+#[derive(Clone, Debug)]
+pub struct Weight(Arc<Inner>);
+
+/// Exposes an endpoint's weight.
+pub trait HasWeight {
+    fn weight(&self) -> &Weight;
+}
+
+#[derive(Debug)]
+struct Inner {
+    value: AtomicU32,
+
+    /// Notified when the weight changes.
+    task: AtomicWaker,
+}
+
+// === impl Weight ===
+
+impl Weight {
+    /// The default weight, corresponding to 1.0.
+    pub const DEFAULT: u32 = 10_000;
+
+    pub fn new(weight: u32) -> Self {
+        Weight(Arc::new(Inner {
+            value: AtomicU32::new(weight),
+            task: AtomicWaker::new(),
+        }))
+    }
+
+    pub fn get(&self) -> u32 {
+        self.0.value.load(Ordering::Relaxed)
+    }
+
+    /// Updates the weight for all clones of this handle, notifying the
+    /// registered task if the weight changed.
+    pub fn set(&self, weight: u32) {
+        if self.0.value.swap(weight, Ordering::Relaxed) != weight {
+            self.0.task.wake();
+        }
+    }
+
+    /// Registers a task to be notified when the weight changes.
+    ///
+    /// Only the most recently registered task is notified.
+    pub fn register(&self, waker: &Waker) {
+        self.0.task.register(waker);
+    }
+}
+
+impl Default for Weight {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::{waker, ArcWake};
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Default)]
+    struct Wakes(AtomicUsize);
+
+    impl ArcWake for Wakes {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn set_notifies_on_change() {
+        let weight = Weight::new(0);
+        let clone = weight.clone();
+        let wakes = Arc::new(Wakes::default());
+        weight.register(&waker(wakes.clone()));
+
+        clone.set(0);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        clone.set(Weight::DEFAULT);
+        assert_eq!(weight.get(), Weight::DEFAULT);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+    }
+}
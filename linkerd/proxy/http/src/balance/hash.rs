@@ -0,0 +1,365 @@
+//! A balancer that routes requests by a consistent hash of a request key.
+//!
+//! Endpoints are placed on a hash ring at many points. Each request is sent to
+//! the first ready endpoint at or after its key's position on the ring, so
+//! requests with the same key are sent to the same endpoint, and only the keys
+//! owned by an endpoint move when it is added or removed.
+//!
+//! Each endpoint is placed on the ring at a number of points proportional to
+//! its weight, so that it owns a proportional share of keys. Endpoints are
+//! re-placed when their weights change. 0-weighted endpoints are placed as if
+//! they had the default weight; they are withheld from the balancer while any
+//! other endpoint is ready.
+
+use futures::{future, ready, TryFutureExt};
+use http::header::{self, HeaderName};
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use linkerd2_proxy_core::{HasWeight, Weight};
+use linkerd2_proxy_transport::listen::Addrs;
+use rand::{rngs::SmallRng, Rng};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::discover::{Change, Discover};
+use tower::ready_cache::{error::Failed, ReadyCache};
+use tracing::{debug, trace};
+
+/// The number of points at which an endpoint with the default weight is placed
+/// on the ring.
+const REPLICAS: usize = 100;
+
+/// Bounds the number of points at which a heavily-weighted endpoint is placed
+/// on the ring.
+const MAX_REPLICAS: usize = 100 * REPLICAS;
+
+/// The part of a request that determines which endpoint it is sent to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashKey {
+    /// The value of a request header.
+    Header(HeaderName),
+
+    /// The value of a request cookie.
+    Cookie(String),
+
+    /// The client's IP address.
+    SourceAddr,
+}
+
+/// Balances requests over a discovered set of endpoints by a consistent hash
+/// of each request's key.
+///
+/// If the endpoint that owns a key is not ready, the request is sent to the
+/// next ready endpoint on the ring. Requests without a key are sent to a
+/// random ready endpoint.
+pub struct Balance<D: Discover, Req> {
+    discover: D,
+    services: ReadyCache<D::Key, D::Service, Req>,
+    ring: Ring<D::Key>,
+    /// Each endpoint's weight and the weight at which it is placed on the
+    /// ring.
+    weights: IndexMap<D::Key, (Weight, u32)>,
+    key: HashKey,
+    rng: SmallRng,
+}
+
+/// Maps hashes to the endpoints that own them.
+#[derive(Debug)]
+struct Ring<K> {
+    points: Vec<(u64, K)>,
+}
+
+// === impl HashKey ===
+
+impl HashKey {
+    /// Hashes the request's key, if it has one.
+    fn hash<B>(&self, req: &http::Request<B>) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        match self {
+            HashKey::Header(name) => req.headers().get(name)?.as_bytes().hash(&mut hasher),
+            HashKey::Cookie(name) => cookie(req, name)?.hash(&mut hasher),
+            HashKey::SourceAddr => req
+                .extensions()
+                .get::<Addrs>()?
+                .peer()
+                .ip()
+                .hash(&mut hasher),
+        }
+        Some(hasher.finish())
+    }
+}
+
+/// Returns the value of the named cookie.
+fn cookie<'r, B>(req: &'r http::Request<B>, name: &str) -> Option<&'r str> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| {
+            let mut kv = pair.trim().splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if k == name => Some(v),
+                _ => None,
+            }
+        })
+}
+
+// === impl Balance ===
+
+impl<D, Req> Balance<D, Req>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: tower::Service<Req> + HasWeight,
+    <D::Service as tower::Service<Req>>::Error: Into<Error>,
+{
+    pub fn new(discover: D, key: HashKey, rng: SmallRng) -> Self {
+        Self {
+            discover,
+            services: ReadyCache::default(),
+            ring: Ring::default(),
+            weights: IndexMap::new(),
+            key,
+            rng,
+        }
+    }
+
+    fn update_pending_from_discover(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>>
+    where
+        D: Unpin,
+        D::Error: Into<Error>,
+    {
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx)) {
+                None => return Poll::Ready(Err("discovery stream closed".into())),
+                Some(Err(e)) => return Poll::Ready(Err(e.into())),
+                Some(Ok(Change::Insert(key, svc))) => {
+                    trace!("Insert");
+                    let weight = svc.weight().clone();
+                    let placed = weight.get();
+                    self.ring.remove(&key);
+                    self.ring.insert(key.clone(), replicas(placed));
+                    self.weights.insert(key.clone(), (weight, placed));
+                    self.services.push(key, svc);
+                }
+                Some(Ok(Change::Remove(key))) => {
+                    trace!("Remove");
+                    self.ring.remove(&key);
+                    self.weights.remove(&key);
+                    self.services.evict(&key);
+                }
+            }
+        }
+    }
+
+    fn promote_pending_to_ready(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.services.poll_pending(cx) {
+                Poll::Ready(Ok(())) | Poll::Pending => return,
+                Poll::Ready(Err(Failed(key, error))) => {
+                    debug!(%error, "Dropping failed endpoint");
+                    self.ring.remove(&key);
+                    self.weights.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Re-places endpoints whose weights have changed since they were placed
+    /// on the ring.
+    fn update_weights(&mut self) {
+        for (key, (weight, placed)) in self.weights.iter_mut() {
+            let value = weight.get();
+            if value != *placed {
+                trace!(from = *placed, to = value, "Reweighting");
+                *placed = value;
+                self.ring.remove(key);
+                self.ring.insert(key.clone(), replicas(value));
+            }
+        }
+    }
+
+    /// Returns the index of the ready endpoint that should receive the request.
+    fn ready_index<B>(&mut self, req: &http::Request<B>) -> usize {
+        if let Some(hash) = self.key.hash(req) {
+            let services = &self.services;
+            let index = self
+                .ring
+                .iter_from(hash)
+                .find_map(|key| services.get_ready(key).map(|(i, _, _)| i));
+            if let Some(index) = index {
+                return index;
+            }
+        }
+
+        self.rng.gen_range(0, self.services.ready_len())
+    }
+}
+
+impl<D, B> tower::Service<http::Request<B>> for Balance<D, http::Request<B>>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
+    D::Service: tower::Service<http::Request<B>> + HasWeight,
+    <D::Service as tower::Service<http::Request<B>>>::Error: Into<Error>,
+{
+    type Response = <D::Service as tower::Service<http::Request<B>>>::Response;
+    type Error = Error;
+    type Future = future::MapErr<
+        <D::Service as tower::Service<http::Request<B>>>::Future,
+        fn(<D::Service as tower::Service<http::Request<B>>>::Error) -> Error,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Process any pending discovery updates, but don't fail while a
+        // discovery update is pending.
+        if let Poll::Ready(Err(e)) = self.update_pending_from_discover(cx) {
+            return Poll::Ready(Err(e));
+        }
+        self.promote_pending_to_ready(cx);
+        self.update_weights();
+
+        if self.services.ready_len() == 0 {
+            trace!(pending = self.services.pending_len(), "No ready endpoints");
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let index = self.ready_index(&req);
+        self.services
+            .call_ready_index(index, req)
+            .map_err(Into::into as fn(_) -> _)
+    }
+}
+
+/// Returns the number of points at which an endpoint with the given weight is
+/// placed on the ring.
+fn replicas(weight: u32) -> usize {
+    if weight == 0 {
+        return REPLICAS;
+    }
+    let replicas = REPLICAS as u64 * u64::from(weight) / u64::from(Weight::DEFAULT);
+    (replicas as usize).max(1).min(MAX_REPLICAS)
+}
+
+// === impl Ring ===
+
+impl<K> Default for Ring<K> {
+    fn default() -> Self {
+        Self { points: Vec::new() }
+    }
+}
+
+impl<K: Hash + Eq> Ring<K> {
+    fn insert(&mut self, key: K, replicas: usize)
+    where
+        K: Clone,
+    {
+        for replica in 0..replicas {
+            let mut hasher = DefaultHasher::new();
+            (&key, replica).hash(&mut hasher);
+            self.points.push((hasher.finish(), key.clone()));
+        }
+        self.points.sort_unstable_by_key(|&(hash, _)| hash);
+    }
+
+    fn remove(&mut self, key: &K) {
+        self.points.retain(|(_, k)| k != key);
+    }
+
+    /// Iterates over the ring's points, starting with the first point at or
+    /// after `hash`.
+    fn iter_from(&self, hash: u64) -> impl Iterator<Item = &K> {
+        let start = match self.points.binary_search_by_key(&hash, |&(h, _)| h) {
+            Ok(i) | Err(i) => i,
+        };
+        let (head, tail) = self.points.split_at(start);
+        tail.iter().chain(head.iter()).map(|(_, k)| k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(ring: &Ring<usize>, key: u64) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        *ring.iter_from(hasher.finish()).next().unwrap()
+    }
+
+    #[test]
+    fn removing_an_endpoint_only_moves_its_keys() {
+        let mut ring = Ring::default();
+        for endpoint in 0..10 {
+            ring.insert(endpoint, REPLICAS);
+        }
+        let before = (0..1000).map(|k| owner(&ring, k)).collect::<Vec<_>>();
+
+        ring.remove(&3);
+        for (key, prior) in before.into_iter().enumerate() {
+            let owner = owner(&ring, key as u64);
+            if prior == 3 {
+                assert_ne!(owner, 3);
+            } else {
+                assert_eq!(owner, prior, "key {} moved", key);
+            }
+        }
+    }
+
+    #[test]
+    fn adding_an_endpoint_only_moves_keys_to_it() {
+        let mut ring = Ring::default();
+        for endpoint in 0..10 {
+            ring.insert(endpoint, REPLICAS);
+        }
+        let before = (0..1000).map(|k| owner(&ring, k)).collect::<Vec<_>>();
+
+        ring.insert(10, REPLICAS);
+        let mut moved = 0;
+        for (key, prior) in before.into_iter().enumerate() {
+            let owner = owner(&ring, key as u64);
+            if owner != prior {
+                assert_eq!(owner, 10, "key {} moved", key);
+                moved += 1;
+            }
+        }
+        assert!(moved > 0 && moved < 200, "{} keys moved", moved);
+    }
+
+    #[test]
+    fn endpoints_own_keys_in_proportion_to_their_weights() {
+        let mut ring = Ring::default();
+        ring.insert(0, replicas(Weight::DEFAULT));
+        ring.insert(1, replicas(3 * Weight::DEFAULT));
+
+        let heavy = (0..10_000).filter(|&k| owner(&ring, k) == 1).count();
+        assert!(6_500 < heavy && heavy < 8_500, "{} keys", heavy);
+    }
+
+    #[test]
+    fn replicas_scale_with_weight() {
+        assert_eq!(replicas(Weight::DEFAULT), REPLICAS);
+        assert_eq!(replicas(Weight::DEFAULT / 2), REPLICAS / 2);
+        assert_eq!(replicas(1), 1);
+        assert_eq!(replicas(0), REPLICAS);
+        assert_eq!(replicas(u32::MAX), MAX_REPLICAS);
+    }
+
+    #[test]
+    fn reads_cookies() {
+        let req = http::Request::builder()
+            .header(header::COOKIE, "a=1; session=abc")
+            .header(header::COOKIE, "b=2")
+            .body(())
+            .unwrap();
+        assert_eq!(cookie(&req, "session"), Some("abc"));
+        assert_eq!(cookie(&req, "b"), Some("2"));
+        assert_eq!(cookie(&req, "c"), None);
+    }
+}
@@ -0,0 +1,585 @@
+//! A weighted variant of `tower::load::PeakEwma`.
+//!
+//! Each endpoint's peak-EWMA cost is divided by its relative weight, so that
+//! the power-of-two-choices balancer sends proportionally more traffic to
+//! endpoints with higher weights. Weights are read as each endpoint's load is
+//! measured, so updates from discovery take effect without rebuilding the
+//! endpoint.
+//!
+//! Endpoints with a weight of 0 are never preferred over other endpoints: they
+//! are withheld from the balancer, by reporting that they are not ready, while
+//! any non 0-weighted endpoint is ready. A withheld endpoint is notified when
+//! no other endpoints remain ready or when its weight changes.
+//!
+//! Newly discovered endpoints may be slow-started: an endpoint's weight is
+//! reduced when it is inserted and then ramps up linearly until it reaches its
+//! full weight at the end of the slow-start window.
+
+use super::metrics::{Registry, Warming};
+use futures::{ready, task::AtomicWaker, Stream};
+use linkerd2_proxy_core::{HasWeight, Weight};
+use pin_project::{pin_project, pinned_drop};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower::discover::{self, Change};
+use tower::load::{completion::TrackCompletionFuture, Load, TrackCompletion};
+use tracing::{debug, trace};
+
+/// Wraps a discovered set of endpoints with weighted peak-EWMA load
+/// measurements.
+#[pin_project(PinnedDrop)]
+pub struct PeakEwmaDiscover<D, C, L: Hash + Eq> {
+    #[pin]
+    discover: D,
+    decay_ns: f64,
+    default_rtt: Duration,
+    slow_start: Duration,
+    completion: C,
+    pool: Arc<Pool>,
+    warming: Arc<Warming>,
+    registry: Registry<L>,
+}
+
+/// Measures the weighted peak-EWMA load of a single endpoint.
+#[derive(Debug)]
+pub struct PeakEwma<S, C> {
+    service: S,
+    weight: Weight,
+    decay_ns: f64,
+    rtt_estimate: Arc<Mutex<RttEstimate>>,
+    completion: C,
+    pool: Arc<Pool>,
+
+    /// Notified when the endpoint may be used despite its 0 weight.
+    zero_weight_waiter: Arc<AtomicWaker>,
+
+    /// Whether this endpoint is counted as a ready, non 0-weighted endpoint.
+    ready_weighted: bool,
+
+    slow_start: Option<SlowStart>,
+}
+
+/// The relative cost of sending a request to an endpoint.
+///
+/// 0-weighted endpoints are always more costly than other endpoints.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost {
+    zero_weight: bool,
+    cost: f64,
+}
+
+/// Updates an endpoint's RTT estimate when its response completes.
+#[derive(Debug)]
+pub struct Handle {
+    sent_at: Instant,
+    decay_ns: f64,
+    rtt_estimate: Arc<Mutex<RttEstimate>>,
+}
+
+/// Tracks the readiness of a single balancer's non 0-weighted endpoints.
+#[derive(Debug, Default)]
+struct Pool {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    weighted_ready: usize,
+
+    /// Wakes 0-weighted endpoints that are waiting for all other endpoints to
+    /// become unavailable.
+    ///
+    /// Each endpoint is registered at most once, and dropped endpoints are
+    /// pruned as new endpoints register.
+    zero_weight_waiters: Vec<Weak<AtomicWaker>>,
+}
+
+/// Tracks an endpoint's slow-start window.
+#[derive(Debug)]
+struct SlowStart {
+    started_at: Instant,
+    window: Duration,
+    warming: Arc<Warming>,
+}
+
+#[derive(Debug)]
+struct RttEstimate {
+    update_at: Instant,
+    rtt_ns: f64,
+}
+
+const NANOS_PER_MILLI: f64 = 1_000_000.0;
+
+/// The share of its weight that an endpoint receives at the start of its
+/// slow-start window.
+const SLOW_START_MIN_RATIO: f64 = 0.1;
+
+// === impl PeakEwmaDiscover ===
+
+impl<D, C, L: Hash + Eq> PeakEwmaDiscover<D, C, L> {
+    pub(super) fn new(
+        discover: D,
+        default_rtt: Duration,
+        decay: Duration,
+        slow_start: Duration,
+        completion: C,
+        warming: Arc<Warming>,
+        registry: Registry<L>,
+    ) -> Self {
+        Self {
+            discover,
+            decay_ns: nanos(decay),
+            default_rtt,
+            slow_start,
+            completion,
+            pool: Arc::new(Pool::default()),
+            warming,
+            registry,
+        }
+    }
+}
+
+impl<D, C, L> Stream for PeakEwmaDiscover<D, C, L>
+where
+    D: discover::Discover,
+    D::Service: HasWeight,
+    C: Clone,
+    L: Hash + Eq,
+{
+    type Item = Result<Change<D::Key, PeakEwma<D::Service, C>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)) {
+            Some(Ok(Change::Insert(key, service))) => {
+                let weight = service.weight().clone();
+                let slow_start = if *this.slow_start > Duration::from_secs(0) {
+                    Some(SlowStart::new(*this.slow_start, this.warming.clone()))
+                } else {
+                    None
+                };
+                let endpoint = PeakEwma {
+                    service,
+                    weight,
+                    decay_ns: *this.decay_ns,
+                    rtt_estimate: Arc::new(Mutex::new(RttEstimate::new(nanos(*this.default_rtt)))),
+                    completion: this.completion.clone(),
+                    pool: this.pool.clone(),
+                    zero_weight_waiter: Arc::new(AtomicWaker::new()),
+                    ready_weighted: false,
+                    slow_start,
+                };
+                Change::Insert(key, endpoint)
+            }
+            Some(Ok(Change::Remove(key))) => Change::Remove(key),
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+#[pinned_drop]
+impl<D, C, L: Hash + Eq> PinnedDrop for PeakEwmaDiscover<D, C, L> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        this.registry.remove(this.warming);
+    }
+}
+
+// === impl PeakEwma ===
+
+impl<S, C> PeakEwma<S, C> {
+    fn handle(&self) -> Handle {
+        Handle {
+            decay_ns: self.decay_ns,
+            sent_at: Instant::now(),
+            rtt_estimate: self.rtt_estimate.clone(),
+        }
+    }
+
+    fn set_ready_weighted(&mut self, ready: bool) {
+        if ready != self.ready_weighted {
+            self.ready_weighted = ready;
+            self.pool.update(|s| {
+                if ready {
+                    s.weighted_ready += 1;
+                } else {
+                    s.weighted_ready -= 1;
+                }
+            });
+        }
+    }
+}
+
+impl<S, C> HasWeight for PeakEwma<S, C> {
+    fn weight(&self) -> &Weight {
+        &self.weight
+    }
+}
+
+impl<S, C, Req> tower::Service<Req> for PeakEwma<S, C>
+where
+    S: tower::Service<Req>,
+    C: TrackCompletion<Handle, S::Response> + Clone,
+{
+    type Response = C::Output;
+    type Error = S::Error;
+    type Future = TrackCompletionFuture<S::Future, C, Handle>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Re-poll the endpoint if its weight changes while it is withheld.
+        self.weight.register(cx.waker());
+        if self.weight.get() == 0 {
+            self.set_ready_weighted(false);
+            if !self.pool.poll_zero_weight(cx, &self.zero_weight_waiter) {
+                trace!("Withholding 0-weighted endpoint");
+                return Poll::Pending;
+            }
+            return self.service.poll_ready(cx);
+        }
+
+        let poll = self.service.poll_ready(cx);
+        let ready = match poll {
+            Poll::Ready(Ok(())) => true,
+            _ => false,
+        };
+        self.set_ready_weighted(ready);
+        poll
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        TrackCompletionFuture::new(
+            self.completion.clone(),
+            self.handle(),
+            self.service.call(req),
+        )
+    }
+}
+
+impl<S, C> Load for PeakEwma<S, C> {
+    type Metric = Cost;
+
+    fn load(&self) -> Self::Metric {
+        // Each in-flight request holds a clone of the estimate.
+        let pending = Arc::strong_count(&self.rtt_estimate) - 1;
+        let estimate = match self.rtt_estimate.lock() {
+            Ok(mut rtt) => rtt.decay(self.decay_ns),
+            Err(_) => return Cost::new(f64::INFINITY, pending, self.weight.get()),
+        };
+        let weight = match self.slow_start.as_ref() {
+            Some(slow_start) => slow_start.scale(self.weight.get()),
+            None => self.weight.get(),
+        };
+        let cost = Cost::new(estimate, pending, weight);
+        trace!(%weight, ?cost, "Load");
+        cost
+    }
+}
+
+impl<S, C> Drop for PeakEwma<S, C> {
+    fn drop(&mut self) {
+        self.set_ready_weighted(false);
+    }
+}
+
+// === impl Cost ===
+
+impl Cost {
+    fn new(rtt_ns: f64, pending: usize, weight: u32) -> Self {
+        let cost = rtt_ns * (pending + 1) as f64;
+        if weight == 0 {
+            return Self {
+                zero_weight: true,
+                cost,
+            };
+        }
+
+        Self {
+            zero_weight: false,
+            cost: cost * f64::from(Weight::DEFAULT) / f64::from(weight),
+        }
+    }
+}
+
+// === impl SlowStart ===
+
+impl SlowStart {
+    fn new(window: Duration, warming: Arc<Warming>) -> Self {
+        let started_at = Instant::now();
+        warming.insert(started_at + window);
+        Self {
+            started_at,
+            window,
+            warming,
+        }
+    }
+
+    /// Reduces a weight according to how far the endpoint is through its
+    /// slow-start window.
+    fn scale(&self, weight: u32) -> u32 {
+        let elapsed = Instant::now().saturating_duration_since(self.started_at);
+        if elapsed >= self.window || weight == 0 {
+            return weight;
+        }
+
+        let ratio = (nanos(elapsed) / nanos(self.window)).max(SLOW_START_MIN_RATIO);
+        ((f64::from(weight) * ratio) as u32).max(1)
+    }
+}
+
+impl Drop for SlowStart {
+    fn drop(&mut self) {
+        self.warming.remove(self.started_at + self.window);
+    }
+}
+
+// === impl Handle ===
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let recv_at = Instant::now();
+        if let Ok(mut rtt) = self.rtt_estimate.lock() {
+            rtt.update(self.sent_at, recv_at, self.decay_ns);
+        }
+    }
+}
+
+// === impl Pool ===
+
+impl Pool {
+    /// Returns true if 0-weighted endpoints may be used. Otherwise, the task
+    /// is notified when no other endpoints are ready.
+    fn poll_zero_weight(&self, cx: &mut Context<'_>, waiter: &Arc<AtomicWaker>) -> bool {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return true,
+        };
+        if state.weighted_ready == 0 {
+            return true;
+        }
+
+        waiter.register(cx.waker());
+        let waiter = Arc::downgrade(waiter);
+        if !state
+            .zero_weight_waiters
+            .iter()
+            .any(|w| Weak::ptr_eq(w, &waiter))
+        {
+            state.zero_weight_waiters.retain(|w| w.strong_count() > 0);
+            state.zero_weight_waiters.push(waiter);
+        }
+        false
+    }
+
+    /// Updates the pool's state, waking 0-weighted endpoints if no other
+    /// endpoints remain ready.
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+
+        f(&mut *state);
+        if state.weighted_ready == 0 && !state.zero_weight_waiters.is_empty() {
+            debug!("No weighted endpoints are ready; using 0-weighted endpoints");
+            for waiter in state.zero_weight_waiters.drain(..) {
+                if let Some(waiter) = waiter.upgrade() {
+                    waiter.wake();
+                }
+            }
+        }
+    }
+}
+
+// === impl RttEstimate ===
+
+impl RttEstimate {
+    fn new(rtt_ns: f64) -> Self {
+        debug_assert!(0.0 < rtt_ns, "rtt must be positive");
+        Self {
+            rtt_ns,
+            update_at: Instant::now(),
+        }
+    }
+
+    /// Decays the RTT estimate with a decay period of `decay_ns`.
+    fn decay(&mut self, decay_ns: f64) -> f64 {
+        // Updates with a 0 duration so that the estimate decays towards 0.
+        let now = Instant::now();
+        self.update(now, now, decay_ns)
+    }
+
+    /// Updates the RTT estimate, favoring the peak observed RTT.
+    fn update(&mut self, sent_at: Instant, recv_at: Instant, decay_ns: f64) -> f64 {
+        debug_assert!(
+            sent_at <= recv_at,
+            "recv_at={:?} after sent_at={:?}",
+            recv_at,
+            sent_at
+        );
+        let rtt = nanos(recv_at - sent_at);
+
+        // If we've received a response more slowly than the current estimate,
+        // the estimate jumps to the new RTT. Otherwise, the estimate decays
+        // towards the observed RTT.
+        if self.rtt_ns < rtt {
+            trace!(
+                rtt = rtt / NANOS_PER_MILLI,
+                prior = self.rtt_ns / NANOS_PER_MILLI,
+                "Peak"
+            );
+            self.rtt_ns = rtt;
+        } else {
+            let elapsed = nanos(recv_at.saturating_duration_since(self.update_at));
+            let decay = (-elapsed / decay_ns).exp();
+            let recency = 1.0 - decay;
+            self.rtt_ns = (self.rtt_ns * decay) + (rtt * recency);
+        }
+
+        self.update_at = recv_at;
+        self.rtt_ns
+    }
+}
+
+fn nanos(d: Duration) -> f64 {
+    const NANOS_PER_SEC: u64 = 1_000_000_000;
+    let n = f64::from(d.subsec_nanos());
+    let s = d.as_secs().saturating_mul(NANOS_PER_SEC) as f64;
+    n + s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Default)]
+    struct Woken(AtomicBool);
+
+    impl futures::task::ArcWake for Woken {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn cost_is_scaled_by_weight() {
+        let rtt = 10.0 * NANOS_PER_MILLI;
+        let default = Cost::new(rtt, 0, Weight::DEFAULT);
+        let double = Cost::new(rtt, 0, 2 * Weight::DEFAULT);
+        let half = Cost::new(rtt, 0, Weight::DEFAULT / 2);
+        assert!(double < default);
+        assert!(default < half);
+
+        // An endpoint with twice the weight costs the same with twice the
+        // requests in flight.
+        assert_eq!(Cost::new(rtt, 1, 2 * Weight::DEFAULT), default);
+    }
+
+    #[test]
+    fn zero_weight_is_never_preferred() {
+        let fast = Cost::new(NANOS_PER_MILLI, 0, 0);
+        let slow = Cost::new(1_000.0 * NANOS_PER_MILLI, 10, 1);
+        assert!(slow < fast);
+        assert!(fast < Cost::new(NANOS_PER_MILLI, 1, 0));
+    }
+
+    #[test]
+    fn slow_start_ramps_weight() {
+        let window = Duration::from_secs(60);
+        let warming = Arc::new(Warming::default());
+        let slow_start = SlowStart::new(window, warming.clone());
+        assert_eq!(slow_start.scale(Weight::DEFAULT), 1_000);
+        assert_eq!(slow_start.scale(0), 0);
+        assert_eq!(slow_start.scale(1), 1);
+
+        let halfway = SlowStart {
+            started_at: Instant::now() - window / 2,
+            window,
+            warming: warming.clone(),
+        };
+        let weight = halfway.scale(Weight::DEFAULT);
+        assert!(5_000 <= weight && weight < 5_100, "weight={}", weight);
+
+        let warm = SlowStart {
+            started_at: Instant::now() - window,
+            window,
+            warming,
+        };
+        assert_eq!(warm.scale(Weight::DEFAULT), Weight::DEFAULT);
+    }
+
+    #[test]
+    fn zero_weight_waits_for_weighted_endpoints() {
+        let pool = Pool::default();
+        let waiter = Arc::new(AtomicWaker::new());
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(pool.poll_zero_weight(&mut cx, &waiter));
+
+        pool.update(|s| s.weighted_ready += 1);
+        assert!(!pool.poll_zero_weight(&mut cx, &waiter));
+
+        pool.update(|s| s.weighted_ready -= 1);
+        assert!(pool.poll_zero_weight(&mut cx, &waiter));
+        assert!(pool.state.lock().unwrap().zero_weight_waiters.is_empty());
+    }
+
+    #[test]
+    fn zero_weight_waiters_are_bounded() {
+        let pool = Pool::default();
+        pool.update(|s| s.weighted_ready += 1);
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // Repeated polls of the same endpoint register it once.
+        let waiter = Arc::new(AtomicWaker::new());
+        for _ in 0..10 {
+            assert!(!pool.poll_zero_weight(&mut cx, &waiter));
+        }
+        assert_eq!(pool.state.lock().unwrap().zero_weight_waiters.len(), 1);
+
+        // Dropped endpoints are pruned as other endpoints register.
+        for _ in 0..10 {
+            let dropped = Arc::new(AtomicWaker::new());
+            assert!(!pool.poll_zero_weight(&mut cx, &dropped));
+        }
+        assert_eq!(pool.state.lock().unwrap().zero_weight_waiters.len(), 2);
+    }
+
+    #[test]
+    fn reweighted_endpoints_are_repolled() {
+        let pool = Arc::new(Pool::default());
+        pool.update(|s| s.weighted_ready += 1);
+        let weight = Weight::new(0);
+        let mut endpoint = PeakEwma {
+            service: tower::service_fn(|()| async { Ok::<_, ()>(()) }),
+            weight: weight.clone(),
+            decay_ns: nanos(Duration::from_secs(10)),
+            rtt_estimate: Arc::new(Mutex::new(RttEstimate::new(NANOS_PER_MILLI))),
+            completion: tower::load::CompleteOnResponse::default(),
+            pool: pool.clone(),
+            zero_weight_waiter: Arc::new(AtomicWaker::new()),
+            ready_weighted: false,
+            slow_start: None,
+        };
+
+        let woken = Arc::new(Woken::default());
+        let waker = futures::task::waker(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let poll = tower::Service::<()>::poll_ready(&mut endpoint, &mut cx);
+        assert!(poll.is_pending());
+
+        weight.set(Weight::DEFAULT);
+        assert!(woken.0.load(std::sync::atomic::Ordering::SeqCst));
+        let poll = tower::Service::<()>::poll_ready(&mut endpoint, &mut cx);
+        assert!(poll.is_ready());
+        assert_eq!(pool.state.lock().unwrap().weighted_ready, 2);
+    }
+}
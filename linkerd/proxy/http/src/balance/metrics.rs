@@ -0,0 +1,206 @@
+use indexmap::IndexMap;
+use linkerd2_metrics::{metrics, FmtLabels, FmtMetrics, Gauge};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+
+metrics! {
+    balancer_warming_endpoints: Gauge {
+        "Number of a balancer's endpoints that are within their slow-start window"
+    }
+}
+
+/// Reports the number of each balancer's endpoints that are warming up.
+///
+/// A destination's balancer may be rebuilt while the balancer it replaces is
+/// still being dropped, so each destination's metrics are shared by all of its
+/// balancers and are reported until the last of them is dropped.
+#[derive(Debug)]
+pub struct Registry<L: Hash + Eq>(Arc<Mutex<IndexMap<Labels<L>, Entry>>>);
+
+#[derive(Debug)]
+struct Entry {
+    balancers: usize,
+    warming: Arc<Warming>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Labels<L> {
+    scope: L,
+    dst: String,
+}
+
+/// Tracks when each of a balancer's warming endpoints finishes its slow-start
+/// window.
+#[derive(Debug, Default)]
+pub(super) struct Warming(Mutex<Vec<Instant>>);
+
+// === impl Registry ===
+
+impl<L: Clone + Hash + Eq> Registry<L> {
+    pub(super) fn warming(&self, scope: &L, dst: &str) -> Arc<Warming> {
+        let labels = Labels {
+            scope: scope.clone(),
+            dst: dst.to_string(),
+        };
+        match self.0.lock() {
+            Ok(mut registry) => {
+                let entry = registry.entry(labels).or_insert_with(|| Entry {
+                    balancers: 0,
+                    warming: Arc::default(),
+                });
+                entry.balancers += 1;
+                entry.warming.clone()
+            }
+            Err(_) => Arc::new(Warming::default()),
+        }
+    }
+}
+
+impl<L: Hash + Eq> Registry<L> {
+    /// Stops reporting a destination's metrics once all of its balancers
+    /// have been dropped.
+    pub(super) fn remove(&self, warming: &Arc<Warming>) {
+        if let Ok(mut registry) = self.0.lock() {
+            registry.retain(|_, entry| {
+                if Arc::ptr_eq(&entry.warming, warming) {
+                    entry.balancers -= 1;
+                }
+                entry.balancers > 0
+            });
+        }
+    }
+}
+
+impl<L: Hash + Eq> Default for Registry<L> {
+    fn default() -> Self {
+        Registry(Default::default())
+    }
+}
+
+impl<L: Hash + Eq> Clone for Registry<L> {
+    fn clone(&self) -> Self {
+        Registry(self.0.clone())
+    }
+}
+
+impl<L: FmtLabels + Hash + Eq> FmtMetrics for Registry<L> {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registry = match self.0.lock() {
+            Ok(registry) => registry,
+            Err(_) => return Ok(()),
+        };
+        if registry.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let gauges = registry
+            .iter()
+            .map(|(labels, entry)| (labels, Gauge::from(entry.warming.count(now))))
+            .collect::<Vec<_>>();
+
+        balancer_warming_endpoints.fmt_help(f)?;
+        balancer_warming_endpoints.fmt_scopes(f, gauges.iter().map(|(l, g)| (*l, g)), |g| g)?;
+
+        Ok(())
+    }
+}
+
+// === impl Labels ===
+
+impl<L: FmtLabels> FmtLabels for Labels<L> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.scope.fmt_labels(f)?;
+        write!(f, ",dst=\"{}\"", self.dst)
+    }
+}
+
+// === impl Warming ===
+
+impl Warming {
+    pub(super) fn insert(&self, warm_at: Instant) {
+        if let Ok(mut warming) = self.0.lock() {
+            warming.push(warm_at);
+        }
+    }
+
+    pub(super) fn remove(&self, warm_at: Instant) {
+        if let Ok(mut warming) = self.0.lock() {
+            if let Some(i) = warming.iter().position(|t| *t == warm_at) {
+                warming.swap_remove(i);
+            }
+        }
+    }
+
+    /// Returns the number of endpoints that are still warming, forgetting
+    /// endpoints that have finished warming.
+    fn count(&self, now: Instant) -> u64 {
+        match self.0.lock() {
+            Ok(mut warming) => {
+                warming.retain(|t| *t > now);
+                warming.len() as u64
+            }
+            Err(_) => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn warming_endpoints(registry: &Registry<&'static str>) -> Vec<(String, u64)> {
+        let now = Instant::now();
+        registry
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(labels, entry)| (labels.dst.clone(), entry.warming.count(now)))
+            .collect()
+    }
+
+    #[test]
+    fn counts_warming_endpoints() {
+        let registry = Registry::default();
+        let warming = registry.warming(&"out", "web:80");
+
+        let now = Instant::now();
+        let warm_at = now + Duration::from_secs(10);
+        warming.insert(warm_at);
+        warming.insert(now + Duration::from_secs(20));
+        warming.insert(now);
+        assert_eq!(warming_endpoints(&registry), vec![("web:80".into(), 2)]);
+
+        warming.remove(warm_at);
+        assert_eq!(warming_endpoints(&registry), vec![("web:80".into(), 1)]);
+
+        registry.remove(&warming);
+        assert!(warming_endpoints(&registry).is_empty());
+    }
+
+    #[test]
+    fn retains_metrics_while_balancers_are_rebuilt() {
+        let registry = Registry::default();
+        let old = registry.warming(&"out", "web:80");
+        let other = registry.warming(&"out", "api:80");
+        let new = registry.warming(&"out", "web:80");
+        assert!(Arc::ptr_eq(&old, &new));
+
+        // Dropping a replaced balancer does not remove the metrics of its
+        // replacement.
+        registry.remove(&old);
+        assert_eq!(
+            warming_endpoints(&registry),
+            vec![("web:80".into(), 0), ("api:80".into(), 0)]
+        );
+
+        registry.remove(&new);
+        assert_eq!(warming_endpoints(&registry), vec![("api:80".into(), 0)]);
+        registry.remove(&other);
+        assert!(warming_endpoints(&registry).is_empty());
+    }
+}
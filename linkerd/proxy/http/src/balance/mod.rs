@@ -0,0 +1,236 @@
+use crate::{canonicalize, Error};
+use futures::{ready, TryFuture};
+use http;
+use hyper::body::HttpBody;
+pub use hyper_balance::{PendingUntilFirstData, PendingUntilFirstDataBody};
+use indexmap::IndexMap;
+use linkerd2_addr::Addr;
+use linkerd2_proxy_core::HasWeight;
+use pin_project::pin_project;
+use rand::{rngs::SmallRng, SeedableRng};
+use std::{
+    fmt,
+    future::Future,
+    hash::Hash,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower::discover::Discover;
+use tower::util::Either;
+pub use tower::{balance::p2c::Balance, load::Load};
+use tracing::debug;
+
+pub mod hash;
+mod load;
+mod metrics;
+mod weight;
+
+pub use self::hash::HashKey;
+pub use self::load::{Cost, PeakEwma, PeakEwmaDiscover};
+pub use self::metrics::Registry;
+pub use self::weight::{MakeWeighted, MakeWeightedFuture, Weighted};
+
+/// A balancer for a single destination: either a power-of-two-choices
+/// balancer or, for destinations configured with a `HashKey`, a
+/// consistent-hash balancer.
+pub type Balancer<D, A, L> = Either<
+    Balance<PeakEwmaDiscover<D, PendingUntilFirstData, L>, http::Request<A>>,
+    hash::Balance<PeakEwmaDiscover<D, PendingUntilFirstData, L>, http::Request<A>>,
+>;
+
+/// Configures a stack to resolve `T` typed targets to balance requests over
+/// `M`-typed endpoint stacks.
+///
+/// Each endpoint's load is scaled by its weight. Endpoints with a weight of 0
+/// are only used while no other endpoints are available.
+///
+/// Requests to destinations that have a configured `HashKey` are balanced by a
+/// consistent hash of that key, so that requests with the same key are sent to
+/// the same endpoint.
+///
+/// When a slow-start window is configured, newly discovered endpoints receive
+/// a reduced share of traffic that ramps up over the window.
+#[derive(Debug)]
+pub struct Layer<A, B, L: Hash + Eq> {
+    decay: Duration,
+    default_rtt: Duration,
+    slow_start: Duration,
+    rng: SmallRng,
+    hash_keys: Arc<IndexMap<Addr, HashKey>>,
+    scope: L,
+    registry: Registry<L>,
+    _marker: PhantomData<fn(A) -> B>,
+}
+
+/// Builds a balancer for each target's discovered endpoints.
+#[derive(Debug)]
+pub struct MakeBalance<M, A, B, L: Hash + Eq> {
+    inner: M,
+    layer: Layer<A, B, L>,
+}
+
+#[pin_project]
+pub struct MakeBalanceFuture<F, A, B, L: Hash + Eq> {
+    #[pin]
+    future: F,
+    dst: String,
+    hash_key: Option<HashKey>,
+    layer: Layer<A, B, L>,
+}
+
+/// Wraps the endpoint services built by an inner `MakeService` so that they
+/// expose their targets' weights to the balancer.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WeightLayer(());
+
+// === impl Layer ===
+
+/// Creates a balancer layer that records metrics in `registry`, labeled with
+/// `scope`.
+pub fn layer<A, B, L: Hash + Eq>(
+    default_rtt: Duration,
+    decay: Duration,
+    slow_start: Duration,
+    hash_keys: IndexMap<Addr, HashKey>,
+    scope: L,
+    registry: Registry<L>,
+) -> Layer<A, B, L> {
+    Layer {
+        decay,
+        default_rtt,
+        slow_start,
+        rng: SmallRng::from_entropy(),
+        hash_keys: Arc::new(hash_keys),
+        scope,
+        registry,
+        _marker: PhantomData,
+    }
+}
+
+impl<A, B, L: Clone + Hash + Eq> Clone for Layer<A, B, L> {
+    fn clone(&self) -> Self {
+        Self {
+            decay: self.decay,
+            default_rtt: self.default_rtt,
+            slow_start: self.slow_start,
+            rng: self.rng.clone(),
+            hash_keys: self.hash_keys.clone(),
+            scope: self.scope.clone(),
+            registry: self.registry.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<M, A, B, L: Clone + Hash + Eq> tower::layer::Layer<M> for Layer<A, B, L> {
+    type Service = MakeBalance<M, A, B, L>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeBalance {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+// === impl MakeBalance ===
+
+impl<M: Clone, A, B, L: Clone + Hash + Eq> Clone for MakeBalance<M, A, B, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<T, M, D, S, A, B, L> tower::Service<T> for MakeBalance<M, A, B, L>
+where
+    T: canonicalize::Target + fmt::Display,
+    M: tower::Service<T, Response = D>,
+    A: HttpBody,
+    B: HttpBody,
+    D: Discover<Service = S>,
+    D::Key: Hash + Clone,
+    S: tower::Service<http::Request<A>, Response = http::Response<B>> + HasWeight,
+    S::Error: Into<Error>,
+    L: Clone + Hash + Eq,
+{
+    type Response = Balancer<D, A, L>;
+    type Error = M::Error;
+    type Future = MakeBalanceFuture<M::Future, A, B, L>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.to_string();
+        let hash_key = self.layer.hash_keys.get(target.addr()).cloned();
+        if let Some(ref key) = hash_key {
+            debug!(%dst, ?key, "Balancing by consistent hash");
+        }
+        MakeBalanceFuture {
+            future: self.inner.call(target),
+            dst,
+            hash_key,
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+// === impl MakeBalanceFuture ===
+
+impl<F, D, S, A, B, L> Future for MakeBalanceFuture<F, A, B, L>
+where
+    F: TryFuture<Ok = D>,
+    A: HttpBody,
+    B: HttpBody,
+    D: Discover<Service = S>,
+    D::Key: Hash + Clone,
+    S: tower::Service<http::Request<A>, Response = http::Response<B>> + HasWeight,
+    S::Error: Into<Error>,
+    L: Clone + Hash + Eq,
+{
+    type Output = Result<Balancer<D, A, L>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let discover = ready!(this.future.try_poll(cx))?;
+
+        let layer = &this.layer;
+        let instrument = PendingUntilFirstData::default();
+        let warming = layer.registry.warming(&layer.scope, this.dst);
+        let loaded = PeakEwmaDiscover::new(
+            discover,
+            layer.default_rtt,
+            layer.decay,
+            layer.slow_start,
+            instrument,
+            warming,
+            layer.registry.clone(),
+        );
+        let balance = match this.hash_key.take() {
+            Some(key) => Either::B(hash::Balance::new(loaded, key, layer.rng.clone())),
+            None => Either::A(Balance::new(loaded, layer.rng.clone())),
+        };
+        Poll::Ready(Ok(balance))
+    }
+}
+
+// === impl WeightLayer ===
+
+pub fn weight_layer() -> WeightLayer {
+    WeightLayer(())
+}
+
+impl<M> tower::layer::Layer<M> for WeightLayer {
+    type Service = MakeWeighted<M>;
+
+    fn layer(&self, inner: M) -> Self::Service {
+        MakeWeighted::new(inner)
+    }
+}
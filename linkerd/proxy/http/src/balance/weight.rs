@@ -0,0 +1,97 @@
+use futures::{ready, TryFuture};
+use linkerd2_proxy_core::{HasWeight, Weight};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Builds endpoint services that hold their targets' weights.
+#[derive(Clone, Debug)]
+pub struct MakeWeighted<M> {
+    inner: M,
+}
+
+#[pin_project]
+pub struct MakeWeightedFuture<F> {
+    #[pin]
+    future: F,
+    weight: Option<Weight>,
+}
+
+/// An endpoint service that knows its weight.
+///
+/// The weight is shared with the endpoint's target, so updates from discovery
+/// are observed without rebuilding the service.
+#[derive(Clone, Debug)]
+pub struct Weighted<S> {
+    inner: S,
+    weight: Weight,
+}
+
+// === impl MakeWeighted ===
+
+impl<M> MakeWeighted<M> {
+    pub(super) fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T, M> tower::Service<T> for MakeWeighted<M>
+where
+    T: HasWeight,
+    M: tower::Service<T>,
+{
+    type Response = Weighted<M::Response>;
+    type Error = M::Error;
+    type Future = MakeWeightedFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let weight = target.weight().clone();
+        MakeWeightedFuture {
+            future: self.inner.call(target),
+            weight: Some(weight),
+        }
+    }
+}
+
+// === impl MakeWeightedFuture ===
+
+impl<F: TryFuture> Future for MakeWeightedFuture<F> {
+    type Output = Result<Weighted<F::Ok>, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.future.try_poll(cx))?;
+        let weight = this.weight.take().expect("polled after ready");
+        Poll::Ready(Ok(Weighted { inner, weight }))
+    }
+}
+
+// === impl Weighted ===
+
+impl<S> HasWeight for Weighted<S> {
+    fn weight(&self) -> &Weight {
+        &self.weight
+    }
+}
+
+impl<S, Req> tower::Service<Req> for Weighted<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
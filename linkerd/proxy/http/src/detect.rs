@@ -4,6 +4,7 @@ use crate::{
     h2::Settings as H2Settings,
     trace, upgrade, Version as HttpVersion,
 };
+use bytes::Bytes;
 use futures::prelude::*;
 use linkerd2_drain as drain;
 use linkerd2_error::Error;
@@ -23,10 +24,26 @@ type Server = hyper::server::conn::Http<trace::Executor>;
 #[derive(Copy, Clone, Debug)]
 pub struct DetectTimeout(());
 
+/// A target's protocol, as known before its connection is read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// The protocol is detected from the connection's first bytes.
+    Detect,
+    Http(HttpVersion),
+    Opaque,
+}
+
+/// Declares the protocol of a target's connections, so that detection can be
+/// skipped.
+pub trait KnownProtocol<T> {
+    fn known_protocol(&self, target: &T) -> Protocol;
+}
+
 #[derive(Clone, Debug)]
-pub struct DetectHttp<F, H> {
+pub struct DetectHttp<F, H, P = ()> {
     tcp: F,
     http: H,
+    known: P,
     timeout: Duration,
     server: Server,
     drain: drain::Watch,
@@ -47,6 +64,7 @@ pub struct AcceptHttp<F, H> {
     timeout: Duration,
     server: hyper::server::conn::Http<trace::Executor>,
     drain: drain::Watch,
+    protocol: Protocol,
 }
 
 // === impl DetectHttp ===
@@ -64,14 +82,30 @@ impl<F, H> DetectHttp<F, H> {
             server,
             tcp,
             http,
+            known: (),
             drain,
         }
     }
 }
 
-impl<T, F, S> Service<T> for DetectHttp<F, S>
+impl<F, H, P> DetectHttp<F, H, P> {
+    /// Skips detection for targets whose protocol is known by `known`.
+    pub fn with_known_protocols<Q>(self, known: Q) -> DetectHttp<F, H, Q> {
+        DetectHttp {
+            timeout: self.timeout,
+            server: self.server,
+            tcp: self.tcp,
+            http: self.http,
+            known,
+            drain: self.drain,
+        }
+    }
+}
+
+impl<T, F, S, P> Service<T> for DetectHttp<F, S, P>
 where
     T: Clone + Send + 'static,
+    P: KnownProtocol<T>,
     F: tower::Service<T> + Clone + Send + 'static,
     F::Error: Into<Error>,
     F::Response: Send + 'static,
@@ -96,6 +130,7 @@ where
         let http = self.http.clone();
         let server = self.server.clone();
         let timeout = self.timeout;
+        let protocol = self.known.known_protocol(&target);
 
         Box::pin(async move {
             let (tcp, http) = futures::try_join!(
@@ -103,7 +138,7 @@ where
                 http.oneshot(target).map_err(Into::<Error>::into)
             )?;
 
-            Ok(AcceptHttp::new(server, timeout, http, tcp, drain))
+            Ok(AcceptHttp::new(server, timeout, http, tcp, drain).with_protocol(protocol))
         })
     }
 }
@@ -118,8 +153,14 @@ impl<F, H> AcceptHttp<F, H> {
             tcp,
             http,
             drain,
+            protocol: Protocol::Detect,
         }
     }
+
+    /// Skips detection when the connection's protocol is known.
+    pub fn with_protocol(self, protocol: Protocol) -> Self {
+        Self { protocol, ..self }
+    }
 }
 
 impl<I, F, S> Service<I> for AcceptHttp<F, S>
@@ -149,14 +190,19 @@ where
         let tcp = self.tcp.clone();
         let http = self.http.clone();
         let mut server = self.server.clone();
+        let protocol = self.protocol;
 
         let timeout = tokio::time::delay_for(self.timeout);
         Box::pin(async move {
-            let (version, io) = tokio::select! {
-                res = HttpVersion::detect(io) => { res? }
-                () = timeout => {
-                    return Err(DetectTimeout(()).into());
-                }
+            let (version, io) = match protocol {
+                Protocol::Http(version) => (Some(version), PrefixedIo::new(Bytes::new(), io)),
+                Protocol::Opaque => (None, PrefixedIo::new(Bytes::new(), io)),
+                Protocol::Detect => tokio::select! {
+                    res = HttpVersion::detect(io) => { res? }
+                    () = timeout => {
+                        return Err(DetectTimeout(()).into());
+                    }
+                },
             };
 
             match version {
@@ -200,6 +246,14 @@ where
     }
 }
 
+// === impl KnownProtocol ===
+
+impl<T> KnownProtocol<T> for () {
+    fn known_protocol(&self, _: &T) -> Protocol {
+        Protocol::Detect
+    }
+}
+
 impl std::fmt::Display for DetectTimeout {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "HTTP detection timeout")
@@ -21,6 +21,7 @@ pub mod settings;
 pub mod strip_header;
 pub mod timeout;
 pub mod trace;
+pub mod tunnel;
 pub mod upgrade;
 mod version;
 
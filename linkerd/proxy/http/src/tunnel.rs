@@ -0,0 +1,142 @@
+//! Carries opaque byte streams over HTTP/2 `CONNECT` streams.
+//!
+//! The client sends a `CONNECT` request whose body carries the bytes it
+//! writes; the server responds with a body carrying the bytes it writes. Both
+//! peers expose their halves of the stream as a single transport so that it
+//! can be proxied like any other connection.
+
+use bytes::{Buf, Bytes};
+use futures::ready;
+use http::uri::Authority;
+use hyper::body::{HttpBody, Sender};
+use linkerd2_error::Error;
+use linkerd2_http_box::Payload;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A transport that reads from an HTTP body and writes to another.
+pub struct TunnelIo<B: HttpBody> {
+    rx: B,
+    buf: Option<B::Data>,
+    tx: Option<Sender>,
+}
+
+/// Indicates whether the request opens a tunnel.
+pub fn is_tunnel<B>(req: &http::Request<B>) -> bool {
+    req.method() == http::Method::CONNECT && req.version() == http::Version::HTTP_2
+}
+
+/// Builds a request that opens a tunnel to `authority`, returning the sender
+/// for the request's body.
+///
+/// The tunnel's transport is built from the sender and the response's body.
+pub fn request(authority: Authority) -> (http::Request<Payload>, Sender) {
+    let (tx, body) = hyper::Body::channel();
+    let mut req = http::Request::new(Payload::new(body));
+    *req.method_mut() = http::Method::CONNECT;
+    *req.version_mut() = http::Version::HTTP_2;
+    *req.uri_mut() = http::Uri::builder()
+        .authority(authority)
+        .build()
+        .expect("authority must be a valid URI");
+    (req, tx)
+}
+
+/// Accepts a tunnel, returning the response to send to the client and the
+/// tunnel's transport.
+pub fn accept<B: HttpBody>(req: http::Request<B>) -> (http::Response<Payload>, TunnelIo<B>) {
+    let (tx, body) = hyper::Body::channel();
+    let rsp = http::Response::new(Payload::new(body));
+    (rsp, TunnelIo::new(req.into_body(), tx))
+}
+
+// === impl TunnelIo ===
+
+impl<B: HttpBody> TunnelIo<B> {
+    /// Reads from `rx` and writes to `tx`.
+    ///
+    /// Shutting down the transport ends the `tx` body.
+    pub fn new(rx: B, tx: Sender) -> Self {
+        Self {
+            rx,
+            buf: None,
+            tx: Some(tx),
+        }
+    }
+}
+
+impl<B> std::fmt::Debug for TunnelIo<B>
+where
+    B: HttpBody,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TunnelIo")
+            .field("closed", &self.tx.is_none())
+            .finish()
+    }
+}
+
+impl<B> AsyncRead for TunnelIo<B>
+where
+    B: HttpBody + Unpin,
+    B::Data: Unpin,
+    B::Error: Into<Error>,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if let Some(data) = self.buf.as_mut() {
+                if data.has_remaining() {
+                    let n = data.remaining().min(buf.len());
+                    data.copy_to_slice(&mut buf[..n]);
+                    return Poll::Ready(Ok(n));
+                }
+            }
+
+            self.buf = match ready!(Pin::new(&mut self.rx).poll_data(cx)) {
+                Some(Ok(data)) => Some(data),
+                Some(Err(e)) => {
+                    return Poll::Ready(Err(io::Error::new(io::ErrorKind::Other, e.into())))
+                }
+                None => return Poll::Ready(Ok(0)),
+            };
+        }
+    }
+}
+
+impl<B> AsyncWrite for TunnelIo<B>
+where
+    B: HttpBody + Unpin,
+    B::Data: Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let tx = match self.tx.as_mut() {
+            Some(tx) => tx,
+            None => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        };
+
+        ready!(tx.poll_ready(cx)).map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))?;
+        tx.try_send_data(Bytes::copy_from_slice(buf))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Dropping the sender ends the stream.
+        self.tx = None;
+        Poll::Ready(Ok(()))
+    }
+}
@@ -142,10 +142,14 @@ impl Config {
 
 impl Local {
     pub fn new(config: &Config) -> (Self, CrtKeySender) {
+        Self::from_parts(config.local_name.clone(), config.trust_anchors.clone())
+    }
+
+    pub(crate) fn from_parts(name: Name, trust_anchors: TrustAnchors) -> (Self, CrtKeySender) {
         let (s, w) = watch::channel(None);
         let l = Local {
-            name: config.local_name.clone(),
-            trust_anchors: config.trust_anchors.clone(),
+            name,
+            trust_anchors,
             crt_key: w,
         };
         (l, s)
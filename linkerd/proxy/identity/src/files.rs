@@ -0,0 +1,160 @@
+use crate::{certify::CrtKeySender, Crt, CrtKey, Key, Local, Name, TrustAnchors};
+use linkerd2_error::Error;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::{fs, time};
+use tracing::{debug, error, trace};
+
+/// Configures a local identity whose certificate and key are read from
+/// files, rather than issued by the Identity service.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub trust_anchors: TrustAnchors,
+    pub local_name: Name,
+    /// A PEM-encoded certificate chain, starting with the leaf certificate.
+    pub crt_path: PathBuf,
+    /// A PEM-encoded PKCS#8 private key.
+    pub key_path: PathBuf,
+    /// How often the files are checked for changes.
+    pub poll_interval: Duration,
+}
+
+/// Identifies a version of the certificate and key files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Version {
+    crt: (SystemTime, u64),
+    key: (SystemTime, u64),
+}
+
+/// Loads the certificate and key whenever the files change.
+///
+/// A certificate is only published once it has been validated against the
+/// trust anchors, so an invalid update leaves the current certificate in
+/// place.
+pub async fn daemon(config: Config, crt_key_watch: CrtKeySender) {
+    let mut interval = time::interval(config.poll_interval);
+    let mut curr = None;
+
+    loop {
+        interval.tick().await;
+
+        let version = match config.version().await {
+            Ok(version) => version,
+            Err(e) => {
+                error!("Failed to read identity files: {}", e);
+                continue;
+            }
+        };
+        if curr.as_ref() == Some(&version) {
+            trace!("identity files unchanged");
+            continue;
+        }
+        curr = Some(version);
+
+        match config.load().await {
+            Err(e) => error!("Failed to load identity: {}", e),
+            Ok(crt_key) => {
+                debug!("loaded certificate");
+                if crt_key_watch.broadcast(Some(crt_key)).is_err() {
+                    // If we can't store a value, than all observations
+                    // have been dropped and we can stop watching.
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// === impl Config ===
+
+impl Config {
+    pub fn local(&self) -> (Local, CrtKeySender) {
+        Local::from_parts(self.local_name.clone(), self.trust_anchors.clone())
+    }
+
+    async fn version(&self) -> Result<Version, Error> {
+        async fn stat(p: &PathBuf) -> Result<(SystemTime, u64), Error> {
+            let meta = fs::metadata(p).await?;
+            Ok((meta.modified()?, meta.len()))
+        }
+        Ok(Version {
+            crt: stat(&self.crt_path).await?,
+            key: stat(&self.key_path).await?,
+        })
+    }
+
+    async fn load(&self) -> Result<CrtKey, Error> {
+        let key = Key::from_pem(&fs::read(&self.key_path).await?)
+            .ok_or_else(|| format!("no PKCS#8 key found in {}", self.key_path.display()))?;
+        let crt = Crt::from_pem(self.local_name.clone(), &fs::read(&self.crt_path).await?)
+            .ok_or_else(|| format!("no certificate found in {}", self.crt_path.display()))?;
+        let crt_key = self.trust_anchors.certify(key, crt)?;
+        Ok(crt_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use linkerd2_identity::test_util::FOO_NS1;
+    use std::path::Path;
+    use tokio::sync::watch;
+
+    const CA1_CRT: &[u8] = include_bytes!("../../../identity/src/testdata/foo-ns1-ca1/crt.pem");
+    const CA1_KEY: &[u8] = include_bytes!("../../../identity/src/testdata/foo-ns1-ca1/key.pem");
+    const CA2_CRT: &[u8] = include_bytes!("../../../identity/src/testdata/foo-ns1-ca2/crt.pem");
+    const CA2_KEY: &[u8] = include_bytes!("../../../identity/src/testdata/foo-ns1-ca2/key.pem");
+
+    /// Writes the files with `padding` trailing newlines, so that each update
+    /// changes the files' lengths.
+    fn write(dir: &Path, crt: &[u8], key: &[u8], padding: usize) {
+        let pad = |pem: &[u8]| [pem, &vec![b'\n'; padding]].concat();
+        std::fs::write(dir.join("crt.pem"), pad(crt)).unwrap();
+        std::fs::write(dir.join("key.pem"), pad(key)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn publishes_valid_certificates() {
+        let dir =
+            std::env::temp_dir().join(format!("linkerd2-identity-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        write(&dir, CA1_CRT, CA1_KEY, 0);
+
+        let config = Config {
+            trust_anchors: FOO_NS1.trust_anchors(),
+            local_name: Name::from_hostname(FOO_NS1.name.as_bytes()).unwrap(),
+            crt_path: dir.join("crt.pem"),
+            key_path: dir.join("key.pem"),
+            poll_interval: Duration::from_millis(10),
+        };
+        let (tx, mut rx) = watch::channel(None);
+        tokio::spawn(daemon(config.clone(), tx));
+        assert!(rx.recv().await.unwrap().is_none());
+
+        // The initial certificate is published.
+        let crt_key = rx
+            .recv()
+            .await
+            .unwrap()
+            .expect("certificate must be loaded");
+        assert_eq!(crt_key.tls_server_name(), config.local_name);
+
+        // Certificates that are not issued by the trust anchors are not
+        // published.
+        write(&dir, CA2_CRT, CA2_KEY, 1);
+        time::delay_for(Duration::from_millis(100)).await;
+        assert!(rx.recv().now_or_never().is_none());
+
+        // Updated certificates are published.
+        write(&dir, CA1_CRT, CA1_KEY, 2);
+        let crt_key = rx
+            .recv()
+            .await
+            .unwrap()
+            .expect("certificate must be reloaded");
+        assert_eq!(crt_key.tls_server_name(), config.local_name);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
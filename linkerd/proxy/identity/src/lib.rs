@@ -1,6 +1,7 @@
 #![deny(warnings, rust_2018_idioms)]
 
 pub mod certify;
+pub mod files;
 
 pub use self::certify::{AwaitCrt, CrtKeySender, Local};
 pub use linkerd2_identity::{Crt, CrtKey, Csr, InvalidName, Key, Name, TokenSource, TrustAnchors};
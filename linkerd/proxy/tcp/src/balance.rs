@@ -0,0 +1,267 @@
+use futures::{future, TryFutureExt};
+use linkerd2_error::Error;
+use pin_project::pin_project;
+use rand::{rngs::SmallRng, SeedableRng};
+use std::hash::Hash;
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower::discover::Discover;
+pub use tower::{
+    balance::p2c::Balance,
+    load::{Load, PendingRequestsDiscover, TrackCompletion},
+};
+
+/// Configures a stack to balance new connections over a discovered set of
+/// endpoints, preferring endpoints with the fewest open connections.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    rng: SmallRng,
+}
+
+/// Builds a connection-establishing service for each discovered endpoint.
+#[derive(Clone, Debug)]
+pub struct MakeEndpoint<C> {
+    connect: C,
+}
+
+/// Establishes connections to a single endpoint.
+#[derive(Clone, Debug)]
+pub struct Endpoint<C, T> {
+    connect: C,
+    target: T,
+}
+
+/// Instruments connections so that an endpoint's load handle is held until the
+/// connection is dropped.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PendingUntilClosed(());
+
+/// A transport that holds a load handle for its lifetime.
+#[pin_project]
+#[derive(Debug)]
+pub struct PendingUntilClosedIo<H, I> {
+    #[pin]
+    io: I,
+    _handle: H,
+}
+
+// === impl Layer ===
+
+pub fn layer() -> Layer {
+    Layer {
+        rng: SmallRng::from_entropy(),
+    }
+}
+
+impl<D, S> tower::layer::Layer<D> for Layer
+where
+    D: Discover<Service = S>,
+    D::Key: Hash,
+    S: tower::Service<()>,
+    S::Error: Into<Error>,
+    Balance<PendingRequestsDiscover<D, PendingUntilClosed>, ()>: tower::Service<()>,
+{
+    type Service = Balance<PendingRequestsDiscover<D, PendingUntilClosed>, ()>;
+
+    fn layer(&self, discover: D) -> Self::Service {
+        let loaded = PendingRequestsDiscover::new(discover, PendingUntilClosed::default());
+        Balance::new(loaded, self.rng.clone())
+    }
+}
+
+// === impl MakeEndpoint ===
+
+impl<C> MakeEndpoint<C> {
+    pub fn new(connect: C) -> Self {
+        Self { connect }
+    }
+}
+
+impl<C: Clone, T> tower::Service<T> for MakeEndpoint<C> {
+    type Response = Endpoint<C, T>;
+    type Error = Error;
+    type Future = future::Ready<Result<Self::Response, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        future::ok(Endpoint {
+            connect: self.connect.clone(),
+            target,
+        })
+    }
+}
+
+// === impl Endpoint ===
+
+impl<C, T> tower::Service<()> for Endpoint<C, T>
+where
+    T: Clone,
+    C: tower::Service<T>,
+    C::Error: Into<Error>,
+{
+    type Response = C::Response;
+    type Error = Error;
+    type Future = future::ErrInto<C::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.connect.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, (): ()) -> Self::Future {
+        self.connect.call(self.target.clone()).err_into::<Error>()
+    }
+}
+
+// === impl PendingUntilClosed ===
+
+impl<H, I> TrackCompletion<H, I> for PendingUntilClosed
+where
+    I: AsyncRead + AsyncWrite,
+{
+    type Output = PendingUntilClosedIo<H, I>;
+
+    fn track_completion(&self, handle: H, io: I) -> Self::Output {
+        PendingUntilClosedIo {
+            io,
+            _handle: handle,
+        }
+    }
+}
+
+// === impl PendingUntilClosedIo ===
+
+impl<H, I: AsyncRead> AsyncRead for PendingUntilClosedIo<H, I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().io.poll_read(cx, buf)
+    }
+
+    fn poll_read_buf<B: bytes::BufMut>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().io.poll_read_buf(cx, buf)
+    }
+
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [MaybeUninit<u8>]) -> bool {
+        self.io.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<H, I: AsyncWrite> AsyncWrite for PendingUntilClosedIo<H, I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().io.poll_write(cx, buf)
+    }
+
+    fn poll_write_buf<B: bytes::Buf>(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut B,
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().io.poll_write_buf(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{channel::mpsc, executor::block_on};
+    use std::io::Cursor;
+    use tower::discover::Change;
+    use tower::layer::Layer as _;
+    use tower::Service;
+
+    /// Connects to an endpoint, returning a transport that identifies it.
+    #[derive(Clone, Debug)]
+    struct Connect;
+
+    impl tower::Service<u8> for Connect {
+        type Response = Cursor<Vec<u8>>;
+        type Error = Error;
+        type Future = future::Ready<Result<Cursor<Vec<u8>>, Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, target: u8) -> Self::Future {
+            future::ok(Cursor::new(vec![target]))
+        }
+    }
+
+    type Update = Result<Change<u8, Endpoint<Connect, u8>>, Error>;
+
+    fn insert(tx: &mpsc::UnboundedSender<Update>, target: u8) {
+        let endpoint = block_on(MakeEndpoint::new(Connect).call(target)).unwrap();
+        tx.unbounded_send(Ok(Change::Insert(target, endpoint)))
+            .unwrap();
+    }
+
+    fn connect<S>(balance: &mut S) -> S::Response
+    where
+        S: tower::Service<()>,
+        S::Error: std::fmt::Debug,
+    {
+        block_on(future::poll_fn(|cx| balance.poll_ready(cx))).expect("balancer must be ready");
+        block_on(balance.call(())).expect("connection must be established")
+    }
+
+    #[test]
+    fn connects_to_the_least_loaded_endpoint() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut balance = layer().layer(rx);
+        insert(&tx, 0);
+        insert(&tx, 1);
+
+        let first = connect(&mut balance);
+        let second = connect(&mut balance);
+        assert_ne!(first.io.get_ref(), second.io.get_ref());
+
+        // Once a connection is closed, its endpoint is the least loaded.
+        let closed = first.io.get_ref().clone();
+        drop(first);
+        let third = connect(&mut balance);
+        assert_eq!(third.io.get_ref(), &closed);
+    }
+
+    #[test]
+    fn follows_discovery_updates() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut balance = layer().layer(rx);
+        insert(&tx, 0);
+
+        let first = connect(&mut balance);
+        assert_eq!(first.io.get_ref(), &[0]);
+
+        insert(&tx, 1);
+        let second = connect(&mut balance);
+        assert_eq!(second.io.get_ref(), &[1]);
+
+        tx.unbounded_send(Ok(Change::Remove(1))).unwrap();
+        for _ in 0..3 {
+            let conn = connect(&mut balance);
+            assert_eq!(conn.io.get_ref(), &[0]);
+        }
+    }
+}
@@ -1,17 +1,31 @@
+use linkerd2_io::EitherIo;
 use std::task::{Context, Poll};
-use std::{future::Future, io, net::SocketAddr, pin::Pin, time::Duration};
-use tokio::net::TcpStream;
+use std::{future::Future, io, net::SocketAddr, path::Path, pin::Pin, time::Duration};
+use tokio::net::{TcpStream, UnixStream};
 use tracing::debug;
 
 pub trait ConnectAddr {
     fn connect_addr(&self) -> SocketAddr;
 }
 
+/// Describes targets that may be reached over a Unix domain socket instead of
+/// their TCP address.
+pub trait ConnectPath: ConnectAddr {
+    fn connect_path(&self) -> Option<&Path>;
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Connect {
     keepalive: Option<Duration>,
 }
 
+/// Connects to a target's Unix domain socket if it has one, and to its TCP
+/// address otherwise.
+#[derive(Copy, Clone, Debug)]
+pub struct ConnectUnix {
+    tcp: Connect,
+}
+
 impl Connect {
     pub fn new(keepalive: Option<Duration>) -> Self {
         Connect { keepalive }
@@ -45,3 +59,126 @@ impl<C: ConnectAddr> tower::Service<C> for Connect {
         })
     }
 }
+
+impl ConnectUnix {
+    pub fn new(keepalive: Option<Duration>) -> Self {
+        Self {
+            tcp: Connect::new(keepalive),
+        }
+    }
+}
+
+impl<C: ConnectPath> tower::Service<C> for ConnectUnix {
+    type Response = EitherIo<TcpStream, UnixStream>;
+    type Error = io::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, io::Error>> + Send + Sync + 'static>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, c: C) -> Self::Future {
+        let path = match c.connect_path() {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let tcp = self.tcp.call(c);
+                return Box::pin(async move { Ok(EitherIo::Left(tcp.await?)) });
+            }
+        };
+
+        debug!(peer.path = %path.display(), "Connecting");
+        Box::pin(async move {
+            let io = UnixStream::connect(&path).await?;
+            debug!(peer.path = %path.display(), "Connected");
+            Ok(EitherIo::Right(io))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use tokio::net::{TcpListener, UnixListener};
+    use tower::util::ServiceExt;
+
+    struct Target {
+        addr: SocketAddr,
+        path: Option<PathBuf>,
+    }
+
+    impl ConnectAddr for Target {
+        fn connect_addr(&self) -> SocketAddr {
+            self.addr
+        }
+    }
+
+    impl ConnectPath for Target {
+        fn connect_path(&self) -> Option<&Path> {
+            self.path.as_deref()
+        }
+    }
+
+    fn socket_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "linkerd2-connect-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn connects_to_unix_sockets() {
+        let path = socket_path("unix");
+        let mut listener = UnixListener::bind(&path).unwrap();
+
+        let target = Target {
+            // Nothing listens on this address.
+            addr: SocketAddr::from(([127, 0, 0, 1], 1)),
+            path: Some(path.clone()),
+        };
+        match ConnectUnix::new(None).oneshot(target).await.unwrap() {
+            EitherIo::Right(_) => {}
+            EitherIo::Left(_) => panic!("must connect over the Unix socket"),
+        }
+        listener
+            .accept()
+            .await
+            .expect("connection must be accepted");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn connects_over_tcp_without_a_path() {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let target = Target {
+            addr: listener.local_addr().unwrap(),
+            path: None,
+        };
+        match ConnectUnix::new(None).oneshot(target).await.unwrap() {
+            EitherIo::Left(_) => {}
+            EitherIo::Right(_) => panic!("must connect over TCP"),
+        }
+        listener
+            .accept()
+            .await
+            .expect("connection must be accepted");
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_socket_does_not_exist() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        // The TCP address is not used as a fallback.
+        let target = Target {
+            addr: listener.local_addr().unwrap(),
+            path: Some(socket_path("missing")),
+        };
+        assert!(ConnectUnix::new(None).oneshot(target).await.is_err());
+    }
+}
@@ -10,7 +10,7 @@ pub mod metrics;
 pub mod tls;
 
 pub use self::{
-    connect::Connect,
+    connect::{Connect, ConnectUnix},
     io::BoxedIo,
     listen::{Bind, DefaultOrigDstAddr, NoOrigDstAddr, OrigDstAddr},
 };
@@ -1,7 +1,13 @@
 use async_stream::try_stream;
 use futures::prelude::*;
-use std::{io, net::SocketAddr, time::Duration};
-use tokio::net::TcpStream;
+use std::{
+    io,
+    net::SocketAddr,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::net::{TcpStream, UnixStream};
 use tracing::trace;
 
 /// A mockable source for address info, i.e., for tests.
@@ -18,6 +24,18 @@ pub struct Bind<O: OrigDstAddr = NoOrigDstAddr> {
 
 pub type Connection = (Addrs, TcpStream);
 
+/// Binds a Unix domain socket listener.
+///
+/// Clients connecting over the socket have no network addresses, so accepted
+/// connections are described by unspecified `Addrs` without an original
+/// destination.
+#[derive(Clone, Debug)]
+pub struct BindUnix {
+    path: PathBuf,
+}
+
+pub type UnixConnection = (Addrs, UnixStream);
+
 #[derive(Clone, Debug)]
 pub struct Addrs {
     local: SocketAddr,
@@ -92,6 +110,51 @@ impl<A: OrigDstAddr> Bind<A> {
     }
 }
 
+impl BindUnix {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn bind(&self) -> io::Result<impl Stream<Item = io::Result<UnixConnection>>> {
+        // Remove a socket that was left behind by a previous process, but never
+        // clobber anything else that happens to live at the configured path.
+        match std::fs::symlink_metadata(&self.path) {
+            Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(&self.path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", self.path.display()),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listen = std::os::unix::net::UnixListener::bind(&self.path)?;
+        // Unix socket peers are necessarily local processes. Neither side has
+        // a port, so connections must name their target explicitly.
+        let local = SocketAddr::from(([127, 0, 0, 1], 0));
+
+        let accept = try_stream! {
+            tokio::pin! {
+                // The tokio listener is built lazily so that it is initialized on
+                // the proper runtime.
+                let listen = tokio::net::UnixListener::from_std(listen).expect("listener must be valid");
+            };
+
+            while let (io, _) = listen.accept().await? {
+                trace!("Accepted");
+                yield (Addrs::new(local, local, None), io);
+            }
+        };
+
+        Ok(accept)
+    }
+}
+
 impl Addrs {
     pub fn new(local: SocketAddr, peer: SocketAddr, orig_dst: Option<SocketAddr>) -> Self {
         Self {
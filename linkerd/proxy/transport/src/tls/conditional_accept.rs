@@ -9,6 +9,15 @@ pub enum Match {
     NotMatched,
 }
 
+/// Describes the server name read from the start of a TLS ClientHello.
+#[derive(Debug, Eq, PartialEq)]
+pub enum ServerName {
+    Incomplete,
+    Name(identity::Name),
+    /// The input is not a ClientHello with a valid SNI extension.
+    None,
+}
+
 /// Determintes whether the given `input` looks like the start of a TLS
 /// connection that the proxy should terminate.
 ///
@@ -24,35 +33,37 @@ pub enum Match {
 /// record, which is what all reasonable implementations do. (If they were not
 /// to, they wouldn't interoperate with picky servers.)
 pub fn match_client_hello(input: &[u8], identity: &identity::Name) -> Match {
+    let m = match read_server_name(input) {
+        ServerName::Name(sni) if sni == *identity => Match::Matched,
+        ServerName::Name(_) | ServerName::None => Match::NotMatched,
+        ServerName::Incomplete => Match::Incomplete,
+    };
+    trace!("match_client_hello: {:?}", m);
+    m
+}
+
+/// Reads the SNI from the start of a TLS ClientHello, without terminating
+/// the TLS connection.
+///
+/// Like `match_client_hello`, this assumes that the ClientHello is sent in a
+/// single TLS record.
+pub fn read_server_name(input: &[u8]) -> ServerName {
     let r = untrusted::Input::from(input).read_all(untrusted::EndOfInput, |input| {
         let r = extract_sni(input);
         input.skip_to_end(); // Ignore anything after what we parsed.
         r
     });
     match r {
-        Ok(Some(sni)) => {
-            let m = identity::Name::from_hostname(sni.as_slice_less_safe())
-                .map(|sni| {
-                    if sni == *identity {
-                        Match::Matched
-                    } else {
-                        Match::NotMatched
-                    }
-                })
-                .unwrap_or(Match::NotMatched);
-            trace!(
-                "match_client_hello: parsed correctly up to SNI; matches: {:?}",
-                m
-            );
-            m
-        }
+        Ok(Some(sni)) => identity::Name::from_hostname(sni.as_slice_less_safe())
+            .map(ServerName::Name)
+            .unwrap_or(ServerName::None),
         Ok(None) => {
-            trace!("match_client_hello: failed to parse up to SNI");
-            Match::NotMatched
+            trace!("read_server_name: failed to parse up to SNI");
+            ServerName::None
         }
         Err(untrusted::EndOfInput) => {
-            trace!("match_client_hello: needs more input");
-            Match::Incomplete
+            trace!("read_server_name: needs more input");
+            ServerName::Incomplete
         }
     }
 }
@@ -228,6 +239,21 @@ mod tests {
         check_all_prefixes(Match::NotMatched, "aexample.com", VALID_EXAMPLE_COM);
     }
 
+    #[test]
+    fn reads_server_name() {
+        let name = identity::Name::from_hostname(b"example.com").unwrap();
+        assert_eq!(read_server_name(&[]), ServerName::Incomplete);
+        assert_eq!(
+            read_server_name(&VALID_EXAMPLE_COM[..VALID_EXAMPLE_COM.len() / 2]),
+            ServerName::Incomplete
+        );
+        assert_eq!(read_server_name(VALID_EXAMPLE_COM), ServerName::Name(name));
+        assert_eq!(
+            read_server_name(b"GET /TheProject.html HTTP/1.0\r\n\r\n"),
+            ServerName::None
+        );
+    }
+
     #[test]
     fn mismatch_http_1_0_request() {
         check_all_prefixes(
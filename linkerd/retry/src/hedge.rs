@@ -0,0 +1,338 @@
+use crate::NewPolicy;
+use linkerd2_error::Error;
+use linkerd2_stack::{NewService, Proxy, ProxyService};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{self, Delay, Instant};
+use tower::util::{Oneshot, ServiceExt};
+use tracing::{debug, trace};
+
+/// Determines whether and when a request is hedged.
+pub trait HedgePolicy<Req>: Clone {
+    /// Returns how long to wait for a response before issuing a hedged
+    /// request, or `None` if requests should not be hedged.
+    fn delay(&self) -> Option<Duration>;
+
+    /// Clones a request so that it may be hedged.
+    ///
+    /// The original request may be updated as well, e.g. so that the two
+    /// requests are not served by the same endpoint.
+    fn clone_request(&self, req: &mut Req) -> Option<Req>;
+
+    /// Determines whether a hedged request may be issued once the delay has
+    /// elapsed, e.g. by withdrawing from a budget.
+    fn can_hedge(&self, req: &Req) -> bool;
+
+    /// Records the latency of a request that completed successfully.
+    fn record_latency(&self, latency: Duration);
+
+    /// Records that a hedged request completed before its original request.
+    fn record_hedge_won(&self);
+}
+
+/// A layer that applies per-target hedging policies.
+///
+/// Composes `NewService`s that produce a `Proxy`.
+#[derive(Clone, Debug)]
+pub struct NewHedgeLayer<P> {
+    new_policy: P,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewHedge<P, N> {
+    new_policy: P,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct Hedge<P, S> {
+    policy: Option<P>,
+    inner: S,
+}
+
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<R, P, S, Req>
+where
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    Disabled(#[pin] P::Future),
+    Hedge(#[pin] Hedging<R, P, S, Req>),
+}
+
+/// Races a request against a hedged copy that is issued after a delay.
+///
+/// Whichever response is received first is returned and the other request is
+/// dropped, canceling it. If the hedged request fails, the original request's
+/// response is used.
+#[pin_project]
+pub struct Hedging<R, P, S, Req>
+where
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    policy: R,
+    service: ProxyService<P, S>,
+    #[pin]
+    primary: P::Future,
+    started_at: Instant,
+    pending: Option<(Req, Delay)>,
+    hedge: Option<(Pin<Box<Oneshot<ProxyService<P, S>, Req>>>, Instant)>,
+}
+
+// === impl NewHedgeLayer ===
+
+impl<P> NewHedgeLayer<P> {
+    pub fn new(new_policy: P) -> Self {
+        Self { new_policy }
+    }
+}
+
+impl<P: Clone, N> tower::layer::Layer<N> for NewHedgeLayer<P> {
+    type Service = NewHedge<P, N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        Self::Service {
+            inner,
+            new_policy: self.new_policy.clone(),
+        }
+    }
+}
+
+// === impl NewHedge ===
+
+impl<T, N, P> NewService<T> for NewHedge<P, N>
+where
+    N: NewService<T>,
+    P: NewPolicy<T>,
+{
+    type Service = Hedge<P::Policy, N::Service>;
+
+    fn new_service(&self, target: T) -> Self::Service {
+        // Determine if there is a hedging policy for the given target.
+        let policy = self.new_policy.new_policy(&target);
+
+        let inner = self.inner.new_service(target);
+        Hedge { policy, inner }
+    }
+}
+
+// === impl Hedge ===
+
+impl<R, P, Req, S> Proxy<Req, S> for Hedge<R, P>
+where
+    R: HedgePolicy<Req>,
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = ResponseFuture<R, P, S, Req>;
+
+    fn proxy(&self, svc: &mut S, mut req: Req) -> Self::Future {
+        let policy = match self.policy.as_ref() {
+            Some(policy) => policy,
+            None => return ResponseFuture::Disabled(self.inner.proxy(svc, req)),
+        };
+
+        let pending = policy.delay().and_then(|delay| {
+            let clone = policy.clone_request(&mut req)?;
+            trace!(?delay, "Hedging request");
+            Some((clone, time::delay_for(delay)))
+        });
+
+        ResponseFuture::Hedge(Hedging {
+            policy: policy.clone(),
+            service: self.inner.clone().wrap_service(svc.clone()),
+            primary: self.inner.proxy(svc, req),
+            started_at: Instant::now(),
+            pending,
+            hedge: None,
+        })
+    }
+}
+
+impl<R, P, S, Req> Future for ResponseFuture<R, P, S, Req>
+where
+    R: HedgePolicy<Req>,
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Disabled(f) => f.poll(cx).map_err(Into::into),
+            ResponseFutureProj::Hedge(f) => f.poll(cx),
+        }
+    }
+}
+
+// === impl Hedging ===
+
+impl<R, P, S, Req> Future for Hedging<R, P, S, Req>
+where
+    R: HedgePolicy<Req>,
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        if let Poll::Ready(res) = this.primary.as_mut().poll(cx) {
+            if res.is_ok() {
+                this.policy.record_latency(this.started_at.elapsed());
+            }
+            return Poll::Ready(res.map_err(Into::into));
+        }
+
+        if let Some((_, delay)) = this.pending.as_mut() {
+            if Pin::new(delay).poll(cx).is_ready() {
+                let (req, _) = this.pending.take().expect("pending hedge must be set");
+                if this.policy.can_hedge(&req) {
+                    debug!("Issuing hedged request");
+                    let hedge = this.service.clone().oneshot(req);
+                    *this.hedge = Some((Box::pin(hedge), Instant::now()));
+                } else {
+                    trace!("Hedged request not permitted");
+                }
+            }
+        }
+
+        if let Some((hedge, started_at)) = this.hedge.as_mut() {
+            match hedge.as_mut().poll(cx) {
+                Poll::Ready(Ok(rsp)) => {
+                    debug!("Hedged request completed first");
+                    this.policy.record_latency(started_at.elapsed());
+                    this.policy.record_hedge_won();
+                    return Poll::Ready(Ok(rsp));
+                }
+                Poll::Ready(Err(error)) => {
+                    debug!(%error, "Hedged request failed");
+                    *this.hedge = None;
+                }
+                Poll::Pending => {}
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const PRIMARY: u32 = 1;
+    const HEDGE: u32 = 2;
+
+    #[derive(Clone, Default)]
+    struct TestPolicy {
+        permitted: bool,
+        hedged: Arc<AtomicUsize>,
+        won: Arc<AtomicUsize>,
+    }
+
+    impl HedgePolicy<u32> for TestPolicy {
+        fn delay(&self) -> Option<Duration> {
+            Some(Duration::from_millis(10))
+        }
+
+        fn clone_request(&self, req: &mut u32) -> Option<u32> {
+            *req = PRIMARY;
+            Some(HEDGE)
+        }
+
+        fn can_hedge(&self, _: &u32) -> bool {
+            if self.permitted {
+                self.hedged.fetch_add(1, Ordering::SeqCst);
+            }
+            self.permitted
+        }
+
+        fn record_latency(&self, _: Duration) {}
+
+        fn record_hedge_won(&self) {
+            self.won.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Serves the original request slowly; the hedge either succeeds
+    /// immediately or fails.
+    fn service(
+        hedge_fails: bool,
+    ) -> impl tower::Service<u32, Response = u32, Error = Error> + Clone {
+        tower::service_fn(move |req: u32| async move {
+            if req == PRIMARY {
+                time::delay_for(Duration::from_millis(100)).await;
+            } else if hedge_fails {
+                return Err(Error::from("hedge failed"));
+            }
+            Ok(req)
+        })
+    }
+
+    fn hedge(policy: &TestPolicy) -> Hedge<TestPolicy, ()> {
+        Hedge {
+            policy: Some(policy.clone()),
+            inner: (),
+        }
+    }
+
+    #[tokio::test]
+    async fn uses_the_first_response() {
+        let policy = TestPolicy {
+            permitted: true,
+            ..Default::default()
+        };
+        let rsp = hedge(&policy).proxy(&mut service(false), 0).await.unwrap();
+        assert_eq!(rsp, HEDGE);
+        assert_eq!(policy.hedged.load(Ordering::SeqCst), 1);
+        assert_eq!(policy.won.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn waits_for_the_original_when_the_hedge_fails() {
+        let policy = TestPolicy {
+            permitted: true,
+            ..Default::default()
+        };
+        let rsp = hedge(&policy).proxy(&mut service(true), 0).await.unwrap();
+        assert_eq!(rsp, PRIMARY);
+        assert_eq!(policy.hedged.load(Ordering::SeqCst), 1);
+        assert_eq!(policy.won.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn does_not_hedge_without_permission() {
+        let policy = TestPolicy::default();
+        let rsp = hedge(&policy).proxy(&mut service(false), 0).await.unwrap();
+        assert_eq!(rsp, PRIMARY);
+        assert_eq!(policy.hedged.load(Ordering::SeqCst), 0);
+        assert_eq!(policy.won.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn does_not_hedge_without_a_policy() {
+        let mut svc = service(false);
+        let disabled = Hedge::<TestPolicy, ()> {
+            policy: None,
+            inner: (),
+        };
+        let rsp = disabled.proxy(&mut svc, PRIMARY).await.unwrap();
+        assert_eq!(rsp, PRIMARY);
+    }
+}
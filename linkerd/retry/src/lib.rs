@@ -10,6 +10,10 @@ pub use tower::retry::{budget::Budget, Policy};
 use tower::util::{Oneshot, ServiceExt};
 use tracing::trace;
 
+pub mod hedge;
+
+pub use self::hedge::{HedgePolicy, NewHedgeLayer};
+
 /// A strategy for obtaining per-target retry polices.
 pub trait NewPolicy<T> {
     type Policy;
@@ -3,6 +3,7 @@ use api::destination_client::DestinationClient;
 use futures::{future, prelude::*, ready, select_biased};
 use http;
 use http_body::Body as HttpBody;
+use indexmap::IndexMap;
 use linkerd2_addr::{Addr, NameAddr};
 use linkerd2_dns as dns;
 use linkerd2_error::{Error, Recover};
@@ -33,6 +34,7 @@ pub struct Client<S, R> {
     initial_timeout: Duration,
     context_token: String,
     suffixes: Vec<dns::Suffix>,
+    route_policies: profiles::RoutePolicies,
 }
 
 pub type Receiver = watch::Receiver<profiles::Routes>;
@@ -71,6 +73,7 @@ where
     #[pin]
     state: State<R::Backoff>,
     request: api::GetDestination,
+    route_policies: Arc<IndexMap<String, profiles::RoutePolicy>>,
 }
 
 #[pin_project(project = StateProj)]
@@ -125,6 +128,15 @@ where
             initial_timeout,
             context_token,
             suffixes: suffixes.into_iter().collect(),
+            route_policies: profiles::RoutePolicies::default(),
+        }
+    }
+
+    /// Applies locally configured policies to the routes of each profile.
+    pub fn with_route_policies(self, route_policies: profiles::RoutePolicies) -> Self {
+        Self {
+            route_policies,
+            ..self
         }
     }
 }
@@ -186,6 +198,7 @@ where
         let inner = Inner {
             service,
             request,
+            route_policies: self.route_policies.get(&dst),
             recover: self.recover.clone(),
             state: State::Disconnected { backoff: None },
         };
@@ -288,6 +301,7 @@ where
 {
     fn poll_rx(
         rx: Pin<&mut grpc::Streaming<api::DestinationProfile>>,
+        route_policies: &IndexMap<String, profiles::RoutePolicy>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<profiles::Routes, grpc::Status>>> {
         trace!("poll");
@@ -298,7 +312,7 @@ where
                 let routes = proto
                     .routes
                     .into_iter()
-                    .filter_map(move |orig| convert_route(orig, retry_budget.as_ref()))
+                    .filter_map(|orig| convert_route(orig, retry_budget.as_ref(), route_policies))
                     .collect();
                 let dst_overrides = proto
                     .dst_overrides
@@ -348,7 +362,7 @@ where
                 }
                 StateProj::Streaming(s) => {
                     trace!("streaming");
-                    let status = match ready!(Self::poll_rx(s, cx)) {
+                    let status = match ready!(Self::poll_rx(s, this.route_policies, cx)) {
                         Some(Ok(profile)) => return Poll::Ready(Ok(profile.into())),
                         None => grpc::Status::new(grpc::Code::Ok, ""),
                         Some(Err(status)) => status,
@@ -373,8 +387,9 @@ where
 fn convert_route(
     orig: api::Route,
     retry_budget: Option<&Arc<Budget>>,
+    route_policies: &IndexMap<String, profiles::RoutePolicy>,
 ) -> Option<(profiles::RequestMatch, profiles::Route)> {
-    let req_match = orig.condition.and_then(convert_req_match)?;
+    let mut req_match = orig.condition.and_then(convert_req_match)?;
     let rsp_classes = orig
         .response_classes
         .into_iter()
@@ -387,6 +402,23 @@ fn convert_route(
     if let Some(timeout) = orig.timeout {
         set_route_timeout(&mut route, timeout.try_into());
     }
+    // The destination API does not describe header or query parameter
+    // matches, or hedging, mirroring, fault injection, or rate limiting
+    // policies, so they are configured locally for each route.
+    let policy = route
+        .labels()
+        .get(profiles::ROUTE_LABEL)
+        .and_then(|name| route_policies.get(name))
+        .cloned();
+    if let Some(policy) = policy {
+        if !policy.matches.is_empty() {
+            let matches = Some(req_match)
+                .into_iter()
+                .chain(policy.matches.iter().cloned());
+            req_match = profiles::RequestMatch::All(matches.collect());
+        }
+        set_route_policy(&mut route, policy, retry_budget);
+    }
     Some((req_match, route))
 }
 
@@ -414,6 +446,28 @@ fn set_route_retry(route: &mut profiles::Route, retry_budget: Option<&Arc<Budget
     route.set_retries(budget);
 }
 
+fn set_route_policy(
+    route: &mut profiles::Route,
+    policy: profiles::RoutePolicy,
+    retry_budget: Option<&Arc<Budget>>,
+) {
+    if let Some(delay) = policy.hedge {
+        match retry_budget {
+            Some(budget) => route.set_hedge(budget.clone(), delay),
+            None => warn!("retry_budget is missing; not hedging: {:?}", route),
+        }
+    }
+    if let Some((addr, ratio)) = policy.mirror {
+        route.set_mirror(addr, ratio);
+    }
+    if let Some(faults) = policy.faults {
+        route.set_faults(faults);
+    }
+    if let Some(limit) = policy.rate_limit {
+        route.set_rate_limit(limit);
+    }
+}
+
 fn set_route_timeout(route: &mut profiles::Route, timeout: Result<Duration, Duration>) {
     match timeout {
         Ok(dur) => {
@@ -567,6 +621,46 @@ mod tests {
             true
         }
     }
+
+    #[test]
+    fn route_policies_add_request_matches() {
+        let route = api::Route {
+            condition: Some(api::RequestMatch {
+                r#match: Some(api::request_match::Match::Path(api::PathMatch {
+                    regex: "/list".into(),
+                })),
+            }),
+            metrics_labels: Some((profiles::ROUTE_LABEL.to_string(), "list".to_string()))
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let mut policies = IndexMap::new();
+        policies.insert(
+            "list".to_string(),
+            profiles::RoutePolicy {
+                matches: vec![profiles::RequestMatch::Header {
+                    name: http::header::HeaderName::from_static("x-tenant"),
+                    value: None,
+                }],
+                ..Default::default()
+            },
+        );
+
+        match convert_route(route.clone(), None, &policies) {
+            Some((profiles::RequestMatch::All(ms), _)) => match ms.as_slice() {
+                [profiles::RequestMatch::Path(_), profiles::RequestMatch::Header { .. }] => {}
+                ms => panic!("unexpected matches: {:?}", ms),
+            },
+            m => panic!("unexpected match: {:?}", m),
+        }
+
+        // Routes without local matches keep the destination's condition.
+        match convert_route(route, None, &IndexMap::new()) {
+            Some((profiles::RequestMatch::Path(_), _)) => {}
+            m => panic!("unexpected match: {:?}", m),
+        }
+    }
 }
 
 impl InvalidProfileAddr {
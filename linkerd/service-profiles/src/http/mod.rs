@@ -15,9 +15,11 @@ use tokio::sync::watch;
 use tower::retry::budget::Budget;
 
 mod concrete;
+mod policy;
 mod requests;
 pub mod service;
 
+pub use self::policy::{RoutePolicies, RoutePolicy, ROUTE_LABEL};
 pub use self::service::Layer;
 
 #[derive(Clone, Debug)]
@@ -90,6 +92,10 @@ pub struct Route {
     labels: Labels,
     response_classes: ResponseClasses,
     retries: Option<Retries>,
+    hedge: Option<Hedge>,
+    mirror: Option<Mirror>,
+    faults: Option<Faults>,
+    rate_limit: Option<RateLimit>,
     timeout: Option<Duration>,
 }
 
@@ -100,6 +106,26 @@ pub enum RequestMatch {
     Not(Box<RequestMatch>),
     Path(Regex),
     Method(http::Method),
+    /// Matches requests with the named header. If a value match is provided,
+    /// at least one of the header's values must match.
+    Header {
+        name: http::header::HeaderName,
+        value: Option<ValueMatch>,
+    },
+    /// Matches requests with the named query parameter. If a value match is
+    /// provided, at least one of the parameter's values must match.
+    ///
+    /// Query parameters are compared without percent-decoding.
+    Query {
+        name: String,
+        value: Option<ValueMatch>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub enum ValueMatch {
+    Exact(String),
+    Regex(Regex),
 }
 
 #[derive(Clone, Debug)]
@@ -127,6 +153,79 @@ pub struct Retries {
     budget: Arc<Budget>,
 }
 
+/// Configures a route to issue a second copy of a request if it has not
+/// completed after a delay.
+#[derive(Clone, Debug)]
+pub struct Hedge {
+    budget: Arc<Budget>,
+    delay: HedgeDelay,
+}
+
+/// Configures a route to send a copy of a share of its requests to another
+/// destination, discarding the responses.
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    addr: NameAddr,
+    ratio: f64,
+}
+
+/// Configures faults to be injected into a share of a route's requests.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Faults {
+    pub delay: Option<DelayFault>,
+    pub abort: Option<AbortFault>,
+}
+
+/// Configures a token bucket that limits the rate of requests.
+///
+/// Requests that arrive while the bucket is empty wait for a token, up to
+/// `max_queued` at a time. Further requests are rejected.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    /// The number of tokens added to the bucket each second.
+    pub rate: u32,
+
+    /// The bucket's capacity, i.e. the largest burst of requests that may be
+    /// sent at once.
+    pub burst: u32,
+
+    pub max_queued: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct DelayFault {
+    /// The share of requests, between 0.0 and 1.0, that are delayed.
+    pub ratio: f64,
+
+    /// Requests are delayed by a random duration between `min` and `max`. The
+    /// delay is fixed when these are equal.
+    pub min: Duration,
+    pub max: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct AbortFault {
+    /// The share of requests, between 0.0 and 1.0, that are aborted.
+    pub ratio: f64,
+    pub status: AbortStatus,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AbortStatus {
+    Http(http::StatusCode),
+    Grpc(u16),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum HedgeDelay {
+    /// Hedges requests that have not completed after a fixed duration.
+    Fixed(Duration),
+
+    /// Hedges requests that have taken longer than the given percentile
+    /// (between 0.0 and 1.0) of the route's observed latencies.
+    Percentile(f64),
+}
+
 #[derive(Clone, Default)]
 struct Labels(Arc<IndexMap<String, String>>);
 
@@ -147,6 +246,10 @@ impl Route {
             labels,
             response_classes: ResponseClasses(response_classes.into()),
             retries: None,
+            hedge: None,
+            mirror: None,
+            faults: None,
+            rate_limit: None,
             timeout: None,
         }
     }
@@ -155,6 +258,21 @@ impl Route {
         &self.labels.0
     }
 
+    /// Returns a copy of the route with an additional label, i.e. so that a
+    /// subset of the route's requests may be distinguished in metrics.
+    pub fn with_label(&self, key: &str, value: &str) -> Self {
+        let labels = self
+            .labels()
+            .iter()
+            .filter(|(k, _)| *k != key)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .chain(Some((key.to_string(), value.to_string())));
+        Self {
+            labels: Self::new(labels, Vec::new()).labels,
+            ..self.clone()
+        }
+    }
+
     pub fn response_classes(&self) -> &ResponseClasses {
         &self.response_classes
     }
@@ -163,6 +281,22 @@ impl Route {
         self.retries.as_ref()
     }
 
+    pub fn hedge(&self) -> Option<&Hedge> {
+        self.hedge.as_ref()
+    }
+
+    pub fn mirror(&self) -> Option<&Mirror> {
+        self.mirror.as_ref()
+    }
+
+    pub fn faults(&self) -> Option<&Faults> {
+        self.faults.as_ref()
+    }
+
+    pub fn rate_limit(&self) -> Option<&RateLimit> {
+        self.rate_limit.as_ref()
+    }
+
     pub fn timeout(&self) -> Option<Duration> {
         self.timeout
     }
@@ -171,6 +305,28 @@ impl Route {
         self.retries = Some(Retries { budget });
     }
 
+    /// Configures the route to hedge requests, drawing from the same budget
+    /// as retries.
+    pub fn set_hedge(&mut self, budget: Arc<Budget>, delay: HedgeDelay) {
+        self.hedge = Some(Hedge { budget, delay });
+    }
+
+    /// Configures the route to mirror the given ratio (between 0.0 and 1.0) of
+    /// its requests to `addr`. Ratios outside of this range are clamped.
+    pub fn set_mirror(&mut self, addr: NameAddr, ratio: f64) {
+        // `f64::max` ignores NaN, so a NaN ratio disables mirroring.
+        let ratio = ratio.max(0.0).min(1.0);
+        self.mirror = Some(Mirror { addr, ratio });
+    }
+
+    pub fn set_faults(&mut self, faults: Faults) {
+        self.faults = Some(faults);
+    }
+
+    pub fn set_rate_limit(&mut self, rate_limit: RateLimit) {
+        self.rate_limit = Some(rate_limit);
+    }
+
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout);
     }
@@ -183,6 +339,39 @@ impl RequestMatch {
         match self {
             RequestMatch::Method(ref method) => req.method() == *method,
             RequestMatch::Path(ref re) => re.is_match(req.uri().path()),
+            RequestMatch::Header {
+                ref name,
+                ref value,
+            } => {
+                let mut values = req.headers().get_all(name).iter();
+                match value {
+                    None => values.next().is_some(),
+                    Some(m) => values.any(|v| v.to_str().map(|v| m.is_match(v)).unwrap_or(false)),
+                }
+            }
+            RequestMatch::Query {
+                ref name,
+                ref value,
+            } => {
+                let mut values = req
+                    .uri()
+                    .query()
+                    .into_iter()
+                    .flat_map(|q| q.split('&'))
+                    .filter_map(|param| {
+                        let mut kv = param.splitn(2, '=');
+                        let k = kv.next()?;
+                        if k == name {
+                            Some(kv.next().unwrap_or(""))
+                        } else {
+                            None
+                        }
+                    });
+                match value {
+                    None => values.next().is_some(),
+                    Some(m) => values.any(|v| m.is_match(v)),
+                }
+            }
             RequestMatch::Not(ref m) => !m.is_match(req),
             RequestMatch::All(ref ms) => ms.iter().all(|m| m.is_match(req)),
             RequestMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(req)),
@@ -190,6 +379,17 @@ impl RequestMatch {
     }
 }
 
+// === impl ValueMatch ===
+
+impl ValueMatch {
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            ValueMatch::Exact(ref v) => v == value,
+            ValueMatch::Regex(ref re) => re.is_match(value),
+        }
+    }
+}
+
 // === impl ResponseClass ===
 
 impl ResponseClass {
@@ -273,6 +473,126 @@ impl Hash for Retries {
     }
 }
 
+// === impl Hedge ===
+
+impl Hedge {
+    pub fn budget(&self) -> &Arc<Budget> {
+        &self.budget
+    }
+
+    pub fn delay(&self) -> HedgeDelay {
+        self.delay
+    }
+}
+
+impl PartialEq for Hedge {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.budget, &other.budget) && self.delay == other.delay
+    }
+}
+
+impl Eq for Hedge {}
+
+impl Hash for Hedge {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(Arc::as_ref(&self.budget) as *const _ as usize);
+        self.delay.hash(state);
+    }
+}
+
+// === impl HedgeDelay ===
+
+impl PartialEq for HedgeDelay {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (HedgeDelay::Fixed(a), HedgeDelay::Fixed(b)) => a == b,
+            (HedgeDelay::Percentile(a), HedgeDelay::Percentile(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for HedgeDelay {}
+
+impl Hash for HedgeDelay {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            HedgeDelay::Fixed(d) => {
+                state.write_u8(0);
+                d.hash(state);
+            }
+            HedgeDelay::Percentile(p) => {
+                state.write_u8(1);
+                state.write_u64(p.to_bits());
+            }
+        }
+    }
+}
+
+// === impl Mirror ===
+
+impl Mirror {
+    pub fn addr(&self) -> &NameAddr {
+        &self.addr
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+}
+
+impl PartialEq for Mirror {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr && self.ratio.to_bits() == other.ratio.to_bits()
+    }
+}
+
+impl Eq for Mirror {}
+
+impl Hash for Mirror {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.addr.hash(state);
+        state.write_u64(self.ratio.to_bits());
+    }
+}
+
+// === impl DelayFault ===
+
+impl PartialEq for DelayFault {
+    fn eq(&self, other: &Self) -> bool {
+        self.ratio.to_bits() == other.ratio.to_bits()
+            && self.min == other.min
+            && self.max == other.max
+    }
+}
+
+impl Eq for DelayFault {}
+
+impl Hash for DelayFault {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.ratio.to_bits());
+        self.min.hash(state);
+        self.max.hash(state);
+    }
+}
+
+// === impl AbortFault ===
+
+impl PartialEq for AbortFault {
+    fn eq(&self, other: &Self) -> bool {
+        self.ratio.to_bits() == other.ratio.to_bits() && self.status == other.status
+    }
+}
+
+impl Eq for AbortFault {}
+
+impl Hash for AbortFault {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.ratio.to_bits());
+        self.status.hash(state);
+    }
+}
+
 // === impl Labels ===
 
 impl PartialEq for Labels {
@@ -294,3 +614,106 @@ impl fmt::Debug for Labels {
         self.0.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(uri: &str, headers: &[(&'static str, &'static str)]) -> http::Request<()> {
+        let mut req = http::Request::builder().uri(uri);
+        for (k, v) in headers {
+            req = req.header(*k, *v);
+        }
+        req.body(()).unwrap()
+    }
+
+    #[test]
+    fn header_match() {
+        let present = RequestMatch::Header {
+            name: http::header::HeaderName::from_static("x-tenant"),
+            value: None,
+        };
+        assert!(present.is_match(&req("/", &[("x-tenant", "a")])));
+        assert!(!present.is_match(&req("/", &[])));
+
+        let exact = RequestMatch::Header {
+            name: http::header::CONTENT_TYPE,
+            value: Some(ValueMatch::Exact("application/grpc".into())),
+        };
+        assert!(exact.is_match(&req("/", &[("content-type", "application/grpc")])));
+        assert!(!exact.is_match(&req("/", &[("content-type", "application/json")])));
+
+        let regex = RequestMatch::Header {
+            name: http::header::CONTENT_TYPE,
+            value: Some(ValueMatch::Regex(
+                Regex::new("^application/grpc(\\+.*)?$").unwrap(),
+            )),
+        };
+        assert!(regex.is_match(&req("/", &[("content-type", "application/grpc+proto")])));
+        assert!(!regex.is_match(&req("/", &[("content-type", "application/json")])));
+    }
+
+    #[test]
+    fn query_match() {
+        let present = RequestMatch::Query {
+            name: "debug".into(),
+            value: None,
+        };
+        assert!(present.is_match(&req("/?debug", &[])));
+        assert!(present.is_match(&req("/?a=b&debug=1", &[])));
+        assert!(!present.is_match(&req("/?debugging=1", &[])));
+        assert!(!present.is_match(&req("/", &[])));
+
+        let exact = RequestMatch::Query {
+            name: "tenant".into(),
+            value: Some(ValueMatch::Exact("a".into())),
+        };
+        assert!(exact.is_match(&req("/?tenant=b&tenant=a", &[])));
+        assert!(!exact.is_match(&req("/?tenant=ab", &[])));
+    }
+
+    fn hash_of<T: Hash>(t: &T) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        t.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn faults_are_equal_when_their_hashes_are() {
+        let delay = |ratio: f64| Faults {
+            delay: Some(DelayFault {
+                ratio,
+                min: Duration::from_millis(10),
+                max: Duration::from_millis(10),
+            }),
+            abort: None,
+        };
+        let abort = |ratio: f64| Faults {
+            delay: None,
+            abort: Some(AbortFault {
+                ratio,
+                status: AbortStatus::Http(http::StatusCode::SERVICE_UNAVAILABLE),
+            }),
+        };
+
+        let cases: [fn(f64) -> Faults; 2] = [delay, abort];
+        for faults in cases.iter() {
+            assert_eq!(faults(0.5), faults(0.5));
+            assert_eq!(hash_of(&faults(0.5)), hash_of(&faults(0.5)));
+            assert_eq!(faults(std::f64::NAN), faults(std::f64::NAN));
+            assert_ne!(faults(0.0), faults(-0.0));
+            assert_ne!(faults(0.1), faults(0.2));
+        }
+        assert_ne!(delay(0.5), abort(0.5));
+    }
+
+    #[test]
+    fn hedge_delays_are_equal_when_their_hashes_are() {
+        let p = HedgeDelay::Percentile;
+        assert_eq!(p(0.99), p(0.99));
+        assert_eq!(hash_of(&p(0.99)), hash_of(&p(0.99)));
+        assert_eq!(p(std::f64::NAN), p(std::f64::NAN));
+        assert_ne!(p(0.0), p(-0.0));
+        assert_ne!(p(0.5), HedgeDelay::Fixed(Duration::from_millis(500)));
+    }
+}
@@ -0,0 +1,53 @@
+use super::{Faults, HedgeDelay, RateLimit, RequestMatch};
+use indexmap::IndexMap;
+use linkerd2_addr::NameAddr;
+use std::sync::Arc;
+
+/// The route label that names a profile's routes.
+pub const ROUTE_LABEL: &str = "route";
+
+/// Policies that are configured locally, rather than by the destination
+/// service, for a profile route.
+#[derive(Clone, Debug, Default)]
+pub struct RoutePolicy {
+    /// Header and query parameter matches that requests must satisfy, in
+    /// addition to the route's condition, to be routed to the route.
+    pub matches: Vec<RequestMatch>,
+
+    /// Hedges the route's requests after a delay.
+    pub hedge: Option<HedgeDelay>,
+
+    /// Mirrors a ratio of the route's requests to another destination.
+    pub mirror: Option<(NameAddr, f64)>,
+
+    /// Injects delays and aborts into a share of the route's requests.
+    pub faults: Option<Faults>,
+
+    /// Limits the rate of the route's requests.
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// Locally configured route policies, by destination and route name.
+#[derive(Clone, Debug, Default)]
+pub struct RoutePolicies(Arc<IndexMap<NameAddr, Arc<IndexMap<String, RoutePolicy>>>>);
+
+// === impl RoutePolicies ===
+
+impl RoutePolicies {
+    pub fn new(policies: IndexMap<NameAddr, IndexMap<String, RoutePolicy>>) -> Self {
+        let policies = policies
+            .into_iter()
+            .map(|(dst, routes)| (dst, Arc::new(routes)))
+            .collect();
+        RoutePolicies(Arc::new(policies))
+    }
+
+    /// Returns the policies for a destination's routes, by route name.
+    pub fn get(&self, dst: &NameAddr) -> Arc<IndexMap<String, RoutePolicy>> {
+        self.0.get(dst).cloned().unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}